@@ -0,0 +1,120 @@
+use crate::config::AppConfig;
+use crate::error::ShortsError;
+use crate::pipeline::{generate_short_with_progress, process_video_with_config, GeneratedShort};
+use crate::progress::ProgressReporter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Async wrappers around the synchronous pipeline for callers on a tokio runtime, e.g. a web
+// service. The work runs on tokio's blocking thread pool (spawn_blocking), so no async worker is
+// held up while FFmpeg and whisper run. Dropping a returned future cancels the run: it stops at
+// the next step, and a running whisper CLI process is killed. An FFmpeg step already under way
+// finishes first.
+
+// Async version of generate_short.
+pub async fn generate_short_async(config: AppConfig) -> Result<GeneratedShort, ShortsError> {
+    generate_short_with_progress_async(config, None).await
+}
+
+// Async version of generate_short_with_progress.
+pub async fn generate_short_with_progress_async(
+    config: AppConfig,
+    reporter: Option<Arc<dyn ProgressReporter + Send + Sync>>,
+) -> Result<GeneratedShort, ShortsError> {
+    run_blocking(reporter, move |reporter| generate_short_with_progress(&config, Some(reporter))).await
+}
+
+// Async version of process_video_with_config.
+pub async fn process_video_with_config_async(
+    config: AppConfig,
+    dry_run: bool,
+    reporter: Option<Arc<dyn ProgressReporter + Send + Sync>>,
+) -> Result<(), ShortsError> {
+    run_blocking(reporter, move |reporter| process_video_with_config(config, dry_run, Some(reporter))).await
+}
+
+// Runs `work` on the blocking thread pool with a reporter that reports cancellation once the
+// returned future is dropped.
+async fn run_blocking<T, F>(reporter: Option<Arc<dyn ProgressReporter + Send + Sync>>, work: F) -> Result<T, ShortsError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn ProgressReporter) -> Result<T, ShortsError> + Send + 'static,
+{
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let _cancel_on_drop = CancelOnDrop(Arc::clone(&cancelled));
+    let reporter = CancellableReporter { inner: reporter, cancelled };
+    match tokio::task::spawn_blocking(move || work(&reporter)).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        // The runtime is shutting down.
+        Err(_) => Err(ShortsError::Cancelled),
+    }
+}
+
+// Sets the flag when dropped, i.e. when the future holding it completes or is dropped.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// Forwards to the caller's reporter, adding the cancellation of the future.
+struct CancellableReporter {
+    inner: Option<Arc<dyn ProgressReporter + Send + Sync>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ProgressReporter for CancellableReporter {
+    fn stage(&self, name: &str) {
+        if let Some(inner) = &self.inner {
+            inner.stage(name);
+        }
+    }
+
+    fn progress(&self, fraction: f64) {
+        if let Some(inner) = &self.inner {
+            inner.progress(fraction);
+        }
+    }
+
+    fn done(&self) {
+        if let Some(inner) = &self.inner {
+            inner.done();
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.inner.as_ref().is_some_and(|inner| inner.is_cancelled())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn async_generate_short_reports_errors_like_the_blocking_call() {
+        let config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "missing.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ]);
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let err = runtime.block_on(generate_short_async(config)).unwrap_err();
+        assert!(matches!(&err, ShortsError::InvalidArgument(message) if message.contains("missing.mp4")));
+    }
+
+    #[test]
+    fn dropping_the_future_cancels_the_run() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let reporter = CancellableReporter { inner: None, cancelled: Arc::clone(&cancelled) };
+        assert!(!reporter.is_cancelled());
+        drop(CancelOnDrop(cancelled));
+        assert!(reporter.is_cancelled());
+    }
+}
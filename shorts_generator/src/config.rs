@@ -1,5 +1,38 @@
 use serde::{Serialize, Deserialize};
-use clap::Parser; // Added clap::Parser
+use clap::{Parser, ValueEnum}; // Added clap::Parser
+use crate::error::ShortsError;
+use log::warn;
+use std::path::Path;
+use std::str::FromStr;
+
+// A (start, duration) window of the input video to turn into its own short.
+// Parsed from the CLI as "START:DURATION" in seconds, e.g. `--segment 90:60`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start_secs: f64,
+    pub duration_secs: f64,
+}
+
+impl FromStr for Segment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, duration) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid segment '{}'. Use START:DURATION in seconds, e.g. '90:60'.", s))?;
+        let start_secs: f64 = start.trim().parse()
+            .map_err(|_| format!("Invalid segment start time in '{}'", s))?;
+        let duration_secs: f64 = duration.trim().parse()
+            .map_err(|_| format!("Invalid segment duration in '{}'", s))?;
+        if start_secs < 0.0 {
+            return Err(format!("Segment start time must not be negative in '{}'", s));
+        }
+        if duration_secs <= 0.0 {
+            return Err(format!("Segment duration must be greater than zero in '{}'", s));
+        }
+        Ok(Segment { start_secs, duration_secs })
+    }
+}
 
 #[derive(Parser, Serialize, Deserialize, Debug, Clone)] // Added Parser, Clone
 #[clap(author, version, about, long_about = None)]
@@ -7,17 +40,464 @@ pub struct VideoConfig {
     #[clap(long, help = "Path to the input video file")]
     pub input_path: String,
     
+    #[clap(long = "append-input", value_name = "PATH", help = "Further input video to join after input_path before trimming (repeatable, joined in order)")]
+    #[serde(default)]
+    pub append_input_paths: Vec<String>,
+    
+    #[clap(long, help = "Index of the input's audio stream to use, e.g. a separate microphone track, instead of the best one (list them with the audio-streams command)")]
+    #[serde(default)]
+    pub audio_stream_index: Option<usize>,
+    
     #[clap(long, help = "Path to save the output video file(s)")]
     pub output_path: String,
     
-    #[clap(long, default_value = "60", help = "Duration of each short video in seconds")]
-    pub short_duration_secs: u64,
+    #[clap(long, default_value = "0.0", allow_negative_numbers = true, help = "Start time of the short within the input video, in seconds")]
+    #[serde(default)]
+    pub start_secs: f64,
+    
+    #[clap(long, default_value = "60", help = "Duration of each short video in seconds, e.g. 58.5")]
+    pub short_duration_secs: f64,
+    
+    #[clap(long, value_enum, help = "How the trim finds the start (accurate: first keyframe at or after it, reading the input from the beginning; fast: seek to the keyframe before it; default: fast for stdin, accurate otherwise)")]
+    #[serde(default)]
+    pub seek_mode: Option<SeekMode>,
+    
+    #[clap(long = "segment", value_name = "START:DURATION", help = "Generate a short for this window of the input (repeatable); outputs are numbered, e.g. out_01.mp4")]
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+    
+    #[clap(long, help = "Split the whole input into shorts of about short_duration_secs each, cutting at the nearest scene change; outputs are numbered like segments")]
+    #[serde(default)]
+    pub auto_split: bool,
+    
+    #[clap(long, default_value = "0.4", help = "Scene change score (between 0 and 1) that counts as a cut for --auto-split; lower values find more cuts")]
+    #[serde(default = "default_scene_threshold")]
+    pub scene_threshold: f32,
+    
+    #[clap(long, help = "Add a chapter marker at each scene change of the short (detected with scene_threshold), for players that list chapters")]
+    #[serde(default)]
+    pub generate_chapters: bool,
+    
+    #[clap(long, help = "Optional path to write a JSON report of the run to (outputs, subtitles, stage timings, status)")]
+    #[serde(default)]
+    pub report_path: Option<String>,
+    
+    #[clap(long, env = ENV_TEMP_DIR, help = "Optional directory for intermediate files (default: the OS temp directory when TMPDIR is set, otherwise next to the output)")]
+    #[serde(default)]
+    pub temp_dir: Option<String>,
+    
+    #[clap(long, help = "Keep the temporary directory with the intermediate files (extracted audio, subtitles, ...) instead of removing it, for debugging")]
+    #[serde(default)]
+    pub keep_temp: bool,
+
+    #[clap(long, help = "With several segments, prepare (trim and transcribe) the next segment while the current one is encoded. Faster on multi-core machines; uses up to two temporary directories at once")]
+    #[serde(default)]
+    pub overlap_segments: bool,
+    
+    #[clap(long, help = "Replace output files that already exist instead of failing (in batch mode: instead of skipping the input)")]
+    #[serde(default)]
+    pub overwrite: bool,
     
-    #[clap(long, help = "Optional output width for the video")]
+    #[clap(long, help = "Fail a short when any packet cannot be written while copying streams, instead of tolerating up to 1% of them")]
+    #[serde(default)]
+    pub strict_packet_writes: bool,
+    
+    #[clap(long, help = "Optional container format (FFmpeg muxer name, e.g. 'mp4', 'mpegts', 'matroska') of the output, instead of inferring it from the output extension")]
+    #[serde(default)]
+    pub output_format: Option<String>,
+    
+    #[clap(long = "output-option", value_name = "KEY=VALUE", value_parser = parse_output_option, help = "FFmpeg muxer option for the output file (repeatable), e.g. 'movflags=+faststart' for web playback")]
+    #[serde(default)]
+    pub extra_output_options: Vec<(String, String)>,
+    
+    #[clap(long, default_value = "true", action = clap::ArgAction::Set, help = "Move the index (moov atom) of MP4 and MOV outputs to the front of the file so web players can start before the download finishes (true, false)")]
+    #[serde(default = "default_faststart")]
+    pub faststart: bool,
+    
+    #[clap(long, help = "Optional output width for the video (keeps the aspect ratio if no height is given)")]
     pub output_width: Option<u32>,
     
-    #[clap(long, help = "Optional output height for the video")]
+    #[clap(long, help = "Optional output height for the video (keeps the aspect ratio if no width is given)")]
     pub output_height: Option<u32>,
+
+    #[clap(long, help = "Optional target aspect ratio of the short (e.g., '9:16', '1:1', '4:5'), center-cropped by default")]
+    #[serde(default)]
+    pub target_aspect_ratio: Option<String>,
+
+    #[clap(long, value_enum, default_value = "crop", help = "How to fit the video into target_aspect_ratio (crop, pad, blur: letterboxed over a blurred copy of itself)")]
+    #[serde(default)]
+    pub aspect_fit_mode: AspectFitMode,
+
+    #[clap(long, default_value = "black", help = "Color of the bars added by aspect_fit_mode pad (e.g., 'black', 'white', '#202020')")]
+    #[serde(default = "default_pad_color")]
+    pub pad_color: String,
+
+    #[clap(long, value_enum, help = "Video encoder used when re-encoding (x264, nvenc, vaapi, videotoolbox, vp9; default: vp9 for WebM outputs, x264 otherwise)")]
+    #[serde(default)]
+    pub encoder: Option<VideoEncoder>,
+
+    #[clap(long, value_enum, default_value = "veryfast", help = "libx264 preset (ultrafast, superfast, veryfast, faster, fast, medium, slow, slower, veryslow); slower presets make smaller files")]
+    #[serde(default)]
+    pub x264_preset: X264Preset,
+
+    #[clap(long, value_enum, help = "libx264 tune for the kind of footage (film, animation, grain, stillimage, fastdecode, zerolatency)")]
+    #[serde(default)]
+    pub x264_tune: Option<X264Tune>,
+
+    #[clap(long, help = "Constant quality for re-encoded video, 0-51 (lower is better; libx264 defaults to 23)")]
+    #[serde(default)]
+    pub crf: Option<u8>,
+
+    #[clap(long, help = "Target bitrate for re-encoded video (e.g., '4M', '2500k')")]
+    #[serde(default)]
+    pub video_bitrate: Option<String>,
+
+    #[clap(long, help = "Pick the video bitrate so each short fits in this many megabytes (1 MB = 1,000,000 bytes)")]
+    #[serde(default)]
+    pub target_size_mb: Option<f64>,
+
+    #[clap(long, help = "Encode the burned subtitles in two libx264 passes so video_bitrate/target_size_mb is hit closely")]
+    #[serde(default)]
+    pub two_pass: bool,
+
+    #[clap(long, help = "Re-encode variable frame rate sources (e.g. screen recordings) as they are instead of converting them to a constant frame rate")]
+    #[serde(default)]
+    pub keep_variable_frame_rate: bool,
+
+    #[clap(long, value_enum, help = "Audio codec of the shorts (aac, opus, copy to keep the source audio when no step changes it; default: opus for WebM outputs, aac otherwise)")]
+    #[serde(default)]
+    pub audio_codec: Option<AudioCodec>,
+
+    #[clap(long, help = "Bitrate for re-encoded audio (e.g., '128k'; default: 128k)")]
+    #[serde(default)]
+    pub audio_bitrate: Option<String>,
+
+    #[clap(long, help = "Normalize the audio loudness (EBU R128) of each short")]
+    #[serde(default)]
+    pub normalize_audio: bool,
+
+    #[clap(long, default_value = "-14.0", allow_negative_numbers = true, help = "Integrated loudness target in LUFS used by --normalize-audio")]
+    #[serde(default = "default_target_lufs")]
+    pub target_lufs: f64,
+
+    #[clap(long, help = "Cut out pauses (dead air) from each short, keeping video and audio in sync")]
+    #[serde(default)]
+    pub trim_silence: bool,
+
+    #[clap(long, default_value = "-35", allow_negative_numbers = true, help = "Audio level in dBFS below which --trim-silence treats audio as silent")]
+    #[serde(default = "default_silence_threshold_db")]
+    pub silence_threshold_db: f32,
+
+    #[clap(long, default_value = "0.6", help = "Shortest pause in seconds that --trim-silence removes")]
+    #[serde(default = "default_min_silence_secs")]
+    pub min_silence_secs: f64,
+
+    #[clap(long, help = "Optional playback speed of each short, e.g. 1.25 to fit more into it or 0.5 for slow motion; applied before transcription so the captions stay in sync")]
+    #[serde(default)]
+    pub speed_factor: Option<f64>,
+
+    #[clap(long, env = ENV_BG_MUSIC_PATH, help = "Optional music file to mix under the audio of each short")]
+    #[serde(default)]
+    pub bg_music_path: Option<String>,
+
+    #[clap(long, default_value = "0.2", help = "Volume of the background music relative to its original level (1.0 = unchanged)")]
+    #[serde(default = "default_bg_music_volume")]
+    pub bg_music_volume: f32,
+
+    #[clap(long, help = "Lower the background music whenever someone is speaking")]
+    #[serde(default)]
+    pub bg_music_duck: bool,
+
+    #[clap(long, default_value = "0", help = "Fade the video and audio in over this many seconds at the start (0 disables)")]
+    #[serde(default)]
+    pub fade_in_secs: f64,
+
+    #[clap(long, default_value = "0", help = "Fade the video and audio out over this many seconds at the end (0 disables)")]
+    #[serde(default)]
+    pub fade_out_secs: f64,
+
+    #[clap(long, help = "Optional time in seconds into each short of the frame to save as its thumbnail, written next to it as <output>_thumb.jpg")]
+    #[serde(default)]
+    pub thumbnail_at_secs: Option<f64>,
+
+    #[clap(long = "preview", value_name = "SECONDS", help = "Render only the first SECONDS of each short, e.g. to check the subtitle style, written as <output>_preview")]
+    #[serde(default)]
+    pub preview_secs: Option<f64>,
+}
+
+fn default_pad_color() -> String {
+    "black".to_string()
+}
+
+fn default_faststart() -> bool {
+    true
+}
+
+fn default_whisper_max_attempts() -> u32 {
+    1
+}
+
+fn default_scene_threshold() -> f32 {
+    crate::video_processing::DEFAULT_SCENE_THRESHOLD
+}
+
+fn default_bg_music_volume() -> f32 {
+    0.2
+}
+
+fn default_target_lufs() -> f64 {
+    crate::video_processing::DEFAULT_TARGET_LUFS
+}
+
+fn default_silence_threshold_db() -> f32 {
+    crate::video_processing::DEFAULT_SILENCE_THRESHOLD_DB
+}
+
+fn default_min_silence_secs() -> f64 {
+    crate::video_processing::DEFAULT_MIN_SILENCE_SECS
+}
+
+// Encoder used whenever video frames are re-encoded (cropping, subtitles, watermarks): H.264
+// through libx264 or a hardware encoder, or VP9 for WebM outputs. The hardware encoders need a
+// matching GPU and an FFmpeg build that includes them; when the requested one isn't available the
+// pipeline falls back to libx264 with a warning.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoEncoder {
+    #[default]
+    X264,
+    Nvenc,
+    Vaapi,
+    #[value(name = "videotoolbox")]
+    VideoToolbox,
+    Vp9,
+}
+
+impl VideoEncoder {
+    // Name of the FFmpeg encoder implementing this choice.
+    pub fn codec_name(&self) -> &'static str {
+        match self {
+            VideoEncoder::X264 => "libx264",
+            VideoEncoder::Nvenc => "h264_nvenc",
+            VideoEncoder::Vaapi => "h264_vaapi",
+            VideoEncoder::VideoToolbox => "h264_videotoolbox",
+            VideoEncoder::Vp9 => "libvpx-vp9",
+        }
+    }
+
+    // Whether this is one of the H.264 encoders, which can stand in for each other.
+    pub fn is_h264(&self) -> bool {
+        *self != VideoEncoder::Vp9
+    }
+}
+
+// libx264 speed/compression tradeoff: slower presets make smaller files at the same quality.
+// Shorts default to veryfast, as encode time matters more than a few percent of size.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum X264Preset {
+    Ultrafast,
+    Superfast,
+    #[default]
+    Veryfast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    Veryslow,
+}
+
+impl X264Preset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            X264Preset::Ultrafast => "ultrafast",
+            X264Preset::Superfast => "superfast",
+            X264Preset::Veryfast => "veryfast",
+            X264Preset::Faster => "faster",
+            X264Preset::Fast => "fast",
+            X264Preset::Medium => "medium",
+            X264Preset::Slow => "slow",
+            X264Preset::Slower => "slower",
+            X264Preset::Veryslow => "veryslow",
+        }
+    }
+}
+
+// libx264 tuning for the kind of footage.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum X264Tune {
+    Film,
+    Animation,
+    Grain,
+    #[value(name = "stillimage")]
+    StillImage,
+    #[value(name = "fastdecode")]
+    FastDecode,
+    #[value(name = "zerolatency")]
+    ZeroLatency,
+}
+
+impl X264Tune {
+    pub fn name(&self) -> &'static str {
+        match self {
+            X264Tune::Film => "film",
+            X264Tune::Animation => "animation",
+            X264Tune::Grain => "grain",
+            X264Tune::StillImage => "stillimage",
+            X264Tune::FastDecode => "fastdecode",
+            X264Tune::ZeroLatency => "zerolatency",
+        }
+    }
+}
+
+// Audio encoding of the delivered shorts. Burning subtitles re-encodes the audio with it unless
+// it is Copy, which keeps the source audio as-is; steps that filter the audio always encode it,
+// with AAC in place of Copy.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    #[default]
+    Aac,
+    Opus,
+    Copy,
+}
+
+impl AudioCodec {
+    // Name of the FFmpeg encoder implementing this choice; None for Copy.
+    pub fn codec_name(&self) -> Option<&'static str> {
+        match self {
+            AudioCodec::Aac => Some("aac"),
+            AudioCodec::Opus => Some("libopus"),
+            AudioCodec::Copy => None,
+        }
+    }
+}
+
+// How the trim reaches start_secs. The streams are copied, so the short has to begin on a
+// keyframe: Accurate reads the input up to the start and begins at the next keyframe, Fast seeks
+// straight to the keyframe before the start, which is much quicker far into long inputs but can
+// begin the short up to one keyframe interval early.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SeekMode {
+    Fast,
+    #[default]
+    Accurate,
+}
+
+// How the source is fitted into target_aspect_ratio when their aspect ratios differ: cropped to
+// fill the frame, letterboxed with bars of pad_color, or letterboxed over a blurred, enlarged copy of itself.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AspectFitMode {
+    #[default]
+    Crop,
+    Pad,
+    #[value(name = "blur")]
+    #[serde(rename = "blur")]
+    BlurBackground,
+}
+
+// Case change applied to the caption text before it is burned, e.g. ALL CAPS captions.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionTextTransform {
+    #[default]
+    None,
+    Upper,
+    Lower,
+    #[value(name = "titlecase")]
+    TitleCase,
+}
+
+// How each caption appears when burned from an ASS script: faded in, popped in from a smaller
+// size, or slid up into place while fading in.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionAnimation {
+    #[default]
+    None,
+    #[value(name = "fadein")]
+    FadeIn,
+    Pop,
+    #[value(name = "slideup")]
+    SlideUp,
+}
+
+// What to do when subtitles are to be transcribed but the input has no audio stream: fail the run,
+// or warn and produce the shorts without captions (e.g. for batches mixing silent clips in).
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleMissingAudioPolicy {
+    #[default]
+    Error,
+    Skip,
+}
+
+// How the captions end up in the shorts: burned into the frames, muxed as a soft subtitle track
+// that players can turn on and off (no re-encoding, so much faster), or both.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleMode {
+    #[default]
+    Burn,
+    #[value(name = "softmux")]
+    SoftMux,
+    Both,
+}
+
+// Where the subtitle font comes from: the font_path file, which renders the same on every machine,
+// or the font_name family as installed on this one, looked up through fontconfig.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSource {
+    #[default]
+    File,
+    Name,
+}
+
+// What to do when the subtitle font file does not exist: fail validation, or warn and burn the
+// subtitles with the system's default sans-serif font (FALLBACK_FONT_NAME).
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingFontPolicy {
+    #[default]
+    Error,
+    Fallback,
+}
+
+// Which Whisper implementation generates the subtitles.
+// `Cli` shells out to the Python `whisper` command; `Native` runs whisper.cpp in-process
+// (requires the `native-whisper` feature and a ggml model file).
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WhisperBackend {
+    #[default]
+    Cli,
+    Native,
+}
+
+// File format of the generated subtitles. Whisper writes SRT and VTT directly; ASS is
+// converted from SRT and, when burned, keeps its own styling instead of force_style.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    #[default]
+    Srt,
+    Vtt,
+    Ass,
+}
+
+impl SubtitleFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Ass => "ass",
+        }
+    }
 }
 
 #[derive(Parser, Serialize, Deserialize, Debug, Clone)] // Added Parser, Clone
@@ -26,45 +506,1337 @@ pub struct SubtitleConfig {
     #[clap(long, default_value = "true", help = "Enable or disable subtitle generation and burning")]
     pub use_subtitles: bool,
     
-    #[clap(long, help = "Path to the Whisper model file (e.g., tiny.en, base, small, medium, large) or directory")]
+    #[clap(long, env = ENV_WHISPER_MODEL_PATH, help = "Path to the Whisper model file (e.g., tiny.en, base, small, medium, large) or directory; the native backend downloads ggml models given by name")]
     pub whisper_model_path: String,
     
-    #[clap(long, help = "Path to the font file for subtitles (.ttf, .otf)")]
+    #[clap(long, value_enum, default_value = "cli", help = "Whisper backend used for transcription (cli, native)")]
+    #[serde(default)]
+    pub whisper_backend: WhisperBackend,
+    
+    #[clap(long, env = ENV_WHISPER_MODEL_CACHE_DIR, help = "Directory the native backend keeps models given by name in (default: ~/.cache/shorts_generator/models)")]
+    #[serde(default)]
+    pub whisper_model_cache_dir: Option<String>,
+    
+    #[clap(long, help = "Fail instead of downloading a whisper model that is not in the cache (native backend)")]
+    #[serde(default)]
+    pub whisper_offline: bool,
+    
+    #[clap(long, env = ENV_WHISPER_BINARY_PATH, help = "Path to the whisper executable used by the cli backend (default: `whisper` on PATH)")]
+    #[serde(default)]
+    pub whisper_binary_path: Option<String>,
+    
+    #[clap(long, env = ENV_LANGUAGE, help = "Spoken language of the video as a whisper code or name (e.g., 'es', 'german'); auto-detected when omitted")]
+    #[serde(default)]
+    pub language: Option<String>,
+    
+    #[clap(long, help = "Translate the subtitles to English, whatever the spoken language")]
+    #[serde(default)]
+    pub translate_to_english: bool,
+    
+    #[clap(long, default_value = "1", help = "Times to run the whisper executable before giving up when it fails, e.g. on transient GPU out-of-memory errors (1 disables retries)")]
+    #[serde(default = "default_whisper_max_attempts")]
+    pub whisper_max_attempts: u32,
+    
+    #[clap(long, env = ENV_WHISPER_DEVICE, help = "Device the whisper executable runs on: cpu, cuda or cuda:<N> (default: whisper's choice when an NVIDIA GPU is found, otherwise cpu)")]
+    #[serde(default)]
+    pub whisper_device: Option<String>,
+    
+    #[clap(long, help = "Whether the whisper executable computes in half precision (true, false; default: whisper's choice, false on cpu)")]
+    #[serde(default)]
+    pub whisper_fp16: Option<bool>,
+    
+    #[clap(long, help = "Burn this existing subtitle file (.srt, .vtt, .ass) instead of transcribing the audio; its timings follow the source video")]
+    #[serde(default)]
+    pub external_subtitle_path: Option<String>,
+    
+    #[clap(long, value_enum, default_value = "error", help = "What to do when the input has no audio to transcribe (error, skip: produce the short without captions)")]
+    #[serde(default)]
+    pub missing_audio_policy: SubtitleMissingAudioPolicy,
+    
+    #[clap(long, value_enum, default_value = "burn", help = "How to add the captions (burn into the video, softmux: as a subtitle track without re-encoding, both)")]
+    #[serde(default)]
+    pub subtitle_mode: SubtitleMode,
+    
+    #[clap(long, value_enum, default_value = "srt", help = "Subtitle file format to generate (srt, vtt, ass)")]
+    #[serde(default)]
+    pub subtitle_format: SubtitleFormat,
+    
+    #[clap(long, help = "Also write the caption text without timings next to each short, as <output>.txt")]
+    #[serde(default)]
+    pub write_transcript: bool,
+    
+    #[clap(long, default_value = "0.0", allow_negative_numbers = true, help = "Shift every caption by this many seconds before burning (negative: earlier), to fix captions that run ahead of or behind the speech")]
+    #[serde(default)]
+    pub subtitle_offset_secs: f64,
+    
+    #[clap(long, help = "Only show captions from this many seconds into the short (cues starting earlier are cut to it)")]
+    #[serde(default)]
+    pub subtitle_window_start_secs: Option<f64>,
+    
+    #[clap(long, help = "Only show captions until this many seconds into the short, e.g. 15 for captions on the intro only")]
+    #[serde(default)]
+    pub subtitle_window_end_secs: Option<f64>,
+    
+    #[clap(long, help = "End captions shown longer than this many seconds early, e.g. ones whisper stretched over a silence")]
+    #[serde(default)]
+    pub max_cue_duration_secs: Option<f64>,
+    
+    #[clap(long, help = "Re-wrap subtitle text to at most this many characters per line (SRT and VTT only)")]
+    #[serde(default)]
+    pub max_line_chars: Option<usize>,
+    
+    #[clap(long, value_enum, default_value = "none", help = "Change the case of the caption text before burning (none, upper, lower, titlecase; SRT and VTT only)")]
+    #[serde(default)]
+    pub caption_text_transform: CaptionTextTransform,
+    
+    #[clap(long, value_enum, default_value = "none", help = "Animation of each caption as it appears (none, fadein, pop, slideup); needs ASS subtitles")]
+    #[serde(default)]
+    pub caption_animation: CaptionAnimation,
+    
+    #[clap(long, env = ENV_FONT_PATH, default_value = "", help = "Path to the font file for subtitles (.ttf, .otf)")]
+    #[serde(default)]
     pub font_path: String,
     
+    #[clap(long, help = "Optional font family for subtitles looked up through fontconfig (e.g., 'DejaVu Sans'), used instead of font_path")]
+    #[serde(default)]
+    pub font_name: Option<String>,
+    
+    #[clap(long, value_enum, help = "Where the subtitle font comes from (file: font_path, name: font_name through fontconfig; default: name when font_name is set, file otherwise)")]
+    #[serde(default)]
+    pub font_source: Option<FontSource>,
+    
+    #[clap(long, value_enum, default_value = "error", help = "What to do when font_path does not exist (error, fallback: use the system's default sans-serif font)")]
+    #[serde(default)]
+    pub missing_font_policy: MissingFontPolicy,
+    
     #[clap(long, default_value = "24", help = "Font size for subtitles")]
     pub font_size: u32,
     
-    #[clap(long, default_value = "white", help = "Font color for subtitles (e.g., 'white', '#FFFFFF')")]
+    #[clap(long, value_parser = parse_percent, help = "Font size for subtitles as a percentage of the video height, e.g. '5%' (overrides font_size)")]
+    #[serde(default)]
+    pub font_size_percent: Option<f64>,
+    
+    #[clap(long, default_value = "white", help = "Font color for subtitles (e.g., 'white', 'yellow', '#FFF', '#FFFFFF')")]
     pub font_color: String,
     
+    #[clap(long, default_value = "black", help = "Outline color for subtitles (e.g., 'black', '#000000')")]
+    #[serde(default = "default_outline_color")]
+    pub outline_color: String,
+    
+    #[clap(long, default_value = "2", help = "Outline width for subtitles in pixels (0 disables the outline)")]
+    #[serde(default = "default_outline_width")]
+    pub outline_width: f64,
+    
+    #[clap(long, default_value = "0", help = "Drop shadow depth for subtitles in pixels (0 disables the shadow)")]
+    #[serde(default)]
+    pub shadow_depth: f64,
+
+    #[clap(long, help = "Draw a box behind the subtitle text instead of an outline; outline_width becomes the padding around the text")]
+    #[serde(default)]
+    pub caption_box: bool,
+
+    #[clap(long, default_value = "black", help = "Color of the caption box, in the same formats as font_color (e.g., 'black', '#00000080')")]
+    #[serde(default = "default_outline_color")]
+    pub caption_box_color: String,
+
+    #[clap(long, default_value = "0.6", help = "Opacity of the caption box from 0 (invisible) to 1 (solid), applied on top of any alpha in caption_box_color")]
+    #[serde(default = "default_caption_box_opacity")]
+    pub caption_box_opacity: f64,
+    
+    #[clap(long, help = "Render subtitles in bold")]
+    #[serde(default)]
+    pub font_bold: bool,
+    
+    #[clap(long, help = "Render subtitles in italics")]
+    #[serde(default)]
+    pub font_italic: bool,
+    
     #[clap(long, default_value = "bottom", help = "Vertical alignment for subtitles (top, center, bottom)")]
     pub subtitle_position_vertical_alignment: String,
     
     #[clap(long, default_value = "center", help = "Horizontal alignment for subtitles (left, center, right)")]
     pub subtitle_position_horizontal_alignment: String,
+    
+    #[clap(long, default_value = "0", help = "Distance in video pixels between the subtitles and the top or bottom edge they are aligned to (SRT and VTT only)")]
+    #[serde(default)]
+    pub subtitle_margin_v: u32,
+    
+    #[clap(long, default_value = "0", help = "Video pixels kept clear of subtitles at the left and right edges (SRT and VTT only)")]
+    #[serde(default)]
+    pub subtitle_margin_h: u32,
+    
+    #[clap(long, help = "Burn this subtitle file (.srt, .vtt, .ass) as a second caption track, e.g. a translation; its timings follow the source video")]
+    #[serde(default)]
+    pub secondary_subtitle_path: Option<String>,
+    
+    #[clap(long, default_value = "top", help = "Vertical alignment of the secondary subtitles (top, center, bottom); must differ from the main subtitles' position")]
+    #[serde(default = "default_secondary_vertical_alignment")]
+    pub secondary_subtitle_position_vertical_alignment: String,
+    
+    #[clap(long, default_value = "center", help = "Horizontal alignment of the secondary subtitles (left, center, right)")]
+    #[serde(default = "default_secondary_horizontal_alignment")]
+    pub secondary_subtitle_position_horizontal_alignment: String,
+    
+    #[clap(long, help = "Font size of the secondary subtitles (default: font_size)")]
+    #[serde(default)]
+    pub secondary_font_size: Option<u32>,
+    
+    #[clap(long, help = "Font color of the secondary subtitles (default: font_color)")]
+    #[serde(default)]
+    pub secondary_font_color: Option<String>,
+    
+    #[clap(long, help = "Add line/align cue settings matching the subtitle position to VTT subtitles, so web players show soft captions where burned ones would be")]
+    #[serde(default)]
+    pub vtt_cue_positioning: bool,
+}
+
+fn default_secondary_vertical_alignment() -> String {
+    "top".to_string()
+}
+
+fn default_secondary_horizontal_alignment() -> String {
+    "center".to_string()
+}
+
+impl VideoConfig {
+    // The seek mode in effect: seek_mode when set, otherwise Fast for stdin and Accurate for files.
+    pub fn resolved_seek_mode(&self) -> SeekMode {
+        self.seek_mode.unwrap_or(if crate::video_processing::is_stdio_path(&self.input_path) { SeekMode::Fast } else { SeekMode::Accurate })
+    }
+}
+
+impl SubtitleConfig {
+    // The font source in effect: font_source when set, otherwise Name when a font_name is given.
+    pub fn resolved_font_source(&self) -> FontSource {
+        self.font_source.unwrap_or(if self.font_name.is_some() { FontSource::Name } else { FontSource::File })
+    }
+
+    // Whether the main subtitles are ASS, which carry their own styling: the external file's
+    // format when one is set, otherwise the generated format.
+    pub(crate) fn primary_is_ass(&self) -> bool {
+        match &self.external_subtitle_path {
+            Some(external_path) => subtitle_file_kind(external_path) == Some(SubtitleFormat::Ass),
+            None => self.subtitle_format == SubtitleFormat::Ass,
+        }
+    }
+
+    // The (start, end) seconds captions are limited to when either window bound is set, with
+    // the start defaulting to 0 and the end to None (the end of the short).
+    pub(crate) fn caption_window(&self) -> Option<(f64, Option<f64>)> {
+        if self.subtitle_window_start_secs.is_none() && self.subtitle_window_end_secs.is_none() {
+            return None;
+        }
+        Some((self.subtitle_window_start_secs.unwrap_or(0.0), self.subtitle_window_end_secs))
+    }
 }
 
+// Format of a subtitle file from its extension (.ssa counts as ASS), None for other files.
+pub(crate) fn subtitle_file_kind(path: &str) -> Option<SubtitleFormat> {
+    match Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("srt") => Some(SubtitleFormat::Srt),
+        Some("vtt") => Some(SubtitleFormat::Vtt),
+        Some("ass") | Some("ssa") => Some(SubtitleFormat::Ass),
+        _ => None,
+    }
+}
+
+// Parses a percentage given as "5%" or plain "5".
+fn parse_percent(s: &str) -> Result<f64, String> {
+    s.trim()
+        .trim_end_matches('%')
+        .trim_end()
+        .parse()
+        .map_err(|_| format!("'{}' is not a percentage, e.g. '5%'", s))
+}
+
+// Parses a muxer option given as "KEY=VALUE"; the value may contain further '=' and be empty.
+fn parse_output_option(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("Invalid output option '{}'. Use KEY=VALUE, e.g. 'movflags=+faststart'.", s)),
+    }
+}
+
+fn default_outline_color() -> String {
+    "black".to_string()
+}
+
+fn default_outline_width() -> f64 {
+    2.0
+}
+
+fn default_caption_box_opacity() -> f64 {
+    0.6
+}
+
+#[derive(Parser, Serialize, Deserialize, Debug, Clone)]
+#[clap(author, version, about, long_about = None)]
+pub struct WatermarkConfig {
+    #[clap(long, env = ENV_WATERMARK_PATH, help = "Optional image (e.g., a PNG logo) to overlay on every short")]
+    #[serde(default)]
+    pub watermark_path: Option<String>,
+
+    #[clap(long, default_value = "top", help = "Vertical position of the watermark (top, center, bottom)")]
+    #[serde(default = "default_watermark_vertical_alignment")]
+    pub watermark_position_vertical_alignment: String,
+
+    #[clap(long, default_value = "right", help = "Horizontal position of the watermark (left, center, right)")]
+    #[serde(default = "default_watermark_horizontal_alignment")]
+    pub watermark_position_horizontal_alignment: String,
+
+    #[clap(long, default_value = "20", help = "Distance in pixels between the watermark and the frame edges")]
+    #[serde(default = "default_watermark_margin")]
+    pub watermark_margin: u32,
+
+    #[clap(long, help = "Optional watermark width as a percentage of the video width (e.g., 15)")]
+    #[serde(default)]
+    pub watermark_scale_percent: Option<f64>,
+}
+
+fn default_watermark_vertical_alignment() -> String {
+    "top".to_string()
+}
+
+fn default_watermark_horizontal_alignment() -> String {
+    "right".to_string()
+}
+
+fn default_watermark_margin() -> u32 {
+    20
+}
+
+// Which of the input's container tags the delivered short drops: none, only the ones that give away
+// where it was recorded (GPS coordinates), or all of them, e.g. also the recording device and
+// software.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataStrip {
+    None,
+    #[default]
+    Location,
+    All,
+}
+
+// Container tags written to the delivered short over the ones copied from the input, e.g. for
+// upload tooling. None keeps the input's value and an empty string removes the tag.
+#[derive(Parser, Serialize, Deserialize, Debug, Clone, Default)]
+#[clap(author, version, about, long_about = None)]
+pub struct MetadataConfig {
+    #[clap(long, help = "Optional title tag of the output (an empty string removes the input's title)")]
+    #[serde(default)]
+    pub metadata_title: Option<String>,
+
+    #[clap(long, help = "Optional author tag of the output, written as the artist tag that players show")]
+    #[serde(default)]
+    pub metadata_author: Option<String>,
+
+    #[clap(long, help = "Optional comment tag of the output, e.g. a description")]
+    #[serde(default)]
+    pub metadata_comment: Option<String>,
+
+    #[clap(long, value_enum, default_value = "location", help = "Input tags to leave out of the output (none, location: GPS/location tags, all); metadata_* tags are still written")]
+    #[serde(default)]
+    pub strip_metadata: MetadataStrip,
+}
+
+impl MetadataConfig {
+    // (tag, value) pairs for remux_video; fields left as None are not included.
+    pub fn overrides(&self) -> Vec<(&str, &str)> {
+        [
+            ("title", &self.metadata_title),
+            ("artist", &self.metadata_author),
+            ("comment", &self.metadata_comment),
+        ]
+        .into_iter()
+        .filter_map(|(tag, value)| value.as_deref().map(|value| (tag, value)))
+        .collect()
+    }
+}
+
+impl Default for WatermarkConfig {
+    fn default() -> Self {
+        WatermarkConfig {
+            watermark_path: None,
+            watermark_position_vertical_alignment: default_watermark_vertical_alignment(),
+            watermark_position_horizontal_alignment: default_watermark_horizontal_alignment(),
+            watermark_margin: default_watermark_margin(),
+            watermark_scale_percent: None,
+        }
+    }
+}
+
+// Environment variables that override configuration fields, mainly paths that differ between
+// environments (e.g. containers). The CLI reads them through clap, and configuration files through
+// AppConfig::apply_env_overrides, so the precedence is CLI > env > file > defaults.
+pub const ENV_WHISPER_MODEL_PATH: &str = "SHORTS_WHISPER_MODEL_PATH";
+pub const ENV_WHISPER_BINARY_PATH: &str = "SHORTS_WHISPER_BINARY_PATH";
+pub const ENV_WHISPER_DEVICE: &str = "SHORTS_WHISPER_DEVICE";
+pub const ENV_WHISPER_MODEL_CACHE_DIR: &str = "SHORTS_WHISPER_MODEL_CACHE_DIR";
+pub const ENV_FONT_PATH: &str = "SHORTS_FONT_PATH";
+pub const ENV_LANGUAGE: &str = "SHORTS_LANGUAGE";
+pub const ENV_WATERMARK_PATH: &str = "SHORTS_WATERMARK_PATH";
+pub const ENV_BG_MUSIC_PATH: &str = "SHORTS_BG_MUSIC_PATH";
+pub const ENV_TEMP_DIR: &str = "SHORTS_TEMP_DIR";
+
 #[derive(Parser, Serialize, Deserialize, Debug, Clone)] // Added Parser, Clone
 #[clap(author, version, about = "Main application configuration for generating video shorts.", long_about = None)]
 pub struct AppConfig {
+    // Schema version of a configuration file (see CONFIG_VERSION); files from before versioning
+    // have none and load as 0.
+    #[clap(skip = CONFIG_VERSION)]
+    #[serde(default)]
+    pub version: u32,
+
+    // What the relative paths of a configuration file are resolved against when it is loaded.
+    #[clap(skip)]
+    #[serde(default)]
+    pub relative_paths: RelativePaths,
+
     #[clap(flatten)]
     pub video: VideoConfig,
     
     #[clap(flatten)]
     pub subtitles: SubtitleConfig,
+
+    #[clap(flatten)]
+    #[serde(default)]
+    pub watermark: WatermarkConfig,
+
+    #[clap(flatten)]
+    #[serde(default)]
+    pub metadata: MetadataConfig,
+}
+
+// What the relative paths of a configuration file (input_path, font_path, ...) are relative to.
+// Config, the default, resolves them against the file's directory, so a folder holding a config
+// and its assets works wherever the tool is run from; Cwd leaves them relative to the working
+// directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RelativePaths {
+    #[default]
+    Config,
+    Cwd,
+}
+
+// Schema version written to configuration files. Bump it when a change to the fields needs more
+// than a serde default to load older files (e.g. a renamed field), and upgrade those files in
+// migrate_config.
+pub const CONFIG_VERSION: u32 = 2;
+
+// Just the version of a configuration file, read before the rest to decide on migrations.
+#[derive(Deserialize)]
+struct ConfigVersion {
+    #[serde(default)]
+    version: u32,
+}
+
+// Upgrades the contents of a configuration file of schema `version` to CONFIG_VERSION, step by
+// step, and returns a note per change made. Fields added since are filled with their defaults
+// when the result is deserialized.
+fn migrate_config(value: &mut serde_json::Value, version: u32) -> Vec<String> {
+    let mut notes = Vec::new();
+    for from_version in version..CONFIG_VERSION {
+        match from_version {
+            // Files from before versioning: every field added since has a default, so they load as is.
+            0 => {}
+            // Relative paths were relative to the working directory before relative_paths existed.
+            1 => {
+                if let Some(fields) = value.as_object_mut() {
+                    if !fields.contains_key("relative_paths") {
+                        fields.insert("relative_paths".to_string(), serde_json::Value::from("cwd"));
+                        notes.push("relative paths stay relative to the working directory (relative_paths: cwd)".to_string());
+                    }
+                }
+            }
+            _ => unreachable!("no migration from configuration version {}", from_version),
+        }
+    }
+    notes
+}
+
+// Parses the contents of a configuration file in `format`, returning the parser's message on failure.
+fn parse_config<T: serde::de::DeserializeOwned>(format: ConfigFormat, contents: &str) -> Result<T, String> {
+    match format {
+        ConfigFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+        ConfigFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+        ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+    }
+}
+
+// On-disk format of a configuration file, chosen from its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    // .toml, .yaml and .yml are recognized; anything else is treated as JSON.
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
 }
 
 impl AppConfig {
-    pub fn save_to_file(&self, path: &str) -> Result<(), anyhow::Error> {
-        let json = serde_json::to_string_pretty(self)?;
-        std::fs::write(path, json)?;
+    // The video encoder and audio codec of the shorts: encoder and audio_codec when set, the
+    // defaults of the output container (see default_codecs_for_container) otherwise.
+    pub fn output_codecs(&self) -> (VideoEncoder, AudioCodec) {
+        let (default_encoder, default_audio_codec) = crate::video_processing::default_codecs_for_container(
+            &self.video.output_path,
+            self.video.output_format.as_deref(),
+        );
+        (
+            self.video.encoder.unwrap_or(default_encoder),
+            self.video.audio_codec.unwrap_or(default_audio_codec),
+        )
+    }
+
+    // The FFmpeg filters the steps this config enables rely on, each with the step that needs it.
+    fn required_filters(&self) -> Vec<(&'static str, &'static str)> {
+        let mut filters = Vec::new();
+        if self.subtitles.use_subtitles && self.subtitles.subtitle_mode != SubtitleMode::SoftMux {
+            filters.push(("subtitles", "burning subtitles"));
+        }
+        if self.video.target_aspect_ratio.is_some() || self.video.output_width.is_some() || self.video.output_height.is_some() {
+            filters.push(("scale", "resizing to the output size"));
+            match self.video.aspect_fit_mode {
+                AspectFitMode::Crop => filters.push(("crop", "aspect_fit_mode crop")),
+                AspectFitMode::Pad => filters.push(("pad", "aspect_fit_mode pad")),
+                AspectFitMode::BlurBackground => {
+                    filters.extend([("boxblur", "aspect_fit_mode blur"), ("overlay", "aspect_fit_mode blur")]);
+                }
+            }
+        }
+        if self.watermark.watermark_path.is_some() {
+            filters.extend([("movie", "the watermark"), ("overlay", "the watermark"), ("scale", "the watermark")]);
+        }
+        if self.video.bg_music_path.is_some() {
+            filters.extend([("amovie", "background music"), ("amix", "background music")]);
+            if self.video.bg_music_duck {
+                filters.push(("sidechaincompress", "bg_music_duck"));
+            }
+        }
+        if self.video.normalize_audio {
+            filters.push(("loudnorm", "normalize_audio"));
+        }
+        if self.video.trim_silence {
+            filters.extend([("silencedetect", "trim_silence"), ("concat", "trim_silence")]);
+        }
+        if self.video.speed_factor.is_some() {
+            filters.push(("atempo", "speed_factor"));
+        }
+        if self.video.fade_in_secs > 0.0 || self.video.fade_out_secs > 0.0 {
+            filters.extend([("fade", "fades"), ("afade", "fades")]);
+        }
+        if self.video.auto_split {
+            filters.push(("select", "auto_split scene detection"));
+        }
+        if self.video.generate_chapters {
+            filters.push(("select", "generate_chapters scene detection"));
+        }
+        if self.output_codecs().0 == VideoEncoder::Vaapi {
+            filters.push(("hwupload", "the vaapi encoder"));
+        }
+        let mut seen = std::collections::HashSet::new();
+        filters.retain(|(filter_name, _)| seen.insert(*filter_name));
+        filters
+    }
+
+    // Checks the configuration up front so that mistakes are reported together, before any
+    // processing starts. Returns every problem found rather than stopping at the first one. This
+    // includes the FFmpeg filters of required_filters, so a build without one fails here instead
+    // of partway through a run.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        use crate::subtitle_generation::{check_whisper_binary, is_whisper_device, resolve_whisper_language, DEFAULT_WHISPER_BINARY};
+        use crate::video_processing::{
+            check_container_codecs, check_video_file, convert_color_to_ffmpeg_bgr, filter_available, filter_build_requirement, is_stdio_path,
+            map_alignment_to_ffmpeg_value, output_format_available, parse_aspect_ratio, parse_bit_rate, OutputContainer, MAX_CRF,
+        };
+
+        let mut problems = Vec::new();
+
+        // stdin is read once, front to back, by the trim; probing it here would consume it.
+        let reads_stdin = is_stdio_path(&self.video.input_path);
+        let writes_stdout = is_stdio_path(&self.video.output_path);
+        if reads_stdin {
+            if !self.video.append_input_paths.is_empty() {
+                problems.push("Appended inputs cannot be joined to stdin ('-'), which can only be read once".to_string());
+            }
+        } else if !Path::new(&self.video.input_path).is_file() {
+            problems.push(format!("Input video does not exist: {}", self.video.input_path));
+        } else if let Err(e) = check_video_file(&self.video.input_path) {
+            problems.push(e.to_string());
+        }
+        for append_input_path in &self.video.append_input_paths {
+            if !Path::new(append_input_path).is_file() {
+                problems.push(format!("Appended input video does not exist: {}", append_input_path));
+            } else if let Err(e) = check_video_file(append_input_path) {
+                problems.push(e.to_string());
+            }
+        }
+        if (reads_stdin || writes_stdout) && (!self.video.segments.is_empty() || self.video.auto_split) {
+            problems.push("segments and auto_split cut several shorts, which need a file as input_path and output_path, not '-'".to_string());
+        }
+        if writes_stdout && self.video.thumbnail_at_secs.is_some() {
+            problems.push("thumbnail_at_secs saves the thumbnail next to the output file, which stdout ('-') does not have".to_string());
+        }
+        if writes_stdout && self.subtitles.write_transcript {
+            problems.push("write_transcript saves the transcript next to the output file, which stdout ('-') does not have".to_string());
+        }
+        if self.subtitles.write_transcript && !self.subtitles.use_subtitles {
+            problems.push("write_transcript needs use_subtitles, since the transcript is the caption text".to_string());
+        }
+        if !self.subtitles.subtitle_offset_secs.is_finite() {
+            problems.push(format!("subtitle_offset_secs must be a number of seconds, got {}", self.subtitles.subtitle_offset_secs));
+        }
+        if let Some((start_secs, end_secs)) = self.subtitles.caption_window() {
+            if !(start_secs.is_finite() && start_secs >= 0.0) {
+                problems.push(format!("subtitle_window_start_secs must not be negative, got {}", start_secs));
+            }
+            if let Some(end_secs) = end_secs.filter(|end_secs| !(end_secs.is_finite() && *end_secs > start_secs)) {
+                problems.push(format!("subtitle_window_end_secs must be after subtitle_window_start_secs, got {}", end_secs));
+            }
+        }
+        if self.video.audio_stream_index.is_some() && !self.video.append_input_paths.is_empty() {
+            problems.push("audio_stream_index cannot be combined with appended inputs, which are joined with their best audio stream".to_string());
+        }
+
+        if let Some(temp_dir) = &self.video.temp_dir {
+            if Path::new(temp_dir).exists() && !Path::new(temp_dir).is_dir() {
+                problems.push(format!("Temp directory is not a directory: {}", temp_dir));
+            }
+        }
+
+        let output_dir = match Path::new(&self.video.output_path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        match std::fs::metadata(output_dir) {
+            Ok(metadata) if !metadata.is_dir() => {
+                problems.push(format!("Output directory is not a directory: {}", output_dir.display()))
+            }
+            Ok(metadata) if metadata.permissions().readonly() => {
+                problems.push(format!("Output directory is not writable: {}", output_dir.display()))
+            }
+            Ok(_) => {}
+            Err(_) => problems.push(format!("Output directory does not exist: {}", output_dir.display())),
+        }
+
+        for (name, value) in [("output_width", self.video.output_width), ("output_height", self.video.output_height)] {
+            if let Some(pixels) = value {
+                if pixels == 0 || pixels % 2 != 0 {
+                    problems.push(format!("{} must be a positive even number of pixels, got {}", name, pixels));
+                }
+            }
+        }
+
+        if !(self.video.start_secs.is_finite() && self.video.start_secs >= 0.0) {
+            problems.push(format!("start_secs must be 0 or more (got {})", self.video.start_secs));
+        }
+        if !(self.video.short_duration_secs.is_finite() && self.video.short_duration_secs > 0.0) {
+            problems.push(format!("short_duration_secs must be greater than 0 (got {})", self.video.short_duration_secs));
+        }
+        if let Some(factor) = self.video.speed_factor {
+            if !(factor.is_finite() && factor > 0.0) {
+                problems.push(format!("speed_factor must be greater than zero, got {}", factor));
+            }
+        }
+        if !(self.video.fade_in_secs >= 0.0 && self.video.fade_out_secs >= 0.0) {
+            problems.push("Fade durations must not be negative".to_string());
+        }
+        if self.video.auto_split && !self.video.segments.is_empty() {
+            problems.push("Use either segments or auto_split, not both".to_string());
+        }
+        if !(self.video.scene_threshold > 0.0 && self.video.scene_threshold < 1.0) {
+            problems.push(format!("Scene threshold must be between 0 and 1 (got {})", self.video.scene_threshold));
+        }
+        if self.video.generate_chapters
+            && !OutputContainer::detect(&self.video.output_path, self.video.output_format.as_deref()).supports_chapters()
+        {
+            problems.push(format!(
+                "Output {} cannot hold chapters; use an .mp4, .mov, .mkv or .webm output or turn off generate_chapters",
+                self.video.output_path
+            ));
+        }
+        if let Some(at_secs) = self.video.thumbnail_at_secs {
+            if !(at_secs.is_finite() && at_secs >= 0.0) {
+                problems.push(format!("Thumbnail time must not be negative (got {}s)", at_secs));
+            }
+        }
+        if let Some(preview_secs) = self.video.preview_secs {
+            if !(preview_secs.is_finite() && preview_secs > 0.0) {
+                problems.push(format!("Preview length must be greater than 0 (got {}s)", preview_secs));
+            }
+        }
+
+        let quality_modes = [
+            self.video.crf.is_some(),
+            self.video.video_bitrate.is_some(),
+            self.video.target_size_mb.is_some(),
+        ];
+        if quality_modes.iter().filter(|&&set| set).count() > 1 {
+            problems.push("Use only one of crf, video_bitrate and target_size_mb".to_string());
+        }
+        if let Some(crf) = self.video.crf {
+            if crf > MAX_CRF {
+                problems.push(format!("crf must be between 0 and {}, got {}", MAX_CRF, crf));
+            }
+        }
+        if let Some(bit_rate) = &self.video.video_bitrate {
+            if let Err(e) = parse_bit_rate(bit_rate) {
+                problems.push(e.to_string());
+            }
+        }
+        if let Some(bit_rate) = &self.video.audio_bitrate {
+            if let Err(e) = parse_bit_rate(bit_rate) {
+                problems.push(format!("Audio: {}", e));
+            }
+            if self.output_codecs().1 == AudioCodec::Copy {
+                problems.push("audio_bitrate has no effect with audio_codec copy".to_string());
+            }
+        }
+        if self.video.x264_tune.is_some() && self.output_codecs().0 != VideoEncoder::X264 {
+            problems.push("x264_tune only applies to encoder x264".to_string());
+        }
+        if let Some(size_mb) = self.video.target_size_mb {
+            if !(size_mb.is_finite() && size_mb > 0.0) {
+                problems.push(format!("target_size_mb must be greater than zero, got {}", size_mb));
+            }
+        }
+        if self.video.two_pass {
+            if self.video.video_bitrate.is_none() && self.video.target_size_mb.is_none() {
+                problems.push("two_pass needs video_bitrate or target_size_mb".to_string());
+            }
+            if self.output_codecs().0 != VideoEncoder::X264 {
+                problems.push("two_pass only applies to encoder x264".to_string());
+            }
+        }
+
+        if let Some(target_aspect) = &self.video.target_aspect_ratio {
+            if let Err(e) = parse_aspect_ratio(target_aspect) {
+                problems.push(e.to_string());
+            }
+        }
+        if let Err(e) = convert_color_to_ffmpeg_bgr(&self.video.pad_color) {
+            problems.push(format!("Pad color: {}", e));
+        }
+
+        if self.video.trim_silence {
+            if !(self.video.silence_threshold_db.is_finite() && self.video.silence_threshold_db < 0.0) {
+                problems.push(format!("silence_threshold_db must be below 0, got {}", self.video.silence_threshold_db));
+            }
+            if !(self.video.min_silence_secs.is_finite() && self.video.min_silence_secs > 0.0) {
+                problems.push(format!("min_silence_secs must be greater than zero, got {}", self.video.min_silence_secs));
+            }
+        }
+
+        let (encoder, audio_codec) = self.output_codecs();
+        if let Err(e) = check_container_codecs(&self.video.output_path, self.video.output_format.as_deref(), encoder, audio_codec) {
+            problems.push(e.to_string());
+        }
+        if let Some(format_name) = &self.video.output_format {
+            if !output_format_available(format_name) {
+                problems.push(format!("Output format '{}' is not supported by this FFmpeg build (see `ffmpeg -muxers`)", format_name));
+            }
+        }
+        for (filter_name, used_for) in self.required_filters() {
+            if !filter_available(filter_name) {
+                let requirement = filter_build_requirement(filter_name).map(|r| format!(", which needs {}", r)).unwrap_or_default();
+                problems.push(format!(
+                    "This FFmpeg build has no '{}' filter{}; it is needed for {} (see `ffmpeg -filters`)",
+                    filter_name, requirement, used_for
+                ));
+            }
+        }
+
+        if let Some(music_path) = &self.video.bg_music_path {
+            if !Path::new(music_path).is_file() {
+                problems.push(format!("Background music file does not exist: {}", music_path));
+            }
+        }
+
+        if self.subtitles.use_subtitles {
+            let burns = self.subtitles.subtitle_mode != SubtitleMode::SoftMux;
+            match (self.subtitles.resolved_font_source(), &self.subtitles.font_name) {
+                (FontSource::Name, None) => problems.push("font_source name needs a font_name".to_string()),
+                (FontSource::Name, Some(font_name)) if font_name.trim().is_empty() => {
+                    problems.push("Font name must not be empty".to_string())
+                }
+                (FontSource::Name, Some(font_name)) if burns => {
+                    if let Err(e) = crate::fonts::check_font_family(font_name) {
+                        problems.push(format!("{} (run `shorts_wizard fonts` to list the installed fonts)", e));
+                    }
+                }
+                (FontSource::Name, Some(_)) => {}
+                (FontSource::File, Some(_)) => problems.push("font_name has no effect with font_source file".to_string()),
+                (FontSource::File, None)
+                    if burns
+                        && self.subtitles.missing_font_policy == MissingFontPolicy::Error
+                        && !Path::new(&self.subtitles.font_path).is_file() =>
+                {
+                    problems.push(format!("Font file does not exist: {}", self.subtitles.font_path))
+                }
+                (FontSource::File, None) if burns && Path::new(&self.subtitles.font_path).is_file() => {
+                    if let Err(e) = crate::fonts::read_font_family(Path::new(&self.subtitles.font_path)) {
+                        problems.push(format!("{} (run `shorts_wizard fonts` to list the installed fonts)", e));
+                    }
+                }
+                (FontSource::File, None) => {}
+            }
+            if self.subtitles.subtitle_mode != SubtitleMode::Burn {
+                let container = OutputContainer::detect(&self.video.output_path, self.video.output_format.as_deref());
+                if !container.supports_soft_subtitles() {
+                    problems.push(format!(
+                        "Output {} cannot hold soft subtitles; use an .mp4, .mov, .mkv or .webm output or subtitle_mode burn",
+                        self.video.output_path
+                    ));
+                }
+                if self.subtitles.primary_is_ass() {
+                    problems.push("Soft subtitles need SRT or VTT captions, not ASS".to_string());
+                }
+            }
+            if let Some(external_path) = &self.subtitles.external_subtitle_path {
+                if !Path::new(external_path).is_file() {
+                    problems.push(format!("External subtitle file does not exist: {}", external_path));
+                } else if subtitle_file_kind(external_path).is_none() {
+                    problems.push(format!("External subtitle file must be .srt, .vtt or .ass: {}", external_path));
+                }
+                if self.video.trim_silence {
+                    problems.push("An external subtitle file cannot be combined with trim_silence, which would move the speech away from its cues".to_string());
+                }
+                if self.video.speed_factor.is_some_and(|factor| factor != 1.0) {
+                    problems.push("An external subtitle file cannot be combined with speed_factor, which would move the speech away from its cues".to_string());
+                }
+            } else if self.subtitles.whisper_backend == WhisperBackend::Cli {
+                let whisper_binary = self.subtitles.whisper_binary_path.as_deref().unwrap_or(DEFAULT_WHISPER_BINARY);
+                if let Err(e) = check_whisper_binary(whisper_binary) {
+                    problems.push(e.to_string());
+                }
+            } else if let Err(e) = crate::whisper_models::check_whisper_model(
+                &self.subtitles.whisper_model_path,
+                self.subtitles.whisper_model_cache_dir.as_deref().map(Path::new),
+                self.subtitles.whisper_offline,
+            ) {
+                problems.push(e.to_string());
+            }
+            if let Some(language) = &self.subtitles.language {
+                if let Err(e) = resolve_whisper_language(language) {
+                    problems.push(e.to_string());
+                }
+            }
+            if let Err(e) = convert_color_to_ffmpeg_bgr(&self.subtitles.font_color) {
+                problems.push(e.to_string());
+            }
+            if let Err(e) = convert_color_to_ffmpeg_bgr(&self.subtitles.outline_color) {
+                problems.push(format!("Subtitle outline: {}", e));
+            }
+            if self.subtitles.whisper_max_attempts == 0 {
+                problems.push("whisper_max_attempts must be at least 1".to_string());
+            }
+            if let Some(device) = &self.subtitles.whisper_device {
+                if !is_whisper_device(device) {
+                    problems.push(format!("whisper_device must be cpu, cuda or cuda:<N>, got '{}'", device));
+                }
+            }
+            let uses_whisper_cli = self.subtitles.external_subtitle_path.is_none() && self.subtitles.whisper_backend == WhisperBackend::Cli;
+            if !uses_whisper_cli && (self.subtitles.whisper_device.is_some() || self.subtitles.whisper_fp16.is_some()) {
+                problems.push("whisper_device and whisper_fp16 only apply to the whisper executable (the cli backend)".to_string());
+            }
+            if let Some(percent) = self.subtitles.font_size_percent {
+                if !(percent > 0.0 && percent <= 100.0) {
+                    problems.push(format!("font_size_percent must be above 0 and at most 100, got {}", percent));
+                }
+            }
+            if let Some(max_secs) = self.subtitles.max_cue_duration_secs.filter(|secs| !(secs.is_finite() && *secs > 0.0)) {
+                problems.push(format!("max_cue_duration_secs must be greater than zero, got {}", max_secs));
+            }
+            if self.subtitles.max_line_chars == Some(0) {
+                problems.push("Maximum subtitle line length must be at least 1 character".to_string());
+            }
+            if self.subtitles.outline_width < 0.0 || self.subtitles.shadow_depth < 0.0 {
+                problems.push("Subtitle outline width and shadow depth must not be negative".to_string());
+            }
+            if self.subtitles.caption_box {
+                if let Err(e) = convert_color_to_ffmpeg_bgr(&self.subtitles.caption_box_color) {
+                    problems.push(format!("Caption box: {}", e));
+                }
+                if !(0.0..=1.0).contains(&self.subtitles.caption_box_opacity) {
+                    problems.push(format!("caption_box_opacity must be between 0 and 1, got {}", self.subtitles.caption_box_opacity));
+                }
+            }
+            let alignment = map_alignment_to_ffmpeg_value(
+                &self.subtitles.subtitle_position_vertical_alignment,
+                &self.subtitles.subtitle_position_horizontal_alignment,
+            );
+            if let Err(e) = &alignment {
+                problems.push(format!("Subtitle position: {}", e));
+            }
+            if self.subtitles.vtt_cue_positioning {
+                let primary_kind = match &self.subtitles.external_subtitle_path {
+                    Some(external_path) => subtitle_file_kind(external_path),
+                    None => Some(self.subtitles.subtitle_format),
+                };
+                if primary_kind != Some(SubtitleFormat::Vtt) {
+                    problems.push("vtt_cue_positioning needs VTT subtitles (subtitle_format vtt or a .vtt external_subtitle_path)".to_string());
+                }
+            }
+            if let Some(secondary_path) = &self.subtitles.secondary_subtitle_path {
+                if !Path::new(secondary_path).is_file() {
+                    problems.push(format!("Secondary subtitle file does not exist: {}", secondary_path));
+                } else if subtitle_file_kind(secondary_path).is_none() {
+                    problems.push(format!("Secondary subtitle file must be .srt, .vtt or .ass: {}", secondary_path));
+                }
+                if self.subtitles.subtitle_mode == SubtitleMode::SoftMux {
+                    problems.push("Secondary subtitles are burned, so they need subtitle_mode burn or both".to_string());
+                }
+                if self.video.trim_silence || self.video.speed_factor.is_some_and(|factor| factor != 1.0) {
+                    problems.push("Secondary subtitles cannot be combined with trim_silence or speed_factor, which would move the speech away from its cues".to_string());
+                }
+                if let Some(color) = &self.subtitles.secondary_font_color {
+                    if let Err(e) = convert_color_to_ffmpeg_bgr(color) {
+                        problems.push(format!("Secondary subtitles: {}", e));
+                    }
+                }
+                match map_alignment_to_ffmpeg_value(
+                    &self.subtitles.secondary_subtitle_position_vertical_alignment,
+                    &self.subtitles.secondary_subtitle_position_horizontal_alignment,
+                ) {
+                    Err(e) => problems.push(format!("Secondary subtitle position: {}", e)),
+                    // ASS files position themselves; SRT/VTT tracks at one position would overlap.
+                    Ok(secondary_alignment)
+                        if alignment.as_ref().is_ok_and(|alignment| *alignment == secondary_alignment)
+                            && !self.subtitles.primary_is_ass()
+                            && subtitle_file_kind(secondary_path) != Some(SubtitleFormat::Ass) =>
+                    {
+                        problems.push("Secondary subtitles need a different position from the main subtitles".to_string())
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+
+        if let Some(watermark_path) = &self.watermark.watermark_path {
+            if !Path::new(watermark_path).is_file() {
+                problems.push(format!("Watermark image does not exist: {}", watermark_path));
+            }
+            if let Err(e) = map_alignment_to_ffmpeg_value(
+                &self.watermark.watermark_position_vertical_alignment,
+                &self.watermark.watermark_position_horizontal_alignment,
+            ) {
+                problems.push(format!("Watermark position: {}", e));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    // Replaces fields with the SHORTS_* environment variables (see the ENV_* constants) that are set
+    // and not empty. Meant for configurations loaded from a file; parsed CLI arguments have already
+    // taken the environment into account.
+    pub fn apply_env_overrides(&mut self) {
+        self.apply_overrides_from(|name| std::env::var(name).ok());
+    }
+
+    // apply_env_overrides with the variables read through `lookup`, so they can be supplied
+    // without touching the process environment.
+    fn apply_overrides_from<F: Fn(&str) -> Option<String>>(&mut self, lookup: F) {
+        let var = |name: &str| lookup(name).filter(|value| !value.is_empty());
+        if let Some(value) = var(ENV_WHISPER_MODEL_PATH) {
+            self.subtitles.whisper_model_path = value;
+        }
+        if let Some(value) = var(ENV_WHISPER_BINARY_PATH) {
+            self.subtitles.whisper_binary_path = Some(value);
+        }
+        if let Some(value) = var(ENV_WHISPER_DEVICE) {
+            self.subtitles.whisper_device = Some(value);
+        }
+        if let Some(value) = var(ENV_WHISPER_MODEL_CACHE_DIR) {
+            self.subtitles.whisper_model_cache_dir = Some(value);
+        }
+        if let Some(value) = var(ENV_FONT_PATH) {
+            self.subtitles.font_path = value;
+        }
+        if let Some(value) = var(ENV_LANGUAGE) {
+            self.subtitles.language = Some(value);
+        }
+        if let Some(value) = var(ENV_WATERMARK_PATH) {
+            self.watermark.watermark_path = Some(value);
+        }
+        if let Some(value) = var(ENV_BG_MUSIC_PATH) {
+            self.video.bg_music_path = Some(value);
+        }
+        if let Some(value) = var(ENV_TEMP_DIR) {
+            self.video.temp_dir = Some(value);
+        }
+    }
+
+    // The configuration as pretty-printed JSON, the layout of a .json configuration file.
+    pub fn to_json_pretty(&self) -> Result<String, ShortsError> {
+        Ok(serde_json::to_string_pretty(self).map_err(anyhow::Error::from)?)
+    }
+
+    // The file format (JSON, TOML or YAML) follows the extension of `path`, see ConfigFormat::from_path.
+    pub fn save_to_file(&self, path: &str) -> Result<(), ShortsError> {
+        let contents = match ConfigFormat::from_path(path) {
+            ConfigFormat::Json => self.to_json_pretty()?,
+            ConfigFormat::Toml => toml::to_string_pretty(self).map_err(anyhow::Error::from)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(self).map_err(anyhow::Error::from)?,
+        };
+        std::fs::write(path, contents)?;
         Ok(())
     }
 
-    pub fn load_from_file(path: &str) -> Result<Self, anyhow::Error> {
-        let json = std::fs::read_to_string(path)?;
-        let config = serde_json::from_str(&json)?;
+    // Files written by older versions (see CONFIG_VERSION) are upgraded with a warning; files
+    // from a newer version are rejected with InvalidConfig. Relative paths are resolved as the
+    // file's relative_paths says, against its directory by default.
+    pub fn load_from_file(path: &str) -> Result<Self, ShortsError> {
+        let contents = std::fs::read_to_string(path)?;
+        let invalid = |e: &dyn std::fmt::Display| ShortsError::InvalidConfig(format!("{}: {}", path, e));
+        let format = ConfigFormat::from_path(path);
+        let version = parse_config::<ConfigVersion>(format, &contents).map_err(|e| invalid(&e))?.version;
+        if version > CONFIG_VERSION {
+            return Err(invalid(&format!(
+                "it has configuration version {}, but this version of shorts_generator only reads up to {}",
+                version, CONFIG_VERSION
+            )));
+        }
+        let mut config: AppConfig = if version < CONFIG_VERSION {
+            let mut value = parse_config::<serde_json::Value>(format, &contents).map_err(|e| invalid(&e))?;
+            let notes = migrate_config(&mut value, version);
+            warn!(
+                "{} has configuration version {}; upgraded it to version {}{}. Settings added since take their defaults; set \"version\" to {} to silence this warning.",
+                path,
+                version,
+                CONFIG_VERSION,
+                if notes.is_empty() { String::new() } else { format!(" ({})", notes.join("; ")) },
+                CONFIG_VERSION
+            );
+            serde_json::from_value(value).map_err(|e| invalid(&e))?
+        } else {
+            parse_config(format, &contents).map_err(|e| invalid(&e))?
+        };
+        config.version = CONFIG_VERSION;
+        if config.relative_paths == RelativePaths::Config {
+            if let Some(config_dir) = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                config.resolve_relative_paths(config_dir);
+            }
+        }
         Ok(config)
     }
+
+    // Makes the relative file and directory paths absolute against `base_dir`. Empty paths and
+    // "-" (stdin/stdout) are left alone, and so are a whisper_model_path that is no file there
+    // (a model name such as "base.en") and a whisper_binary_path without a directory (looked up
+    // on PATH).
+    fn resolve_relative_paths(&mut self, base_dir: &Path) {
+        let resolve = |path: &mut String| {
+            if !path.is_empty() && !crate::video_processing::is_stdio_path(path) && Path::new(path.as_str()).is_relative() {
+                *path = base_dir.join(path.as_str()).to_string_lossy().into_owned();
+            }
+        };
+        resolve(&mut self.video.input_path);
+        self.video.append_input_paths.iter_mut().for_each(resolve);
+        resolve(&mut self.video.output_path);
+        let optional_paths = [
+            &mut self.video.report_path,
+            &mut self.video.temp_dir,
+            &mut self.video.bg_music_path,
+            &mut self.subtitles.whisper_model_cache_dir,
+            &mut self.subtitles.external_subtitle_path,
+            &mut self.subtitles.secondary_subtitle_path,
+            &mut self.watermark.watermark_path,
+        ];
+        optional_paths.into_iter().flatten().for_each(resolve);
+        resolve(&mut self.subtitles.font_path);
+        if base_dir.join(&self.subtitles.whisper_model_path).is_file() {
+            resolve(&mut self.subtitles.whisper_model_path);
+        }
+        if let Some(binary) = self.subtitles.whisper_binary_path.as_mut().filter(|binary| Path::new(binary.as_str()).components().count() > 1) {
+            resolve(binary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "does/not/exist.mp4",
+            "--append-input", "does/not/exist_part2.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--whisper-backend", "native", // Keeps the result independent of whether `whisper` is installed
+            "--font-path", "does/not/exist.ttf",
+            "--font-color", "not-a-color",
+            "--subtitle-position-vertical-alignment", "sideways",
+        ]);
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(problems[0].contains("Input video does not exist"));
+        assert!(problems[1].contains("Appended input video does not exist: does/not/exist_part2.mp4"));
+        assert!(problems[2].contains("Font file does not exist"));
+        assert!(problems[3].contains("Unsupported color string"));
+        assert!(problems[4].contains("Subtitle position"));
+    }
+
+    #[test]
+    fn required_filters_follow_the_enabled_steps() {
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+            "--target-aspect-ratio", "9:16",
+            "--aspect-fit-mode", "blur",
+            "--watermark-path", "logo.png",
+        ]);
+        let filters = |config: &AppConfig| config.required_filters().into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(filters(&config), ["subtitles", "scale", "boxblur", "overlay", "movie"]);
+        config.subtitles.subtitle_mode = SubtitleMode::SoftMux;
+        config.video.target_aspect_ratio = None;
+        config.watermark.watermark_path = None;
+        assert!(filters(&config).is_empty());
+    }
+
+    #[test]
+    fn stdin_and_stdout_only_take_a_single_sequentially_cut_short() {
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "-",
+            "--output-path", "-",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ]);
+        let problems = config.validate().unwrap_err();
+        assert!(!problems.iter().any(|p| p.contains("Input video") || p.contains("'-'")), "{:?}", problems);
+
+        assert_eq!(config.video.resolved_seek_mode(), SeekMode::Fast);
+        config.video.segments = vec![Segment { start_secs: 0.0, duration_secs: 30.0 }];
+        config.video.thumbnail_at_secs = Some(1.0);
+        let problems = config.validate().unwrap_err();
+        for expected in ["segments and auto_split", "thumbnail_at_secs"] {
+            assert!(problems.iter().any(|p| p.contains(expected)), "{}: {:?}", expected, problems);
+        }
+    }
+
+    #[test]
+    fn config_round_trips_through_every_format() {
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+            "--segment", "10:30",
+            "--encoder", "videotoolbox",
+            "--output-option", "movflags=+faststart",
+            "--output-option", "brand=isom",
+        ]);
+        assert_eq!(config.video.extra_output_options[0], ("movflags".to_string(), "+faststart".to_string()));
+        // Keeps the paths as written; resolving them is covered separately.
+        config.relative_paths = RelativePaths::Cwd;
+        let dir = std::env::temp_dir().join(format!("shorts_config_formats_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for file_name in ["config.json", "config.toml", "config.yaml", "config.yml"] {
+            let path = dir.join(file_name);
+            let path = path.to_str().unwrap();
+            config.save_to_file(path).unwrap();
+            let loaded = AppConfig::load_from_file(path).unwrap();
+            assert_eq!(loaded.video.input_path, "in.mp4");
+            assert_eq!(loaded.video.segments, config.video.segments);
+            assert_eq!(loaded.subtitles.font_path, "font.ttf");
+            assert_eq!(loaded.video.encoder, Some(VideoEncoder::VideoToolbox));
+            assert_eq!(loaded.video.extra_output_options, config.video.extra_output_options);
+            assert_eq!(loaded.version, CONFIG_VERSION);
+            assert_eq!(loaded.relative_paths, RelativePaths::Cwd);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unversioned_configs_are_upgraded_and_newer_ones_rejected() {
+        let dir = std::env::temp_dir().join(format!("shorts_config_version_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A file saved before versioning, with only the settings of the time.
+        let old = dir.join("old.json");
+        std::fs::write(
+            &old,
+            r#"{"video": {"input_path": "in.mp4", "output_path": "out.mp4", "short_duration_secs": 60},
+                "subtitles": {"use_subtitles": true, "whisper_model_path": "base", "font_path": "font.ttf", "font_size": 24,
+                              "font_color": "white", "subtitle_position_vertical_alignment": "bottom",
+                              "subtitle_position_horizontal_alignment": "center"}}"#,
+        )
+        .unwrap();
+        let newer = dir.join("newer.toml");
+        std::fs::write(&newer, format!("version = {}\n", CONFIG_VERSION + 1)).unwrap();
+
+        let loaded = AppConfig::load_from_file(old.to_str().unwrap());
+        let rejected = AppConfig::load_from_file(newer.to_str().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.version, CONFIG_VERSION);
+        assert_eq!(loaded.video.short_duration_secs, 60.0);
+        assert!(loaded.video.faststart);
+        // Older files keep resolving their relative paths against the working directory.
+        assert_eq!(loaded.relative_paths, RelativePaths::Cwd);
+        assert_eq!(loaded.video.input_path, "in.mp4");
+        let expected = format!("configuration version {}", CONFIG_VERSION + 1);
+        assert!(matches!(rejected, Err(ShortsError::InvalidConfig(message)) if message.contains(&expected)));
+    }
+
+    #[test]
+    fn relative_paths_resolve_against_the_config_file_unless_it_asks_for_cwd() {
+        let dir = std::env::temp_dir().join(format!("shorts_config_paths_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("models")).unwrap();
+        std::fs::write(dir.join("models/custom.bin"), b"model").unwrap();
+        let absolute_font = dir.join("fonts/Caption.ttf").to_string_lossy().into_owned();
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "clips/in.mp4",
+            "--output-path", "-",
+            "--whisper-model-path", "models/custom.bin",
+            "--whisper-binary-path", "whisper",
+            "--font-path", absolute_font.as_str(),
+            "--watermark-path", "logo.png",
+        ]);
+        let bundled = dir.join("bundle.json");
+        config.save_to_file(bundled.to_str().unwrap()).unwrap();
+        config.relative_paths = RelativePaths::Cwd;
+        let cwd_relative = dir.join("cwd.json");
+        config.save_to_file(cwd_relative.to_str().unwrap()).unwrap();
+        config.subtitles.whisper_model_path = "base.en".to_string();
+        config.relative_paths = RelativePaths::Config;
+        let model_name = dir.join("model_name.json");
+        config.save_to_file(model_name.to_str().unwrap()).unwrap();
+
+        let bundled = AppConfig::load_from_file(bundled.to_str().unwrap()).unwrap();
+        let cwd_relative = AppConfig::load_from_file(cwd_relative.to_str().unwrap()).unwrap();
+        let model_name = AppConfig::load_from_file(model_name.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let in_dir = |path: &str| dir.join(path).to_string_lossy().into_owned();
+        assert_eq!(bundled.video.input_path, in_dir("clips/in.mp4"));
+        assert_eq!(bundled.video.output_path, "-");
+        assert_eq!(bundled.subtitles.whisper_model_path, in_dir("models/custom.bin"));
+        assert_eq!(bundled.subtitles.whisper_binary_path.as_deref(), Some("whisper"));
+        assert_eq!(bundled.subtitles.font_path, absolute_font);
+        assert_eq!(bundled.watermark.watermark_path, Some(in_dir("logo.png")));
+        assert_eq!(cwd_relative.video.input_path, "clips/in.mp4");
+        assert_eq!(cwd_relative.subtitles.whisper_model_path, "models/custom.bin");
+        assert_eq!(model_name.subtitles.whisper_model_path, "base.en");
+    }
+
+    #[test]
+    fn fractional_short_durations_parse_and_integer_ones_still_load() {
+        let config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+            "--short-duration-secs", "58.5",
+        ]);
+        assert_eq!(config.video.short_duration_secs, 58.5);
+
+        // Configs saved while the duration was a whole number of seconds.
+        let dir = std::env::temp_dir().join(format!("shorts_config_duration_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file_name in ["config.json", "config.toml"] {
+            let path = dir.join(file_name);
+            let path = path.to_str().unwrap();
+            config.save_to_file(path).unwrap();
+            let contents = std::fs::read_to_string(path).unwrap().replace("58.5", "45");
+            std::fs::write(path, contents).unwrap();
+            assert_eq!(AppConfig::load_from_file(path).unwrap().video.short_duration_secs, 45.0, "{}", file_name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut config = config;
+        config.video.short_duration_secs = 0.0;
+        assert!(config.validate().unwrap_err().iter().any(|p| p.contains("short_duration_secs")));
+    }
+
+    #[test]
+    fn negative_or_non_finite_start_is_rejected() {
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--start-secs", "-2.5",
+        ]);
+        assert_eq!(config.video.start_secs, -2.5);
+        assert!(config.validate().unwrap_err().iter().any(|p| p.contains("start_secs must be 0 or more (got -2.5)")));
+        config.video.start_secs = f64::NAN;
+        assert!(config.validate().unwrap_err().iter().any(|p| p.contains("start_secs")));
+        config.video.start_secs = 0.0;
+        assert!(!config.validate().unwrap_err().iter().any(|p| p.contains("start_secs")));
+    }
+
+    #[test]
+    fn x264_preset_defaults_to_veryfast_and_rejects_typos() {
+        let args = ["shorts_wizard", "--input-path", "in.mp4", "--output-path", "out.mp4", "--whisper-model-path", "base"];
+        let config = AppConfig::parse_from(args);
+        assert_eq!(config.video.x264_preset, X264Preset::Veryfast);
+        assert_eq!(config.video.x264_tune, None);
+
+        let config = AppConfig::parse_from(args.iter().copied().chain(["--x264-preset", "slow", "--x264-tune", "stillimage"]));
+        assert_eq!(config.video.x264_preset.name(), "slow");
+        assert_eq!(config.video.x264_tune, Some(X264Tune::StillImage));
+        assert!(AppConfig::try_parse_from(args.iter().copied().chain(["--x264-preset", "veryfst"])).is_err());
+
+        let mut json = serde_json::to_value(&config).unwrap();
+        json["video"]["x264_preset"] = "veryfst".into();
+        assert!(serde_json::from_value::<AppConfig>(json).is_err());
+    }
+
+    #[test]
+    fn env_overrides_replace_file_values() {
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ]);
+        config.apply_overrides_from(|name| match name {
+            ENV_WHISPER_BINARY_PATH => Some("/opt/whisper/bin/whisper".to_string()),
+            ENV_FONT_PATH => Some(String::new()), // Empty variables are ignored
+            _ => None,
+        });
+        assert_eq!(config.subtitles.whisper_binary_path.as_deref(), Some("/opt/whisper/bin/whisper"));
+        assert_eq!(config.subtitles.font_path, "font.ttf");
+        assert_eq!(config.subtitles.whisper_model_path, "base");
+    }
+
+    #[test]
+    fn config_format_follows_extension() {
+        assert_eq!(ConfigFormat::from_path("pipeline.YAML"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("pipeline.yml"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("pipeline.toml"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path("pipeline.cfg"), ConfigFormat::Json);
+    }
 }
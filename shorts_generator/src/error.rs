@@ -0,0 +1,131 @@
+use ffmpeg_next as ffmpeg;
+use std::fmt;
+
+// Error type of the library's public functions, so callers can tell failure kinds apart (e.g. retry
+// on Ffmpeg/Io errors but not on InvalidColor). Internally the crate still uses anyhow; a
+// ShortsError raised inside an anyhow chain is recovered by the From<anyhow::Error> conversion,
+// and anything else ends up in Other. ShortsError converts back into anyhow::Error with `?`.
+#[derive(Debug)]
+pub enum ShortsError {
+    // The input (path given) has no audio stream, but the step needs one.
+    NoAudioStream(String),
+    // The input at `path` is not a video, e.g. an audio, subtitle or image file; `reason` says
+    // what was found instead.
+    NotAVideo { path: String, reason: String },
+    // The whisper executable (name or path given) could not be run because it is not installed,
+    // not on PATH, or not at the configured path.
+    WhisperNotFound(String),
+    // Unparseable color string (as given) for the subtitle font or outline.
+    InvalidColor(String),
+    InvalidAlignment { vertical: String, horizontal: String },
+    // A configuration file that could not be parsed (path and parser message).
+    InvalidConfig(String),
+    // A parameter outside its allowed range, e.g. a negative trim start or fade duration.
+    InvalidArgument(String),
+    // The output (path given) already exists and overwrite is off.
+    OutputExists(String),
+    // Too many packets (or, in strict mode, any packet) could not be written to the output at
+    // `path`, which is likely to be corrupt.
+    PacketWriteFailed { path: String, failed: usize, total: usize },
+    // The ProgressReporter asked to stop (see ProgressReporter::is_cancelled).
+    Cancelled,
+    FfmpegInit(String),
+    Ffmpeg(ffmpeg::Error),
+    Io(std::io::Error),
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for ShortsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShortsError::NoAudioStream(path) => write!(f, "No audio stream found in '{}'", path),
+            ShortsError::NotAVideo { path, reason } => write!(f, "'{}' is not a video: {}", path, reason),
+            ShortsError::WhisperNotFound(binary) => write!(
+                f,
+                "Whisper executable '{}' was not found. Install openai-whisper (`pip install -U openai-whisper`) and make sure it is on your PATH, or set whisper_binary_path (--whisper-binary-path) to its location.",
+                binary
+            ),
+            ShortsError::InvalidColor(color) => write!(
+                f,
+                "Unsupported color string: {}. Use CSS color names, #RGB, #RRGGBB or #RRGGBBAA hex.",
+                color
+            ),
+            ShortsError::InvalidAlignment { vertical, horizontal } => write!(
+                f,
+                "Invalid alignment combination: vertical='{}', horizontal='{}'. Use 'top/center/bottom' and 'left/center/right'.",
+                vertical, horizontal
+            ),
+            ShortsError::InvalidConfig(message) => write!(f, "Invalid configuration file {}", message),
+            ShortsError::InvalidArgument(message) => write!(f, "{}", message),
+            ShortsError::OutputExists(path) => write!(
+                f,
+                "Output file '{}' already exists. Pass --overwrite (overwrite: true in a config file) to replace it.",
+                path
+            ),
+            ShortsError::PacketWriteFailed { path, failed, total } => write!(
+                f,
+                "Failed to write {} of {} packets to '{}'; the output is likely corrupt",
+                failed, total, path
+            ),
+            ShortsError::Cancelled => write!(f, "Processing was cancelled"),
+            ShortsError::FfmpegInit(message) => write!(f, "Failed to initialize FFmpeg: {}", message),
+            ShortsError::Ffmpeg(e) => write!(f, "FFmpeg error: {}", e),
+            ShortsError::Io(e) => write!(f, "{}", e),
+            ShortsError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ShortsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShortsError::Ffmpeg(e) => Some(e),
+            ShortsError::Io(e) => e.source(),
+            // Display already shows the outermost message, so continue the chain below it.
+            ShortsError::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ShortsError {
+    fn from(e: std::io::Error) -> Self {
+        ShortsError::Io(e)
+    }
+}
+
+impl From<ffmpeg::Error> for ShortsError {
+    fn from(e: ffmpeg::Error) -> Self {
+        ShortsError::Ffmpeg(e)
+    }
+}
+
+impl From<anyhow::Error> for ShortsError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<ShortsError>() {
+            Ok(e) => e,
+            Err(e) => ShortsError::Other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn anyhow_round_trip_keeps_the_variant() {
+        let err: anyhow::Error = ShortsError::InvalidColor("mauve".to_string()).into();
+        assert!(matches!(ShortsError::from(err), ShortsError::InvalidColor(c) if c == "mauve"));
+
+        let err = Err::<(), _>(ShortsError::NoAudioStream("in.mp4".to_string()))
+            .context("Normalizing audio")
+            .unwrap_err();
+        assert!(matches!(ShortsError::from(err), ShortsError::NoAudioStream(_)));
+
+        let err = ShortsError::from(anyhow::Error::msg("something else"));
+        assert!(matches!(err, ShortsError::Other(_)));
+        assert_eq!(err.to_string(), "something else");
+    }
+}
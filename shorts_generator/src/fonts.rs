@@ -0,0 +1,297 @@
+use crate::error::ShortsError;
+use log::warn;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Extensions (lowercase) of the font files picked up by list_fonts.
+const FONT_EXTENSIONS: [&str; 2] = ["ttf", "otf"];
+
+// How deep list_fonts descends below each font directory, e.g. /usr/share/fonts/truetype/dejavu.
+const MAX_FONT_DIR_DEPTH: usize = 8;
+
+// A font file found by list_fonts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontInfo {
+    pub path: PathBuf,
+    pub family: Option<String>, // The family name stored in the font, None when it could not be read
+}
+
+impl std::fmt::Display for FontInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.family {
+            Some(family) => write!(f, "{}: {}", family, self.path.display()),
+            None => write!(f, "(unreadable font): {}", self.path.display()),
+        }
+    }
+}
+
+// Directories fonts are usually installed in on Linux, macOS and Windows, whether or not they exist.
+fn system_font_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = ["/usr/share/fonts", "/usr/local/share/fonts", "/Library/Fonts", "/System/Library/Fonts", "C:\\Windows\\Fonts"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    if let Some(home) = std::env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        dirs.extend([home.join(".fonts"), home.join(".local/share/fonts"), home.join("Library/Fonts")]);
+    }
+    if let Some(local_app_data) = std::env::var_os("LOCALAPPDATA") {
+        dirs.push(PathBuf::from(local_app_data).join("Microsoft\\Windows\\Fonts"));
+    }
+    dirs
+}
+
+// Finds the .ttf/.otf files in the system font directories and `extra_dirs` (searched first),
+// with the family name each one declares, e.g. to pick a working font_path. Missing or unreadable
+// directories are skipped. The list is sorted by family name, then path.
+pub fn list_fonts<P: AsRef<Path>>(extra_dirs: &[P]) -> Vec<FontInfo> {
+    let mut font_paths = Vec::new();
+    let mut seen_dirs = HashSet::new();
+    let dirs = extra_dirs.iter().map(|dir| dir.as_ref().to_path_buf()).chain(system_font_dirs());
+    for dir in dirs {
+        collect_font_paths(&dir, 0, &mut seen_dirs, &mut font_paths);
+    }
+
+    let mut fonts: Vec<FontInfo> = font_paths
+        .into_iter()
+        .map(|path| FontInfo { family: read_font_family(&path).ok(), path })
+        .collect();
+    fonts.sort_by(|a, b| {
+        let family = |font: &FontInfo| font.family.as_ref().map(|family| family.to_lowercase());
+        // Unreadable fonts (None) go last.
+        (family(a).is_none(), family(a), &a.path).cmp(&(family(b).is_none(), family(b), &b.path))
+    });
+    fonts
+}
+
+// Adds the font files below `dir` to `font_paths`. Directories are visited once even when they
+// are reached twice, e.g. through a symlink or an extra dir inside a system one.
+fn collect_font_paths(dir: &Path, depth: usize, seen_dirs: &mut HashSet<PathBuf>, font_paths: &mut Vec<PathBuf>) {
+    if depth > MAX_FONT_DIR_DEPTH {
+        return;
+    }
+    let Ok(canonical_dir) = dir.canonicalize() else {
+        return;
+    };
+    if !seen_dirs.insert(canonical_dir) {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_font_paths(&path, depth + 1, seen_dirs, font_paths);
+        } else if is_font_file(&path) {
+            font_paths.push(path);
+        }
+    }
+}
+
+fn is_font_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| FONT_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+// Executable used to look font families up the way libass does on Linux, found on PATH.
+const FC_MATCH_BINARY: &str = "fc-match";
+
+// Checks that fontconfig resolves the font family `family` (e.g. "DejaVu Sans") to an installed
+// font rather than substituting another one, and returns the file it resolves to. fontconfig
+// always answers with its closest match, so the family names of that match are compared with
+// `family`, ignoring case. Returns None when fc-match is not installed (e.g. on macOS or Windows,
+// where libass uses the system's own font lookup), after a warning.
+pub fn check_font_family(family: &str) -> Result<Option<PathBuf>, ShortsError> {
+    let output = match Command::new(FC_MATCH_BINARY)
+        .arg("--format=%{family}\n%{file}")
+        .arg(fontconfig_pattern(family))
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!("'{}' is not installed; cannot check that font family '{}' exists", FC_MATCH_BINARY, family);
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (families, file) = stdout.split_once('\n').unwrap_or((&stdout, ""));
+    if !output.status.success() || !matched_families(families).any(|matched| matched.eq_ignore_ascii_case(family.trim())) {
+        let substitute = matched_families(families).next().unwrap_or("nothing");
+        return Err(ShortsError::InvalidArgument(format!(
+            "Font family '{}' is not installed; fontconfig resolves it to '{}'",
+            family, substitute
+        )));
+    }
+    Ok(Some(PathBuf::from(file.trim())))
+}
+
+// Escapes the characters with a meaning in fontconfig patterns, so `family` is matched as a
+// family name: "Noto Sans:Bold" would otherwise ask for the bold style.
+fn fontconfig_pattern(family: &str) -> String {
+    let mut pattern = String::with_capacity(family.len());
+    for c in family.trim().chars() {
+        if matches!(c, '\\' | '-' | ':' | ',') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+// The family names fc-match prints for a font, e.g. "Noto Sans,Noto Sans Regular".
+fn matched_families(families: &str) -> impl Iterator<Item = &str> {
+    families.split(',').map(str::trim).filter(|family| !family.is_empty())
+}
+
+// Reads the family name (e.g. "DejaVu Sans") from the `name` table of a TrueType/OpenType font.
+// Only the table directory and the name table are read, not the glyph data; for a collection
+// (.ttc) that is the first font's. Fails with InvalidArgument when the file is not such a font or
+// declares no family name.
+pub fn read_font_family(path: &Path) -> Result<String, ShortsError> {
+    let not_a_font = || ShortsError::InvalidArgument(format!("'{}' is not a TrueType/OpenType font with a family name", path.display()));
+    let mut file = File::open(path)?;
+
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header).map_err(|_| not_a_font())?;
+    if &header[..4] == b"ttcf" {
+        // The collection header continues with the offsets of its fonts.
+        let mut first_offset = [0u8; 4];
+        file.read_exact(&mut first_offset).map_err(|_| not_a_font())?;
+        file.seek(SeekFrom::Start(u32::from_be_bytes(first_offset) as u64))?;
+        file.read_exact(&mut header).map_err(|_| not_a_font())?;
+    }
+    // TrueType (1.0 or "true") or CFF-based OpenType ("OTTO").
+    if !matches!(&header[..4], [0, 1, 0, 0] | b"true" | b"OTTO") {
+        return Err(not_a_font());
+    }
+    let table_count = read_u16(&header, 4).ok_or_else(not_a_font)? as usize;
+    let mut table_records = vec![0u8; table_count * 16];
+    file.read_exact(&mut table_records).map_err(|_| not_a_font())?;
+    let (offset, length) = table_records
+        .chunks_exact(16)
+        .find(|record| &record[..4] == b"name")
+        .and_then(|record| Some((read_u32(record, 8)?, read_u32(record, 12)?)))
+        .ok_or_else(not_a_font)?;
+
+    let mut name_table = vec![0u8; length as usize];
+    file.seek(SeekFrom::Start(offset as u64))?;
+    file.read_exact(&mut name_table).map_err(|_| not_a_font())?;
+    parse_family_name(&name_table).ok_or_else(not_a_font)
+}
+
+// Picks the family name (name ID 1) from a `name` table, preferring the US English Unicode
+// entry, then any Unicode one, then the Macintosh Roman one.
+fn parse_family_name(table: &[u8]) -> Option<String> {
+    const FAMILY_NAME_ID: u16 = 1;
+    const US_ENGLISH: u16 = 0x0409;
+    let count = read_u16(table, 2)? as usize;
+    let strings_offset = read_u16(table, 4)? as usize;
+
+    let mut best: Option<(u8, String)> = None;
+    for index in 0..count {
+        let record = table.get(6 + index * 12..6 + (index + 1) * 12)?;
+        let (platform, encoding, language, name_id) =
+            (read_u16(record, 0)?, read_u16(record, 2)?, read_u16(record, 4)?, read_u16(record, 6)?);
+        if name_id != FAMILY_NAME_ID {
+            continue;
+        }
+        let start = strings_offset + read_u16(record, 10)? as usize;
+        let Some(bytes) = table.get(start..start + read_u16(record, 8)? as usize) else {
+            continue;
+        };
+        // Platforms 0 (Unicode) and 3 (Windows) store UTF-16BE; platform 1 encoding 0 is Mac Roman,
+        // read here as Latin-1, which matches it for the ASCII family names fonts use.
+        let (rank, name) = match (platform, encoding) {
+            (0, _) | (3, _) => {
+                let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                let Ok(name) = String::from_utf16(&units) else {
+                    continue;
+                };
+                (if platform == 0 || language == US_ENGLISH { 0 } else { 1 }, name)
+            }
+            (1, 0) => (2, bytes.iter().map(|&b| b as char).collect()),
+            _ => continue,
+        };
+        let name = name.trim().to_string();
+        if !name.is_empty() && best.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank) {
+            best = Some((rank, name));
+        }
+    }
+    best.map(|(_, name)| name)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A font file reduced to its table directory and a `name` table holding `family` twice: as a
+    // Mac Roman entry and as a US English Windows one.
+    fn minimal_font(family: &str) -> Vec<u8> {
+        let utf16: Vec<u8> = family.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect();
+        let mut name_table = Vec::new();
+        for value in [0u16, 2, 6 + 2 * 12] {
+            name_table.extend_from_slice(&value.to_be_bytes()); // format, count, string offset
+        }
+        for (platform, encoding, language, length, offset) in
+            [(1u16, 0u16, 0u16, family.len(), 0), (3, 1, 0x0409, utf16.len(), family.len())]
+        {
+            for value in [platform, encoding, language, 1, length as u16, offset as u16] {
+                name_table.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        name_table.extend_from_slice(family.as_bytes());
+        name_table.extend_from_slice(&utf16);
+
+        let mut font = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        font.extend_from_slice(b"name");
+        for value in [0u32, 12 + 16, name_table.len() as u32] {
+            font.extend_from_slice(&value.to_be_bytes()); // checksum, offset, length
+        }
+        font.extend_from_slice(&name_table);
+        font
+    }
+
+    #[test]
+    fn fonts_are_listed_with_their_family_names() {
+        let dir = std::env::temp_dir().join(format!("shorts_fonts_test_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("nested/Caption-Bold.TTF"), minimal_font("Caption Sans")).unwrap();
+        std::fs::write(dir.join("broken.otf"), b"not a font").unwrap();
+        std::fs::write(dir.join("readme.txt"), b"not a font either").unwrap();
+
+        let fonts: Vec<FontInfo> = list_fonts(&[&dir]).into_iter().filter(|font| font.path.starts_with(&dir)).collect();
+        let family = read_font_family(&dir.join("nested/Caption-Bold.TTF"));
+        let broken = read_font_family(&dir.join("broken.otf"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(family.unwrap(), "Caption Sans");
+        assert!(matches!(broken, Err(ShortsError::InvalidArgument(_))));
+        assert_eq!(fonts.len(), 2, "{:?}", fonts);
+        assert_eq!(fonts[0].family.as_deref(), Some("Caption Sans"));
+        assert_eq!(fonts[1].family, None);
+    }
+
+    #[test]
+    fn font_families_are_escaped_for_fontconfig_and_matched_by_any_name() {
+        assert_eq!(fontconfig_pattern(" Noto Sans "), "Noto Sans");
+        assert_eq!(fontconfig_pattern("Fira-Code:Bold,Thin\\"), "Fira\\-Code\\:Bold\\,Thin\\\\");
+        let families: Vec<&str> = matched_families("Noto Sans, Noto Sans Regular,").collect();
+        assert_eq!(families, ["Noto Sans", "Noto Sans Regular"]);
+    }
+}
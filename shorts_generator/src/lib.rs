@@ -1,23 +1,55 @@
 pub mod config;
+pub mod error;
 pub mod video_processing;
 pub mod subtitle_generation;
-pub use config::{AppConfig, SubtitleConfig, VideoConfig};
-pub use video_processing::{extract_audio, trim_video, burn_subtitles}; // Updated this line
-pub use subtitle_generation::generate_subtitle_file;
+pub mod pipeline;
+pub mod progress;
+pub mod report;
+pub mod fonts;
+pub mod whisper_models;
+#[cfg(feature = "tokio")]
+pub mod async_api;
+#[cfg(feature = "native-whisper")]
+mod whisper_native;
+#[cfg(test)]
+mod test_support;
+pub use error::ShortsError;
+pub use config::{AppConfig, AspectFitMode, AudioCodec, CaptionAnimation, CaptionTextTransform, ConfigFormat, FontSource, MetadataConfig, MetadataStrip, MissingFontPolicy, RelativePaths, SeekMode, SubtitleConfig, SubtitleFormat, SubtitleMissingAudioPolicy, SubtitleMode, VideoConfig, VideoEncoder, WatermarkConfig, WhisperBackend, X264Preset, X264Tune};
+pub use video_processing::{
+    build_subtitles_filter, build_subtitle_tracks_filter, burn_subtitle_tracks, burn_subtitle_tracks_with_progress, SubtitleTrack, extract_audio, trim_video, trim_video_with_progress, concat_videos, burn_subtitles, burn_subtitles_with_progress, font_size_for_height_percent,
+    remux_video, mux_soft_subtitles, write_chapters, build_chapters, Chapter, output_format_available, crop_to_vertical, fit_to_aspect, fit_with_blur_background, pad_to_aspect, scale_video, normalize_audio_loudness, overlay_watermark, WatermarkOptions, SubtitleStyle, mix_background_music, apply_fades, change_speed, extract_thumbnail, probe_duration_secs,
+    detect_silences, remove_silence, detect_scenes, audio_peaks, probe_audio_streams, AudioStreamInfo, probe_video, VideoInfo, probe_metadata, is_location_tag, check_video_file, is_video_file,
+    VideoEncodeSettings, EncodePass, bit_rate_for_target_size, PacketWriteStats, MAX_PACKET_WRITE_FAILURE_RATIO, available_video_encoders, resolve_video_encoder,
+    OutputContainer, default_codecs_for_container, check_container_codecs, reencode_video,
+}; // Updated this line
+pub use pipeline::{generate_short, generate_short_with_progress, plan_segments, process_video_with_config, remove_live_temp_dirs, GeneratedShort};
+#[cfg(feature = "tokio")]
+pub use async_api::{generate_short_async, generate_short_with_progress_async, process_video_with_config_async};
+pub use progress::ProgressReporter;
+pub use fonts::{check_font_family, list_fonts, read_font_family, FontInfo};
+pub use whisper_models::{check_whisper_model, default_model_cache_dir, resolve_whisper_model};
+pub use report::{OutputReport, ProcessReport, ReportStatus, StageTiming};
+pub use subtitle_generation::{
+    analyze_cues, animate_ass_cues, apply_vtt_positioning, cut_subtitles_to_window, generate_subtitle_file, generate_subtitle_file_with_cues, limit_cue_durations, parse_srt, parse_subtitle_cues, restrict_subtitles_to_window, shift_srt_timing, transcript_text, transform_srt_text, wrap_srt_lines, CueIssue, SubtitleCue, SubtitleStats, WhisperOptions,
+};
 
 // Initialize FFmpeg globally for the library.
-// This should ideally be called by the application, but for simplicity in this context,
-// we'll do it here. It's safe to call multiple times.
-// TODO: Consider moving initialization to the application or providing an explicit init function.
-// Made this function public within the crate so video_processing.rs can call it.
-// Making it fully public so main.rs can also call it easily.
-pub fn init_ffmpeg() {
-    static FFMPEG_INIT: std::sync::Once = std::sync::Once::new();
-    FFMPEG_INIT.call_once(|| {
-        ffmpeg_next::init().expect("Failed to initialize FFmpeg");
-    });
+// Returns the initialization error instead of panicking, which matters when the crate is embedded
+// in a long-running service. The outcome is cached in a OnceLock, so calls after a failed
+// initialization keep reporting the original error rather than retrying.
+// Safe to call multiple times and from multiple threads.
+pub fn try_init_ffmpeg() -> Result<(), ShortsError> {
+    static FFMPEG_INIT: std::sync::OnceLock<Result<(), String>> = std::sync::OnceLock::new();
+    FFMPEG_INIT
+        .get_or_init(|| ffmpeg_next::init().map_err(|e| e.to_string()))
+        .clone()
+        .map_err(ShortsError::FfmpegInit)
 }
 
+// Thin panicking wrapper around try_init_ffmpeg for the CLI, where there is nothing to recover.
+pub fn init_ffmpeg() {
+    try_init_ffmpeg().expect("Failed to initialize FFmpeg");
+}
 
 #[cfg(test)]
 mod tests {
@@ -1,16 +1,28 @@
 use clap::Parser;
-use shorts_generator::config::AppConfig;
-use shorts_generator::{video_processing, subtitle_generation}; // Removed init_ffmpeg from here
-use anyhow::{Result, Error, Context};
+use shorts_generator::config::{AppConfig, RelativePaths};
+use shorts_generator::pipeline::{glob_output_path, preview_output_path, process_video_with_config, segment_output_path};
+use shorts_generator::progress::ProgressReporter;
+use shorts_generator::video_processing::is_stdio_path;
+use shorts_generator::{analyze_cues, list_fonts, parse_subtitle_cues, probe_audio_streams};
+use anyhow::{bail, Result, Error, Context};
+use std::cell::{Cell, RefCell};
 use std::path::Path;
 use std::fs;
-use log::{info, error, warn}; // Added log imports
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use log::{info, warn, error}; // Added log imports
 
 #[derive(Parser, Debug)]
 #[clap(name = "shorts_wizard", version = "0.1.0", author = "AI Agent")]
 struct Cli {
     #[clap(subcommand)]
     command: CliCommand,
+
+    #[clap(long, global = true, help = "Validate the configuration and log the processing plan without running FFmpeg or Whisper")]
+    dry_run: bool,
+
+    #[clap(long, global = true, help = "Print the effective configuration as JSON (after defaults, environment variables and the config file) before running the command")]
+    print_config: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -18,9 +30,9 @@ enum CliCommand {
     #[clap(about = "Generate video shorts directly with specified configuration options")]
     Generate(AppConfig), // AppConfig already derives Parser and flattens Video/SubtitleConfig
 
-    #[clap(about = "Configure and save settings to a JSON file")]
+    #[clap(about = "Configure and save settings to a JSON, TOML or YAML file")]
     Configure {
-        #[clap(long, help = "Path to save the configuration JSON file")]
+        #[clap(long, help = "Path to save the configuration file (.json, .toml, .yaml or .yml)")]
         output_config_path: String,
 
         #[clap(flatten)]
@@ -29,9 +41,47 @@ enum CliCommand {
 
     #[clap(about = "Run video generation using a configuration file")]
     RunFromFile {
-        #[clap(long, help = "Path to the configuration JSON file")]
+        #[clap(long, help = "Path to the configuration file (.json, .toml, .yaml or .yml)")]
         config_path: String,
     },
+
+    #[clap(about = "Generate shorts for every video (mp4, mov, mkv) in a directory with the same configuration")]
+    // Input and output paths are derived per file, so they are not asked for here. Each file is
+    // processed on its own, so there is nothing to append to it either.
+    #[clap(mut_arg("input_path", |arg| arg.required(false).default_value("").hide(true)))]
+    #[clap(mut_arg("output_path", |arg| arg.required(false).default_value("").hide(true)))]
+    #[clap(mut_arg("append_input_paths", |arg| arg.hide(true)))]
+    Batch {
+        #[clap(long, help = "Directory containing the input videos")]
+        input_dir: String,
+
+        #[clap(long, help = "Directory to write the generated shorts to (created if missing)")]
+        output_dir: String,
+
+        #[clap(long, value_parser = clap::value_parser!(u32).range(1..), help = "Number of videos to process in parallel (default: number of CPUs)")]
+        jobs: Option<u32>,
+
+        #[clap(flatten)]
+        config: AppConfig,
+    },
+
+    #[clap(about = "List the audio streams of a video with their indices, for --audio-stream-index")]
+    AudioStreams {
+        #[clap(long, help = "Path to the video file")]
+        input_path: String,
+    },
+
+    #[clap(about = "List the installed .ttf/.otf fonts with their family names, for --font-path and --font-name")]
+    Fonts {
+        #[clap(long = "font-dir", help = "Additional directory to search for fonts (can be repeated)")]
+        font_dirs: Vec<String>,
+    },
+
+    #[clap(about = "Print statistics of an SRT, VTT or ASS file and list suspicious cues (overlaps, repeated text, absurd speeds)")]
+    SubtitlesInfo {
+        #[clap(help = "Path to the subtitle file")]
+        subtitle_path: String,
+    },
 }
 
 fn main() -> Result<()> {
@@ -39,153 +89,467 @@ fn main() -> Result<()> {
 
     env_logger::init(); // Initialize logger
 
+    // Exiting on Ctrl-C skips the cleanup at the end of each run, so the temporary directories
+    // of the runs in progress are removed here.
+    if let Err(e) = ctrlc::set_handler(|| {
+        warn!("Interrupted; removing temporary files");
+        shorts_generator::pipeline::remove_live_temp_dirs();
+        std::process::exit(130);
+    }) {
+        warn!("Failed to install the Ctrl-C handler, interrupted runs will leave their temporary files behind: {}", e);
+    }
+
+    let dry_run = cli.dry_run;
+    let print_config = cli.print_config;
+    if print_config && matches!(cli.command, CliCommand::AudioStreams { .. } | CliCommand::Fonts { .. } | CliCommand::SubtitlesInfo { .. }) {
+        warn!("--print-config has no effect on commands that take no configuration");
+    }
     match cli.command {
+        CliCommand::Generate(config) if is_input_pattern(&config.video.input_path) => {
+            if print_config {
+                print_effective_config(&config);
+            }
+            let summary = match run_input_pattern(&config, dry_run) {
+                Ok(summary) => summary,
+                Err(e) => {
+                    error!("Video processing failed: {:?}", e);
+                    std::process::exit(1);
+                }
+            };
+            info!("Finished {} input(s): {} succeeded, {} failed.", summary.succeeded + summary.failed, summary.succeeded, summary.failed);
+            if summary.failed > 0 {
+                std::process::exit(1);
+            }
+        }
         CliCommand::Generate(config) => {
+            if print_config {
+                print_effective_config(&config);
+            }
+            validate_or_exit(&config);
             info!("Starting video generation with directly provided config...");
-            if let Err(e) = process_video_with_config(config.clone()) {
-                error!("Video processing failed: {:?}", e);
+            if let Err(e) = process_video_with_config(config.clone(), dry_run, Some(&LogProgressReporter::new(""))) {
+                error!("Video processing failed: {:?}", Error::from(e));
                 std::process::exit(1);
             }
-            info!("Video processing completed successfully.");
+            if dry_run {
+                info!("Dry run completed.");
+            } else {
+                info!("Video processing completed successfully.");
+            }
         }
-        CliCommand::Configure { output_config_path, config } => {
+        CliCommand::Configure { output_config_path, mut config } => {
+            // Relative paths on the command line are relative to the working directory, not to
+            // wherever the file is written.
+            if Path::new(&output_config_path).parent().is_some_and(|dir| !dir.as_os_str().is_empty()) {
+                config.relative_paths = RelativePaths::Cwd;
+            }
+            if print_config {
+                print_effective_config(&config);
+            }
+            validate_or_exit(&config);
+            if dry_run {
+                info!("Dry run: would save configuration to {}", output_config_path);
+                return Ok(());
+            }
             info!("Saving configuration to {}...", output_config_path);
             if let Err(e) = config.save_to_file(&output_config_path) {
-                error!("Failed to save configuration: {:?}", e);
+                error!("Failed to save configuration: {:?}", Error::from(e));
                 std::process::exit(1);
             }
             info!("Configuration saved successfully to {}", output_config_path);
         }
         CliCommand::RunFromFile { config_path } => {
             info!("Loading configuration from {}...", config_path);
-            let config = match AppConfig::load_from_file(&config_path) {
+            let mut config = match AppConfig::load_from_file(&config_path) {
                 Ok(c) => c,
                 Err(e) => {
-                    error!("Failed to load configuration from '{}': {:?}", config_path, e);
+                    error!("Failed to load configuration from '{}': {:?}", config_path, Error::from(e));
                     std::process::exit(1);
                 }
             };
+            config.apply_env_overrides();
+            if print_config {
+                print_effective_config(&config);
+            }
+            validate_or_exit(&config);
             info!("Starting video generation with config from file: {}...", config_path);
-            if let Err(e) = process_video_with_config(config.clone()) {
-                error!("Video processing failed: {:?}", e);
+            if let Err(e) = process_video_with_config(config.clone(), dry_run, Some(&LogProgressReporter::new(""))) {
+                error!("Video processing failed: {:?}", Error::from(e));
                 std::process::exit(1);
             }
-            info!("Video processing completed successfully.");
+            if dry_run {
+                info!("Dry run completed.");
+            } else {
+                info!("Video processing completed successfully.");
+            }
+        }
+        CliCommand::Batch { input_dir, output_dir, jobs, config } => {
+            // Every file runs with this configuration, with its own input and output paths.
+            if print_config {
+                print_effective_config(&config);
+            }
+            let jobs = jobs
+                .map(|n| n as usize)
+                .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+            let summary = match run_batch(&input_dir, &output_dir, jobs, dry_run, &config) {
+                Ok(summary) => summary,
+                Err(e) => {
+                    error!("Batch processing failed: {:?}", e);
+                    std::process::exit(1);
+                }
+            };
+            info!(
+                "Batch finished: {} succeeded, {} failed, {} skipped.",
+                summary.succeeded, summary.failed, summary.skipped
+            );
+            if summary.failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        CliCommand::AudioStreams { input_path } => {
+            let streams = match probe_audio_streams(&input_path) {
+                Ok(streams) => streams,
+                Err(e) => {
+                    error!("Failed to read the streams of '{}': {:?}", input_path, Error::from(e));
+                    std::process::exit(1);
+                }
+            };
+            if streams.is_empty() {
+                println!("No audio streams in {}", input_path);
+            }
+            for stream in streams {
+                println!("{}", stream);
+            }
+        }
+        CliCommand::Fonts { font_dirs } => {
+            let fonts = list_fonts(&font_dirs);
+            if fonts.is_empty() {
+                println!("No .ttf or .otf fonts found; pass --font-dir to search another directory");
+            }
+            for font in fonts {
+                println!("{}", font);
+            }
+        }
+        CliCommand::SubtitlesInfo { subtitle_path } => {
+            let cues = match parse_subtitle_cues(Path::new(&subtitle_path)) {
+                Ok(cues) => cues,
+                Err(e) => {
+                    error!("Failed to read subtitles from '{}': {:?}", subtitle_path, Error::from(e));
+                    std::process::exit(1);
+                }
+            };
+            let stats = analyze_cues(&cues);
+            println!("{}: {} cues, {} words", subtitle_path, stats.cue_count, stats.word_count);
+            println!(
+                "Caption time: {:.1}s total, {:.1}s average",
+                stats.total_duration.as_secs_f64(),
+                stats.average_duration.as_secs_f64()
+            );
+            if let Some((index, duration)) = stats.longest_cue {
+                println!("Longest cue: {} ({:.1}s)", index, duration.as_secs_f64());
+            }
+            println!("Speed: {:.0} words per minute", stats.words_per_minute);
+            if stats.issues.is_empty() {
+                println!("No suspicious cues");
+            } else {
+                println!("{} suspicious cue(s):", stats.issues.len());
+                for issue in &stats.issues {
+                    println!("  {}", issue);
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-fn process_video_with_config(config: AppConfig) -> Result<(), Error> {
-    shorts_generator::init_ffmpeg();
-    info!("Starting video processing for: {}", config.video.output_path);
-
-    // Create a temporary processing directory
-    let output_dir_path = Path::new(&config.video.output_path)
-        .parent()
-        .ok_or_else(|| Error::msg(format!("Invalid output path (could not get parent directory): {}", config.video.output_path)))?;
-    
-    let input_file_stem = Path::new(&config.video.input_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("video");
-    let temp_dir_name = format!("{}_processing_temp_{}", input_file_stem, chrono::Utc::now().timestamp_millis());
-    let temp_dir = output_dir_path.join(&temp_dir_name);
-    
-    if temp_dir.exists() {
-        fs::remove_dir_all(&temp_dir)
-            .with_context(|| format!("Failed to clean up existing temp directory: {:?}", temp_dir))?;
-    }
-    fs::create_dir_all(&temp_dir)
-        .with_context(|| format!("Failed to create temp directory: {:?}", temp_dir))?;
-
-    info!("Temporary processing directory created at: {:?}", temp_dir);
-
-    // Trim Video
-    let trimmed_video_filename = format!("{}_trimmed.mp4", input_file_stem);
-    let trimmed_video_path = temp_dir.join(&trimmed_video_filename);
-    let trimmed_video_path_str = trimmed_video_path.to_str()
-        .ok_or_else(|| Error::msg("Failed to convert trimmed video path to string"))?;
-
-    info!("Trimming video: {} to {}s. Output: {}", config.video.input_path, config.video.short_duration_secs, trimmed_video_path_str);
-    video_processing::trim_video(
-        &config.video.input_path,
-        trimmed_video_path_str,
-        0.0, // Assuming start from beginning for the short
-        config.video.short_duration_secs as f64,
-    )
-    .with_context(|| format!("Failed to trim video from '{}'", config.video.input_path))?;
-    info!("Video trimmed successfully. Output: {}", trimmed_video_path_str);
-
-    let final_output_path_str = &config.video.output_path;
-
-    if config.subtitles.use_subtitles {
-        info!("Subtitle generation enabled.");
-        // Extract Audio
-        let audio_filename = format!("{}_extracted_audio.wav", input_file_stem);
-        let audio_path = temp_dir.join(&audio_filename);
-        let audio_path_str = audio_path.to_str()
-            .ok_or_else(|| Error::msg("Failed to convert audio path to string"))?;
-
-        info!("Extracting audio from: {}. Output: {}", trimmed_video_path_str, audio_path_str);
-        video_processing::extract_audio(trimmed_video_path_str, audio_path_str)
-            .with_context(|| format!("Failed to extract audio from '{}'", trimmed_video_path_str))?;
-        info!("Audio extracted successfully. Output: {}", audio_path_str);
-
-        // Generate Subtitle File
-        info!("Generating subtitles for: {}. Model: {}", audio_path_str, config.subtitles.whisper_model_path);
-        let subtitle_file_path_str = subtitle_generation::generate_subtitle_file(
-            audio_path_str,
-            &config.subtitles.whisper_model_path,
-            temp_dir.to_str().ok_or_else(|| Error::msg("Failed to convert temp_dir to string for subtitle generation"))?,
-        )
-        .with_context(|| "Failed to generate subtitle file")?;
-        info!("Subtitles generated successfully. Output: {}", subtitle_file_path_str);
-
-        // Burn Subtitles
-        info!("Burning subtitles from {} into video. Output: {}", subtitle_file_path_str, final_output_path_str);
-        video_processing::burn_subtitles(
-            trimmed_video_path_str,
-            &subtitle_file_path_str,
-            final_output_path_str,
-            &config.subtitles.font_path,
-            config.subtitles.font_size,
-            &config.subtitles.font_color,
-            &config.subtitles.subtitle_position_vertical_alignment,
-            &config.subtitles.subtitle_position_horizontal_alignment,
-        )
-        .with_context(|| format!("Failed to burn subtitles onto '{}'", trimmed_video_path_str))?;
-        info!("Subtitles burned successfully.");
+// Extensions (lowercase) of the files picked up by the batch command.
+const BATCH_VIDEO_EXTENSIONS: [&str; 3] = ["mp4", "mov", "mkv"];
 
-    } else {
-        info!("Subtitle generation disabled. Copying trimmed video to output: {}", final_output_path_str);
-        fs::rename(&trimmed_video_path, Path::new(final_output_path_str))
-            .or_else(|e| {
-                warn!("Failed to move trimmed video (attempting copy instead): {:?}", e);
-                fs::copy(&trimmed_video_path, Path::new(final_output_path_str)).map(|_| ()).map_err(anyhow::Error::from)
-            })
-            .and_then(|_| { 
-                if Path::new(final_output_path_str).exists() && trimmed_video_path.exists() {
-                    fs::remove_file(&trimmed_video_path)
-                        .with_context(|| format!("Failed to remove original trimmed video after copy: {:?}", trimmed_video_path))?; // Add ? to propagate anyhow::Error
+#[derive(Debug, Default)]
+struct BatchSummary {
+    succeeded: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+// Lists the video files directly inside `input_dir`, sorted by name.
+fn find_batch_inputs(input_dir: &Path) -> Result<Vec<std::path::PathBuf>, Error> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(input_dir)
+        .with_context(|| format!("Failed to read input directory: {}", input_dir.display()))?
+    {
+        let path = entry?.path();
+        let is_video = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| BATCH_VIDEO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+            .unwrap_or(false);
+        if is_video && path.is_file() {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+// One file of a batch, ready to be processed.
+struct BatchJob {
+    label: String, // e.g. "[3/50] clips/intro.mov", used to tell interleaved worker logs apart
+    config: AppConfig,
+}
+
+// Runs the pipeline for every video in `input_dir`, writing "<stem>.mp4" into `output_dir`, on
+// `jobs` worker threads. A failing (or panicking) file is logged and counted, and the batch moves
+// on to the next one. Each file gets its own temp directory and output, so workers share nothing
+// but the one-time FFmpeg initialization, which try_init_ffmpeg guards with a OnceLock.
+fn run_batch(
+    input_dir: &str,
+    output_dir: &str,
+    jobs: usize,
+    dry_run: bool,
+    template: &AppConfig,
+) -> Result<BatchSummary, Error> {
+    let inputs = find_batch_inputs(Path::new(input_dir))?;
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create output directory: {}", output_dir))?;
+    info!("Found {} video(s) in {}", inputs.len(), input_dir);
+
+    let mut summary = BatchSummary::default();
+    let mut batch_jobs = Vec::new();
+    let mut claimed_outputs = std::collections::HashSet::new();
+    for (index, input) in inputs.iter().enumerate() {
+        let input_path = input
+            .to_str()
+            .ok_or_else(|| Error::msg(format!("Input path is not valid UTF-8: {}", input.display())))?;
+        let label = format!("[{}/{}] {}", index + 1, inputs.len(), input_path);
+        let stem = input
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| Error::msg(format!("Could not get file stem of: {}", input_path)))?;
+        let output_path = Path::new(output_dir).join(format!("{}.mp4", stem));
+        let output_path = output_path
+            .to_str()
+            .ok_or_else(|| Error::msg("Output path is not valid UTF-8"))?;
+
+        // e.g. intro.mov and intro.mp4 would both write intro.mp4 (and race on it when run in parallel).
+        if !claimed_outputs.insert(output_path.to_string()) {
+            error!("{}: Failed: another input in this batch also writes {}", label, output_path);
+            summary.failed += 1;
+            continue;
+        }
+
+        let mut config = template.clone();
+        config.video.input_path = input_path.to_string();
+        config.video.append_input_paths.clear();
+        config.video.output_path = output_path.to_string();
+        config.video.report_path = job_report_path(template, stem)?;
+
+        let written_path = match config.video.preview_secs {
+            Some(_) => preview_output_path(output_path)?,
+            None => output_path.to_string(),
+        };
+        // With segments the first numbered output stands in for the whole file.
+        let existing_output = if config.video.segments.is_empty() && !config.video.auto_split {
+            written_path
+        } else {
+            segment_output_path(&written_path, 1)?
+        };
+        if !config.video.overwrite && Path::new(&existing_output).exists() {
+            info!("{}: Skipping, {} already exists", label, existing_output);
+            summary.skipped += 1;
+            continue;
+        }
+
+        batch_jobs.push(BatchJob { label, config });
+    }
+
+    let (succeeded, failed) = run_batch_jobs(&batch_jobs, jobs, dry_run);
+    summary.succeeded += succeeded;
+    summary.failed += failed;
+    Ok(summary)
+}
+
+// One report per file, e.g. report.json -> report_intro.json for `suffix` "intro", instead of each
+// job overwriting the last.
+fn job_report_path(template: &AppConfig, suffix: &str) -> Result<Option<String>, Error> {
+    let Some(report_path) = &template.video.report_path else {
+        return Ok(None);
+    };
+    let report_path = Path::new(report_path);
+    let report_stem = report_path.file_stem().and_then(|s| s.to_str()).unwrap_or("report");
+    let report_ext = report_path.extension().and_then(|e| e.to_str()).unwrap_or("json");
+    let job_report_path = report_path.with_file_name(format!("{}_{}.{}", report_stem, suffix, report_ext));
+    Ok(Some(
+        job_report_path
+            .to_str()
+            .ok_or_else(|| Error::msg("Report path is not valid UTF-8"))?
+            .to_string(),
+    ))
+}
+
+// Runs `batch_jobs` on `jobs` worker threads and returns how many succeeded and failed.
+fn run_batch_jobs(batch_jobs: &[BatchJob], jobs: usize, dry_run: bool) -> (usize, usize) {
+    let worker_count = jobs.clamp(1, batch_jobs.len().max(1));
+    info!("Processing {} video(s) with {} worker(s)", batch_jobs.len(), worker_count);
+
+    let next_job = AtomicUsize::new(0);
+    let succeeded = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..worker_count {
+            scope.spawn(|| {
+                while let Some(job) = batch_jobs.get(next_job.fetch_add(1, Ordering::SeqCst)) {
+                    info!("{}: Processing", job.label);
+                    // A panic is contained to its own file so the worker can keep going.
+                    let outcome = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        job.config
+                            .validate()
+                            .map_err(|problems| Error::msg(format!("Invalid configuration: {}", problems.join("; "))))
+                            .and_then(|_| {
+                                let reporter = LogProgressReporter::new(&format!("{}: ", job.label));
+                                process_video_with_config(job.config.clone(), dry_run, Some(&reporter)).map_err(Error::from)
+                            })
+                    }));
+                    match outcome {
+                        Ok(Ok(())) => {
+                            info!("{}: Done", job.label);
+                            succeeded.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Ok(Err(e)) => {
+                            error!("{}: Failed: {:?}", job.label, e);
+                            failed.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(_) => {
+                            error!("{}: Failed: processing panicked", job.label);
+                            failed.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
                 }
-                Ok(()) // Ensure this path returns Ok(()) of the correct type
-            })
-            .with_context(|| {
-                format!(
-                    "Failed to move or copy trimmed video from {:?} to {}",
-                    trimmed_video_path, final_output_path_str
-                )
-            })?;
-        info!("Trimmed video moved/copied to: {}", final_output_path_str);
-    }
-
-    info!("Cleaning up temporary directory: {:?}", temp_dir);
-    fs::remove_dir_all(&temp_dir)
-        .with_context(|| format!("Failed to clean up temp directory: {:?}", temp_dir))?;
-    info!("Temporary directory cleaned up successfully.");
-    
-    info!("Video processing completed successfully for: {}", config.video.output_path);
-    Ok(())
+            });
+        }
+    });
+
+    (succeeded.into_inner(), failed.into_inner())
+}
+
+// Whether `input_path` is a wildcard pattern such as "clips/*.mp4" rather than a file. A file
+// whose name happens to contain wildcard characters is still read as that file.
+fn is_input_pattern(input_path: &str) -> bool {
+    input_path.contains(['*', '?', '[']) && !Path::new(input_path).exists()
+}
+
+// Runs the generate command for every file the input_path pattern of `template` matches, in path
+// order and one after another, writing each to a glob_output_path name, e.g. "out_02_intro.mp4".
+// All of the configurations are validated before the first one runs; after that, a failing file
+// is logged and counted like in a batch, and the rest still run.
+fn run_input_pattern(template: &AppConfig, dry_run: bool) -> Result<BatchSummary, Error> {
+    let pattern = &template.video.input_path;
+    if is_stdio_path(&template.video.output_path) {
+        bail!("Cannot write the shorts for input path pattern '{}' to stdout; use an output file name to number", pattern);
+    }
+    let mut inputs = Vec::new();
+    for entry in glob::glob(pattern).with_context(|| format!("Invalid input path pattern: {}", pattern))? {
+        let path = entry.with_context(|| format!("Failed to read a match of input path pattern: {}", pattern))?;
+        if path.is_file() {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+    if inputs.is_empty() {
+        bail!("Input path pattern '{}' matched no files", pattern);
+    }
+    info!("Input path pattern {} matched {} file(s)", pattern, inputs.len());
+
+    let mut batch_jobs = Vec::new();
+    let mut problems = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        let input_path = input
+            .to_str()
+            .ok_or_else(|| Error::msg(format!("Input path is not valid UTF-8: {}", input.display())))?;
+        let label = format!("[{}/{}] {}", index + 1, inputs.len(), input_path);
+        let output_path = glob_output_path(&template.video.output_path, index + 1, input_path)?;
+        let output_stem = Path::new(&output_path).file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+
+        let mut config = template.clone();
+        config.video.input_path = input_path.to_string();
+        config.video.report_path = job_report_path(template, output_stem)?;
+        config.video.output_path = output_path.clone();
+        if let Err(config_problems) = config.validate() {
+            problems.extend(config_problems.into_iter().map(|problem| format!("{}: {}", input_path, problem)));
+        }
+        batch_jobs.push(BatchJob { label, config });
+    }
+    if !problems.is_empty() {
+        bail!("Invalid configuration ({} problem(s)): {}", problems.len(), problems.join("; "));
+    }
+
+    let (succeeded, failed) = run_batch_jobs(&batch_jobs, 1, dry_run);
+    Ok(BatchSummary { succeeded, failed, skipped: 0 })
+}
+
+// Prints the configuration a command runs with as JSON, for --print-config. It goes to stderr
+// instead of stdout when the short itself is written to stdout.
+fn print_effective_config(config: &AppConfig) {
+    let json = match config.to_json_pretty() {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to serialize the configuration: {:?}", Error::from(e));
+            std::process::exit(1);
+        }
+    };
+    if is_stdio_path(&config.video.output_path) {
+        eprintln!("{}", json);
+    } else {
+        println!("{}", json);
+    }
+}
+
+// Reports every problem with the configuration at once and exits, instead of failing partway
+// through processing.
+fn validate_or_exit(config: &AppConfig) {
+    if let Err(problems) = config.validate() {
+        error!("Invalid configuration ({} problem(s)):", problems.len());
+        for problem in &problems {
+            error!("  - {}", problem);
+        }
+        std::process::exit(1);
+    }
+}
+
+// Logs each pipeline stage and its completion percentage in 10% steps. `prefix` tells
+// interleaved batch workers apart.
+struct LogProgressReporter {
+    prefix: String,
+    stage: RefCell<String>,
+    last_logged_step: Cell<i32>,
+}
+
+impl LogProgressReporter {
+    fn new(prefix: &str) -> Self {
+        LogProgressReporter {
+            prefix: prefix.to_string(),
+            stage: RefCell::new(String::new()),
+            last_logged_step: Cell::new(-1),
+        }
+    }
+}
+
+impl ProgressReporter for LogProgressReporter {
+    fn stage(&self, name: &str) {
+        *self.stage.borrow_mut() = name.to_string();
+        self.last_logged_step.set(-1);
+    }
+
+    fn progress(&self, fraction: f64) {
+        let step = (fraction * 10.0).floor() as i32;
+        if step > self.last_logged_step.get() {
+            self.last_logged_step.set(step);
+            info!("{}{}: {:.0}%", self.prefix, self.stage.borrow(), fraction * 100.0);
+        }
+    }
+
+    fn done(&self) {
+        info!("{}All steps finished.", self.prefix);
+    }
 }
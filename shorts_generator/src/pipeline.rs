@@ -0,0 +1,2328 @@
+use crate::config::{subtitle_file_kind, AppConfig, AspectFitMode, CaptionAnimation, CaptionTextTransform, FontSource, MetadataStrip, MissingFontPolicy, Segment, SubtitleConfig, SubtitleFormat, SubtitleMissingAudioPolicy, SubtitleMode, VideoEncoder, WhisperBackend};
+use crate::error::ShortsError;
+use crate::progress::ProgressReporter;
+use crate::report::{OutputReport, ProcessReport, ReportStatus, StageTiming};
+use crate::{subtitle_generation, video_processing, whisper_models};
+use anyhow::{Context, Error, Result};
+use log::{info, warn};
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+// How often process_segments_overlapped checks for cancellation while it waits for a segment.
+const OVERLAP_CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Forwards to the optional ProgressReporter, doing nothing when there is none. Also times each
+// stage and remembers the generated subtitles, for the run report.
+struct Reporter<'a> {
+    progress: Option<&'a dyn ProgressReporter>,
+    stop: Option<&'a AtomicBool>, // Cancels like ProgressReporter::is_cancelled once set
+    current_stage: RefCell<Option<(String, Instant)>>,
+    finished_stages: RefCell<Vec<StageTiming>>,
+    subtitles: RefCell<Option<(String, usize)>>, // Subtitle path and cue count
+    transcript: RefCell<Option<String>>,
+    thumbnail: RefCell<Option<String>>,
+    packet_write_failures: Cell<usize>,
+}
+
+impl<'a> Reporter<'a> {
+    fn new(progress: Option<&'a dyn ProgressReporter>) -> Self {
+        Reporter {
+            progress,
+            stop: None,
+            current_stage: RefCell::new(None),
+            finished_stages: RefCell::new(Vec::new()),
+            subtitles: RefCell::new(None),
+            transcript: RefCell::new(None),
+            thumbnail: RefCell::new(None),
+            packet_write_failures: Cell::new(0),
+        }
+    }
+
+    // Starts the step `name`, or fails with Cancelled instead when the run is to stop.
+    fn stage(&self, name: &str) -> Result<(), ShortsError> {
+        self.check_cancelled()?;
+        self.end_stage();
+        *self.current_stage.borrow_mut() = Some((name.to_string(), Instant::now()));
+        if let Some(reporter) = self.progress {
+            reporter.stage(name);
+        }
+        Ok(())
+    }
+
+    fn done(&self) {
+        if let Some(reporter) = self.progress {
+            reporter.done();
+        }
+    }
+
+    // Progress callback for the video_processing *_with_progress functions.
+    fn callback(&self) -> impl FnMut(f64) + '_ {
+        move |fraction| {
+            if let Some(reporter) = self.progress {
+                reporter.progress(fraction);
+            }
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed)) || self.progress.is_some_and(|reporter| reporter.is_cancelled())
+    }
+
+    fn check_cancelled(&self) -> Result<(), ShortsError> {
+        if self.is_cancelled() {
+            return Err(ShortsError::Cancelled);
+        }
+        Ok(())
+    }
+
+    fn record_subtitles(&self, subtitle_path: &str, cue_count: usize) {
+        *self.subtitles.borrow_mut() = Some((subtitle_path.to_string(), cue_count));
+    }
+
+    fn record_transcript(&self, transcript: String) {
+        *self.transcript.borrow_mut() = Some(transcript);
+    }
+
+    fn record_thumbnail(&self, thumbnail_path: &str) {
+        *self.thumbnail.borrow_mut() = Some(thumbnail_path.to_string());
+    }
+
+    fn record_packet_write_failures(&self, failed: usize) {
+        self.packet_write_failures.set(self.packet_write_failures.get() + failed);
+    }
+
+    fn end_stage(&self) {
+        if let Some((stage, started)) = self.current_stage.borrow_mut().take() {
+            self.finished_stages.borrow_mut().push(StageTiming { stage, secs: started.elapsed().as_secs_f64() });
+        }
+    }
+
+    // Adds what was recorded since the previous output to `output` and starts afresh. Adding lets
+    // an output prepared on another Reporter (see process_segments_overlapped) collect both parts.
+    fn take_output_record(&self, output: &mut OutputReport) {
+        self.end_stage();
+        output.stages.append(&mut self.finished_stages.borrow_mut());
+        if let Some((subtitle_path, cue_count)) = self.subtitles.borrow_mut().take() {
+            output.subtitle_path = Some(subtitle_path);
+            output.subtitle_cue_count = Some(cue_count);
+        }
+        if let Some(transcript) = self.transcript.borrow_mut().take() {
+            output.transcript = Some(transcript);
+        }
+        if let Some(thumbnail_path) = self.thumbnail.borrow_mut().take() {
+            output.thumbnail_path = Some(thumbnail_path);
+        }
+        output.packet_write_failures += self.packet_write_failures.replace(0);
+    }
+}
+
+// Generates the short(s) described by `config`: one output, or one per segment. With `dry_run`
+// the plan is only logged. `reporter`, when given, is told about each step as it runs. When
+// report_path is set, a ProcessReport is written there, whether or not processing succeeds.
+pub fn process_video_with_config(
+    mut config: AppConfig,
+    dry_run: bool,
+    reporter: Option<&dyn ProgressReporter>,
+) -> Result<(), ShortsError> {
+    config.video.output_path = final_output_path(&config)?;
+    check_outputs_writable(&config)?;
+    resolve_missing_font(&mut config);
+    if dry_run {
+        return Ok(log_dry_run_plan(&config)?);
+    }
+
+    run_with_report(config, reporter)?;
+    Ok(())
+}
+
+// Runs the pipeline for `config`, whose output_path is already final_output_path, and returns the
+// report of the run, which is also written to report_path when that is set.
+fn run_with_report(mut config: AppConfig, reporter: Option<&dyn ProgressReporter>) -> Result<ProcessReport, ShortsError> {
+    let reporter = Reporter::new(reporter);
+    let mut report = ProcessReport::new(&config);
+    let result = run(&mut config, &reporter, &mut report);
+    report.finish(&config, result.as_ref().err());
+    log_stage_timings(&report);
+    if let Some(report_path) = &config.video.report_path {
+        match (report.write_to_file(report_path), &result) {
+            (Ok(()), _) => info!("Run report written to {}", report_path),
+            (Err(e), Ok(())) => return Err(e),
+            // The processing error is the one to return; the missing report only gets a warning.
+            (Err(e), Err(_)) => warn!("Failed to write run report to {}: {}", report_path, e),
+        }
+    }
+    result?;
+    reporter.done();
+    Ok(report)
+}
+
+// Fails with OutputExists when an output of `config` is already there and overwrite is off, so
+// nothing is trimmed or transcribed for a render that couldn't be written. Auto-split outputs are
+// only known once the input is read; the first one stands in for them here and process_output
+// checks each of them.
+fn check_outputs_writable(config: &AppConfig) -> Result<(), ShortsError> {
+    if config.video.segments.is_empty() && !config.video.auto_split {
+        return check_output_writable(config, &config.video.output_path);
+    }
+    let segment_count = if config.video.auto_split { 1 } else { config.video.segments.len() };
+    for segment_number in 1..=segment_count {
+        check_output_writable(config, &segment_output_path(&config.video.output_path, segment_number)?)?;
+    }
+    Ok(())
+}
+
+fn check_output_writable(config: &AppConfig, output_path: &str) -> Result<(), ShortsError> {
+    if !config.video.overwrite && !video_processing::is_stdio_path(output_path) && Path::new(output_path).exists() {
+        return Err(ShortsError::OutputExists(output_path.to_string()));
+    }
+    Ok(())
+}
+
+// Logs where the time of the run went, slowest stage first, e.g. "Transcribing: 41.2s (68%)".
+fn log_stage_timings(report: &ProcessReport) {
+    let mut totals = report.stage_totals();
+    if totals.is_empty() {
+        return;
+    }
+    totals.sort_by(|a, b| b.secs.total_cmp(&a.secs));
+    info!("Stage timings ({:.1}s in total):", report.elapsed_secs);
+    for total in totals {
+        let share = if report.elapsed_secs > 0.0 { 100.0 * total.secs / report.elapsed_secs } else { 0.0 };
+        info!("  {}: {:.1}s ({:.0}%)", total.stage, total.secs, share);
+    }
+}
+
+// What generate_short produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedShort {
+    pub output_path: PathBuf,
+    pub subtitle_cue_count: usize, // 0 without subtitles
+    pub transcript: Option<String>, // Caption text as in the short, one line per cue; None without subtitles
+}
+
+// One-call entry point for embedding the crate: validates `config`, generates its single short
+// (trim, transcribe, burn and the optional steps, with the temporary files cleaned up) and returns
+// the output path with what was said in it. Segmented and auto-split runs write several outputs,
+// so they are rejected here in favor of process_video_with_config.
+pub fn generate_short(config: &AppConfig) -> Result<GeneratedShort, ShortsError> {
+    generate_short_with_progress(config, None)
+}
+
+// Same as generate_short, telling `reporter` about each step as it runs.
+pub fn generate_short_with_progress(config: &AppConfig, reporter: Option<&dyn ProgressReporter>) -> Result<GeneratedShort, ShortsError> {
+    if !config.video.segments.is_empty() || config.video.auto_split {
+        return Err(ShortsError::InvalidArgument(
+            "generate_short produces a single short; use process_video_with_config for segments or auto_split".to_string(),
+        ));
+    }
+    config
+        .validate()
+        .map_err(|problems| ShortsError::InvalidArgument(format!("Invalid configuration: {}", problems.join("; "))))?;
+    let mut config = config.clone();
+    config.video.output_path = final_output_path(&config)?;
+    check_outputs_writable(&config)?;
+    resolve_missing_font(&mut config);
+    let output_path = PathBuf::from(&config.video.output_path);
+    let mut report = run_with_report(config, reporter)?;
+    let output = report.outputs.pop();
+    Ok(GeneratedShort {
+        output_path,
+        subtitle_cue_count: output.as_ref().and_then(|output| output.subtitle_cue_count).unwrap_or(0),
+        transcript: output.and_then(|output| output.transcript),
+    })
+}
+
+// The output path as written: output_path, or its preview name in preview mode so a quick sample
+// never overwrites a full render.
+fn final_output_path(config: &AppConfig) -> Result<String, ShortsError> {
+    match config.video.preview_secs {
+        Some(_) if !video_processing::is_stdio_path(&config.video.output_path) => preview_output_path(&config.video.output_path),
+        _ => Ok(config.video.output_path.clone()),
+    }
+}
+
+// Derives the output path of a preview, e.g. "out.mp4" -> "out_preview.mp4". Segments of a
+// preview are numbered after it, e.g. "out_preview_01.mp4".
+pub fn preview_output_path(output_path: &str) -> Result<String, ShortsError> {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).ok_or_else(|| {
+        ShortsError::InvalidArgument(format!("Invalid output path (could not get file stem): {}", output_path))
+    })?;
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}_preview.{}", stem, ext),
+        None => format!("{}_preview", stem),
+    };
+    path.with_file_name(file_name)
+        .to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| ShortsError::InvalidArgument("Failed to convert preview output path to string".to_string()))
+}
+
+// Length to cut for a short requested as `duration_secs` long: at most preview_secs in preview mode.
+fn requested_duration_secs(config: &AppConfig, duration_secs: f64) -> f64 {
+    match config.video.preview_secs {
+        Some(preview_secs) => duration_secs.min(preview_secs),
+        None => duration_secs,
+    }
+}
+
+fn run(config: &mut AppConfig, reporter: &Reporter, report: &mut ProcessReport) -> Result<(), Error> {
+    crate::try_init_ffmpeg()?;
+    let (encoder, audio_codec) = config.output_codecs();
+    config.video.encoder = Some(video_processing::resolve_video_encoder(encoder)?);
+    config.video.audio_codec = Some(audio_codec);
+    if config.video.append_input_paths.is_empty() {
+        process_segments(config, reporter, report)
+    } else {
+        join_inputs_and_process_segments(config, reporter, report)
+    }
+}
+
+// Joins input_path and append_input_paths into one video inside a temporary directory and cuts
+// the outputs from that instead. The directory is removed whether or not processing succeeds.
+fn join_inputs_and_process_segments(config: &AppConfig, reporter: &Reporter, report: &mut ProcessReport) -> Result<(), Error> {
+    let temp_dir = TempDirGuard::create(config, join_temp_dir(config)?)?;
+
+    let result = (|| {
+        // Keeping the first input's file name keeps the intermediate names of the later stages unchanged.
+        let joined_path = temp_dir.path().join(joined_input_file_name(config));
+        let joined_path_str = joined_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert joined video path to string"))?;
+        let input_paths: Vec<&str> = std::iter::once(config.video.input_path.as_str())
+            .chain(config.video.append_input_paths.iter().map(|s| s.as_str()))
+            .collect();
+
+        reporter.stage("Joining inputs")?;
+        info!("Joining {} input videos. Output: {}", input_paths.len(), joined_path_str);
+        // Only an intermediate: the outputs are re-encoded from it as their own settings require.
+        let join_settings = video_processing::VideoEncodeSettings {
+            encoder: config.output_codecs().0,
+            crf: config.video.crf,
+            x264_preset: config.video.x264_preset,
+            x264_tune: config.video.x264_tune,
+            bit_rate: None,
+            keep_variable_frame_rate: config.video.keep_variable_frame_rate,
+            ..Default::default()
+        };
+        video_processing::concat_videos(&input_paths, joined_path_str, &join_settings)
+            .context("Failed to join the input videos")?;
+        info!("Input videos joined successfully. Output: {}", joined_path_str);
+
+        let mut joined_config = config.clone();
+        joined_config.video.input_path = joined_path_str.to_string();
+        joined_config.video.append_input_paths.clear();
+        process_segments(&joined_config, reporter, report)
+    })();
+
+    temp_dir.finish(result)
+}
+
+// Temporary directory holding the joined input, e.g. "clips/intro_joining_temp_<unique suffix>".
+fn join_temp_dir(config: &AppConfig) -> Result<PathBuf, Error> {
+    let temp_dir_name = format!("{}_joining_temp_{}", input_file_stem(config), unique_temp_suffix());
+    Ok(temp_root(config, &config.video.output_path)?.join(temp_dir_name))
+}
+
+fn joined_input_file_name(config: &AppConfig) -> String {
+    format!("{}.mp4", input_file_stem(config))
+}
+
+fn process_segments(config: &AppConfig, reporter: &Reporter, report: &mut ProcessReport) -> Result<(), Error> {
+    // Without a known duration nothing is clamped, and trimming past the end yields a shorter clip.
+    let source = if video_processing::is_stdio_path(&config.video.input_path) {
+        // Probing would consume the input the trim has to read.
+        info!("Reading the input from stdin; it is not probed, so requested durations will not be checked against it");
+        None
+    } else {
+        match video_processing::probe_video(&config.video.input_path) {
+            Ok(source) => Some(source),
+            Err(e) => {
+                warn!("Failed to probe '{}': {}; requested durations will not be checked against the source", config.video.input_path, e);
+                None
+            }
+        }
+    };
+    let source_duration = source.as_ref().and_then(|source| source.duration_secs);
+    if source.is_some() && source_duration.is_none() {
+        warn!("Could not determine the duration of '{}'; requested durations will not be checked against the source", config.video.input_path);
+    }
+
+    let captionless_config;
+    let config = if transcription_lacks_audio(config, source.as_ref())? {
+        match config.subtitles.missing_audio_policy {
+            SubtitleMissingAudioPolicy::Error => {
+                return Err(ShortsError::NoAudioStream(config.video.input_path.clone()))
+                    .context("Cannot transcribe subtitles (set missing_audio_policy to skip to produce the shorts without them)");
+            }
+            SubtitleMissingAudioPolicy::Skip => {
+                warn!("{} has no audio stream to transcribe; producing the shorts without subtitles", config.video.input_path);
+                captionless_config = AppConfig {
+                    subtitles: SubtitleConfig { use_subtitles: false, ..config.subtitles.clone() },
+                    ..config.clone()
+                };
+                &captionless_config
+            }
+        }
+    } else {
+        config
+    };
+
+    report.source_duration_secs = source_duration;
+    report.source = source;
+
+    let auto_segments;
+    let segments = if config.video.auto_split {
+        auto_segments = auto_split_source(config, source_duration, reporter)?;
+        &auto_segments
+    } else {
+        &config.video.segments
+    };
+
+    if segments.is_empty() {
+        let output = OutputRequest {
+            start_secs: config.video.start_secs,
+            duration_secs: config.video.short_duration_secs,
+            output_path: &config.video.output_path,
+            segment_number: None,
+        };
+        return process_output(config, &output, source_duration, reporter, report);
+    }
+
+    let segment_count = segments.len();
+    info!("Generating {} segments from: {}", segment_count, config.video.input_path);
+    if config.video.overlap_segments && segment_count > 1 {
+        return process_segments_overlapped(config, segments, source_duration, reporter, report);
+    }
+    for (index, segment) in segments.iter().enumerate() {
+        let segment_number = index + 1;
+        let segment_output_path = segment_output_path(&config.video.output_path, segment_number)?;
+        info!(
+            "Processing segment {}/{} (start {}s, duration {}s). Output: {}",
+            segment_number, segment_count, segment.start_secs, segment.duration_secs, segment_output_path
+        );
+        let output = OutputRequest {
+            start_secs: segment.start_secs,
+            duration_secs: segment.duration_secs,
+            output_path: &segment_output_path,
+            segment_number: Some(segment_number),
+        };
+        process_output(config, &output, source_duration, reporter, report)
+            .with_context(|| format!("Failed to generate segment {} of {}", segment_number, segment_count))?;
+    }
+
+    info!("All {} segments generated successfully.", segment_count);
+    Ok(())
+}
+
+// Like the segment loop of process_segments, but with overlap_segments: a second thread runs
+// prepare_clip (trimming and transcription) for the next segment while this one runs finish_clip
+// (burning and encoding) for the current one. At most one prepared segment waits at a time, so at
+// most two temporary directories exist. Only the finishing stages reach the progress reporter;
+// the report still times both. Stops at the first failing segment, as the sequential loop does.
+// The preparing thread cannot poll the ProgressReporter itself, so cancellation reaches it through
+// `stop`, which this thread sets while it waits for the next segment.
+fn process_segments_overlapped(
+    config: &AppConfig,
+    segments: &[Segment],
+    source_duration: Option<f64>,
+    reporter: &Reporter,
+    report: &mut ProcessReport,
+) -> Result<(), Error> {
+    let segment_count = segments.len();
+    let output_paths = (1..=segment_count)
+        .map(|segment_number| segment_output_path(&config.video.output_path, segment_number))
+        .collect::<Result<Vec<_>, ShortsError>>()?;
+    let input_file_stem = input_file_stem(config);
+    let stop = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        // A rendezvous channel: the next segment is prepared while the current one is finished,
+        // and handed over once the consumer is ready for it.
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<(OutputReport, Result<(f64, TempDirGuard, PreparedClip), Error>)>(0);
+        let (output_paths, stop) = (&output_paths, &stop);
+        scope.spawn(move || {
+            let preparer = Reporter { stop: Some(stop), ..Reporter::new(None) };
+            for (index, segment) in segments.iter().enumerate() {
+                let segment_number = index + 1;
+                let output = OutputRequest {
+                    start_secs: segment.start_secs,
+                    duration_secs: segment.duration_secs,
+                    output_path: &output_paths[index],
+                    segment_number: Some(segment_number),
+                };
+                info!(
+                    "Preparing segment {}/{} (start {}s, duration {}s). Output: {}",
+                    segment_number, segment_count, segment.start_secs, segment.duration_secs, output.output_path
+                );
+                let (duration_secs, mut output_report) = start_output(config, &output, source_duration);
+                let prepared = duration_secs.and_then(|duration_secs| {
+                    let temp_dir = create_output_temp_dir(config, output.output_path, output.segment_number)?;
+                    let clip = prepare_clip(config, output.start_secs, duration_secs, temp_dir.path(), input_file_stem, &preparer)?;
+                    Ok((duration_secs, temp_dir, clip))
+                });
+                preparer.take_output_record(&mut output_report);
+                let failed = prepared.is_err();
+                // When the consumer stopped at a failed segment this one is never finished; dropping
+                // it removes its temporary directory.
+                if sender.send((output_report, prepared)).is_err() {
+                    return;
+                }
+                if failed {
+                    return;
+                }
+            }
+        });
+
+        let finished = (|| {
+            for (index, output_path) in output_paths.iter().enumerate() {
+                let (mut output_report, prepared) = loop {
+                    match receiver.recv_timeout(OVERLAP_CANCEL_POLL_INTERVAL) {
+                        Ok(next) => break next,
+                        Err(RecvTimeoutError::Timeout) if reporter.is_cancelled() => stop.store(true, Ordering::Relaxed),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        // The preparing thread sends every segment or stops at a failure, which ends this
+                        // loop first, so it only hangs up early when it panicked. The scope re-raises that
+                        // panic once this returns; the error only stops the wait for a segment that will
+                        // never come.
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return Err(Error::msg("Segment preparation stopped unexpectedly"));
+                        }
+                    }
+                };
+                let segment_number = index + 1;
+                let result = prepared.and_then(|(duration_secs, temp_dir, clip)| {
+                    info!("Finishing segment {}/{}. Output: {}", segment_number, segment_count, output_path);
+                    let result = finish_clip(config, duration_secs, output_path, temp_dir.path(), input_file_stem, clip, reporter);
+                    temp_dir.finish(result)
+                });
+                reporter.take_output_record(&mut output_report);
+                if result.is_ok() {
+                    output_report.status = ReportStatus::Succeeded;
+                    info!("Video processing completed successfully for: {}", output_path);
+                }
+                report.outputs.push(output_report);
+                result.with_context(|| format!("Failed to generate segment {} of {}", segment_number, segment_count))?;
+            }
+            Ok::<(), Error>(())
+        })();
+        // Stops a preparation still running after a failed segment.
+        stop.store(true, Ordering::Relaxed);
+        finished
+    })?;
+
+    info!("All {} segments generated successfully.", segment_count);
+    Ok(())
+}
+
+// Whether subtitles are to be transcribed from an input without any audio stream. External
+// subtitle files need no audio. The input's streams are read when it could not be probed into
+// `source`; stdin can't be read twice, so it is assumed to have audio, and the audio extraction
+// fails later if it has none.
+fn transcription_lacks_audio(config: &AppConfig, source: Option<&video_processing::VideoInfo>) -> Result<bool, Error> {
+    if !config.subtitles.use_subtitles || config.subtitles.external_subtitle_path.is_some() {
+        return Ok(false);
+    }
+    if source.is_none() && video_processing::is_stdio_path(&config.video.input_path) {
+        return Ok(false);
+    }
+    if let Some(source) = source {
+        return Ok(!source.has_audio);
+    }
+    let audio_streams = video_processing::probe_audio_streams(&config.video.input_path)
+        .with_context(|| format!("Failed to read the streams of '{}'", config.video.input_path))?;
+    Ok(audio_streams.is_empty())
+}
+
+// Splits the whole input into segments of about short_duration_secs at its scene changes.
+fn auto_split_source(config: &AppConfig, source_duration: Option<f64>, reporter: &Reporter) -> Result<Vec<Segment>, Error> {
+    let source_duration = source_duration.ok_or_else(|| {
+        Error::msg(format!("Cannot auto-split '{}': its duration is unknown", config.video.input_path))
+    })?;
+    reporter.stage("Detecting scenes")?;
+    info!("Detecting scene changes in {} (threshold {})", config.video.input_path, config.video.scene_threshold);
+    let scene_starts = video_processing::detect_scenes(&config.video.input_path, config.video.scene_threshold)
+        .with_context(|| format!("Failed to detect scenes in '{}'", config.video.input_path))?;
+    if scene_starts.is_empty() {
+        info!("No scene changes found; splitting into fixed {}s segments", config.video.short_duration_secs);
+        return Ok(plan_segments(source_duration, config.video.short_duration_secs, 0.0, true)?);
+    }
+    info!("Found {} scene changes", scene_starts.len());
+    Ok(auto_split_segments(&scene_starts, source_duration, config.video.short_duration_secs))
+}
+
+// Durations closer than this count as equal when planning segments, so that float rounding
+// neither drops the end of a window nor leaves a sliver of a remainder.
+const SEGMENT_PLAN_TOLERANCE_SECS: f64 = 1e-6;
+
+// Divides a `total_duration` long video into windows of `segment_secs`, each starting
+// `segment_secs - overlap_secs` after the previous one (back to back with no overlap). Windows stop
+// once one reaches the end. What is left after the last full window, shorter than `segment_secs`,
+// becomes a final shorter segment with `keep_remainder` and is dropped otherwise; this includes the
+// whole video when it is shorter than `segment_secs`. Fails with InvalidArgument unless
+// segment_secs is positive and overlap_secs is at least 0 and below it.
+pub fn plan_segments(total_duration: f64, segment_secs: f64, overlap_secs: f64, keep_remainder: bool) -> Result<Vec<Segment>, ShortsError> {
+    if !total_duration.is_finite() {
+        return Err(ShortsError::InvalidArgument(format!("Total duration must be finite, got {}", total_duration)));
+    }
+    if !(segment_secs > 0.0 && segment_secs.is_finite()) {
+        return Err(ShortsError::InvalidArgument(format!("Segment length must be positive, got {}", segment_secs)));
+    }
+    if !(0.0..segment_secs).contains(&overlap_secs) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Segment overlap must be at least 0 and shorter than the segment length ({}s), got {}",
+            segment_secs, overlap_secs
+        )));
+    }
+    let step = segment_secs - overlap_secs;
+    let mut segments = Vec::new();
+    // Starts are multiples of the step rather than a running sum, which would accumulate rounding errors.
+    for index in 0.. {
+        let start_secs = index as f64 * step;
+        let remaining = total_duration - start_secs;
+        if remaining <= SEGMENT_PLAN_TOLERANCE_SECS {
+            break;
+        }
+        if remaining < segment_secs - SEGMENT_PLAN_TOLERANCE_SECS {
+            if keep_remainder {
+                segments.push(Segment { start_secs, duration_secs: remaining });
+            }
+            break;
+        }
+        segments.push(Segment { start_secs, duration_secs: segment_secs });
+        if remaining <= segment_secs + SEGMENT_PLAN_TOLERANCE_SECS {
+            break;
+        }
+    }
+    Ok(segments)
+}
+
+// Splits a `total_duration` long video into consecutive segments of about `target_secs`. Each
+// segment ends at the scene start closest to its ideal end, if one lies within half a segment of
+// it, and at the ideal end otherwise. The last segment runs to the end of the video, however
+// short that leaves it.
+fn auto_split_segments(scene_starts: &[f64], total_duration: f64, target_secs: f64) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut start = 0.0;
+    while total_duration - start > target_secs {
+        let ideal_end = start + target_secs;
+        let earliest = start + target_secs / 2.0;
+        let latest = (ideal_end + target_secs / 2.0).min(total_duration);
+        let end = scene_starts
+            .iter()
+            .copied()
+            .filter(|&t| t > earliest && t < latest)
+            .min_by(|a, b| (a - ideal_end).abs().total_cmp(&(b - ideal_end).abs()))
+            .unwrap_or(ideal_end);
+        segments.push(Segment { start_secs: start, duration_secs: end - start });
+        start = end;
+    }
+    if total_duration > start {
+        segments.push(Segment { start_secs: start, duration_secs: total_duration - start });
+    }
+    segments
+}
+
+// A short to cut from the input: the single output, or one segment.
+struct OutputRequest<'a> {
+    start_secs: f64,
+    duration_secs: f64,
+    output_path: &'a str,
+    segment_number: Option<usize>,
+}
+
+// Clamps `output` to the source and generates it, adding it to `report` whether or not it succeeds.
+fn process_output(
+    config: &AppConfig,
+    output: &OutputRequest,
+    source_duration: Option<f64>,
+    reporter: &Reporter,
+    report: &mut ProcessReport,
+) -> Result<(), Error> {
+    let (duration_secs, mut output_report) = start_output(config, output, source_duration);
+    let result = duration_secs.and_then(|duration_secs| {
+        process_segment(config, output.start_secs, duration_secs, output.output_path, output.segment_number, reporter)
+    });
+    reporter.take_output_record(&mut output_report);
+    if result.is_ok() {
+        output_report.status = ReportStatus::Succeeded;
+    }
+    report.outputs.push(output_report);
+    result
+}
+
+// The duration to cut for `output`, clamped to the source, and its report entry, marked as failed
+// until the output is written.
+fn start_output(config: &AppConfig, output: &OutputRequest, source_duration: Option<f64>) -> (Result<f64, Error>, OutputReport) {
+    let requested_secs = requested_duration_secs(config, output.duration_secs);
+    if requested_secs < output.duration_secs {
+        info!("Preview: rendering only the first {}s of {}", requested_secs, output.output_path);
+    }
+    let duration_secs = match source_duration {
+        Some(source_duration) => clamp_to_source(output.start_secs, requested_secs, source_duration),
+        None => Ok(requested_secs),
+    };
+    let output_report = OutputReport {
+        output_path: output.output_path.to_string(),
+        status: ReportStatus::Failed,
+        start_secs: output.start_secs,
+        duration_secs: *duration_secs.as_ref().unwrap_or(&requested_secs),
+        subtitle_path: None,
+        subtitle_cue_count: None,
+        transcript: None,
+        thumbnail_path: None,
+        packet_write_failures: 0,
+        stages: Vec::new(),
+    };
+    (duration_secs, output_report)
+}
+
+// Returns the duration to trim so that the clip starting at `start_secs` ends within a
+// `source_duration` long source, warning when it had to be shortened. A start at or past the
+// end of the source is an error.
+fn clamp_to_source(start_secs: f64, duration_secs: f64, source_duration: f64) -> Result<f64, Error> {
+    if start_secs >= source_duration {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Start time {}s is past the end of the source video ({:.2}s long)",
+            start_secs, source_duration
+        ))
+        .into());
+    }
+    if start_secs + duration_secs > source_duration {
+        let clamped = source_duration - start_secs;
+        warn!(
+            "Requested {}s from {}s runs past the end of the source video ({:.2}s long); using {:.2}s instead",
+            duration_secs, start_secs, source_duration, clamped
+        );
+        return Ok(clamped);
+    }
+    Ok(duration_secs)
+}
+
+// Derives the output path for a numbered segment, e.g. "out.mp4" -> "out_01.mp4".
+pub fn segment_output_path(output_path: &str, segment_number: usize) -> Result<String, ShortsError> {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).ok_or_else(|| {
+        ShortsError::InvalidArgument(format!("Invalid output path (could not get file stem): {}", output_path))
+    })?;
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}_{:02}.{}", stem, segment_number, ext),
+        None => format!("{}_{:02}", stem, segment_number),
+    };
+    path.with_file_name(file_name)
+        .to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| ShortsError::InvalidArgument("Failed to convert segment output path to string".to_string()))
+}
+
+// Derives the output path for one of the inputs an input_path pattern matched, numbered in match
+// order and named after the input, e.g. "out.mp4" and the 2nd match "clips/intro.mov" -> "out_02_intro.mp4".
+pub fn glob_output_path(output_path: &str, match_number: usize, input_path: &str) -> Result<String, ShortsError> {
+    let input_stem = Path::new(input_path).file_stem().and_then(|s| s.to_str()).ok_or_else(|| {
+        ShortsError::InvalidArgument(format!("Invalid input path (could not get file stem): {}", input_path))
+    })?;
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).ok_or_else(|| {
+        ShortsError::InvalidArgument(format!("Invalid output path (could not get file stem): {}", output_path))
+    })?;
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}_{:02}_{}.{}", stem, match_number, input_stem, ext),
+        None => format!("{}_{:02}_{}", stem, match_number, input_stem),
+    };
+    path.with_file_name(file_name)
+        .to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| ShortsError::InvalidArgument("Failed to convert output path to string".to_string()))
+}
+
+// Records the packets that a stream-copy step could not write to `output_path`. A few are
+// tolerated, but with strict_packet_writes any of them fails the short.
+fn check_packet_writes(
+    config: &AppConfig,
+    reporter: &Reporter,
+    stats: video_processing::PacketWriteStats,
+    output_path: &str,
+) -> Result<(), Error> {
+    reporter.record_packet_write_failures(stats.failed);
+    if stats.failed > 0 {
+        warn!("{} of {} packets could not be written to {}", stats.failed, stats.total, output_path);
+    }
+    stats.check(output_path, config.video.strict_packet_writes)?;
+    Ok(())
+}
+
+// Derives the transcript path for an output, e.g. "out_01.mp4" -> "out_01.txt".
+fn transcript_path(output_path: &str) -> Result<String, Error> {
+    let path = Path::new(output_path);
+    path.file_stem()
+        .ok_or_else(|| Error::msg(format!("Invalid output path (could not get file stem): {}", output_path)))?;
+    path.with_extension("txt")
+        .to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::msg("Failed to convert transcript path to string"))
+}
+
+// Derives the thumbnail path for an output, e.g. "out_01.mp4" -> "out_01_thumb.jpg".
+fn thumbnail_path(output_path: &str) -> Result<String, Error> {
+    let path = Path::new(output_path);
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| Error::msg(format!("Invalid output path (could not get file stem): {}", output_path)))?;
+    path.with_file_name(format!("{}_thumb.jpg", stem))
+        .to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::msg("Failed to convert thumbnail path to string"))
+}
+
+// Base name of the temporary files: the input's file name, or "stdin".
+fn input_file_stem(config: &AppConfig) -> &str {
+    if video_processing::is_stdio_path(&config.video.input_path) {
+        return "stdin";
+    }
+    Path::new(&config.video.input_path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("video")
+}
+
+// Temporary directory for one output, inside temp_root, e.g. "clips/intro_02_processing_temp_<unique suffix>".
+fn segment_temp_dir(config: &AppConfig, output_path: &str, segment_number: Option<usize>) -> Result<PathBuf, Error> {
+    let input_file_stem = input_file_stem(config);
+    let temp_label = match segment_number {
+        Some(number) => format!("{}_{:02}", input_file_stem, number),
+        None => input_file_stem.to_string(),
+    };
+    let temp_dir_name = format!("{}_processing_temp_{}", temp_label, unique_temp_suffix());
+    Ok(temp_root(config, output_path)?.join(temp_dir_name))
+}
+
+// Counts the temporary directory names handed out by this process.
+static TEMP_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// "<millis>_<process id>_<counter>": the time alone repeats when runs start in the same
+// millisecond, in parallel processes or threads of one, and a clashing name would have one run
+// remove the other's directory as a leftover.
+fn unique_temp_suffix() -> String {
+    format!(
+        "{}_{}_{}",
+        chrono::Utc::now().timestamp_millis(),
+        std::process::id(),
+        TEMP_DIR_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+// Directory the temporary directories are created in: temp_dir when set, otherwise the OS temp
+// directory when TMPDIR is set, otherwise the directory of `output_path`.
+fn temp_root(config: &AppConfig, output_path: &str) -> Result<PathBuf, Error> {
+    if let Some(temp_dir) = &config.video.temp_dir {
+        return Ok(PathBuf::from(temp_dir));
+    }
+    if std::env::var_os("TMPDIR").is_some_and(|value| !value.is_empty()) {
+        return Ok(std::env::temp_dir());
+    }
+    Path::new(output_path)
+        .parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| Error::msg(format!("Invalid output path (could not get parent directory): {}", output_path)))
+}
+
+// Temporary directories of the runs in progress that are to be removed afterwards, for
+// remove_live_temp_dirs.
+static LIVE_TEMP_DIRS: std::sync::Mutex<Vec<PathBuf>> = std::sync::Mutex::new(Vec::new());
+
+// A temporary directory that is removed when it goes out of scope (unless keep_temp is set), so
+// that neither a failing step nor a panic leaves it behind. finish() removes it once the work in
+// it is done and reports how that went.
+struct TempDirGuard {
+    path: PathBuf,
+    keep: bool,
+    finished: bool,
+}
+
+impl TempDirGuard {
+    // Creates the (empty) directory at `path`, replacing one left behind under the same name.
+    fn create(config: &AppConfig, path: PathBuf) -> Result<Self, Error> {
+        remove_leftover_temp_dir(&path)?;
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create temp directory: {:?}", path))?;
+        let keep = config.video.keep_temp;
+        if !keep {
+            LIVE_TEMP_DIRS.lock().unwrap_or_else(|e| e.into_inner()).push(path.clone());
+        }
+        Ok(TempDirGuard { path, keep, finished: false })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Removes the directory once the work in it has produced `result`, or only logs where it is
+    // with keep_temp. The pipeline error is the one worth reporting; a failed cleanup on top of it
+    // only gets a warning.
+    fn finish(mut self, result: Result<(), Error>) -> Result<(), Error> {
+        if self.keep {
+            info!("Keeping temporary directory for inspection: {:?}", self.path);
+            return result;
+        }
+        self.finished = true;
+        info!("Cleaning up temporary directory: {:?}", self.path);
+        let cleanup_result = fs::remove_dir_all(&self.path)
+            .with_context(|| format!("Failed to clean up temp directory: {:?}", self.path));
+        if let Err(e) = result {
+            if let Err(cleanup_err) = cleanup_result {
+                warn!("{:?}", cleanup_err);
+            }
+            return Err(e);
+        }
+        cleanup_result?;
+        info!("Temporary directory cleaned up successfully.");
+        Ok(())
+    }
+}
+
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        LIVE_TEMP_DIRS.lock().unwrap_or_else(|e| e.into_inner()).retain(|path| *path != self.path);
+        // finish() was never reached, e.g. after an error or during a panic.
+        if !self.finished && self.path.exists() {
+            info!("Cleaning up temporary directory: {:?}", self.path);
+            if let Err(e) = fs::remove_dir_all(&self.path) {
+                warn!("Failed to clean up temp directory {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+// Removes the temporary directories of the runs still in progress in this process (except with
+// keep_temp), e.g. from a Ctrl-C handler right before exiting, which skips the cleanup at the end
+// of each run. Runs still going afterwards fail when they next touch their files.
+pub fn remove_live_temp_dirs() {
+    let live_temp_dirs = std::mem::take(&mut *LIVE_TEMP_DIRS.lock().unwrap_or_else(|e| e.into_inner()));
+    for temp_dir in live_temp_dirs {
+        if let Err(e) = fs::remove_dir_all(&temp_dir) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to clean up temp directory {:?}: {}", temp_dir, e);
+            }
+        }
+    }
+}
+
+// Removes a temporary directory left behind under the same name, e.g. by a run that crashed.
+fn remove_leftover_temp_dir(temp_dir: &Path) -> Result<(), Error> {
+    if temp_dir.exists() {
+        warn!("Removing leftover temporary directory: {:?}", temp_dir);
+        fs::remove_dir_all(temp_dir)
+            .with_context(|| format!("Failed to clean up existing temp directory: {:?}", temp_dir))?;
+    }
+    Ok(())
+}
+
+// Video encoding settings for a short of `duration_secs`, which the target-size mode needs to
+// turn a file size into a bitrate.
+fn encode_settings(config: &AppConfig, duration_secs: f64) -> Result<video_processing::VideoEncodeSettings, Error> {
+    let audio_bit_rate = config.video.audio_bitrate.as_deref().map(video_processing::parse_bit_rate).transpose()?;
+    let bit_rate = match (&config.video.video_bitrate, config.video.target_size_mb) {
+        (Some(bit_rate), _) => Some(video_processing::parse_bit_rate(bit_rate)?),
+        (None, Some(size_mb)) => Some(video_processing::bit_rate_for_target_size(
+            (size_mb * 1_000_000.0) as u64,
+            duration_secs,
+            audio_bit_rate.unwrap_or(video_processing::DEFAULT_AUDIO_BIT_RATE),
+        )?),
+        (None, None) => None,
+    };
+    let (encoder, audio_codec) = config.output_codecs();
+    Ok(video_processing::VideoEncodeSettings {
+        encoder,
+        crf: config.video.crf,
+        bit_rate,
+        x264_preset: config.video.x264_preset,
+        x264_tune: config.video.x264_tune,
+        audio_codec,
+        audio_bit_rate,
+        two_pass_stats: None,
+        pass: None,
+        keep_variable_frame_rate: config.video.keep_variable_frame_rate,
+    })
+}
+
+// Length of a short cut `duration_secs` from the source, once speed_factor is applied.
+fn output_duration_secs(config: &AppConfig, duration_secs: f64) -> f64 {
+    duration_secs / config.video.speed_factor.unwrap_or(1.0)
+}
+
+fn subtitle_style(config: &AppConfig) -> video_processing::SubtitleStyle<'_> {
+    video_processing::SubtitleStyle {
+        font_name: subtitle_font_name(config),
+        outline_color: &config.subtitles.outline_color,
+        outline_width: config.subtitles.outline_width,
+        shadow_depth: config.subtitles.shadow_depth,
+        box_color: config.subtitles.caption_box.then_some(config.subtitles.caption_box_color.as_str()),
+        box_opacity: config.subtitles.caption_box_opacity,
+        bold: config.subtitles.font_bold,
+        italic: config.subtitles.font_italic,
+        margin_v: config.subtitles.subtitle_margin_v,
+        margin_h: config.subtitles.subtitle_margin_h,
+    }
+}
+
+// The tag overrides of the delivered output: metadata_* over an empty value (i.e. removal) for
+// each tag of `source_tags` that strip_metadata leaves out.
+fn output_metadata_overrides<'a>(config: &'a AppConfig, source_tags: &'a [(String, String)]) -> Vec<(&'a str, &'a str)> {
+    let mut overrides: Vec<(&str, &str)> = source_tags
+        .iter()
+        .filter(|(key, _)| match config.metadata.strip_metadata {
+            MetadataStrip::None => false,
+            MetadataStrip::Location => video_processing::is_location_tag(key),
+            MetadataStrip::All => true,
+        })
+        .map(|(key, _)| (key.as_str(), ""))
+        .collect();
+    overrides.extend(config.metadata.overrides());
+    overrides
+}
+
+// The muxer options of the delivered output: movflags=+faststart for MP4 and MOV outputs with
+// faststart, then extra_output_options, which replace an earlier option with the same key.
+fn output_muxer_options(config: &AppConfig, container: video_processing::OutputContainer) -> Vec<(&str, &str)> {
+    let mut options = Vec::new();
+    if config.video.faststart && matches!(container, video_processing::OutputContainer::Mp4 | video_processing::OutputContainer::Mov) {
+        options.push(("movflags", "+faststart"));
+    }
+    for (key, value) in &config.video.extra_output_options {
+        options.retain(|(existing, _)| existing != key);
+        options.push((key.as_str(), value.as_str()));
+    }
+    options
+}
+
+// font_size, or font_size_percent of the frame height when that is set.
+fn subtitle_font_size(config: &AppConfig) -> u32 {
+    match config.subtitles.font_size_percent {
+        Some(percent) => video_processing::font_size_for_height_percent(percent),
+        None => config.subtitles.font_size,
+    }
+}
+
+// The tracks to burn: the main subtitles at `subtitle_path` and, when configured, the secondary
+// ones prepared at `secondary_path`, which share the main font and style.
+fn subtitle_tracks<'a>(
+    config: &'a AppConfig,
+    subtitle_path: &'a str,
+    secondary_path: Option<&'a str>,
+    style: &'a video_processing::SubtitleStyle<'a>,
+) -> Vec<video_processing::SubtitleTrack<'a>> {
+    let mut tracks = vec![video_processing::SubtitleTrack {
+        subtitle_file_path: subtitle_path,
+        font_path: &config.subtitles.font_path,
+        font_size: subtitle_font_size(config),
+        font_color: &config.subtitles.font_color,
+        vertical_alignment: &config.subtitles.subtitle_position_vertical_alignment,
+        horizontal_alignment: &config.subtitles.subtitle_position_horizontal_alignment,
+        style,
+    }];
+    if let Some(secondary_path) = secondary_path {
+        tracks.push(video_processing::SubtitleTrack {
+            subtitle_file_path: secondary_path,
+            font_path: &config.subtitles.font_path,
+            font_size: config.subtitles.secondary_font_size.unwrap_or_else(|| subtitle_font_size(config)),
+            font_color: config.subtitles.secondary_font_color.as_deref().unwrap_or(&config.subtitles.font_color),
+            vertical_alignment: &config.subtitles.secondary_subtitle_position_vertical_alignment,
+            horizontal_alignment: &config.subtitles.secondary_subtitle_position_horizontal_alignment,
+            style,
+        });
+    }
+    tracks
+}
+
+// Where the secondary subtitles are cut to the clip's window, when secondary_subtitle_path is set.
+fn secondary_subtitle_temp_path(config: &AppConfig, temp_dir: &Path, stem: &str) -> Option<PathBuf> {
+    config.subtitles.secondary_subtitle_path.as_ref().map(|secondary_path| {
+        let extension = Path::new(secondary_path).extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+        temp_dir.join(format!("{}_secondary.{}", stem, extension))
+    })
+}
+
+// The font family to burn the subtitles with instead of font_path: font_name with font_source
+// name, which resolve_missing_font also sets up for the fallback font.
+fn subtitle_font_name(config: &AppConfig) -> Option<&str> {
+    if config.subtitles.resolved_font_source() == FontSource::Name {
+        return config.subtitles.font_name.as_deref();
+    }
+    None
+}
+
+// Switches the subtitles to the fallback font by name when font_path is missing and
+// missing_font_policy allows it, warning once for the run instead of for every output or track.
+fn resolve_missing_font(config: &mut AppConfig) {
+    let subtitles = &mut config.subtitles;
+    if subtitles.use_subtitles
+        && subtitles.resolved_font_source() == FontSource::File
+        && subtitles.missing_font_policy == MissingFontPolicy::Fallback
+        && !Path::new(&subtitles.font_path).is_file()
+    {
+        warn!(
+            "Font file '{}' does not exist; using the default font '{}' instead",
+            subtitles.font_path,
+            video_processing::FALLBACK_FONT_NAME
+        );
+        subtitles.font_source = Some(FontSource::Name);
+        subtitles.font_name = Some(video_processing::FALLBACK_FONT_NAME.to_string());
+    }
+}
+
+// Logs what process_video_with_config would do for `config`: every stage, the intermediate and
+// output paths and the FFmpeg filter strings that can be known without probing the input.
+// Nothing is read, encoded or transcribed.
+fn log_dry_run_plan(config: &AppConfig) -> Result<(), Error> {
+    info!("Dry run: nothing will be encoded or transcribed.");
+    let outputs = if config.video.auto_split {
+        let first_output_path = segment_output_path(&config.video.output_path, 1)?;
+        info!(
+            "Auto split: {} is split into shorts of about {}s at its scene changes (threshold {}), numbered from {}. The split points are only known once the input is read; each short follows this plan:",
+            config.video.input_path,
+            config.video.short_duration_secs,
+            config.video.scene_threshold,
+            first_output_path
+        );
+        vec![(0.0, config.video.short_duration_secs, first_output_path, Some(1))]
+    } else if config.video.segments.is_empty() {
+        vec![(config.video.start_secs, config.video.short_duration_secs, config.video.output_path.clone(), None)]
+    } else {
+        config
+            .video
+            .segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                segment_output_path(&config.video.output_path, index + 1)
+                    .map(|path| (segment.start_secs, segment.duration_secs, path, Some(index + 1)))
+            })
+            .collect::<Result<Vec<_>, ShortsError>>()?
+    };
+
+    if !config.video.append_input_paths.is_empty() {
+        info!(
+            "Join: {} followed by {} into {} (stream-copied when the codecs match, re-encoded otherwise); every output below is cut from the joined video",
+            config.video.input_path,
+            config.video.append_input_paths.join(", "),
+            join_temp_dir(config)?.join(joined_input_file_name(config)).display()
+        );
+    }
+
+    if let Some(preview_secs) = config.video.preview_secs {
+        info!("Preview: each short is cut to at most its first {}s", preview_secs);
+    }
+
+    if config.video.overlap_segments && (config.video.auto_split || outputs.len() > 1) {
+        info!("Overlap: each segment is trimmed and transcribed while the previous one is encoded");
+    }
+
+    let stem = input_file_stem(config);
+    for (start_secs, duration_secs, output_path, segment_number) in outputs {
+        let duration_secs = requested_duration_secs(config, duration_secs);
+        let temp_dir = segment_temp_dir(config, &output_path, segment_number)?;
+        info!("Plan for {}:", output_path);
+        info!(
+            "  Temp directory: {}{}",
+            temp_dir.display(),
+            if config.video.keep_temp { " (kept afterwards)" } else { "" }
+        );
+        info!("  Trim: {} from {}s for {}s ({:?} seek)", config.video.input_path, start_secs, duration_secs, config.video.resolved_seek_mode());
+        if let Some(index) = config.video.audio_stream_index {
+            info!("  Audio stream: {} of the input, the others are dropped", index);
+        }
+        let settings = encode_settings(config, output_duration_secs(config, duration_secs))?;
+        info!(
+            "  Video encoder: {} (crf: {:?}, bitrate: {:?} b/s)",
+            settings.encoder.codec_name(),
+            settings.crf,
+            settings.bit_rate
+        );
+        if settings.encoder == VideoEncoder::X264 {
+            info!("  x264 preset: {}, tune: {}", settings.x264_preset.name(), settings.x264_tune.map_or("none", |tune| tune.name()));
+        }
+        if config.video.two_pass {
+            info!("  Two-pass encoding of the burned subtitles, stats file in the temp directory");
+        }
+        if config.video.keep_variable_frame_rate {
+            info!("  Variable frame rate sources are re-encoded with their frame timing kept");
+        }
+        match settings.audio_codec.codec_name() {
+            Some(codec_name) => info!(
+                "  Audio encoder: {} at {} b/s",
+                codec_name,
+                settings.audio_bit_rate.unwrap_or(video_processing::DEFAULT_AUDIO_BIT_RATE)
+            ),
+            None => info!("  Audio encoder: copy (aac where the audio is filtered)"),
+        }
+        match (&config.video.target_aspect_ratio, config.video.aspect_fit_mode) {
+            (Some(target_aspect), AspectFitMode::Crop) => {
+                info!("  Crop: center crop to {} (crop size depends on the input dimensions)", target_aspect)
+            }
+            (Some(target_aspect), AspectFitMode::Pad) => {
+                info!("  Fit to {}: Pad mode with {} bars", target_aspect, config.video.pad_color)
+            }
+            (Some(target_aspect), mode) => info!("  Fit to {}: {:?} mode", target_aspect, mode),
+            (None, _) => info!("  Crop: none"),
+        }
+        if let Some(filter_string) = video_processing::build_scale_filter(config.video.output_width, config.video.output_height) {
+            info!("  Scale: {}", filter_string);
+        }
+        if config.video.normalize_audio {
+            info!("  Normalize audio: {}", video_processing::build_loudnorm_filter(config.video.target_lufs));
+        } else {
+            info!("  Normalize audio: no");
+        }
+        if config.video.trim_silence {
+            info!(
+                "  Remove silence: pauses below {} dB longer than {}s (cut points depend on the audio)",
+                config.video.silence_threshold_db, config.video.min_silence_secs
+            );
+        }
+        if let Some(factor) = config.video.speed_factor.filter(|factor| *factor != 1.0) {
+            let (video_filter, audio_filter) = video_processing::build_speed_filters(factor);
+            info!(
+                "  Change speed: {}x, {}s long afterwards (video {}; audio {})",
+                factor,
+                output_duration_secs(config, duration_secs),
+                video_filter,
+                audio_filter
+            );
+        }
+        if config.subtitles.use_subtitles {
+            let audio_path = temp_dir.join(format!("{}_extracted_audio.wav", stem));
+            let subtitle_path = match &config.subtitles.external_subtitle_path {
+                Some(external_path) => temp_dir.join(format!(
+                    "{}_external.{}",
+                    stem,
+                    Path::new(external_path).extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase()
+                )),
+                None => audio_path.with_extension(config.subtitles.subtitle_format.extension()),
+            };
+            let subtitle_path_str = subtitle_path.to_str()
+                .ok_or_else(|| Error::msg("Failed to convert subtitle path to string"))?;
+            if let Some(external_path) = &config.subtitles.external_subtitle_path {
+                info!(
+                    "  External subtitles: {} cut to {}s..{}s, writing {}",
+                    external_path, start_secs, start_secs + duration_secs, subtitle_path_str
+                );
+            } else {
+                info!("  Extract audio: {}", audio_path.display());
+                if config.subtitles.whisper_backend == WhisperBackend::Native
+                    && !Path::new(&config.subtitles.whisper_model_path).is_file()
+                {
+                    info!(
+                        "  Whisper model: {} from the model cache{}",
+                        config.subtitles.whisper_model_path,
+                        if config.subtitles.whisper_offline { "" } else { ", downloaded first when missing" }
+                    );
+                }
+                info!(
+                    "  Transcribe: {:?} backend, model {}, language {}{}, writing {}",
+                    config.subtitles.whisper_backend,
+                    config.subtitles.whisper_model_path,
+                    config.subtitles.language.as_deref().unwrap_or("auto-detected"),
+                    if config.subtitles.translate_to_english { ", translated to English" } else { "" },
+                    subtitle_path_str
+                );
+            }
+            if config.subtitles.subtitle_offset_secs != 0.0 {
+                info!("  Shift subtitles: {}s", config.subtitles.subtitle_offset_secs);
+            }
+            if let Some((window_start_secs, window_end_secs)) = config.subtitles.caption_window() {
+                info!(
+                    "  Caption window: {}s..{}",
+                    window_start_secs,
+                    window_end_secs.map_or("the end".to_string(), |end_secs| format!("{}s", end_secs))
+                );
+            }
+            if let Some(max_secs) = config.subtitles.max_cue_duration_secs {
+                info!("  Cue duration: at most {}s", max_secs);
+            }
+            if let Some(max_chars) = config.subtitles.max_line_chars {
+                info!("  Wrap subtitle lines: at most {} characters", max_chars);
+            }
+            if config.subtitles.caption_text_transform != CaptionTextTransform::None {
+                info!("  Caption text transform: {:?}", config.subtitles.caption_text_transform);
+            }
+            if config.subtitles.subtitle_mode != SubtitleMode::Burn {
+                info!("  Soft subtitles: muxed into {} as a subtitle track", output_path);
+            }
+            if config.subtitles.subtitle_mode != SubtitleMode::SoftMux {
+                let secondary_path = secondary_subtitle_temp_path(config, &temp_dir, stem);
+                let secondary_path_str = secondary_path.as_deref().map(|path| {
+                    path.to_str().ok_or_else(|| Error::msg("Failed to convert secondary subtitle path to string"))
+                }).transpose()?;
+                if let (Some(secondary_source), Some(secondary_path_str)) = (&config.subtitles.secondary_subtitle_path, secondary_path_str) {
+                    info!(
+                        "  Secondary subtitles: {} cut to {}s..{}s, writing {}",
+                        secondary_source, start_secs, start_secs + duration_secs, secondary_path_str
+                    );
+                }
+                let style = subtitle_style(config);
+                let filter_string = video_processing::build_subtitle_tracks_filter(
+                    &subtitle_tracks(config, subtitle_path_str, secondary_path_str, &style),
+                    None,
+                )?;
+                info!("  Burn subtitles: {}", filter_string);
+            }
+            if config.subtitles.subtitle_mode != SubtitleMode::SoftMux
+                && (config.subtitles.subtitle_margin_v > 0 || config.subtitles.subtitle_margin_h > 0)
+            {
+                info!(
+                    "  Subtitle margins: {}px vertical, {}px horizontal (scaled to the video size when burning)",
+                    config.subtitles.subtitle_margin_v,
+                    config.subtitles.subtitle_margin_h
+                );
+            }
+        } else {
+            info!("  Subtitles: disabled");
+        }
+        if let Some(watermark_path) = &config.watermark.watermark_path {
+            info!(
+                "  Watermark: {} at {}/{} with a {}px margin (overlay size depends on the image and video dimensions)",
+                watermark_path,
+                config.watermark.watermark_position_vertical_alignment,
+                config.watermark.watermark_position_horizontal_alignment,
+                config.watermark.watermark_margin
+            );
+        }
+        if let Some(music_path) = &config.video.bg_music_path {
+            info!(
+                "  Background music: {}",
+                video_processing::build_music_mix_filter(music_path, config.video.bg_music_volume, config.video.bg_music_duck)
+            );
+        }
+        let (video_fades, audio_fades) =
+            video_processing::build_fade_filters(config.video.fade_in_secs, config.video.fade_out_secs, output_duration_secs(config, duration_secs));
+        if let Some(video_fades) = video_fades {
+            info!(
+                "  Fades (assuming the clip is the full {}s): {}; audio: {}",
+                output_duration_secs(config, duration_secs),
+                video_fades,
+                audio_fades.unwrap_or_default()
+            );
+        }
+        match &config.video.output_format {
+            Some(format_name) => info!("  Output: {} (remuxed as {})", output_path, format_name),
+            None => info!("  Output: {}", output_path),
+        }
+        let container = video_processing::OutputContainer::detect(&output_path, config.video.output_format.as_deref());
+        if container == video_processing::OutputContainer::WebM {
+            info!("  Encoding: {} and {} for WebM, when no earlier step re-encoded the clip", settings.encoder.codec_name(), settings.audio_codec.codec_name().unwrap_or("copy"));
+        }
+        for (tag, value) in config.metadata.overrides() {
+            if value.is_empty() {
+                info!("  Metadata: remove {}", tag);
+            } else {
+                info!("  Metadata: {} = {:?}", tag, value);
+            }
+        }
+        if config.video.generate_chapters {
+            info!(
+                "  Chapters: one per scene change of the short (threshold {}), detected once it is encoded",
+                config.video.scene_threshold
+            );
+        }
+        if config.subtitles.write_transcript {
+            info!("  Transcript: caption text written to {}", transcript_path(&output_path)?);
+        }
+        if let Some(at_secs) = config.video.thumbnail_at_secs {
+            info!("  Thumbnail: frame at {}s, written to {}", at_secs, thumbnail_path(&output_path)?);
+        }
+    }
+    Ok(())
+}
+
+// Produces a single output inside its own temporary directory. The directory is removed
+// whether or not the pipeline succeeds, so a failing segment never leaves intermediates behind.
+fn process_segment(
+    config: &AppConfig,
+    start_secs: f64,
+    duration_secs: f64,
+    output_path: &str,
+    segment_number: Option<usize>,
+    reporter: &Reporter,
+) -> Result<(), Error> {
+    info!("Starting video processing for: {}", output_path);
+    let input_file_stem = input_file_stem(config);
+    let temp_dir = create_output_temp_dir(config, output_path, segment_number)?;
+
+    let result = run_pipeline(config, start_secs, duration_secs, output_path, temp_dir.path(), input_file_stem, reporter);
+
+    temp_dir.finish(result)?;
+    
+    info!("Video processing completed successfully for: {}", output_path);
+    Ok(())
+}
+
+// Creates the (empty) temporary directory for one output, once it is known the output may be
+// written.
+fn create_output_temp_dir(config: &AppConfig, output_path: &str, segment_number: Option<usize>) -> Result<TempDirGuard, Error> {
+    check_output_writable(config, output_path)?;
+    let temp_dir = TempDirGuard::create(config, segment_temp_dir(config, output_path, segment_number)?)?;
+    info!("Temporary processing directory created at: {:?}", temp_dir.path());
+    Ok(temp_dir)
+}
+
+// Runs every step of one output: prepare_clip, then finish_clip.
+fn run_pipeline(
+    config: &AppConfig,
+    start_secs: f64,
+    duration_secs: f64,
+    output_path: &str,
+    temp_dir: &Path,
+    input_file_stem: &str,
+    reporter: &Reporter,
+) -> Result<(), Error> {
+    let clip = prepare_clip(config, start_secs, duration_secs, temp_dir, input_file_stem, reporter)?;
+    finish_clip(config, duration_secs, output_path, temp_dir, input_file_stem, clip, reporter)
+}
+
+// A clip cut and processed up to its subtitles, ready for finish_clip.
+struct PreparedClip {
+    working_video_path: PathBuf,
+    subtitle_path: Option<String>, // The generated (or cut external) subtitles, when enabled
+    secondary_subtitle_path: Option<String>, // secondary_subtitle_path cut to the clip
+}
+
+// The steps up to and including the subtitle file: trimming, the video and audio steps that
+// change what is transcribed, and the transcription. Writes only inside `temp_dir`.
+fn prepare_clip(
+    config: &AppConfig,
+    start_secs: f64,
+    duration_secs: f64,
+    temp_dir: &Path,
+    input_file_stem: &str,
+    reporter: &Reporter,
+) -> Result<PreparedClip, Error> {
+    let encode_settings = encode_settings(config, output_duration_secs(config, duration_secs))?;
+    let reencodes_video = config.video.target_aspect_ratio.is_some()
+        || config.video.output_width.is_some()
+        || config.video.output_height.is_some()
+        || config.subtitles.use_subtitles
+        || config.watermark.watermark_path.is_some()
+        || config.video.fade_in_secs > 0.0
+        || config.video.fade_out_secs > 0.0;
+    if !reencodes_video && (encode_settings.crf.is_some() || encode_settings.bit_rate.is_some()) {
+        warn!("No enabled step re-encodes the video, so the crf/bitrate settings have no effect.");
+    } else if let Some(bit_rate) = encode_settings.bit_rate {
+        info!("Re-encoding video at {} kb/s", bit_rate / 1000);
+    }
+
+    // Trim Video
+    let trimmed_video_filename = format!("{}_trimmed.mp4", input_file_stem);
+    let trimmed_video_path = temp_dir.join(&trimmed_video_filename);
+    let trimmed_video_path_str = trimmed_video_path.to_str()
+        .ok_or_else(|| Error::msg("Failed to convert trimmed video path to string"))?;
+
+    reporter.stage("Trimming")?;
+    info!("Trimming video: {} from {}s for {}s. Output: {}", config.video.input_path, start_secs, duration_secs, trimmed_video_path_str);
+    let write_stats = video_processing::trim_video_with_progress(
+        &config.video.input_path,
+        trimmed_video_path_str,
+        start_secs,
+        duration_secs,
+        config.video.audio_stream_index,
+        config.video.resolved_seek_mode(),
+        reporter.callback(),
+    )
+    .with_context(|| format!("Failed to trim video from '{}'", config.video.input_path))?;
+    check_packet_writes(config, reporter, write_stats, trimmed_video_path_str)
+        .with_context(|| format!("Failed to trim video from '{}'", config.video.input_path))?;
+    info!("Video trimmed successfully. Output: {}", trimmed_video_path_str);
+
+    // Crop to the target aspect ratio (e.g., vertical 9:16) if requested
+    let working_video_path = if let Some(target_aspect) = &config.video.target_aspect_ratio {
+        let cropped_video_filename = format!("{}_cropped.mp4", input_file_stem);
+        let cropped_video_path = temp_dir.join(&cropped_video_filename);
+        let cropped_video_path_str = cropped_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert cropped video path to string"))?;
+
+        reporter.stage("Cropping")?;
+        info!(
+            "Fitting video to aspect ratio {} ({:?} mode). Output: {}",
+            target_aspect, config.video.aspect_fit_mode, cropped_video_path_str
+        );
+        video_processing::fit_to_aspect(
+            trimmed_video_path_str,
+            cropped_video_path_str,
+            target_aspect,
+            config.video.aspect_fit_mode,
+            &config.video.pad_color,
+            &encode_settings,
+        )
+        .with_context(|| format!("Failed to fit video to aspect ratio '{}'", target_aspect))?;
+        info!("Video cropped successfully. Output: {}", cropped_video_path_str);
+        cropped_video_path
+    } else {
+        trimmed_video_path.clone()
+    };
+
+    // Scale to the requested output size before subtitles and watermarks are drawn, so their
+    // sizes are relative to the final frame
+    let working_video_path = if config.video.output_width.is_some() || config.video.output_height.is_some() {
+        let scaled_video_filename = format!("{}_scaled.mp4", input_file_stem);
+        let scaled_video_path = temp_dir.join(&scaled_video_filename);
+        let scaled_video_path_str = scaled_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert scaled video path to string"))?;
+        let input_video_path_str = working_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+        reporter.stage("Scaling")?;
+        info!("Scaling video to {:?}x{:?}. Output: {}", config.video.output_width, config.video.output_height, scaled_video_path_str);
+        video_processing::scale_video(
+            input_video_path_str,
+            scaled_video_path_str,
+            config.video.output_width,
+            config.video.output_height,
+            &encode_settings,
+        )
+        .with_context(|| format!("Failed to scale video '{}'", input_video_path_str))?;
+        info!("Video scaled successfully. Output: {}", scaled_video_path_str);
+        scaled_video_path
+    } else {
+        working_video_path
+    };
+
+    // Normalize the audio loudness if requested, before subtitles are transcribed from it
+    let working_video_path = if config.video.normalize_audio {
+        let normalized_video_filename = format!("{}_normalized.mp4", input_file_stem);
+        let normalized_video_path = temp_dir.join(&normalized_video_filename);
+        let normalized_video_path_str = normalized_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert normalized video path to string"))?;
+        let input_video_path_str = working_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+        reporter.stage("Normalizing audio")?;
+        info!("Normalizing audio loudness to {} LUFS. Output: {}", config.video.target_lufs, normalized_video_path_str);
+        video_processing::normalize_audio_loudness(input_video_path_str, normalized_video_path_str, config.video.target_lufs, &encode_settings)
+            .with_context(|| format!("Failed to normalize audio loudness of '{}'", input_video_path_str))?;
+        info!("Audio normalized successfully. Output: {}", normalized_video_path_str);
+        normalized_video_path
+    } else {
+        working_video_path
+    };
+
+    // Cut out dead air before transcribing, so the subtitles follow the tightened timeline
+    let working_video_path = if config.video.trim_silence {
+        let desilenced_video_filename = format!("{}_desilenced.mp4", input_file_stem);
+        let desilenced_video_path = temp_dir.join(&desilenced_video_filename);
+        let desilenced_video_path_str = desilenced_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert desilenced video path to string"))?;
+        let input_video_path_str = working_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+        reporter.stage("Removing silence")?;
+        info!(
+            "Removing silences below {} dB longer than {}s. Output: {}",
+            config.video.silence_threshold_db, config.video.min_silence_secs, desilenced_video_path_str
+        );
+        match video_processing::remove_silence(
+            input_video_path_str,
+            desilenced_video_path_str,
+            config.video.silence_threshold_db,
+            config.video.min_silence_secs,
+            &encode_settings,
+        ) {
+            Ok(()) => {
+                info!("Silence removed successfully. Output: {}", desilenced_video_path_str);
+                desilenced_video_path
+            }
+            Err(ShortsError::NoAudioStream(_)) => {
+                warn!("'{}' has no audio stream; skipping silence removal.", input_video_path_str);
+                working_video_path
+            }
+            Err(e) => {
+                return Err(Error::from(e).context(format!("Failed to remove silence from '{}'", input_video_path_str)))
+            }
+        }
+    } else {
+        working_video_path
+    };
+
+    // Change the speed before the audio is extracted and transcribed, so the subtitle timings
+    // match the sped-up video. Retiming after subtitle generation would desync the captions.
+    let working_video_path = match config.video.speed_factor {
+        Some(factor) if factor != 1.0 => {
+            let speed_video_filename = format!("{}_speed.mp4", input_file_stem);
+            let speed_video_path = temp_dir.join(&speed_video_filename);
+            let speed_video_path_str = speed_video_path.to_str()
+                .ok_or_else(|| Error::msg("Failed to convert speed-changed video path to string"))?;
+            let input_video_path_str = working_video_path.to_str()
+                .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+            reporter.stage("Changing speed")?;
+            info!("Changing the speed by a factor of {}. Output: {}", factor, speed_video_path_str);
+            video_processing::change_speed(input_video_path_str, speed_video_path_str, factor, &encode_settings)
+                .with_context(|| format!("Failed to change the speed of '{}'", input_video_path_str))?;
+            info!("Speed changed successfully. Output: {}", speed_video_path_str);
+            speed_video_path
+        }
+        _ => working_video_path,
+    };
+    let working_video_path_str = working_video_path.to_str()
+        .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+    let (subtitle_path, secondary_subtitle_path) = if config.subtitles.use_subtitles {
+        info!("Subtitle generation enabled.");
+        let (subtitle_file_path_str, is_ass) = if let Some(external_path) = &config.subtitles.external_subtitle_path {
+            // The external file is timed against the source video, so cut it to this clip's window
+            reporter.stage("Preparing subtitles")?;
+            let extension = Path::new(external_path)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_ascii_lowercase())
+                .unwrap_or_default();
+            let subtitle_path = temp_dir.join(format!("{}_external.{}", input_file_stem, extension));
+            let subtitle_file_path_str = subtitle_path.to_str()
+                .ok_or_else(|| Error::msg("Failed to convert subtitle path to string"))?
+                .to_string();
+            info!("Using external subtitles {} from {}s for {}s. Output: {}", external_path, start_secs, duration_secs, subtitle_file_path_str);
+            let cue_count = subtitle_generation::cut_subtitles_to_window(external_path, &subtitle_file_path_str, start_secs, duration_secs)
+                .with_context(|| format!("Failed to prepare external subtitle file '{}'", external_path))?;
+            info!("External subtitles prepared ({} cues). Output: {}", cue_count, subtitle_file_path_str);
+            reporter.record_subtitles(&subtitle_file_path_str, cue_count);
+            (subtitle_file_path_str, extension == "ass" || extension == "ssa")
+        } else {
+            // Extract Audio
+            let audio_filename = format!("{}_extracted_audio.wav", input_file_stem);
+            let audio_path = temp_dir.join(&audio_filename);
+            let audio_path_str = audio_path.to_str()
+                .ok_or_else(|| Error::msg("Failed to convert audio path to string"))?;
+
+            reporter.stage("Extracting audio")?;
+            info!("Extracting audio from: {}. Output: {}", working_video_path_str, audio_path_str);
+            // The trimmed video only kept the selected audio stream, so the best one is it.
+            let write_stats = video_processing::extract_audio(working_video_path_str, audio_path_str, None)
+                .with_context(|| format!("Failed to extract audio from '{}'", working_video_path_str))?;
+            check_packet_writes(config, reporter, write_stats, audio_path_str)
+                .with_context(|| format!("Failed to extract audio from '{}'", working_video_path_str))?;
+            info!("Audio extracted successfully. Output: {}", audio_path_str);
+
+            // Generate Subtitle File
+            reporter.stage("Transcribing")?;
+            // The native backend needs a ggml file; a model name is downloaded into the cache.
+            let whisper_model_path = match config.subtitles.whisper_backend {
+                WhisperBackend::Native => whisper_models::resolve_whisper_model(
+                    &config.subtitles.whisper_model_path,
+                    config.subtitles.whisper_model_cache_dir.as_deref().map(Path::new),
+                    config.subtitles.whisper_offline,
+                )
+                .with_context(|| format!("Failed to get whisper model '{}'", config.subtitles.whisper_model_path))?
+                .to_str()
+                .ok_or_else(|| Error::msg("Failed to convert whisper model path to string"))?
+                .to_string(),
+                WhisperBackend::Cli => config.subtitles.whisper_model_path.clone(),
+            };
+            info!(
+                "Generating subtitles for: {}. Model: {}. Backend: {:?}. Language: {}. Translate to English: {}",
+                audio_path_str,
+                whisper_model_path,
+                config.subtitles.whisper_backend,
+                config.subtitles.language.as_deref().unwrap_or("auto-detect"),
+                config.subtitles.translate_to_english
+            );
+            let whisper_options = subtitle_generation::WhisperOptions {
+                backend: config.subtitles.whisper_backend,
+                binary_path: config.subtitles.whisper_binary_path.as_deref(),
+                language: config.subtitles.language.as_deref(),
+                translate_to_english: config.subtitles.translate_to_english,
+                max_attempts: config.subtitles.whisper_max_attempts,
+                device: config.subtitles.whisper_device.as_deref(),
+                fp16: config.subtitles.whisper_fp16,
+                is_cancelled: Some(&|| reporter.is_cancelled()),
+            };
+            let (subtitle_file_path_str, cues) = subtitle_generation::generate_subtitle_file_with_cues(
+                audio_path_str,
+                &whisper_model_path,
+                temp_dir.to_str().ok_or_else(|| Error::msg("Failed to convert temp_dir to string for subtitle generation"))?,
+                &whisper_options,
+                config.subtitles.subtitle_format,
+            )
+            .with_context(|| "Failed to generate subtitle file")?;
+            info!("Subtitles generated successfully ({} cues). Output: {}", cues.len(), subtitle_file_path_str);
+            reporter.record_subtitles(&subtitle_file_path_str, cues.len());
+            (subtitle_file_path_str, config.subtitles.subtitle_format == SubtitleFormat::Ass)
+        };
+
+        if config.subtitles.subtitle_offset_secs != 0.0 {
+            info!("Shifting the subtitles by {}s.", config.subtitles.subtitle_offset_secs);
+            let cue_count = subtitle_generation::shift_srt_timing(&subtitle_file_path_str, config.subtitles.subtitle_offset_secs)
+                .with_context(|| format!("Failed to shift the subtitles in '{}'", subtitle_file_path_str))?;
+            reporter.record_subtitles(&subtitle_file_path_str, cue_count);
+        }
+
+        if let Some((window_start_secs, window_end_secs)) = config.subtitles.caption_window() {
+            info!(
+                "Limiting the subtitles to {}s..{}.",
+                window_start_secs,
+                window_end_secs.map_or("the end".to_string(), |end_secs| format!("{}s", end_secs))
+            );
+            let cue_count = subtitle_generation::restrict_subtitles_to_window(&subtitle_file_path_str, window_start_secs, window_end_secs)
+                .with_context(|| format!("Failed to limit the subtitles in '{}' to the caption window", subtitle_file_path_str))?;
+            reporter.record_subtitles(&subtitle_file_path_str, cue_count);
+        }
+
+        if let Some(max_secs) = config.subtitles.max_cue_duration_secs {
+            let shortened = subtitle_generation::limit_cue_durations(&subtitle_file_path_str, max_secs)
+                .with_context(|| format!("Failed to limit the cue durations in '{}'", subtitle_file_path_str))?;
+            info!("Ended {} subtitle cues longer than {}s early.", shortened, max_secs);
+        }
+
+        if let Some(max_chars) = config.subtitles.max_line_chars {
+            if is_ass {
+                warn!("max_line_chars only applies to SRT and VTT subtitles; leaving the ASS file as generated.");
+            } else {
+                info!("Wrapping subtitle lines to at most {} characters.", max_chars);
+                subtitle_generation::wrap_srt_lines(&subtitle_file_path_str, max_chars)
+                    .with_context(|| format!("Failed to wrap subtitle lines in '{}'", subtitle_file_path_str))?;
+            }
+        }
+
+        if config.subtitles.caption_text_transform != CaptionTextTransform::None {
+            if is_ass {
+                warn!("caption_text_transform only applies to SRT and VTT subtitles; leaving the ASS file as generated.");
+            } else {
+                info!("Applying the {:?} text transform to the subtitles.", config.subtitles.caption_text_transform);
+                subtitle_generation::transform_srt_text(&subtitle_file_path_str, config.subtitles.caption_text_transform)
+                    .with_context(|| format!("Failed to transform subtitle text in '{}'", subtitle_file_path_str))?;
+            }
+        }
+
+        if config.subtitles.caption_animation != CaptionAnimation::None {
+            if is_ass {
+                info!("Animating the captions with {:?}.", config.subtitles.caption_animation);
+                subtitle_generation::animate_ass_cues(&subtitle_file_path_str, config.subtitles.caption_animation)
+                    .with_context(|| format!("Failed to animate the captions in '{}'", subtitle_file_path_str))?;
+            } else {
+                warn!("caption_animation only applies to ASS subtitles (subtitle_format ass); leaving the captions unanimated.");
+            }
+        }
+
+        if config.subtitles.caption_box && is_ass {
+            warn!("caption_box only applies to SRT and VTT subtitles; the ASS file keeps its own styles.");
+        }
+
+        if config.subtitles.vtt_cue_positioning && subtitle_file_kind(&subtitle_file_path_str) == Some(SubtitleFormat::Vtt) {
+            info!("Adding cue positions to the VTT subtitles.");
+            subtitle_generation::apply_vtt_positioning(
+                &subtitle_file_path_str,
+                &config.subtitles.subtitle_position_vertical_alignment,
+                &config.subtitles.subtitle_position_horizontal_alignment,
+            )
+            .with_context(|| format!("Failed to position the cues in '{}'", subtitle_file_path_str))?;
+        }
+
+        reporter.record_transcript(
+            subtitle_generation::transcript_text(Path::new(&subtitle_file_path_str))
+                .with_context(|| format!("Failed to read the transcript from '{}'", subtitle_file_path_str))?,
+        );
+
+        // The secondary file is timed against the source video too, like an external one.
+        let secondary_path = secondary_subtitle_temp_path(config, temp_dir, input_file_stem);
+        let secondary_path_str = match (&config.subtitles.secondary_subtitle_path, &secondary_path) {
+            (Some(secondary_source), Some(secondary_path)) => {
+                let secondary_path_str = secondary_path.to_str()
+                    .ok_or_else(|| Error::msg("Failed to convert secondary subtitle path to string"))?;
+                info!("Using secondary subtitles {} from {}s for {}s. Output: {}", secondary_source, start_secs, duration_secs, secondary_path_str);
+                let cue_count = subtitle_generation::cut_subtitles_to_window(secondary_source, secondary_path_str, start_secs, duration_secs)
+                    .with_context(|| format!("Failed to prepare secondary subtitle file '{}'", secondary_source))?;
+                info!("Secondary subtitles prepared ({} cues). Output: {}", cue_count, secondary_path_str);
+                Some(secondary_path_str.to_string())
+            }
+            _ => None,
+        };
+        (Some(subtitle_file_path_str), secondary_path_str)
+    } else {
+        info!("Subtitle generation disabled.");
+        (None, None)
+    };
+
+    Ok(PreparedClip { working_video_path, subtitle_path, secondary_subtitle_path })
+}
+
+// The steps after the subtitle file: burning (or keeping) the subtitles, watermark, music, fades
+// and writing the output with its transcript and thumbnail.
+fn finish_clip(
+    config: &AppConfig,
+    duration_secs: f64,
+    output_path: &str,
+    temp_dir: &Path,
+    input_file_stem: &str,
+    clip: PreparedClip,
+    reporter: &Reporter,
+) -> Result<(), Error> {
+    let encode_settings = encode_settings(config, output_duration_secs(config, duration_secs))?;
+    let PreparedClip { working_video_path, subtitle_path, secondary_subtitle_path } = clip;
+    let working_video_path_str = working_video_path.to_str()
+        .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+    // Read from the subtitles as prepared, so it matches the captions after wrapping and case changes.
+    let transcript = match &subtitle_path {
+        Some(subtitle_path) if config.subtitles.write_transcript => Some(
+            subtitle_generation::transcript_text(Path::new(subtitle_path))
+                .with_context(|| format!("Failed to read the transcript from '{}'", subtitle_path))?,
+        ),
+        _ => None,
+    };
+
+    // A short for stdout is written into temp_dir first, where every muxer can seek (MP4 writes
+    // its index last), and copied to stdout at the end.
+    let writes_stdout = video_processing::is_stdio_path(output_path);
+    let stdout_staging_path = match config.video.output_format {
+        Some(_) => temp_dir.join(format!("{}_stdout", input_file_stem)),
+        None => temp_dir.join(format!("{}_stdout.mp4", input_file_stem)),
+    };
+    let final_output_path_str = if writes_stdout {
+        stdout_staging_path.to_str().ok_or_else(|| Error::msg("Failed to convert stdout staging path to string"))?
+    } else {
+        output_path
+    };
+
+    let (working_video_path, soft_subtitle_path) = if let Some(subtitle_file_path_str) = subtitle_path {
+        // Soft subtitles are muxed in when the output is written, after the later video steps.
+        let soft_subtitle_path = (config.subtitles.subtitle_mode != SubtitleMode::Burn).then(|| subtitle_file_path_str.clone());
+        if config.subtitles.subtitle_mode == SubtitleMode::SoftMux {
+            info!("Keeping the subtitles as a soft track instead of burning them.");
+            (working_video_path, soft_subtitle_path)
+        } else {
+            // Burn Subtitles
+            let subtitled_video_filename = format!("{}_subtitled.mp4", input_file_stem);
+            let subtitled_video_path = temp_dir.join(&subtitled_video_filename);
+            let subtitled_video_path_str = subtitled_video_path.to_str()
+                .ok_or_else(|| Error::msg("Failed to convert subtitled video path to string"))?;
+
+            let secondary_path_str = secondary_subtitle_path.as_deref();
+
+            // The two-pass stats file is only needed while burning, so it stays in the temp dir.
+            let burn_settings = video_processing::VideoEncodeSettings {
+                two_pass_stats: config.video.two_pass.then(|| temp_dir.join(format!("{}_x264_2pass.log", input_file_stem))),
+                ..encode_settings.clone()
+            };
+
+            reporter.stage("Burning subtitles")?;
+            info!("Burning subtitles from {} into video. Output: {}", subtitle_file_path_str, subtitled_video_path_str);
+            let style = subtitle_style(config);
+            let write_stats = video_processing::burn_subtitle_tracks_with_progress(
+                working_video_path_str,
+                &subtitle_tracks(config, &subtitle_file_path_str, secondary_path_str, &style),
+                subtitled_video_path_str,
+                &burn_settings,
+                reporter.callback(),
+            )
+            .with_context(|| format!("Failed to burn subtitles onto '{}'", working_video_path_str))?;
+            check_packet_writes(config, reporter, write_stats, subtitled_video_path_str)
+                .with_context(|| format!("Failed to burn subtitles onto '{}'", working_video_path_str))?;
+            info!("Subtitles burned successfully.");
+            (subtitled_video_path, soft_subtitle_path)
+        }
+    } else {
+        (working_video_path, None)
+    };
+
+    // Overlay the watermark/logo if one is configured
+    let working_video_path = if let Some(watermark_path) = &config.watermark.watermark_path {
+        let watermarked_video_filename = format!("{}_watermarked.mp4", input_file_stem);
+        let watermarked_video_path = temp_dir.join(&watermarked_video_filename);
+        let watermarked_video_path_str = watermarked_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert watermarked video path to string"))?;
+        let input_video_path_str = working_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+        reporter.stage("Adding watermark")?;
+        info!("Overlaying watermark {}. Output: {}", watermark_path, watermarked_video_path_str);
+        let watermark_options = video_processing::WatermarkOptions {
+            image_path: watermark_path,
+            vertical_alignment: &config.watermark.watermark_position_vertical_alignment,
+            horizontal_alignment: &config.watermark.watermark_position_horizontal_alignment,
+            margin_px: config.watermark.watermark_margin,
+            scale_percent: config.watermark.watermark_scale_percent,
+        };
+        video_processing::overlay_watermark(input_video_path_str, watermarked_video_path_str, &watermark_options, &encode_settings)
+            .with_context(|| format!("Failed to overlay watermark '{}'", watermark_path))?;
+        info!("Watermark overlaid successfully. Output: {}", watermarked_video_path_str);
+        watermarked_video_path
+    } else {
+        working_video_path
+    };
+
+    // Mix in background music if configured
+    let working_video_path = if let Some(music_path) = &config.video.bg_music_path {
+        let mixed_video_filename = format!("{}_music.mp4", input_file_stem);
+        let mixed_video_path = temp_dir.join(&mixed_video_filename);
+        let mixed_video_path_str = mixed_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert music-mixed video path to string"))?;
+        let input_video_path_str = working_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+        reporter.stage("Mixing background music")?;
+        info!("Mixing background music {} (volume {}, ducking {}). Output: {}", music_path, config.video.bg_music_volume, config.video.bg_music_duck, mixed_video_path_str);
+        video_processing::mix_background_music(
+            input_video_path_str,
+            music_path,
+            mixed_video_path_str,
+            config.video.bg_music_volume,
+            config.video.bg_music_duck,
+            &encode_settings,
+        )
+        .with_context(|| format!("Failed to mix background music '{}'", music_path))?;
+        info!("Background music mixed successfully. Output: {}", mixed_video_path_str);
+        mixed_video_path
+    } else {
+        working_video_path
+    };
+
+    // Fade in/out last, so the fades cover everything drawn or mixed in by the earlier steps
+    let working_video_path = if config.video.fade_in_secs > 0.0 || config.video.fade_out_secs > 0.0 {
+        let faded_video_filename = format!("{}_faded.mp4", input_file_stem);
+        let faded_video_path = temp_dir.join(&faded_video_filename);
+        let faded_video_path_str = faded_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert faded video path to string"))?;
+        let input_video_path_str = working_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+
+        // The clip can be shorter than requested when the input ends early, so measure it.
+        reporter.stage("Applying fades")?;
+        let clip_duration = video_processing::probe_duration_secs(input_video_path_str)?;
+        info!("Applying fades ({}s in, {}s out over {:.2}s). Output: {}", config.video.fade_in_secs, config.video.fade_out_secs, clip_duration, faded_video_path_str);
+        video_processing::apply_fades(
+            input_video_path_str,
+            faded_video_path_str,
+            config.video.fade_in_secs,
+            config.video.fade_out_secs,
+            clip_duration,
+            &encode_settings,
+        )
+        .with_context(|| format!("Failed to apply fades to '{}'", input_video_path_str))?;
+        info!("Fades applied successfully. Output: {}", faded_video_path_str);
+        faded_video_path
+    } else {
+        working_video_path
+    };
+
+    // A short that no step re-encoded still has the source's codecs, which WebM cannot hold.
+    let container = video_processing::OutputContainer::detect(final_output_path_str, config.video.output_format.as_deref());
+    let working_video_path_str = working_video_path.to_str()
+        .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+    let working_video_path = if video_processing::container_accepts_streams(working_video_path_str, container)? {
+        working_video_path
+    } else {
+        let encoded_video_path = temp_dir.join(format!("{}_encoded.mp4", input_file_stem));
+        let encoded_video_path_str = encoded_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert encoded video path to string"))?;
+        reporter.stage("Encoding")?;
+        info!("Re-encoding for {:?} output with {}. Output: {}", container, encode_settings.encoder.codec_name(), encoded_video_path_str);
+        video_processing::reencode_video(working_video_path_str, encoded_video_path_str, &encode_settings)
+            .with_context(|| format!("Failed to re-encode '{}'", working_video_path_str))?;
+        info!("Video re-encoded successfully. Output: {}", encoded_video_path_str);
+        encoded_video_path
+    };
+
+    let chapters = if config.video.generate_chapters {
+        let working_video_path_str = working_video_path.to_str()
+            .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+        reporter.stage("Detecting chapters")?;
+        info!("Detecting scene changes in {} for chapters (threshold {})", working_video_path_str, config.video.scene_threshold);
+        let duration_secs = video_processing::probe_duration_secs(working_video_path_str)
+            .with_context(|| format!("Failed to read the duration of '{}'", working_video_path_str))?;
+        let scene_starts = video_processing::detect_scenes(working_video_path_str, config.video.scene_threshold)
+            .with_context(|| format!("Failed to detect scenes in '{}'", working_video_path_str))?;
+        let chapters = video_processing::build_chapters(&scene_starts, &[], duration_secs);
+        info!("Adding {} chapters to {}", chapters.len(), final_output_path_str);
+        chapters
+    } else {
+        Vec::new()
+    };
+
+    reporter.stage("Writing output")?;
+    // The output may have appeared while this one was processed, e.g. from a parallel run; the
+    // rename below would replace it without asking.
+    check_output_writable(config, final_output_path_str)?;
+    let working_video_path_str = working_video_path.to_str()
+        .ok_or_else(|| Error::msg("Failed to convert working video path to string"))?;
+    let format_name = config.video.output_format.as_deref();
+    let working_video_tags = match config.metadata.strip_metadata {
+        MetadataStrip::None => Vec::new(),
+        _ => video_processing::probe_metadata(working_video_path_str)
+            .with_context(|| format!("Failed to read the tags of '{}'", working_video_path_str))?,
+    };
+    let metadata_overrides = output_metadata_overrides(config, &working_video_tags);
+    let output_options = output_muxer_options(config, container);
+    if output_options.contains(&("movflags", "+faststart")) {
+        info!("Moving the index of {} to the front for progressive playback (faststart)", final_output_path_str);
+    }
+    if let Some(subtitle_path) = &soft_subtitle_path {
+        info!("Muxing soft subtitles from {} into output: {}", subtitle_path, final_output_path_str);
+        let write_stats = video_processing::mux_soft_subtitles(working_video_path_str, subtitle_path, final_output_path_str, format_name, &metadata_overrides, &output_options, &chapters)
+            .with_context(|| format!("Failed to write output to {}", final_output_path_str))?;
+        check_packet_writes(config, reporter, write_stats, final_output_path_str)
+            .with_context(|| format!("Failed to write output to {}", final_output_path_str))?;
+    } else if config.video.output_format.is_some()
+        || !metadata_overrides.is_empty()
+        || !output_options.is_empty()
+        || !chapters.is_empty()
+        || container != video_processing::OutputContainer::Mp4
+    {
+        // The intermediates are MP4 files; only the delivered output gets the requested container
+        // (from output_format or the output extension), tags, chapters and muxer options.
+        info!("Remuxing processed video as {} to output: {}", format_name.unwrap_or("mp4"), final_output_path_str);
+        let write_stats = video_processing::remux_video(working_video_path_str, final_output_path_str, format_name, &metadata_overrides, &output_options, &chapters)
+            .with_context(|| format!("Failed to write output to {}", final_output_path_str))?;
+        check_packet_writes(config, reporter, write_stats, final_output_path_str)
+            .with_context(|| format!("Failed to write output to {}", final_output_path_str))?;
+    } else if config.video.keep_temp {
+        // Leave the last intermediate (e.g. the trimmed clip when nothing else ran) in place.
+        info!("Copying processed video to output: {}", final_output_path_str);
+        fs::copy(&working_video_path, Path::new(final_output_path_str)).with_context(|| {
+            format!("Failed to copy processed video from {:?} to {}", working_video_path, final_output_path_str)
+        })?;
+    } else {
+        info!("Moving processed video to output: {}", final_output_path_str);
+        fs::rename(&working_video_path, Path::new(final_output_path_str))
+            .or_else(|e| {
+                warn!("Failed to move processed video (attempting copy instead): {:?}", e);
+                fs::copy(&working_video_path, Path::new(final_output_path_str)).map(|_| ()).map_err(anyhow::Error::from)
+            })
+            .and_then(|_| { 
+                if Path::new(final_output_path_str).exists() && working_video_path.exists() {
+                    fs::remove_file(&working_video_path)
+                        .with_context(|| format!("Failed to remove original working video after copy: {:?}", working_video_path))?; // Add ? to propagate anyhow::Error
+                }
+                Ok(()) // Ensure this path returns Ok(()) of the correct type
+            })
+            .with_context(|| {
+                format!(
+                    "Failed to move or copy processed video from {:?} to {}",
+                    working_video_path, final_output_path_str
+                )
+            })?;
+    }
+    info!("Processed video moved/copied to: {}", final_output_path_str);
+    if writes_stdout {
+        let mut staged = fs::File::open(final_output_path_str)
+            .with_context(|| format!("Failed to open processed video {}", final_output_path_str))?;
+        std::io::copy(&mut staged, &mut std::io::stdout().lock()).context("Failed to write the output to stdout")?;
+        info!("Processed video written to stdout");
+    }
+
+    if let Some(transcript) = transcript {
+        let transcript_path = transcript_path(final_output_path_str)?;
+        fs::write(&transcript_path, format!("{}\n", transcript))
+            .with_context(|| format!("Failed to write the transcript to {}", transcript_path))?;
+        info!("Transcript written to {}", transcript_path);
+    }
+
+    if let Some(at_secs) = config.video.thumbnail_at_secs {
+        let thumbnail_path = thumbnail_path(final_output_path_str)?;
+        reporter.stage("Extracting thumbnail")?;
+        info!("Extracting thumbnail at {}s. Output: {}", at_secs, thumbnail_path);
+        video_processing::extract_thumbnail(final_output_path_str, &thumbnail_path, at_secs)
+            .with_context(|| format!("Failed to extract a thumbnail from '{}'", final_output_path_str))?;
+        reporter.record_thumbnail(&thumbnail_path);
+        info!("Thumbnail extracted successfully. Output: {}", thumbnail_path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The config the CLI parses for a run on `input_path`, with `extra` after the required arguments.
+    fn parse_config(input_path: &str, extra: &[&str]) -> AppConfig {
+        use clap::Parser;
+        let mut args = vec![
+            "shorts_wizard",
+            "--input-path", input_path,
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ];
+        args.extend_from_slice(extra);
+        AppConfig::parse_from(args)
+    }
+
+    #[test]
+    fn clamp_to_source_shortens_or_rejects_out_of_range_clips() {
+        assert_eq!(clamp_to_source(10.0, 30.0, 60.0).unwrap(), 30.0);
+        assert_eq!(clamp_to_source(45.0, 30.0, 60.0).unwrap(), 15.0);
+        let err = ShortsError::from(clamp_to_source(60.0, 30.0, 60.0).unwrap_err());
+        assert!(matches!(err, ShortsError::InvalidArgument(_)));
+    }
+
+    #[test]
+    fn auto_split_cuts_at_the_nearest_scene_change() {
+        let spans = |segments: Vec<Segment>| -> Vec<(f64, f64)> {
+            segments.iter().map(|s| (s.start_secs, s.start_secs + s.duration_secs)).collect()
+        };
+        // 63 is closer to the ideal 60s cut than 55; 12 is too early to be considered.
+        assert_eq!(
+            spans(auto_split_segments(&[12.0, 55.0, 63.0, 130.0], 150.0, 60.0)),
+            vec![(0.0, 63.0), (63.0, 130.0), (130.0, 150.0)]
+        );
+        assert_eq!(spans(auto_split_segments(&[20.0], 45.0, 60.0)), vec![(0.0, 45.0)]);
+    }
+
+    #[test]
+    fn segment_plans_handle_remainders_and_overlaps() {
+        let spans = |total, length, overlap, keep_remainder| -> Vec<(f64, f64)> {
+            plan_segments(total, length, overlap, keep_remainder)
+                .unwrap()
+                .iter()
+                .map(|s| (s.start_secs, s.start_secs + s.duration_secs))
+                .collect()
+        };
+        assert_eq!(spans(130.0, 60.0, 0.0, true), vec![(0.0, 60.0), (60.0, 120.0), (120.0, 130.0)]);
+        assert_eq!(spans(130.0, 60.0, 0.0, false), vec![(0.0, 60.0), (60.0, 120.0)]);
+        assert_eq!(spans(120.0, 60.0, 0.0, true), vec![(0.0, 60.0), (60.0, 120.0)]);
+        // Windows stop once one reaches the end, rather than adding one inside the last.
+        assert_eq!(spans(100.0, 60.0, 20.0, true), vec![(0.0, 60.0), (40.0, 100.0)]);
+        assert_eq!(spans(110.0, 60.0, 20.0, true), vec![(0.0, 60.0), (40.0, 100.0), (80.0, 110.0)]);
+        // A source shorter than one segment is all remainder.
+        assert_eq!(spans(45.0, 60.0, 0.0, true), vec![(0.0, 45.0)]);
+        assert!(spans(45.0, 60.0, 0.0, false).is_empty());
+        // 0.1 is not exact in binary; 3 * 0.1 must still make three full windows and no sliver.
+        assert_eq!(plan_segments(0.3, 0.1, 0.0, true).unwrap().len(), 3);
+
+        assert!(matches!(plan_segments(100.0, 0.0, 0.0, true), Err(ShortsError::InvalidArgument(_))));
+        assert!(matches!(plan_segments(100.0, 60.0, 60.0, true), Err(ShortsError::InvalidArgument(_))));
+        assert!(matches!(plan_segments(100.0, 60.0, -1.0, true), Err(ShortsError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn configured_temp_dir_holds_the_segment_temp_dirs() {
+        use clap::Parser;
+        let config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "talk.mp4",
+            "--output-path", "/mnt/share/out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+            "--temp-dir", "/scratch",
+        ]);
+        let temp_dir = segment_temp_dir(&config, "/mnt/share/out_02.mp4", Some(2)).unwrap();
+        assert_eq!(temp_dir.parent(), Some(Path::new("/scratch")));
+        assert!(temp_dir.file_name().unwrap().to_str().unwrap().starts_with("talk_02_processing_temp_"));
+        // A second name differs even when it is asked for in the same millisecond.
+        assert_ne!(segment_temp_dir(&config, "/mnt/share/out_02.mp4", Some(2)).unwrap(), temp_dir);
+    }
+
+    #[test]
+    fn temp_dir_guards_remove_their_directory_unless_kept() {
+        let root = std::env::temp_dir().join(format!("shorts_temp_dir_guard_{}", std::process::id()));
+        let (config, kept_config) = (parse_config("talk.mp4", &[]), parse_config("talk.mp4", &["--keep-temp"]));
+
+        let finished = TempDirGuard::create(&config, root.join("finished")).unwrap();
+        std::fs::write(finished.path().join("clip.mp4"), b"clip").unwrap();
+        finished.finish(Ok(())).unwrap();
+        let failed = TempDirGuard::create(&config, root.join("failed")).unwrap();
+        // E.g. a step returning early with `?`, or unwinding from a panic.
+        drop(failed);
+        let kept = TempDirGuard::create(&kept_config, root.join("kept")).unwrap();
+        let error = kept.finish(Err(Error::msg("burning failed"))).unwrap_err();
+        // Listed for remove_live_temp_dirs only while it exists (not called here, as it would
+        // remove the directories of tests running in parallel too).
+        let is_live = |path: &Path| LIVE_TEMP_DIRS.lock().unwrap().iter().any(|live| live == path);
+        let live = TempDirGuard::create(&config, root.join("live")).unwrap();
+        let listed = (is_live(live.path()), is_live(&root.join("kept")));
+        drop(live);
+        let listed_after_drop = is_live(&root.join("live"));
+
+        let remaining: Vec<_> = std::fs::read_dir(&root).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(remaining, ["kept"]);
+        assert_eq!(error.to_string(), "burning failed");
+        assert_eq!(listed, (true, false));
+        assert!(!listed_after_drop);
+    }
+
+    #[test]
+    fn generate_short_rejects_multi_output_and_invalid_configs() {
+        let err = generate_short(&parse_config("missing.mp4", &["--auto-split"])).unwrap_err();
+        assert!(err.to_string().contains("process_video_with_config"));
+        let err = generate_short(&parse_config("missing.mp4", &[])).unwrap_err();
+        assert!(matches!(&err, ShortsError::InvalidArgument(message) if message.contains("missing.mp4")));
+    }
+
+    #[test]
+    fn glob_outputs_are_numbered_and_named_after_their_inputs() {
+        assert_eq!(glob_output_path("shorts/out.mp4", 2, "clips/intro.mov").unwrap(), "shorts/out_02_intro.mp4");
+        assert_eq!(glob_output_path("out", 1, "talk.mkv").unwrap(), "out_01_talk");
+        assert_eq!(segment_output_path(&glob_output_path("out.mp4", 1, "talk.mp4").unwrap(), 3).unwrap(), "out_01_talk_03.mp4");
+    }
+
+    #[test]
+    fn preview_is_written_next_to_the_output_and_shortened() {
+        assert_eq!(preview_output_path("clips/out.mp4").unwrap(), "clips/out_preview.mp4");
+        assert_eq!(segment_output_path(&preview_output_path("out.mp4").unwrap(), 2).unwrap(), "out_preview_02.mp4");
+
+        use clap::Parser;
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ]);
+        assert_eq!(final_output_path(&config).unwrap(), "out.mp4");
+        assert_eq!(requested_duration_secs(&config, 60.0), 60.0);
+        config.video.preview_secs = Some(5.0);
+        assert_eq!(final_output_path(&config).unwrap(), "out_preview.mp4");
+        assert_eq!(requested_duration_secs(&config, 60.0), 5.0);
+        assert_eq!(requested_duration_secs(&config, 3.0), 3.0);
+    }
+
+    #[test]
+    fn only_transcription_needs_an_audio_stream() {
+        use clap::Parser;
+        // The input does not exist, so any probe would fail.
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "missing.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+            "--external-subtitle-path", "subs.srt",
+        ]);
+        assert!(!transcription_lacks_audio(&config, None).unwrap());
+        config.subtitles.external_subtitle_path = None;
+        config.subtitles.use_subtitles = false;
+        assert!(!transcription_lacks_audio(&config, None).unwrap());
+    }
+
+    #[test]
+    fn existing_outputs_are_kept_unless_overwrite_is_set() {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join(format!("shorts_overwrite_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output_path = dir.join("out.mp4");
+        fs::write(dir.join("out_02.mp4"), b"earlier render").unwrap();
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", output_path.to_str().unwrap(),
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ]);
+        assert!(check_outputs_writable(&config).is_ok());
+        config.video.segments = vec![Segment { start_secs: 0.0, duration_secs: 30.0 }, Segment { start_secs: 30.0, duration_secs: 30.0 }];
+        let result = check_outputs_writable(&config);
+        config.video.overwrite = true;
+        let overwrite_result = check_outputs_writable(&config);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(ShortsError::OutputExists(path)) if path.ends_with("out_02.mp4")));
+        assert!(overwrite_result.is_ok());
+    }
+
+    #[test]
+    fn stripped_input_tags_are_removed_from_the_output() {
+        use clap::Parser;
+        let mut config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+            "--metadata-title", "My short",
+        ]);
+        let tags: Vec<(String, String)> = [
+            ("title", "IMG_0042"),
+            ("location", "+48.8583+002.2945/"),
+            ("com.apple.quicktime.location.ISO6709", "+48.8583+002.2945+035.000/"),
+            ("com.apple.quicktime.model", "iPhone"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        assert_eq!(config.metadata.strip_metadata, MetadataStrip::Location);
+        assert_eq!(
+            output_metadata_overrides(&config, &tags),
+            [("location", ""), ("com.apple.quicktime.location.ISO6709", ""), ("title", "My short")]
+        );
+        config.metadata.strip_metadata = MetadataStrip::All;
+        assert_eq!(output_metadata_overrides(&config, &tags).len(), 5);
+        config.metadata.strip_metadata = MetadataStrip::None;
+        assert_eq!(output_metadata_overrides(&config, &tags), [("title", "My short")]);
+    }
+
+    #[test]
+    fn faststart_applies_to_mp4_outputs_unless_an_output_option_replaces_it() {
+        use video_processing::OutputContainer;
+        let config = parse_config("in.mp4", &[]);
+        assert_eq!(output_muxer_options(&config, OutputContainer::Mp4), [("movflags", "+faststart")]);
+        assert!(output_muxer_options(&config, OutputContainer::Matroska).is_empty());
+        assert!(output_muxer_options(&parse_config("in.mp4", &["--faststart", "false"]), OutputContainer::Mp4).is_empty());
+
+        let config = parse_config("in.mp4", &["--output-option", "movflags=+frag_keyframe", "--output-option", "brand=isom"]);
+        assert_eq!(
+            output_muxer_options(&config, OutputContainer::Mov),
+            [("movflags", "+frag_keyframe"), ("brand", "isom")]
+        );
+    }
+
+    #[test]
+    fn an_output_collects_the_records_of_both_reporters() {
+        // As in process_segments_overlapped: one reporter prepares the clip, another finishes it.
+        let (preparer, finisher) = (Reporter::new(None), Reporter::new(None));
+        preparer.stage("Trimming").unwrap();
+        preparer.record_subtitles("tmp/talk.ass", 12);
+        preparer.record_packet_write_failures(2);
+        finisher.stage("Burning subtitles").unwrap();
+        finisher.record_thumbnail("out_01_thumb.jpg");
+        finisher.record_packet_write_failures(1);
+
+        let mut output = OutputReport {
+            output_path: "out_01.mp4".to_string(),
+            status: ReportStatus::Failed,
+            start_secs: 0.0,
+            duration_secs: 30.0,
+            subtitle_path: None,
+            subtitle_cue_count: None,
+            transcript: None,
+            thumbnail_path: None,
+            packet_write_failures: 0,
+            stages: Vec::new(),
+        };
+        preparer.take_output_record(&mut output);
+        finisher.take_output_record(&mut output);
+
+        let stages: Vec<&str> = output.stages.iter().map(|timing| timing.stage.as_str()).collect();
+        assert_eq!(stages, ["Trimming", "Burning subtitles"]);
+        assert_eq!((output.subtitle_path.as_deref(), output.subtitle_cue_count), (Some("tmp/talk.ass"), Some(12)));
+        assert_eq!(output.thumbnail_path.as_deref(), Some("out_01_thumb.jpg"));
+        assert_eq!(output.packet_write_failures, 3);
+    }
+
+    #[test]
+    fn thumbnail_sits_next_to_its_output() {
+        assert_eq!(thumbnail_path("clips/out_01.mp4").unwrap(), "clips/out_01_thumb.jpg");
+        assert_eq!(thumbnail_path("out").unwrap(), "out_thumb.jpg");
+    }
+
+    #[test]
+    fn a_missing_font_falls_back_by_name_only_when_the_policy_allows_it() {
+        let mut config = parse_config("talk.mp4", &["--missing-font-policy", "fallback"]);
+        resolve_missing_font(&mut config);
+        assert_eq!(subtitle_font_name(&config), Some(video_processing::FALLBACK_FONT_NAME));
+
+        let mut config = parse_config("talk.mp4", &[]);
+        resolve_missing_font(&mut config);
+        assert_eq!(subtitle_font_name(&config), None);
+    }
+
+    #[test]
+    fn transcript_sits_next_to_its_output() {
+        assert_eq!(transcript_path("clips/out_01.mp4").unwrap(), "clips/out_01.txt");
+        assert_eq!(transcript_path("out").unwrap(), "out.txt");
+    }
+}
@@ -0,0 +1,15 @@
+// Structured progress reporting, e.g. to drive a progress bar per step in a GUI.
+// pipeline::process_video_with_config calls `stage` when a step starts (e.g. "Trimming",
+// "Transcribing", "Burning subtitles"), `progress` with the completed fraction (0.0..=1.0) of
+// the current step for steps that can measure it, and `done` once every output has been written.
+// `is_cancelled` is polled between steps and while the whisper CLI runs; once it returns true the
+// run stops with ShortsError::Cancelled, killing a running whisper process.
+pub trait ProgressReporter {
+    fn stage(&self, name: &str);
+    fn progress(&self, fraction: f64);
+    fn done(&self);
+
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
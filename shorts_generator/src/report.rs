@@ -0,0 +1,149 @@
+use crate::config::AppConfig;
+use crate::error::ShortsError;
+use crate::video_processing::VideoInfo;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+// Machine-readable summary of a process_video_with_config run, written as JSON to the configured
+// report_path. It is written for failed runs too, with whatever was produced up to the failure.
+#[derive(Serialize, Debug, Clone)]
+pub struct ProcessReport {
+    pub status: ReportStatus,
+    pub error: Option<String>, // The failure and its causes, when status is "failed"
+    pub started_at: DateTime<Utc>,
+    pub elapsed_secs: f64,
+    pub input_path: String,
+    pub source_duration_secs: Option<f64>, // None when FFmpeg could not determine it
+    pub source: Option<VideoInfo>, // The input as probed, None when it could not be read
+    pub outputs: Vec<OutputReport>, // One per short, in processing order; outputs never attempted are left out
+    pub config: AppConfig, // The configuration as run, e.g. with the encoder actually used
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    Succeeded,
+    Failed,
+}
+
+// One generated short (the single output, or one segment).
+#[derive(Serialize, Debug, Clone)]
+pub struct OutputReport {
+    pub output_path: String,
+    pub status: ReportStatus,
+    pub start_secs: f64,
+    pub duration_secs: f64, // After clamping to the end of the source
+    pub subtitle_path: Option<String>, // Generated inside the temporary directory
+    pub subtitle_cue_count: Option<usize>,
+    pub transcript: Option<String>, // Text of the captions as burned/muxed, one line per cue
+    pub thumbnail_path: Option<String>,
+    pub packet_write_failures: usize, // Packets dropped by the stream-copy steps (trim, audio extraction, subtitles)
+    pub stages: Vec<StageTiming>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StageTiming {
+    pub stage: String, // As passed to ProgressReporter::stage, e.g. "Transcribing"
+    pub secs: f64,
+}
+
+impl ProcessReport {
+    // Starts a report for `config`, marked as failed until finish() says otherwise.
+    pub(crate) fn new(config: &AppConfig) -> Self {
+        ProcessReport {
+            status: ReportStatus::Failed,
+            error: None,
+            started_at: Utc::now(),
+            elapsed_secs: 0.0,
+            input_path: config.video.input_path.clone(),
+            source_duration_secs: None,
+            source: None,
+            outputs: Vec::new(),
+            config: config.clone(),
+        }
+    }
+
+    pub(crate) fn finish(&mut self, config: &AppConfig, error: Option<&anyhow::Error>) {
+        self.status = if error.is_some() { ReportStatus::Failed } else { ReportStatus::Succeeded };
+        self.error = error.map(|e| format!("{:#}", e));
+        self.elapsed_secs = (Utc::now() - self.started_at).num_milliseconds() as f64 / 1000.0;
+        self.config = config.clone();
+    }
+
+    // Time spent in each stage summed over all outputs, in the order the stages first ran, e.g.
+    // to see whether transcription or encoding dominates a run.
+    pub fn stage_totals(&self) -> Vec<StageTiming> {
+        let mut totals: Vec<StageTiming> = Vec::new();
+        for timing in self.outputs.iter().flat_map(|output| &output.stages) {
+            match totals.iter_mut().find(|total| total.stage == timing.stage) {
+                Some(total) => total.secs += timing.secs,
+                None => totals.push(timing.clone()),
+            }
+        }
+        totals
+    }
+
+    pub fn write_to_file(&self, path: &str) -> Result<(), ShortsError> {
+        let json = serde_json::to_string_pretty(self).map_err(anyhow::Error::from)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn failed_report_serializes_status_and_error_chain() {
+        let config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ]);
+        let mut report = ProcessReport::new(&config);
+        let error = anyhow::Error::msg("no audio").context("Failed to extract audio");
+        report.finish(&config, Some(&error));
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert_eq!(json["error"], "Failed to extract audio: no audio");
+        assert_eq!(json["input_path"], "in.mp4");
+        assert_eq!(json["config"]["video"]["output_path"], "out.mp4");
+    }
+
+    #[test]
+    fn stage_totals_sum_each_stage_over_the_outputs() {
+        let config = AppConfig::parse_from([
+            "shorts_wizard",
+            "--input-path", "in.mp4",
+            "--output-path", "out.mp4",
+            "--whisper-model-path", "base",
+            "--font-path", "font.ttf",
+        ]);
+        let output = |stages: &[(&str, f64)]| OutputReport {
+            output_path: "out.mp4".to_string(),
+            status: ReportStatus::Succeeded,
+            start_secs: 0.0,
+            duration_secs: 60.0,
+            subtitle_path: None,
+            subtitle_cue_count: None,
+            transcript: None,
+            thumbnail_path: None,
+            packet_write_failures: 0,
+            stages: stages.iter().map(|(stage, secs)| StageTiming { stage: stage.to_string(), secs: *secs }).collect(),
+        };
+        let mut report = ProcessReport::new(&config);
+        report.outputs.push(output(&[("Trimming", 1.0), ("Transcribing", 20.0)]));
+        report.outputs.push(output(&[("Trimming", 0.5), ("Burning subtitles", 4.0), ("Transcribing", 10.0)]));
+
+        let totals: Vec<(String, f64)> = report.stage_totals().into_iter().map(|t| (t.stage, t.secs)).collect();
+        assert_eq!(
+            totals,
+            [("Trimming".to_string(), 1.5), ("Transcribing".to_string(), 30.0), ("Burning subtitles".to_string(), 4.0)]
+        );
+    }
+}
@@ -1,18 +1,305 @@
-use anyhow::{Result, Error, bail};
-use std::process::Command;
+use anyhow::{anyhow, bail, Error, Result};
+use crate::config::{CaptionAnimation, CaptionTextTransform, SubtitleFormat, WhisperBackend};
+use crate::error::ShortsError;
+use log::{info, warn};
+use std::fs::File;
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// whisper.cpp model files start with the ggml magic number 0x67676d6c ("ggml"), stored little-endian.
+const GGML_FILE_MAGIC: [u8; 4] = *b"lmgg";
+
+// Checks that the model file looks like a whisper.cpp ggml model before handing it to the native backend,
+// so a wrong file (e.g. a PyTorch .pt checkpoint) produces a clear error instead of a crash inside whisper.cpp.
+fn check_ggml_model(model_path: &Path) -> Result<(), Error> {
+    let mut file = File::open(model_path)
+        .map_err(|e| Error::msg(format!("Failed to open whisper model '{}': {}", model_path.display(), e)))?;
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_err() || magic != GGML_FILE_MAGIC {
+        bail!(
+            "Whisper model '{}' is not a valid ggml model file. The native backend needs a whisper.cpp model such as ggml-base.en.bin.",
+            model_path.display()
+        );
+    }
+    Ok(())
+}
+
+// Executable run by the CLI backend when no whisper_binary_path is configured, looked up on PATH.
+pub const DEFAULT_WHISPER_BINARY: &str = "whisper";
+
+// Runs `<whisper_binary> --help` to check that the CLI backend can be started, so a missing
+// install is reported before any video is processed.
+pub fn check_whisper_binary(whisper_binary: &str) -> Result<(), ShortsError> {
+    let status = Command::new(whisper_binary)
+        .arg("--help")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| whisper_spawn_error(whisper_binary, e))?;
+    if !status.success() {
+        return Err(anyhow!("'{} --help' failed with status: {}", whisper_binary, status).into());
+    }
+    Ok(())
+}
+
+fn whisper_spawn_error(whisper_binary: &str, e: std::io::Error) -> ShortsError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => ShortsError::WhisperNotFound(whisper_binary.to_string()),
+        _ => ShortsError::Io(e),
+    }
+}
+
+// Languages whisper can transcribe, as (code, name) pairs in whisper's own order.
+const WHISPER_LANGUAGES: [(&str, &str); 100] = [
+    ("en", "english"), ("zh", "chinese"), ("de", "german"), ("es", "spanish"), ("ru", "russian"),
+    ("ko", "korean"), ("fr", "french"), ("ja", "japanese"), ("pt", "portuguese"), ("tr", "turkish"),
+    ("pl", "polish"), ("ca", "catalan"), ("nl", "dutch"), ("ar", "arabic"), ("sv", "swedish"),
+    ("it", "italian"), ("id", "indonesian"), ("hi", "hindi"), ("fi", "finnish"), ("vi", "vietnamese"),
+    ("he", "hebrew"), ("uk", "ukrainian"), ("el", "greek"), ("ms", "malay"), ("cs", "czech"),
+    ("ro", "romanian"), ("da", "danish"), ("hu", "hungarian"), ("ta", "tamil"), ("no", "norwegian"),
+    ("th", "thai"), ("ur", "urdu"), ("hr", "croatian"), ("bg", "bulgarian"), ("lt", "lithuanian"),
+    ("la", "latin"), ("mi", "maori"), ("ml", "malayalam"), ("cy", "welsh"), ("sk", "slovak"),
+    ("te", "telugu"), ("fa", "persian"), ("lv", "latvian"), ("bn", "bengali"), ("sr", "serbian"),
+    ("az", "azerbaijani"), ("sl", "slovenian"), ("kn", "kannada"), ("et", "estonian"), ("mk", "macedonian"),
+    ("br", "breton"), ("eu", "basque"), ("is", "icelandic"), ("hy", "armenian"), ("ne", "nepali"),
+    ("mn", "mongolian"), ("bs", "bosnian"), ("kk", "kazakh"), ("sq", "albanian"), ("sw", "swahili"),
+    ("gl", "galician"), ("mr", "marathi"), ("pa", "punjabi"), ("si", "sinhala"), ("km", "khmer"),
+    ("sn", "shona"), ("yo", "yoruba"), ("so", "somali"), ("af", "afrikaans"), ("oc", "occitan"),
+    ("ka", "georgian"), ("be", "belarusian"), ("tg", "tajik"), ("sd", "sindhi"), ("gu", "gujarati"),
+    ("am", "amharic"), ("yi", "yiddish"), ("lo", "lao"), ("uz", "uzbek"), ("fo", "faroese"),
+    ("ht", "haitian creole"), ("ps", "pashto"), ("tk", "turkmen"), ("nn", "nynorsk"), ("mt", "maltese"),
+    ("sa", "sanskrit"), ("lb", "luxembourgish"), ("my", "myanmar"), ("bo", "tibetan"), ("tl", "tagalog"),
+    ("mg", "malagasy"), ("as", "assamese"), ("tt", "tatar"), ("haw", "hawaiian"), ("ln", "lingala"),
+    ("ha", "hausa"), ("ba", "bashkir"), ("jw", "javanese"), ("su", "sundanese"), ("yue", "cantonese"),
+];
+
+// Maps a language code or English name (case-insensitive, e.g. "de" or "German") to the code
+// whisper expects, so an unknown language is reported before whisper is started.
+pub fn resolve_whisper_language(language: &str) -> Result<&'static str, ShortsError> {
+    let wanted = language.trim().to_lowercase();
+    WHISPER_LANGUAGES
+        .iter()
+        .find(|(code, name)| *code == wanted || *name == wanted)
+        .map(|(code, _)| *code)
+        .ok_or_else(|| {
+            ShortsError::InvalidArgument(format!(
+                "Unknown whisper language '{}'. Use a code such as 'en', 'es' or 'de' (or a name such as 'spanish'), or leave it unset to auto-detect.",
+                language
+            ))
+        })
+}
+
+// How whisper is run by generate_subtitle_file.
+#[derive(Clone, Copy, Default)]
+pub struct WhisperOptions<'a> {
+    pub backend: WhisperBackend,
+    pub binary_path: Option<&'a str>, // CLI backend executable, DEFAULT_WHISPER_BINARY when None
+    pub language: Option<&'a str>, // Spoken language (code or name); None lets whisper detect it
+    pub translate_to_english: bool, // Produce English subtitles whatever the spoken language
+    pub max_attempts: u32, // CLI backend runs before giving up on a failing whisper; 1 disables retries
+    pub device: Option<&'a str>, // CLI backend device, e.g. "cpu" or "cuda"; see whisper_device_args for None
+    pub fp16: Option<bool>, // CLI backend half precision; whisper's choice (off on the CPU) when None
+    pub is_cancelled: Option<&'a dyn Fn() -> bool>, // Polled while the CLI backend runs; true kills it
+}
+
+impl std::fmt::Debug for WhisperOptions<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WhisperOptions")
+            .field("backend", &self.backend)
+            .field("binary_path", &self.binary_path)
+            .field("language", &self.language)
+            .field("translate_to_english", &self.translate_to_english)
+            .field("max_attempts", &self.max_attempts)
+            .field("device", &self.device)
+            .field("fp16", &self.fp16)
+            .field("is_cancelled", &self.is_cancelled.is_some())
+            .finish()
+    }
+}
+
+// Whether `device` names a device the whisper executable can run on: "cpu", "cuda" or "cuda:<N>".
+pub fn is_whisper_device(device: &str) -> bool {
+    match device.strip_prefix("cuda:") {
+        Some(index) => !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()),
+        None => device == "cpu" || device == "cuda",
+    }
+}
+
+// Whether an NVIDIA GPU answers `nvidia-smi -L`.
+fn nvidia_gpu_available() -> bool {
+    Command::new("nvidia-smi")
+        .arg("-L")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+// The --device and --fp16 arguments for the whisper executable. Without a device, whisper picks
+// CUDA whenever PyTorch was built with it, and crashes on machines without a working GPU, so cpu
+// is asked for unless `gpu_available` says there is one. Half precision is turned off on the CPU,
+// which does not support it, unless `fp16` says otherwise.
+fn whisper_device_args(device: Option<&str>, fp16: Option<bool>, gpu_available: impl FnOnce() -> bool) -> Vec<String> {
+    let device = match device {
+        Some(device) => Some(device),
+        None if gpu_available() => None,
+        None => {
+            info!("No NVIDIA GPU found; running whisper on the CPU");
+            Some("cpu")
+        }
+    };
+    let fp16 = fp16.or((device == Some("cpu")).then_some(false));
+    let mut args = Vec::new();
+    if let Some(device) = device {
+        args.extend(["--device".to_string(), device.to_string()]);
+    }
+    if let Some(fp16) = fp16 {
+        args.extend(["--fp16".to_string(), if fp16 { "True" } else { "False" }.to_string()]);
+    }
+    args
+}
+
+// Wait before the first retry of a failed whisper run; it doubles for each further retry.
+const WHISPER_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+// How often a running whisper process checks whether it has been cancelled.
+const WHISPER_CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Runs the whisper command until it succeeds, at most `max_attempts` times, waiting `retry_delay`
+// (doubled each time) between runs. Whisper can fail transiently, e.g. running out of GPU memory.
+// An executable that cannot be started is reported right away, since running it again cannot help.
+// Once `is_cancelled` returns true the running process is killed and no retry is made.
+fn run_whisper_with_retries(
+    command: &mut Command,
+    whisper_binary: &str,
+    max_attempts: u32,
+    retry_delay: Duration,
+    is_cancelled: Option<&dyn Fn() -> bool>,
+) -> Result<Output, ShortsError> {
+    let max_attempts = max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        if max_attempts > 1 {
+            info!("Running whisper (attempt {} of {})", attempt, max_attempts);
+        }
+        let output = match is_cancelled {
+            Some(is_cancelled) => run_cancellable(command, whisper_binary, is_cancelled)?,
+            None => command.output().map_err(|e| whisper_spawn_error(whisper_binary, e))?,
+        };
+        if output.status.success() {
+            return Ok(output);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if attempt >= max_attempts {
+            return Err(anyhow!(
+                "Whisper command failed with status: {}{}. Stderr: {}",
+                output.status,
+                if max_attempts > 1 { format!(" after {} attempts", max_attempts) } else { String::new() },
+                stderr
+            )
+            .into());
+        }
+        let delay = retry_delay * 2u32.saturating_pow(attempt - 1);
+        warn!(
+            "Whisper failed with status {} (attempt {} of {}); retrying in {:?}. Stderr: {}",
+            output.status, attempt, max_attempts, delay, stderr.trim()
+        );
+        std::thread::sleep(delay);
+        if is_cancelled.is_some_and(|is_cancelled| is_cancelled()) {
+            return Err(ShortsError::Cancelled);
+        }
+        attempt += 1;
+    }
+}
+
+// Like Command::output, but kills the process and fails with Cancelled once `is_cancelled`
+// returns true. The output pipes are read on their own threads, so a chatty whisper never stalls
+// on a full pipe while it is polled.
+fn run_cancellable(command: &mut Command, whisper_binary: &str, is_cancelled: &dyn Fn() -> bool) -> Result<Output, ShortsError> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| whisper_spawn_error(whisper_binary, e))?;
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if is_cancelled() {
+            warn!("Cancelled; killing the whisper process");
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(ShortsError::Cancelled);
+        }
+        std::thread::sleep(WHISPER_CANCEL_POLL_INTERVAL);
+    };
+    Ok(Output { status, stdout: stdout.join().unwrap_or_default(), stderr: stderr.join().unwrap_or_default() })
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+#[cfg(feature = "native-whisper")]
+fn transcribe_native(
+    audio_path: &Path,
+    model_path: &Path,
+    srt_path: &Path,
+    language: Option<&str>,
+    translate_to_english: bool,
+) -> Result<(), Error> {
+    crate::whisper_native::transcribe_to_srt(audio_path, model_path, srt_path, language, translate_to_english)
+}
+
+#[cfg(not(feature = "native-whisper"))]
+fn transcribe_native(
+    _audio_path: &Path,
+    _model_path: &Path,
+    _srt_path: &Path,
+    _language: Option<&str>,
+    _translate_to_english: bool,
+) -> Result<(), Error> {
+    bail!("The native whisper backend is not available: rebuild shorts_generator with `--features native-whisper`.")
+}
 
 pub fn generate_subtitle_file(
     audio_input_path: &str,
     whisper_model_path: &str,
     output_dir: &str,
-) -> Result<String, Error> {
+    whisper: &WhisperOptions,
+    format: SubtitleFormat,
+) -> Result<String, ShortsError> {
+    generate_subtitle_file_with_cues(audio_input_path, whisper_model_path, output_dir, whisper, format)
+        .map(|(subtitle_path, _)| subtitle_path)
+}
+
+// Same as generate_subtitle_file, also returning the transcript as parsed cues.
+pub fn generate_subtitle_file_with_cues(
+    audio_input_path: &str,
+    whisper_model_path: &str,
+    output_dir: &str,
+    whisper: &WhisperOptions,
+    format: SubtitleFormat,
+) -> Result<(String, Vec<SubtitleCue>), ShortsError> {
+    let language = whisper.language.map(resolve_whisper_language).transpose()?;
     let audio_path = Path::new(audio_input_path);
     let model_path = Path::new(whisper_model_path);
     let out_dir_path = Path::new(output_dir);
 
     if !audio_path.exists() {
-        bail!("Audio input path does not exist: {}", audio_input_path);
+        return Err(ShortsError::InvalidArgument(format!("Audio input path does not exist: {}", audio_input_path)));
     }
     if !model_path.exists() {
         // Note: Whisper might load models by name (e.g., "base", "small") 
@@ -27,58 +314,1286 @@ pub fn generate_subtitle_file(
     if !out_dir_path.exists() {
         std::fs::create_dir_all(out_dir_path)?;
     } else if !out_dir_path.is_dir() {
-        bail!("Output directory path exists but is not a directory: {}", output_dir);
+        return Err(ShortsError::InvalidArgument(format!(
+            "Output directory path exists but is not a directory: {}",
+            output_dir
+        )));
     }
 
-    let mut command = Command::new("whisper");
-    command
-        .arg(audio_input_path)
-        .arg("--model")
-        .arg(whisper_model_path)
-        .arg("--output_dir")
-        .arg(output_dir)
-        .arg("--output_format")
-        .arg("srt");
+    let audio_file_name = audio_path
+        .file_stem()
+        .ok_or_else(|| Error::msg(format!("Could not extract file stem from audio path: {}", audio_input_path)))?
+        .to_str()
+        .ok_or_else(|| Error::msg("Audio file stem is not valid UTF-8"))?;
 
-    // Optional: Log the command
-    // println!("Executing command: {:?}", command);
+    // Whisper writes SRT and VTT itself; ASS is converted from its SRT output afterwards.
+    let whisper_format = match format {
+        SubtitleFormat::Vtt => SubtitleFormat::Vtt,
+        SubtitleFormat::Srt | SubtitleFormat::Ass => SubtitleFormat::Srt,
+    };
 
-    let output = command.output()?;
+    let whisper_output_path = if whisper.backend == WhisperBackend::Native {
+        check_ggml_model(model_path)?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!(
-            "Whisper command failed with status: {}. Stderr: {}",
-            output.status,
-            stderr
+        // The native backend only writes SRT, named the same way the CLI would name it.
+        let srt_path = out_dir_path.join(format!("{}.srt", audio_file_name));
+        transcribe_native(audio_path, model_path, &srt_path, language, whisper.translate_to_english)?;
+        srt_path
+    } else {
+        let whisper_binary = whisper.binary_path.unwrap_or(DEFAULT_WHISPER_BINARY);
+        let mut command = Command::new(whisper_binary);
+        command
+            .arg(audio_input_path)
+            .arg("--model")
+            .arg(whisper_model_path)
+            .arg("--output_dir")
+            .arg(output_dir)
+            .arg("--output_format")
+            .arg(whisper_format.extension());
+        if let Some(language) = language {
+            command.arg("--language").arg(language);
+        }
+        if whisper.translate_to_english {
+            command.arg("--task").arg("translate");
+        }
+        command.args(whisper_device_args(whisper.device, whisper.fp16, nvidia_gpu_available));
+
+        // Optional: Log the command
+        // println!("Executing command: {:?}", command);
+
+        let output = run_whisper_with_retries(&mut command, whisper_binary, whisper.max_attempts, WHISPER_RETRY_DELAY, whisper.is_cancelled)?;
+
+        // Determine the expected output file path
+        let subtitle_file_name = format!("{}.{}", audio_file_name, whisper_format.extension());
+        let mut subtitle_path = PathBuf::from(output_dir);
+        subtitle_path.push(subtitle_file_name);
+
+        if !subtitle_path.exists() {
+            // Whisper might sometimes put files in a subdirectory named after the model,
+            // or have other naming conventions if the input has unusual characters.
+            // For now, we assume direct output in output_dir.
+            // A more robust solution might involve listing files in output_dir if this assumption fails.
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!(
+                "Subtitle file not found at expected path: {:?}. Whisper stdout: {}, stderr: {}",
+                subtitle_path,
+                stdout,
+                stderr
+            )
+            .into());
+        }
+        subtitle_path
+    };
+
+    // Cues are read from whisper's own SRT/VTT output, which parse_srt understands even when it
+    // is converted to ASS below.
+    let cues = parse_srt(&whisper_output_path)?;
+
+    let subtitle_path = if whisper_output_path.extension().and_then(|e| e.to_str()) == Some(format.extension()) {
+        whisper_output_path
+    } else {
+        let converted_path = whisper_output_path.with_extension(format.extension());
+        convert_srt_file(&whisper_output_path, &converted_path, format)?;
+        converted_path
+    };
+
+    let subtitle_path = subtitle_path
+        .to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::msg("Subtitle file path is not valid UTF-8"))?;
+    Ok((subtitle_path, cues))
+}
+
+// A single subtitle cue. `index` is the cue number from the file (1-based).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleCue {
+    pub index: usize,
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+// Parses "HH:MM:SS,mmm" (or the "HH:MM:SS.mmm" variant) into milliseconds. The hours may be left
+// out, as VTT allows and whisper does for files shorter than an hour ("MM:SS.mmm").
+fn parse_srt_timestamp(timestamp: &str) -> Option<u64> {
+    let (hms, millis) = timestamp.trim().split_once([',', '.'])?;
+    let parts = hms.split(':').map(|part| part.parse::<u64>().ok()).collect::<Option<Vec<u64>>>()?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        [minutes, seconds] => (0, minutes, seconds),
+        _ => return None,
+    };
+    let millis: u64 = millis.parse().ok()?;
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+// Parses a "start --> end" cue timing line into milliseconds.
+fn parse_timing_line(line: &str) -> Option<(u64, u64)> {
+    let (start, end) = line.split_once("-->")?;
+    // VTT cue settings (e.g. "align:start") may follow the end timestamp.
+    let end = end.split_whitespace().next()?;
+    Some((parse_srt_timestamp(start)?, parse_srt_timestamp(end)?))
+}
+
+// Splits SRT content into cues. Blocks without a valid "start --> end" line are skipped, and cues
+// without a usable number are numbered by their position.
+fn parse_srt_cues(content: &str) -> Vec<SubtitleCue> {
+    let content = content.replace("\r\n", "\n");
+    content
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.lines().skip_while(|l| l.trim().is_empty()).peekable();
+            let number = lines.next_if(|l| !l.contains("-->")).and_then(|l| l.trim().parse().ok());
+            let mut lines = lines.skip_while(|l| !l.contains("-->"));
+            let (start_ms, end_ms) = parse_timing_line(lines.next()?)?;
+            Some((number, start_ms, end_ms, lines.collect::<Vec<_>>().join("\n")))
+        })
+        .enumerate()
+        .map(|(position, (number, start_ms, end_ms, text))| SubtitleCue {
+            index: number.unwrap_or(position + 1),
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+            text,
+        })
+        .collect()
+}
+
+// Reads an SRT file (or a VTT file, which shares its cue layout) into cues, e.g. to pick
+// highlights from the transcript. Multi-line cue text is joined with '\n'.
+pub fn parse_srt(path: &Path) -> Result<Vec<SubtitleCue>, ShortsError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", path.display(), e)))?;
+    Ok(parse_srt_cues(&content))
+}
+
+// The spoken text of an SRT, VTT or ASS file (by extension) without timings or styling: one line
+// per cue, with the lines of a cue joined by spaces and ASS override tags such as {\fad(200,0)}
+// removed. Cues without text are left out.
+pub fn transcript_text(subtitle_path: &Path) -> Result<String, ShortsError> {
+    Ok(parse_subtitle_cues(subtitle_path)?
+        .iter()
+        .map(|cue| cue.text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+// Reads the cues of an SRT, VTT or ASS file (by extension). ASS events are numbered in file order,
+// with their override tags such as {\fad(200,0)} removed and line breaks (\N) turned into '\n'.
+pub fn parse_subtitle_cues(subtitle_path: &Path) -> Result<Vec<SubtitleCue>, ShortsError> {
+    let content = std::fs::read_to_string(subtitle_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", subtitle_path.display(), e)))?;
+    let is_ass = matches!(
+        subtitle_path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref(),
+        Some("ass") | Some("ssa")
+    );
+    Ok(if is_ass { parse_ass_cues(&content) } else { parse_srt_cues(&content) })
+}
+
+fn parse_ass_cues(content: &str) -> Vec<SubtitleCue> {
+    content
+        .lines()
+        .filter_map(|line| line.strip_prefix("Dialogue:"))
+        .filter_map(|fields| {
+            // Text is the last of the 10 event fields and may itself contain commas.
+            let fields: Vec<&str> = fields.splitn(10, ',').collect();
+            let (start_ms, end_ms) = (parse_ass_timestamp(fields.get(1)?)?, parse_ass_timestamp(fields.get(2)?)?);
+            let text = strip_ass_override_tags(fields.get(9)?).replace("\\N", "\n").replace("\\n", "\n").replace("\\h", " ");
+            Some((start_ms, end_ms, text))
+        })
+        .enumerate()
+        .map(|(position, (start_ms, end_ms, text))| SubtitleCue {
+            index: position + 1,
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+            text,
+        })
+        .collect()
+}
+
+// Cues shown longer than this are reported by analyze_cues; whisper stretches a cue over a long
+// silence, or hallucinates one, more often than anyone speaks a caption that long.
+const SUSPICIOUS_CUE_SECS: f64 = 15.0;
+
+// Cues read faster than this many words per minute are reported by analyze_cues. Fast speech is
+// around 250; more usually means the cue's timing is wrong.
+const SUSPICIOUS_WORDS_PER_MINUTE: f64 = 450.0;
+
+// Summary of a subtitle file's cues, from analyze_cues.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SubtitleStats {
+    pub cue_count: usize,
+    pub total_duration: Duration, // Sum of the cue lengths, whether or not they overlap
+    pub average_duration: Duration,
+    pub longest_cue: Option<(usize, Duration)>, // Cue index and length
+    pub word_count: usize,
+    pub words_per_minute: f64, // Words over the total caption time, 0 without any
+    pub issues: Vec<CueIssue>,
+}
+
+// Something wrong, or likely wrong, with a cue; `index` is the cue's own number.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CueIssue {
+    OutOfOrder { index: usize, previous: usize }, // Starts before the previous cue does
+    Overlap { index: usize, previous: usize, overlap: Duration },
+    EndsBeforeStart { index: usize },
+    RepeatedText { index: usize, previous: usize }, // Same text as the previous cue, a typical whisper hallucination
+    TooFast { index: usize, words_per_minute: f64 },
+    TooLong { index: usize, duration: Duration },
+}
+
+impl std::fmt::Display for CueIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CueIssue::OutOfOrder { index, previous } => write!(f, "cue {} starts before the cue before it ({})", index, previous),
+            CueIssue::Overlap { index, previous, overlap } => {
+                write!(f, "cue {} overlaps cue {} by {:.3}s", index, previous, overlap.as_secs_f64())
+            }
+            CueIssue::EndsBeforeStart { index } => write!(f, "cue {} ends before it starts", index),
+            CueIssue::RepeatedText { index, previous } => write!(f, "cue {} repeats the text of cue {}", index, previous),
+            CueIssue::TooFast { index, words_per_minute } => {
+                write!(f, "cue {} reads at {:.0} words per minute", index, words_per_minute)
+            }
+            CueIssue::TooLong { index, duration } => write!(f, "cue {} is shown for {:.1}s", index, duration.as_secs_f64()),
+        }
+    }
+}
+
+// Counts and times the cues, in file order, and reports the ones that look wrong: out of order,
+// overlapping, consecutive cues with the same text, or cues too long (SUSPICIOUS_CUE_SECS) or too
+// fast to read (SUSPICIOUS_WORDS_PER_MINUTE).
+pub fn analyze_cues(cues: &[SubtitleCue]) -> SubtitleStats {
+    let mut stats = SubtitleStats { cue_count: cues.len(), ..SubtitleStats::default() };
+    let normalized = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    for (position, cue) in cues.iter().enumerate() {
+        let duration = cue.end.saturating_sub(cue.start);
+        let words = cue.text.split_whitespace().count();
+        stats.total_duration += duration;
+        stats.word_count += words;
+        if stats.longest_cue.is_none_or(|(_, longest)| duration > longest) {
+            stats.longest_cue = Some((cue.index, duration));
+        }
+
+        if let Some(previous) = position.checked_sub(1).map(|previous| &cues[previous]) {
+            if cue.start < previous.start {
+                stats.issues.push(CueIssue::OutOfOrder { index: cue.index, previous: previous.index });
+            } else if cue.start < previous.end && cue.end >= cue.start {
+                // An inverted cue is reported as EndsBeforeStart below instead.
+                let overlap = previous.end.min(cue.end) - cue.start;
+                stats.issues.push(CueIssue::Overlap { index: cue.index, previous: previous.index, overlap });
+            }
+            if words > 0 && normalized(&cue.text) == normalized(&previous.text) {
+                stats.issues.push(CueIssue::RepeatedText { index: cue.index, previous: previous.index });
+            }
+        }
+        if cue.end < cue.start {
+            stats.issues.push(CueIssue::EndsBeforeStart { index: cue.index });
+        } else if duration.as_secs_f64() > SUSPICIOUS_CUE_SECS {
+            stats.issues.push(CueIssue::TooLong { index: cue.index, duration });
+        } else if words > 1 {
+            let words_per_minute = words as f64 * 60.0 / duration.as_secs_f64();
+            if words_per_minute > SUSPICIOUS_WORDS_PER_MINUTE {
+                stats.issues.push(CueIssue::TooFast { index: cue.index, words_per_minute });
+            }
+        }
+    }
+    if !cues.is_empty() {
+        stats.average_duration = stats.total_duration / cues.len() as u32;
+    }
+    if !stats.total_duration.is_zero() {
+        stats.words_per_minute = stats.word_count as f64 * 60.0 / stats.total_duration.as_secs_f64();
+    }
+    stats
+}
+
+fn strip_ass_override_tags(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '{' => in_tag = true,
+            '}' if in_tag => in_tag = false,
+            _ if !in_tag => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+}
+
+// Formats milliseconds as an ASS timestamp (H:MM:SS.cc).
+fn format_ass_timestamp(total_ms: u64) -> String {
+    let centis = total_ms / 10;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        (centis % 360_000) / 6_000,
+        (centis % 6_000) / 100,
+        centis % 100
+    )
+}
+
+// Formats milliseconds as an SRT timestamp (HH:MM:SS,mmm).
+fn format_srt_timestamp(total_ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        total_ms / 3_600_000,
+        (total_ms % 3_600_000) / 60_000,
+        (total_ms % 60_000) / 1000,
+        total_ms % 1000
+    )
+}
+
+// Parses an ASS timestamp (H:MM:SS.cc) into milliseconds.
+fn parse_ass_timestamp(timestamp: &str) -> Option<u64> {
+    let (hms, centis) = timestamp.trim().split_once('.')?;
+    let mut parts = hms.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    let centis: u64 = centis.parse().ok()?;
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + centis * 10)
+}
+
+// Moves a cue shown from `start_ms` to `end_ms` of the source into a window of the source
+// starting at `window_start_ms` and `window_ms` long, clipping it to the window. Returns None
+// for cues entirely outside of it.
+fn cue_in_window(start_ms: u64, end_ms: u64, window_start_ms: u64, window_ms: u64) -> Option<(u64, u64)> {
+    let window_end_ms = window_start_ms + window_ms;
+    if end_ms <= window_start_ms || start_ms >= window_end_ms {
+        return None;
+    }
+    Some((start_ms.max(window_start_ms) - window_start_ms, end_ms.min(window_end_ms) - window_start_ms))
+}
+
+// Clips a cue shown from `start_ms` to `end_ms` to the part between `window_start_ms` and
+// `window_end_ms`, keeping its timeline. Returns None for cues entirely outside of it.
+fn cue_clipped_to(start_ms: u64, end_ms: u64, window_start_ms: u64, window_end_ms: u64) -> Option<(u64, u64)> {
+    if end_ms <= window_start_ms || start_ms >= window_end_ms {
+        return None;
+    }
+    Some((start_ms.max(window_start_ms), end_ms.min(window_end_ms)))
+}
+
+// Moves a cue shown from `start_ms` to `end_ms` by `offset_ms`, clamping its start at zero.
+// Returns None for cues that would end at or before zero.
+fn shifted_cue(start_ms: u64, end_ms: u64, offset_ms: i64) -> Option<(u64, u64)> {
+    let end_ms = end_ms as i64 + offset_ms;
+    if end_ms <= 0 {
+        return None;
+    }
+    Some(((start_ms as i64 + offset_ms).max(0) as u64, end_ms as u64))
+}
+
+// Rewrites the Dialogue timings of an ASS script with `map_timing`, dropping the events it
+// returns None for. Everything else, styles included, is kept. Returns the script and the number
+// of events kept.
+fn map_ass_timings<F: Fn(u64, u64) -> Option<(u64, u64)>>(content: &str, map_timing: F) -> (String, usize) {
+    let mut mapped = String::with_capacity(content.len());
+    let mut event_count = 0;
+    for line in content.replace("\r\n", "\n").lines() {
+        if let Some(fields) = line.strip_prefix("Dialogue:") {
+            let mut parts = fields.splitn(4, ',');
+            let (layer, start, end, rest) = (parts.next(), parts.next(), parts.next(), parts.next());
+            if let (Some(layer), Some(start), Some(end), Some(rest)) = (layer, start, end, rest) {
+                if let (Some(start_ms), Some(end_ms)) = (parse_ass_timestamp(start), parse_ass_timestamp(end)) {
+                    if let Some((start_ms, end_ms)) = map_timing(start_ms, end_ms) {
+                        mapped.push_str(&format!(
+                            "Dialogue:{},{},{},{}\n",
+                            layer,
+                            format_ass_timestamp(start_ms),
+                            format_ass_timestamp(end_ms),
+                            rest
+                        ));
+                        event_count += 1;
+                    }
+                    continue;
+                }
+            }
+        }
+        mapped.push_str(line);
+        mapped.push('\n');
+    }
+    (mapped, event_count)
+}
+
+// Rewrites the timings of SRT or VTT content with `map_timing`, dropping the cues it returns None
+// for and renumbering the rest; VTT content stays VTT (without its NOTE and STYLE blocks).
+// Returns the content and the number of cues kept.
+fn map_srt_timings<F: Fn(u64, u64) -> Option<(u64, u64)>>(
+    content: &str,
+    is_vtt: bool,
+    map_timing: F,
+) -> Result<(String, usize), ShortsError> {
+    // parse_srt_cues skips such cues, which rewriting the file would silently drop.
+    if let Some(line) = content.lines().find(|line| line.contains("-->") && parse_timing_line(line).is_none()) {
+        return Err(ShortsError::InvalidArgument(format!("Unreadable cue timing '{}'", line.trim())));
+    }
+    let mut srt = String::new();
+    let mut cue_count = 0;
+    for cue in parse_srt_cues(content) {
+        if let Some((start_ms, end_ms)) = map_timing(cue.start.as_millis() as u64, cue.end.as_millis() as u64) {
+            cue_count += 1;
+            srt.push_str(&format!(
+                "{}\n{} --> {}\n{}\n\n",
+                cue_count,
+                format_srt_timestamp(start_ms),
+                format_srt_timestamp(end_ms),
+                cue.text
+            ));
+        }
+    }
+    if is_vtt {
+        Ok((srt_to_vtt(&srt), cue_count))
+    } else {
+        Ok((srt, cue_count))
+    }
+}
+
+// Rewrites `content`, an SRT, VTT or ASS file according to its `path`, with `map_timing` applied
+// to every cue (see map_srt_timings and map_ass_timings).
+fn map_subtitle_timings<F: Fn(u64, u64) -> Option<(u64, u64)>>(
+    path: &str,
+    content: &str,
+    map_timing: F,
+) -> Result<(String, usize), ShortsError> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("ass") | Some("ssa") => Ok(map_ass_timings(content, map_timing)),
+        Some("srt") => map_srt_timings(content, false, map_timing),
+        Some("vtt") => map_srt_timings(content, true, map_timing),
+        _ => Err(ShortsError::InvalidArgument(format!(
+            "Unsupported subtitle file '{}'; use an .srt, .vtt or .ass file",
+            path
+        ))),
+    }
+}
+
+// Copies a subtitle file written for the whole source video to `output_path`, keeping only what
+// is shown between `start_secs` and `start_secs + duration_secs` and moving it to start at zero,
+// so it lines up with a clip trimmed to that window. SRT and VTT cues are renumbered; ASS scripts
+// keep their styling. The format follows the extension of `input_path` and is kept. Returns the
+// number of cues written.
+pub fn cut_subtitles_to_window(
+    input_path: &str,
+    output_path: &str,
+    start_secs: f64,
+    duration_secs: f64,
+) -> Result<usize, ShortsError> {
+    if !(start_secs.is_finite() && start_secs >= 0.0 && duration_secs.is_finite() && duration_secs > 0.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Invalid subtitle window: {}s long from {}s",
+            duration_secs, start_secs
+        )));
+    }
+    let content = std::fs::read_to_string(input_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", input_path, e)))?;
+    let window_start_ms = (start_secs * 1000.0).round() as u64;
+    let window_ms = (duration_secs * 1000.0).round() as u64;
+
+    let (cut, cue_count) = map_subtitle_timings(input_path, &content, |start_ms, end_ms| {
+        cue_in_window(start_ms, end_ms, window_start_ms, window_ms)
+    })?;
+    std::fs::write(output_path, cut)?;
+    Ok(cue_count)
+}
+
+// Rewrites an SRT, VTT or ASS subtitle file (by extension) in place so captions only show between
+// `start_secs` and `end_secs` (None: until the end), e.g. for captions on the intro of a short
+// only. Cues partly inside the window are cut to it and the others are dropped; unlike
+// cut_subtitles_to_window, the timings stay where they are. Returns the number of cues kept.
+pub fn restrict_subtitles_to_window(subtitle_path: &str, start_secs: f64, end_secs: Option<f64>) -> Result<usize, ShortsError> {
+    let end_is_valid = end_secs.is_none_or(|end_secs| end_secs.is_finite() && end_secs > start_secs);
+    if !(start_secs.is_finite() && start_secs >= 0.0 && end_is_valid) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Invalid caption window: from {}s to {}",
+            start_secs,
+            end_secs.map_or("the end".to_string(), |end_secs| format!("{}s", end_secs))
+        )));
+    }
+    let content = std::fs::read_to_string(subtitle_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", subtitle_path, e)))?;
+    let window_start_ms = (start_secs * 1000.0).round() as u64;
+    let window_end_ms = end_secs.map_or(u64::MAX, |end_secs| (end_secs * 1000.0).round() as u64);
+    let (restricted, cue_count) = map_subtitle_timings(subtitle_path, &content, |start_ms, end_ms| {
+        cue_clipped_to(start_ms, end_ms, window_start_ms, window_end_ms)
+    })?;
+    std::fs::write(subtitle_path, restricted)?;
+    Ok(cue_count)
+}
+
+// Rewrites an SRT, VTT or ASS subtitle file (by extension) in place so no cue is shown longer
+// than `max_secs`, ending longer ones early, e.g. a cue whisper stretched over a long silence that
+// would otherwise stay frozen on screen. Ends only move earlier, so no cue comes to overlap the
+// next one. Returns the number of cues shortened.
+pub fn limit_cue_durations(subtitle_path: &str, max_secs: f64) -> Result<usize, ShortsError> {
+    if !(max_secs.is_finite() && max_secs > 0.0) {
+        return Err(ShortsError::InvalidArgument(format!("Maximum cue duration must be greater than zero, got {}s", max_secs)));
+    }
+    let content = std::fs::read_to_string(subtitle_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", subtitle_path, e)))?;
+    let max_ms = (max_secs * 1000.0).round() as u64;
+    let shortened = std::cell::Cell::new(0);
+    let (limited, _) = map_subtitle_timings(subtitle_path, &content, |start_ms, end_ms| {
+        if end_ms.saturating_sub(start_ms) > max_ms {
+            shortened.set(shortened.get() + 1);
+            return Some((start_ms, start_ms + max_ms));
+        }
+        Some((start_ms, end_ms))
+    })?;
+    std::fs::write(subtitle_path, limited)?;
+    Ok(shortened.get())
+}
+
+// Rewrites an SRT, VTT or ASS subtitle file (by extension) in place with every cue moved by
+// `offset_secs`: later when positive, earlier when negative, e.g. to line captions up with speech
+// they run ahead of. Cues pushed before zero start at zero, and those that would end at or before
+// it are dropped; SRT and VTT cues are renumbered. Returns the number of cues kept.
+pub fn shift_srt_timing(subtitle_path: &str, offset_secs: f64) -> Result<usize, ShortsError> {
+    if !offset_secs.is_finite() {
+        return Err(ShortsError::InvalidArgument(format!("Invalid subtitle offset: {}s", offset_secs)));
+    }
+    let content = std::fs::read_to_string(subtitle_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", subtitle_path, e)))?;
+    let offset_ms = (offset_secs * 1000.0).round() as i64;
+    let (shifted, cue_count) =
+        map_subtitle_timings(subtitle_path, &content, |start_ms, end_ms| shifted_cue(start_ms, end_ms, offset_ms))?;
+    std::fs::write(subtitle_path, shifted)?;
+    Ok(cue_count)
+}
+
+// WebVTT is SRT with a header and '.' as the millisecond separator.
+fn srt_to_vtt(content: &str) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for line in content.replace("\r\n", "\n").lines() {
+        if line.contains("-->") {
+            vtt.push_str(&line.replace(',', "."));
+        } else {
+            vtt.push_str(line);
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+// Builds an ASS script with a single Default style. PlayRes and the style values match
+// what FFmpeg uses when it renders a plain SRT, so both look the same when burned.
+fn srt_to_ass(content: &str) -> String {
+    let mut ass = String::from(
+        "[Script Info]\n\
+         ScriptType: v4.00+\n\
+         PlayResX: 384\n\
+         PlayResY: 288\n\
+         ScaledBorderAndShadow: yes\n\
+         \n\
+         [V4+ Styles]\n\
+         Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Default,Arial,16,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,1,0,2,10,10,10,1\n\
+         \n\
+         [Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+    for cue in parse_srt_cues(content) {
+        ass.push_str(&format!(
+            "Dialogue: 0,{},{},Default,,0,0,0,,{}\n",
+            format_ass_timestamp(cue.start.as_millis() as u64),
+            format_ass_timestamp(cue.end.as_millis() as u64),
+            cue.text.replace('\n', "\\N")
+        ));
+    }
+    ass
+}
+
+// Greedily fills lines of at most `max_chars` characters, breaking only between words.
+// A word longer than `max_chars` is kept whole on a line of its own.
+fn wrap_cue_text(text: &str, max_chars: usize) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines.join("\n")
+}
+
+// Replaces the text of every cue with `map_text(text lines)`. Cue numbers and timings are kept as
+// they are. Works on SRT and VTT content, as both put the text after the "start --> end" line;
+// blocks without one (such as the WEBVTT header) are left untouched.
+fn map_subtitle_cue_text<F: Fn(&[&str]) -> String>(content: &str, map_text: F) -> String {
+    let content = content.replace("\r\n", "\n");
+    let blocks: Vec<String> = content
+        .split("\n\n")
+        .map(|block| {
+            let lines: Vec<&str> = block.lines().collect();
+            match lines.iter().position(|l| l.contains("-->")) {
+                Some(timing) if timing + 1 < lines.len() => {
+                    let mut mapped = lines[..=timing].join("\n");
+                    mapped.push('\n');
+                    mapped.push_str(&map_text(&lines[timing + 1..]));
+                    mapped
+                }
+                _ => block.to_string(),
+            }
+        })
+        .collect();
+    let mut mapped = blocks.join("\n\n");
+    if content.ends_with('\n') && !mapped.ends_with('\n') {
+        mapped.push('\n');
+    }
+    mapped
+}
+
+// Re-flows the text of every cue to at most `max_chars` characters per line.
+fn wrap_subtitle_content(content: &str, max_chars: usize) -> String {
+    map_subtitle_cue_text(content, |text_lines| wrap_cue_text(&text_lines.join(" "), max_chars))
+}
+
+// Rewrites an SRT (or VTT) subtitle file in place so no line is longer than `max_chars`
+// characters, splitting on word boundaries and preserving cue timing.
+pub fn wrap_srt_lines(srt_path: &str, max_chars: usize) -> Result<(), ShortsError> {
+    if max_chars == 0 {
+        return Err(ShortsError::InvalidArgument(
+            "Maximum subtitle line length must be at least 1 character".to_string(),
+        ));
+    }
+    let content = std::fs::read_to_string(srt_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", srt_path, e)))?;
+    std::fs::write(srt_path, wrap_subtitle_content(&content, max_chars))?;
+    Ok(())
+}
+
+// Applies `transform` to one line of caption text, character by character so non-ASCII letters
+// are mapped too (e.g. "é" -> "É", "ß" -> "SS"). Markup such as <i> tags and {\an8} overrides is
+// left as written.
+fn transform_caption_line(line: &str, transform: CaptionTextTransform) -> String {
+    let mut transformed = String::with_capacity(line.len());
+    let mut in_markup = None;
+    let mut at_word_start = true;
+    for c in line.chars() {
+        match (in_markup, c) {
+            (None, '<') => in_markup = Some('>'),
+            (None, '{') => in_markup = Some('}'),
+            (Some(end), c) if c == end => {
+                in_markup = None;
+                transformed.push(c);
+                continue;
+            }
+            _ => {}
+        }
+        if in_markup.is_some() {
+            transformed.push(c);
+            continue;
+        }
+        let upper = match transform {
+            CaptionTextTransform::None => None,
+            CaptionTextTransform::Upper => Some(true),
+            CaptionTextTransform::Lower => Some(false),
+            CaptionTextTransform::TitleCase => Some(at_word_start),
+        };
+        match upper {
+            Some(true) => transformed.extend(c.to_uppercase()),
+            Some(false) => transformed.extend(c.to_lowercase()),
+            None => transformed.push(c),
+        }
+        if c.is_alphanumeric() {
+            at_word_start = false;
+        } else if c.is_whitespace() {
+            at_word_start = true;
+        }
+    }
+    transformed
+}
+
+// Rewrites an SRT (or VTT) subtitle file in place with `transform` applied to the caption text.
+// Cue numbers, timings and line breaks are kept as they are.
+pub fn transform_srt_text(srt_path: &str, transform: CaptionTextTransform) -> Result<(), ShortsError> {
+    let content = std::fs::read_to_string(srt_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", srt_path, e)))?;
+    let transformed = map_subtitle_cue_text(&content, |text_lines| {
+        text_lines
+            .iter()
+            .map(|line| transform_caption_line(line, transform))
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    std::fs::write(srt_path, transformed)?;
+    Ok(())
+}
+
+// WebVTT cue settings placing a cue like burned subtitles with this alignment: `line` for the
+// vertical position and `align` for the horizontal one.
+fn vtt_cue_settings(vertical: &str, horizontal: &str) -> Result<[(&'static str, &'static str); 2], ShortsError> {
+    let alignment = crate::video_processing::map_alignment_to_ffmpeg_value(vertical, horizontal)?;
+    let line = match (alignment - 1) / 3 {
+        0 => "-1", // The last line of the video, where cues go by default
+        1 => "50%",
+        _ => "0",
+    };
+    let align = match (alignment - 1) % 3 {
+        0 => "left",
+        1 => "center",
+        _ => "right",
+    };
+    Ok([("line", line), ("align", align)])
+}
+
+// Appends `settings` to every cue timing line that doesn't set them already. The cue text,
+// including voice (<v Name>) and class (<c.yellow>) tags, is left untouched.
+fn add_vtt_cue_settings(content: &str, settings: &[(&str, &str)]) -> String {
+    let content = content.replace("\r\n", "\n");
+    let mut updated: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let Some((_, after_arrow)) = line.split_once("-->") else {
+                return line.to_string();
+            };
+            // The end timestamp comes first, then the existing "name:value" settings.
+            let existing: Vec<&str> = after_arrow
+                .split_whitespace()
+                .skip(1)
+                .filter_map(|setting| setting.split_once(':').map(|(name, _)| name))
+                .collect();
+            let mut line = line.trim_end().to_string();
+            for (name, value) in settings {
+                if !existing.contains(name) {
+                    line.push_str(&format!(" {}:{}", name, value));
+                }
+            }
+            line
+        })
+        .collect();
+    if content.ends_with('\n') {
+        updated.push(String::new());
+    }
+    updated.join("\n")
+}
+
+// Rewrites a WebVTT file in place so players show its cues at the position burned subtitles
+// with this alignment would have (e.g. "top", "center"), for soft captions that look like the
+// burned ones. Settings a cue already has are kept.
+pub fn apply_vtt_positioning(vtt_path: &str, vertical_alignment: &str, horizontal_alignment: &str) -> Result<(), ShortsError> {
+    let settings = vtt_cue_settings(vertical_alignment, horizontal_alignment)?;
+    let content = std::fs::read_to_string(vtt_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", vtt_path, e)))?;
+    if !content.trim_start_matches('\u{feff}').starts_with("WEBVTT") {
+        return Err(ShortsError::InvalidArgument(format!("Not a WebVTT file: {}", vtt_path)));
+    }
+    std::fs::write(vtt_path, add_vtt_cue_settings(&content, &settings))?;
+    Ok(())
+}
+
+// Longest fade, pop or slide of an animated caption; shorter cues animate over their whole length.
+const CAPTION_ANIMATION_MS: u64 = 200;
+
+// How far a SlideUp caption travels, as a fraction of the script height.
+const SLIDE_UP_DISTANCE: f64 = 0.05;
+
+// Where a style puts its text: the numpad alignment and the left, right and vertical margins.
+#[derive(Debug, Clone, Copy)]
+struct AssPlacement {
+    alignment: u8,
+    margins: [f64; 3],
+}
+
+// The script size (PlayResX/PlayResY, libass' 384x288 default when missing) and the placement of
+// each style by name, read through the [V4+ Styles] Format line.
+fn ass_script_layout(content: &str) -> ((f64, f64), std::collections::HashMap<String, AssPlacement>) {
+    let mut play_res = (None, None);
+    let mut styles = std::collections::HashMap::new();
+    let mut style_format: Vec<String> = Vec::new();
+    let mut in_styles = false;
+    for line in content.lines() {
+        if line.starts_with('[') {
+            in_styles = line.trim().eq_ignore_ascii_case("[V4+ Styles]") || line.trim().eq_ignore_ascii_case("[V4 Styles]");
+        } else if let Some(value) = line.strip_prefix("PlayResX:") {
+            play_res.0 = value.trim().parse::<f64>().ok();
+        } else if let Some(value) = line.strip_prefix("PlayResY:") {
+            play_res.1 = value.trim().parse::<f64>().ok();
+        } else if let Some(fields) = line.strip_prefix("Format:").filter(|_| in_styles) {
+            style_format = fields.split(',').map(|field| field.trim().to_ascii_lowercase()).collect();
+        } else if let Some(fields) = line.strip_prefix("Style:") {
+            let values: Vec<&str> = fields.split(',').map(|value| value.trim()).collect();
+            let field = |name: &str| style_format.iter().position(|field| field == name).and_then(|index| values.get(index));
+            let number = |name: &str| field(name).and_then(|value| value.parse::<f64>().ok()).unwrap_or(0.0);
+            if let Some(name) = field("name") {
+                styles.insert(
+                    name.to_string(),
+                    AssPlacement {
+                        alignment: field("alignment").and_then(|value| value.parse().ok()).unwrap_or(2),
+                        margins: [number("marginl"), number("marginr"), number("marginv")],
+                    },
+                );
+            }
+        }
+    }
+    let play_res = match play_res {
+        (Some(x), Some(y)) => (x, y),
+        (Some(x), None) => (x, x * 3.0 / 4.0),
+        (None, Some(y)) => (y * 4.0 / 3.0, y),
+        (None, None) => (384.0, 288.0),
+    };
+    (play_res, styles)
+}
+
+// The anchor point of text placed with `placement` in a `play_res` script, as \pos would take it.
+fn ass_anchor_point(placement: AssPlacement, play_res: (f64, f64)) -> (f64, f64) {
+    let [margin_l, margin_r, margin_v] = placement.margins;
+    let x = match (placement.alignment.clamp(1, 9) - 1) % 3 {
+        0 => margin_l,
+        1 => (margin_l + play_res.0 - margin_r) / 2.0,
+        _ => play_res.0 - margin_r,
+    };
+    let y = match (placement.alignment.clamp(1, 9) - 1) / 3 {
+        0 => play_res.1 - margin_v,
+        1 => play_res.1 / 2.0,
+        _ => margin_v,
+    };
+    (x, y)
+}
+
+// The override tags starting a caption shown for `cue_ms` with `animation`. `slide_from` is the
+// caption's anchor point for SlideUp, None when the cue positions itself (\pos, \move) and
+// only fades in.
+fn caption_animation_tags(animation: CaptionAnimation, cue_ms: u64, slide_from: Option<(f64, f64)>, play_res_y: f64) -> String {
+    let ms = CAPTION_ANIMATION_MS.min(cue_ms);
+    match animation {
+        CaptionAnimation::None => String::new(),
+        CaptionAnimation::FadeIn => format!("{{\\fad({},0)}}", ms),
+        CaptionAnimation::Pop => format!("{{\\fad({},0)\\fscx80\\fscy80\\t(0,{},\\fscx100\\fscy100)}}", ms, ms),
+        CaptionAnimation::SlideUp => match slide_from {
+            Some((x, y)) => format!(
+                "{{\\fad({},0)\\move({:.0},{:.0},{:.0},{:.0},0,{})}}",
+                ms,
+                x,
+                y + play_res_y * SLIDE_UP_DISTANCE,
+                x,
+                y,
+                ms
+            ),
+            None => format!("{{\\fad({},0)}}", ms),
+        },
+    }
+}
+
+// Rewrites the Dialogue events of an ASS script in place so each caption appears with
+// `animation`, e.g. popping in from 80% of its size. The animation is at most 200ms and never
+// longer than the cue. SlideUp moves a caption up into the place its style (or an \an tag) puts
+// it; captions that position themselves only fade in. Styles and timings are left unchanged.
+pub fn animate_ass_cues(ass_path: &str, animation: CaptionAnimation) -> Result<(), ShortsError> {
+    let content = std::fs::read_to_string(ass_path)
+        .map_err(|e| Error::msg(format!("Failed to read subtitle file '{}': {}", ass_path, e)))?;
+    let content = content.replace("\r\n", "\n");
+    let (play_res, styles) = ass_script_layout(&content);
+
+    let mut animated = String::with_capacity(content.len());
+    for line in content.lines() {
+        let event = line.strip_prefix("Dialogue:").map(|fields| fields.splitn(10, ',').collect::<Vec<_>>());
+        let Some(fields) = event.filter(|fields| fields.len() == 10) else {
+            animated.push_str(line);
+            animated.push('\n');
+            continue;
+        };
+        let cue_ms = match (parse_ass_timestamp(fields[1]), parse_ass_timestamp(fields[2])) {
+            (Some(start_ms), Some(end_ms)) => end_ms.saturating_sub(start_ms),
+            _ => CAPTION_ANIMATION_MS,
+        };
+        let text = fields[9];
+        let slide_from = if text.contains("\\pos(") || text.contains("\\move(") {
+            None
+        } else {
+            let mut placement = styles.get(fields[3].trim().trim_start_matches('*')).copied().unwrap_or(AssPlacement { alignment: 2, margins: [10.0; 3] });
+            // An \an tag in the text overrides the style's alignment, and non-zero event margins its margins.
+            if let Some(alignment) = text.split("\\an").nth(1).and_then(|rest| rest.chars().next()).and_then(|c| c.to_digit(10)) {
+                placement.alignment = alignment as u8;
+            }
+            for (margin, event_margin) in placement.margins.iter_mut().zip(&fields[5..8]) {
+                if let Some(value) = event_margin.trim().parse::<f64>().ok().filter(|value| *value != 0.0) {
+                    *margin = value;
+                }
+            }
+            Some(ass_anchor_point(placement, play_res))
+        };
+        animated.push_str(&format!(
+            "Dialogue:{},{}{}\n",
+            fields[..9].join(","),
+            caption_animation_tags(animation, cue_ms, slide_from, play_res.1),
+            text
+        ));
+    }
+    std::fs::write(ass_path, animated)?;
+    Ok(())
+}
+
+// Converts an SRT file written by whisper into the requested subtitle format.
+fn convert_srt_file(srt_path: &Path, output_path: &Path, format: SubtitleFormat) -> Result<(), Error> {
+    let content = std::fs::read_to_string(srt_path)?;
+    let converted = match format {
+        SubtitleFormat::Srt => content,
+        SubtitleFormat::Vtt => srt_to_vtt(&content),
+        SubtitleFormat::Ass => srt_to_ass(&content),
+    };
+    std::fs::write(output_path, converted)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDir;
+
+    #[test]
+    fn whisper_languages_resolve_from_codes_and_names() {
+        assert_eq!(resolve_whisper_language("de").unwrap(), "de");
+        assert_eq!(resolve_whisper_language("Spanish").unwrap(), "es");
+        assert_eq!(resolve_whisper_language(" YUE ").unwrap(), "yue");
+        assert!(matches!(resolve_whisper_language("klingon"), Err(ShortsError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn whisper_runs_on_the_cpu_without_fp16_unless_a_gpu_is_found() {
+        let args = |device, fp16, gpu_available: bool| whisper_device_args(device, fp16, || gpu_available);
+        assert_eq!(args(None, None, false), ["--device", "cpu", "--fp16", "False"]);
+        assert!(args(None, None, true).is_empty());
+        assert_eq!(args(Some("cuda:1"), Some(true), false), ["--device", "cuda:1", "--fp16", "True"]);
+        assert_eq!(args(Some("cpu"), Some(true), true), ["--device", "cpu", "--fp16", "True"]);
+        assert!(is_whisper_device("cuda:0") && is_whisper_device("cpu"));
+        assert!(!is_whisper_device("gpu") && !is_whisper_device("cuda:"));
+    }
+
+    #[test]
+    fn missing_whisper_binary_is_reported_as_not_found() {
+        let err = check_whisper_binary("/nonexistent/bin/whisper").unwrap_err();
+        assert!(matches!(err, ShortsError::WhisperNotFound(ref binary) if binary == "/nonexistent/bin/whisper"));
+        assert!(err.to_string().contains("--whisper-binary-path"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn whisper_is_retried_after_a_failure_but_not_when_missing() {
+        let dir = TestDir::new("whisper_retry");
+        // Fails on the first run only, like a transient out-of-memory error.
+        let marker = dir.join("ran_once");
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!(
+            "if [ -e '{0}' ]; then exit 0; else touch '{0}'; echo 'CUDA out of memory' >&2; exit 1; fi",
+            marker.display()
+        ));
+        assert!(run_whisper_with_retries(&mut command, "sh", 1, Duration::ZERO, None).is_err());
+        std::fs::remove_file(&marker).unwrap();
+        assert!(run_whisper_with_retries(&mut command, "sh", 2, Duration::ZERO, None).is_ok());
+
+        let mut missing = Command::new("/nonexistent/bin/whisper");
+        let err = run_whisper_with_retries(&mut missing, "/nonexistent/bin/whisper", 3, Duration::ZERO, None).unwrap_err();
+        assert!(matches!(err, ShortsError::WhisperNotFound(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancelling_kills_a_running_whisper() {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo transcribing; sleep 30");
+        let started = std::time::Instant::now();
+        let is_cancelled = || started.elapsed() > Duration::from_millis(200);
+        let err = run_whisper_with_retries(&mut command, "sh", 3, Duration::ZERO, Some(&is_cancelled)).unwrap_err();
+        assert!(matches!(err, ShortsError::Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo done");
+        let output = run_whisper_with_retries(&mut command, "sh", 1, Duration::ZERO, Some(&|| false)).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+    }
+
+    #[test]
+    fn check_ggml_model_rejects_non_ggml_files() {
+        let dir = TestDir::new("ggml_check");
+
+        let valid = dir.join("ggml-tiny.bin");
+        std::fs::write(&valid, b"lmgg\x00\x00\x00\x00").unwrap();
+        assert!(check_ggml_model(&valid).is_ok());
+
+        let invalid = dir.join("model.pt");
+        std::fs::write(&invalid, b"PK\x03\x04").unwrap();
+        assert!(check_ggml_model(&invalid).is_err());
+    }
+
+    #[test]
+    fn cut_subtitles_to_window_shifts_clips_and_drops_cues() {
+        let dir = TestDir::new("cut_subtitles");
+        let srt_in = dir.join("in.srt");
+        let srt_out = dir.join("out.srt");
+        std::fs::write(
+            &srt_in,
+            "1\n00:00:05,000 --> 00:00:08,000\nBefore\n\n2\n00:00:09,000 --> 00:00:12,000\nEdge\n\n3\n00:00:15,500 --> 00:00:17,000\nInside\n\n4\n00:01:00,000 --> 00:01:01,000\nAfter\n",
+        )
+        .unwrap();
+        let count = cut_subtitles_to_window(srt_in.to_str().unwrap(), srt_out.to_str().unwrap(), 10.0, 30.0).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            std::fs::read_to_string(&srt_out).unwrap(),
+            "1\n00:00:00,000 --> 00:00:02,000\nEdge\n\n2\n00:00:05,500 --> 00:00:07,000\nInside\n\n"
         );
+
+        let (ass, count) = map_ass_timings(
+            "[Events]\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Gone\nDialogue: 0,0:00:12.50,0:00:14.00,Default,,0,0,0,,Kept, with a comma\n",
+            |start_ms, end_ms| cue_in_window(start_ms, end_ms, 10_000, 30_000),
+        );
+        assert_eq!(count, 1);
+        assert_eq!(ass, "[Events]\nDialogue: 0,0:00:02.50,0:00:04.00,Default,,0,0,0,,Kept, with a comma\n");
     }
 
-    // Determine the expected output file path
-    let audio_file_name = audio_path
-        .file_stem()
-        .ok_or_else(|| Error::msg(format!("Could not extract file stem from audio path: {}", audio_input_path)))?
-        .to_str()
-        .ok_or_else(|| Error::msg("Audio file stem is not valid UTF-8"))?;
-    
-    let srt_file_name = format!("{}.srt", audio_file_name);
-    let mut srt_path = PathBuf::from(output_dir);
-    srt_path.push(srt_file_name);
-
-    if !srt_path.exists() {
-        // Whisper might sometimes put files in a subdirectory named after the model,
-        // or have other naming conventions if the input has unusual characters.
-        // For now, we assume direct output in output_dir.
-        // A more robust solution might involve listing files in output_dir if this assumption fails.
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!(
-            "Subtitle file not found at expected path: {:?}. Whisper stdout: {}, stderr: {}",
-            srt_path,
-            stdout,
-            stderr
+    #[test]
+    fn restrict_subtitles_to_window_clips_and_drops_cues() {
+        let dir = TestDir::new("caption_window");
+        let srt = dir.join("captions.srt");
+        std::fs::write(
+            &srt,
+            "1\n00:00:01,000 --> 00:00:04,000\nWelcome\n\n2\n00:00:13,000 --> 00:00:17,000\nTo the show\n\n3\n00:00:20,000 --> 00:00:22,000\nLater\n",
+        )
+        .unwrap();
+        let count = restrict_subtitles_to_window(srt.to_str().unwrap(), 2.0, Some(15.0)).unwrap();
+        let content = std::fs::read_to_string(&srt).unwrap();
+        let ass = dir.join("captions.ass");
+        std::fs::write(&ass, "[Events]\nDialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Intro\nDialogue: 0,0:00:30.00,0:00:31.00,Default,,0,0,0,,Outro\n").unwrap();
+        let ass_count = restrict_subtitles_to_window(ass.to_str().unwrap(), 0.0, Some(15.0)).unwrap();
+        let invalid = restrict_subtitles_to_window(srt.to_str().unwrap(), 5.0, Some(5.0));
+
+        assert_eq!(count, 2);
+        assert_eq!(content, "1\n00:00:02,000 --> 00:00:04,000\nWelcome\n\n2\n00:00:13,000 --> 00:00:15,000\nTo the show\n\n");
+        assert_eq!(ass_count, 1);
+        assert!(matches!(invalid, Err(ShortsError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn limit_cue_durations_ends_long_cues_early() {
+        let dir = TestDir::new("cue_durations");
+        let srt = dir.join("captions.srt");
+        std::fs::write(
+            &srt,
+            "1\n00:00:01,000 --> 00:00:12,000\nLingering\n\n2\n00:00:12,000 --> 00:00:14,000\nNext\n",
+        )
+        .unwrap();
+        let shortened = limit_cue_durations(srt.to_str().unwrap(), 5.0).unwrap();
+        let content = std::fs::read_to_string(&srt).unwrap();
+        let invalid = limit_cue_durations(srt.to_str().unwrap(), 0.0);
+
+        assert_eq!(shortened, 1);
+        assert_eq!(content, "1\n00:00:01,000 --> 00:00:06,000\nLingering\n\n2\n00:00:12,000 --> 00:00:14,000\nNext\n\n");
+        assert!(matches!(invalid, Err(ShortsError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn shift_srt_timing_moves_clamps_and_drops_cues() {
+        let dir = TestDir::new("shift_subtitles");
+        let srt = dir.join("captions.srt");
+        let shift = |content: &str, offset_secs: f64| {
+            std::fs::write(&srt, content).unwrap();
+            let count = shift_srt_timing(srt.to_str().unwrap(), offset_secs).unwrap();
+            (count, std::fs::read_to_string(&srt).unwrap())
+        };
+
+        let later = shift(SAMPLE_SRT, 0.25);
+        let earlier = shift(SAMPLE_SRT, -0.5);
+        // The first cue starts before zero and is clamped; a cue ending at zero is dropped.
+        let clamped = shift("1\n00:00:00,500 --> 00:00:01,000\nGone\n\n2\n00:00:01,000 --> 00:00:03,000\nClamped\n", -1.5);
+        let vtt = dir.join("captions.vtt");
+        // Hourless timestamps, as whisper writes them.
+        std::fs::write(&vtt, "WEBVTT\n\n00:02.000 --> 00:03.000\nHi\n").unwrap();
+        let vtt_count = shift_srt_timing(vtt.to_str().unwrap(), -1.0).unwrap();
+        let vtt_content = std::fs::read_to_string(&vtt).unwrap();
+        let invalid = shift_srt_timing(srt.to_str().unwrap(), f64::NAN);
+        let broken_content = "1\n00:00:01,000 --> later\nHi\n\n";
+        std::fs::write(&srt, broken_content).unwrap();
+        let broken = shift_srt_timing(srt.to_str().unwrap(), 1.0);
+        let broken_after = std::fs::read_to_string(&srt).unwrap();
+
+        assert_eq!(
+            later,
+            (2, "1\n00:00:01,250 --> 00:00:02,750\nHello there\nsecond line\n\n2\n00:01:00,290 --> 00:01:02,250\nBye\n\n".to_string())
+        );
+        assert_eq!(
+            earlier,
+            (2, "1\n00:00:00,500 --> 00:00:02,000\nHello there\nsecond line\n\n2\n00:00:59,540 --> 00:01:01,500\nBye\n\n".to_string())
+        );
+        assert_eq!(clamped, (1, "1\n00:00:00,000 --> 00:00:01,500\nClamped\n\n".to_string()));
+        assert_eq!((vtt_count, vtt_content.as_str()), (1, "WEBVTT\n\n1\n00:00:01.000 --> 00:00:02.000\nHi\n\n"));
+        assert!(matches!(invalid, Err(ShortsError::InvalidArgument(_))));
+        assert!(matches!(broken, Err(ShortsError::InvalidArgument(message)) if message.contains("later")));
+        assert_eq!(broken_after, broken_content);
+    }
+
+    const SAMPLE_SRT: &str = "1\n00:00:01,000 --> 00:00:02,500\nHello there\nsecond line\n\n2\n00:01:00,040 --> 00:01:02,000\nBye\n";
+
+    #[test]
+    fn parse_srt_cues_reads_numbers_timing_and_multiline_text() {
+        let cues = parse_srt_cues(&SAMPLE_SRT.replace('\n', "\r\n"));
+        assert_eq!(cues.len(), 2);
+        assert_eq!(cues[0], SubtitleCue {
+            index: 1,
+            start: Duration::from_millis(1_000),
+            end: Duration::from_millis(2_500),
+            text: "Hello there\nsecond line".to_string(),
+        });
+        assert_eq!(cues[1].start, Duration::from_millis(60_040));
+
+        // VTT uses periods and may omit cue numbers.
+        let vtt = parse_srt_cues("WEBVTT\n\n00:00:01.000 --> 00:00:02.000 align:start\nHi\n");
+        assert_eq!(vtt.len(), 1);
+        assert_eq!((vtt[0].index, vtt[0].end), (1, Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn whisper_vtt_without_hours_parses() {
+        // As openai-whisper writes VTT for audio shorter than an hour.
+        let vtt = parse_srt_cues(
+            "WEBVTT\n\n00:00.000 --> 00:03.500\nHello and welcome back.\n\n00:03.500 --> 01:12.040\nToday we are looking at Rust.\n\n",
+        );
+        assert_eq!(vtt.len(), 2);
+        assert_eq!((vtt[0].start, vtt[0].end), (Duration::ZERO, Duration::from_millis(3_500)));
+        assert_eq!(vtt[1].end, Duration::from_millis(72_040));
+        assert_eq!(vtt[1].text, "Today we are looking at Rust.");
+        assert_eq!(parse_srt_timestamp("1:02:03:04.000"), None);
+    }
+
+    #[test]
+    fn srt_to_vtt_adds_header_and_uses_periods() {
+        let vtt = srt_to_vtt(SAMPLE_SRT);
+        assert!(vtt.starts_with("WEBVTT\n\n"));
+        assert!(vtt.contains("00:00:01.000 --> 00:00:02.500"));
+        assert!(vtt.contains("Hello there\nsecond line"));
+    }
+
+    #[test]
+    fn caption_transforms_change_case_but_not_markup() {
+        let line = "<i>élan</i> vital, don't STOP {\\an8}";
+        assert_eq!(transform_caption_line(line, CaptionTextTransform::Upper), "<i>ÉLAN</i> VITAL, DON'T STOP {\\an8}");
+        assert_eq!(transform_caption_line(line, CaptionTextTransform::Lower), "<i>élan</i> vital, don't stop {\\an8}");
+        assert_eq!(transform_caption_line(line, CaptionTextTransform::TitleCase), "<i>Élan</i> Vital, Don't Stop {\\an8}");
+        assert_eq!(transform_caption_line("straße", CaptionTextTransform::Upper), "STRASSE");
+        assert_eq!(transform_caption_line(line, CaptionTextTransform::None), line);
+    }
+
+    #[test]
+    fn wrap_subtitle_content_reflows_cues_and_keeps_timing() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,500\nthe quick brown\nfox jumps over\n\n2\n00:00:03,000 --> 00:00:04,000\nsupercalifragilistic\n";
+        assert_eq!(
+            wrap_subtitle_content(srt, 10),
+            "1\n00:00:01,000 --> 00:00:02,500\nthe quick\nbrown fox\njumps over\n\n2\n00:00:03,000 --> 00:00:04,000\nsupercalifragilistic\n"
+        );
+        let vtt = srt_to_vtt(srt);
+        assert!(wrap_subtitle_content(&vtt, 10).starts_with("WEBVTT\n\n1\n00:00:01.000 --> 00:00:02.500\nthe quick\n"));
+    }
+
+    #[test]
+    fn vtt_positioning_adds_missing_cue_settings_only() {
+        let vtt = "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\n<v Ana>Hola</v>\n\n00:00:03.000 --> 00:00:04.000 align:start\n<c.yellow>Adios</c>\n";
+        let settings = vtt_cue_settings("top", "center").unwrap();
+        assert_eq!(
+            add_vtt_cue_settings(vtt, &settings),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:02.000 line:0 align:center\n<v Ana>Hola</v>\n\n\
+             00:00:03.000 --> 00:00:04.000 align:start line:0\n<c.yellow>Adios</c>\n"
+        );
+        // Applying it twice changes nothing more.
+        let once = add_vtt_cue_settings(vtt, &settings);
+        assert_eq!(add_vtt_cue_settings(&once, &settings), once);
+
+        assert_eq!(vtt_cue_settings("bottom", "right").unwrap(), [("line", "-1"), ("align", "right")]);
+        assert!(vtt_cue_settings("above", "center").is_err());
+    }
+
+    #[test]
+    fn caption_animations_are_clamped_to_the_cue_and_slide_into_place() {
+        let dir = TestDir::new("animate");
+        let path = dir.join("captions.ass");
+        let path = path.to_str().unwrap();
+        let ass = srt_to_ass("1\n00:00:01,000 --> 00:00:03,000\nLong cue\n\n2\n00:00:03,000 --> 00:00:03,120\n{\\an8}Blink\n");
+
+        std::fs::write(path, &ass).unwrap();
+        animate_ass_cues(path, CaptionAnimation::Pop).unwrap();
+        let popped = std::fs::read_to_string(path).unwrap();
+        assert!(popped.contains(",,{\\fad(200,0)\\fscx80\\fscy80\\t(0,200,\\fscx100\\fscy100)}Long cue"), "{}", popped);
+        assert!(popped.contains(",,{\\fad(120,0)\\fscx80\\fscy80\\t(0,120,\\fscx100\\fscy100)}{\\an8}Blink"), "{}", popped);
+        assert!(popped.contains("Style: Default,Arial,16"));
+
+        // The Default style is bottom center with 10px margins in a 384x288 script; \an8 moves
+        // the second cue to the top.
+        std::fs::write(path, &ass).unwrap();
+        animate_ass_cues(path, CaptionAnimation::SlideUp).unwrap();
+        let slid = std::fs::read_to_string(path).unwrap();
+        assert!(slid.contains("{\\fad(200,0)\\move(192,292,192,278,0,200)}Long cue"), "{}", slid);
+        assert!(slid.contains("{\\fad(120,0)\\move(192,24,192,10,0,120)}{\\an8}Blink"), "{}", slid);
+    }
+
+    #[test]
+    fn srt_to_ass_writes_dialogue_lines() {
+        let ass = srt_to_ass(SAMPLE_SRT);
+        assert!(ass.contains("Dialogue: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,Hello there\\Nsecond line"));
+        assert!(ass.contains("Dialogue: 0,0:01:00.04,0:01:02.00,Default,,0,0,0,,Bye"));
+    }
+
+    #[test]
+    fn transcripts_drop_timings_and_styling() {
+        let dir = TestDir::new("transcript");
+        let srt_path = dir.join("captions.srt");
+        let ass_path = dir.join("captions.ass");
+        std::fs::write(&srt_path, SAMPLE_SRT).unwrap();
+        std::fs::write(&ass_path, srt_to_ass(SAMPLE_SRT).replace(",,Bye", ",,{\\fad(120,0)}Bye, for now")).unwrap();
+
+        let srt_transcript = transcript_text(&srt_path).unwrap();
+        let ass_transcript = transcript_text(&ass_path).unwrap();
+
+        assert_eq!(srt_transcript, "Hello there second line\nBye");
+        assert_eq!(ass_transcript, "Hello there second line\nBye, for now");
+    }
+
+    #[test]
+    fn cue_analysis_times_the_cues_and_flags_suspicious_ones() {
+        let srt = "1\n00:00:00,000 --> 00:00:02,000\nHello there\n\n\
+                   2\n00:00:01,500 --> 00:00:04,000\nhello  there\n\n\
+                   3\n00:00:04,000 --> 00:00:24,000\nThanks for watching\n\n\
+                   4\n00:00:03,000 --> 00:00:04,000\none two three four five six seven eight nine ten\n\n";
+        let stats = analyze_cues(&parse_srt_cues(srt));
+
+        assert_eq!(stats.cue_count, 4);
+        assert_eq!(stats.word_count, 17);
+        assert_eq!(stats.total_duration, Duration::from_millis(25_500));
+        assert_eq!(stats.average_duration, Duration::from_millis(6_375));
+        assert_eq!(stats.longest_cue, Some((3, Duration::from_secs(20))));
+        assert_eq!(
+            stats.issues,
+            [
+                CueIssue::Overlap { index: 2, previous: 1, overlap: Duration::from_millis(500) },
+                CueIssue::RepeatedText { index: 2, previous: 1 },
+                CueIssue::TooLong { index: 3, duration: Duration::from_secs(20) },
+                CueIssue::OutOfOrder { index: 4, previous: 3 },
+                CueIssue::TooFast { index: 4, words_per_minute: 600.0 },
+            ]
         );
+        assert_eq!(analyze_cues(&[]), SubtitleStats::default());
     }
 
-    Ok(srt_path.to_str().unwrap().to_string())
+    #[test]
+    fn cue_analysis_reports_an_overlapping_inverted_cue_as_ending_before_it_starts() {
+        let cue = |index: usize, start_ms: u64, end_ms: u64, text: &str| SubtitleCue {
+            index,
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+            text: text.to_string(),
+        };
+        let stats = analyze_cues(&[cue(1, 0, 2_000, "Hello there"), cue(2, 1_500, 1_000, "General Kenobi")]);
+
+        assert_eq!(stats.issues, [CueIssue::EndsBeforeStart { index: 2 }]);
+        assert_eq!(stats.total_duration, Duration::from_secs(2));
+    }
 }
@@ -0,0 +1,37 @@
+// Helpers shared by the unit tests.
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+// A directory of its own for one test under the system temp dir, named after the test and the
+// process. It is created empty and removed when dropped, including when the test panics.
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("shorts_{}_{}", name, std::process::id()));
+        // A previous run of this process id may have left it behind.
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,6 +1,11 @@
-use anyhow::{Result, Error, bail}; // Added bail
+use anyhow::{anyhow, bail, Context, Error, Result};
+use crate::config::{AspectFitMode, AudioCodec, SeekMode, VideoEncoder, X264Preset, X264Tune};
+use crate::error::ShortsError;
+use crate::subtitle_generation::SubtitleCue;
 use ffmpeg_next as ffmpeg;
-use std::path::Path;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 
 // Ensure FFmpeg is initialized.
 // This function is declared in lib.rs and should be called before ffmpeg operations.
@@ -12,11 +17,114 @@ use std::path::Path;
 //     // Alternatively, each public function here could call crate::init_ffmpeg();
 // }
 
-fn ensure_ffmpeg_initialized() {
+fn ensure_ffmpeg_initialized() -> Result<(), Error> {
     // This function ensures that FFmpeg is initialized.
-    // It calls the init_ffmpeg function from lib.rs.
-    // Note: The init_ffmpeg in lib.rs uses std::sync::Once, so it's safe to call this multiple times.
-    crate::init_ffmpeg();
+    // It calls the try_init_ffmpeg function from lib.rs and propagates a failure instead of panicking.
+    // Note: try_init_ffmpeg caches its outcome in a OnceLock, so it's safe to call this multiple times.
+    Ok(crate::try_init_ffmpeg()?)
+}
+
+// FFmpeg reports container durations in AV_TIME_BASE units (microseconds).
+const AV_TIME_BASE: f64 = 1_000_000.0;
+
+// Returns the container duration in seconds, or None when FFmpeg cannot determine it.
+fn input_duration_secs(ictx: &ffmpeg::format::context::Input) -> Option<f64> {
+    let duration = ictx.duration();
+    if duration > 0 {
+        Some(duration as f64 / AV_TIME_BASE)
+    } else {
+        None
+    }
+}
+
+// Turns packet timestamps into a completion fraction (0.0..=1.0) for progress callbacks.
+// Streams can end at different times, so reported values never go backwards and the
+// final report is always exactly 1.0 once the packet loop finishes.
+struct ProgressTracker<F: FnMut(f64)> {
+    callback: F,
+    start_secs: f64,
+    total_secs: f64,
+    last_reported: f64,
+}
+
+impl<F: FnMut(f64)> ProgressTracker<F> {
+    // Minimum increase between two reports, so callers aren't called back for every packet.
+    const STEP: f64 = 0.01;
+
+    fn new(callback: F, start_secs: f64, total_secs: f64) -> Self {
+        ProgressTracker { callback, start_secs, total_secs, last_reported: 0.0 }
+    }
+
+    fn update(&mut self, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) {
+        if self.total_secs <= 0.0 {
+            return;
+        }
+        if let Some(pts) = packet.pts() {
+            let packet_secs = pts as f64 * f64::from(time_base);
+            let fraction = ((packet_secs - self.start_secs) / self.total_secs).clamp(0.0, 1.0);
+            if fraction >= self.last_reported + Self::STEP {
+                self.last_reported = fraction;
+                (self.callback)(fraction);
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.last_reported < 1.0 {
+            self.last_reported = 1.0;
+            (self.callback)(1.0);
+        }
+    }
+}
+
+// Highest share of packets that may fail to be written before an output is treated as corrupt.
+pub const MAX_PACKET_WRITE_FAILURE_RATIO: f64 = 0.01;
+
+// How many packets a stream-copy loop passed to the muxer, and how many of them it rejected.
+// Rejected packets are logged and skipped, so a handful of them still leaves a playable output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketWriteStats {
+    pub total: usize,
+    pub failed: usize,
+}
+
+impl PacketWriteStats {
+    fn record(&mut self, result: Result<(), ffmpeg::Error>, output_path: &str) {
+        self.total += 1;
+        if let Err(e) = result {
+            self.failed += 1;
+            warn!("Failed to write packet to '{}': {}", output_path, e);
+        }
+    }
+
+    pub fn failure_ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.failed as f64 / self.total as f64
+        }
+    }
+
+    // Returns the stats unchanged, or PacketWriteFailed when more than
+    // MAX_PACKET_WRITE_FAILURE_RATIO of the packets failed, or with `strict` when any did.
+    pub fn check(self, output_path: &str, strict: bool) -> Result<Self, ShortsError> {
+        if self.failure_ratio() > MAX_PACKET_WRITE_FAILURE_RATIO || (strict && self.failed > 0) {
+            return Err(ShortsError::PacketWriteFailed {
+                path: output_path.to_string(),
+                failed: self.failed,
+                total: self.total,
+            });
+        }
+        Ok(self)
+    }
+}
+
+// The input_path that reads the input from stdin and the output_path that writes the short to
+// stdout, as with the ffmpeg CLI.
+pub const STDIO_PATH: &str = "-";
+
+pub fn is_stdio_path(path: &str) -> bool {
+    path == STDIO_PATH
 }
 
 pub fn trim_video(
@@ -24,87 +132,2709 @@ pub fn trim_video(
     output_path: &str,
     start_secs: f64,
     duration_secs: f64,
+) -> Result<PacketWriteStats, ShortsError> {
+    trim_video_with_progress(input_path, output_path, start_secs, duration_secs, None, SeekMode::Accurate, |_| {})
+}
+
+// Same as trim_video, calling `progress` with the fraction of the trim window written so far.
+// With `audio_stream_index` only that audio stream of the input (see probe_audio_streams) is kept,
+// so the later steps, which use the best audio stream, work on it.
+//
+// The streams are copied, so the video has to begin on a keyframe. SeekMode::Accurate reads the
+// input from the beginning and starts at the first keyframe at or after `start_secs`.
+// SeekMode::Fast seeks the input to the last keyframe at or before `start_secs` instead, without
+// reading anything before it; the short then starts up to one keyframe interval early. stdin
+// can't be seeked, so there Fast reads up to the start and begins at that same keyframe.
+#[allow(clippy::too_many_arguments)]
+pub fn trim_video_with_progress<F: FnMut(f64)>(
+    input_path: &str,
+    output_path: &str,
+    start_secs: f64,
+    duration_secs: f64,
+    audio_stream_index: Option<usize>,
+    seek_mode: SeekMode,
+    progress: F,
+) -> Result<PacketWriteStats, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+
+    if start_secs < 0.0 {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Trim start time must not be negative (got {}s)",
+            start_secs
+        )));
+    }
+
+    // stdin is opened through FFmpeg's pipe protocol and can only be read front to back.
+    let reads_stdin = is_stdio_path(input_path);
+    let mut ictx = ffmpeg::format::input(&Path::new(if reads_stdin { "pipe:0" } else { input_path }))?;
+    if let Some(index) = audio_stream_index {
+        select_audio_stream(&ictx, input_path, Some(index))?;
+    }
+
+    let mut expected_secs = duration_secs;
+    if let Some(input_duration) = input_duration_secs(&ictx) {
+        if start_secs >= input_duration {
+            return Err(ShortsError::InvalidArgument(format!(
+                "Trim start time {}s is past the end of '{}' (duration {:.2}s)",
+                start_secs,
+                input_path,
+                input_duration
+            )));
+        }
+        expected_secs = expected_secs.min(input_duration - start_secs);
+    }
+    let mut tracker = ProgressTracker::new(progress, start_secs, expected_secs);
+
+    let mut octx = ffmpeg::format::output(&Path::new(output_path))?;
+
+    // Maps input stream index -> output stream index for the streams that are copied.
+    let mut copied_streams: Vec<Option<usize>> = vec![None; ictx.nb_streams() as usize];
+    for ist_stream in ictx.streams() {
+        let ist_params = ist_stream.parameters();
+        let copied = match ist_params.medium() {
+            ffmpeg::media::Type::Video => true,
+            ffmpeg::media::Type::Audio => audio_stream_index.is_none_or(|index| index == ist_stream.index()),
+            _ => false,
+        };
+        if copied {
+            let mut ost_stream = octx.add_stream(None)?; 
+            ost_stream.set_parameters(ist_params.clone());
+            // Codec tag is part of parameters, should be copied by set_parameters if relevant.
+            // Explicit tag setting removed as it was causing errors and set_parameters should handle it.
+            copied_streams[ist_stream.index()] = Some(ost_stream.index());
+        }
+    }
+    
+    octx.set_metadata(ictx.metadata().to_owned());
+    octx.write_header()?;
+    let ost_time_bases: Vec<ffmpeg::Rational> = octx.streams().map(|stream| stream.time_base()).collect();
+    let ist_streams: Vec<(ffmpeg::Rational, ffmpeg::media::Type)> =
+        ictx.streams().map(|stream| (stream.time_base(), stream.parameters().medium())).collect();
+
+    // The time that becomes 0 in the output: start_secs, or with Fast the first packet copied
+    // after the seek, which can be earlier.
+    let mut zero_secs = match seek_mode {
+        SeekMode::Accurate => Some(start_secs),
+        SeekMode::Fast if reads_stdin => None,
+        SeekMode::Fast => {
+            let target_ts = (start_secs * AV_TIME_BASE) as i64;
+            ictx.seek(target_ts, ..=target_ts)?;
+            None
+        }
+    };
+    // With Fast on stdin, the packets from the last keyframe before start_secs on are held back
+    // until the start is reached, and then copied as if the input had been seeked to it.
+    let mut held_back: Option<Vec<(usize, ffmpeg::Packet)>> = (reads_stdin && seek_mode == SeekMode::Fast).then(Vec::new);
+    let end_secs = start_secs + duration_secs;
+    let mut started = vec![false; copied_streams.len()];
+    let mut finished = vec![false; copied_streams.len()];
+
+    let mut write_stats = PacketWriteStats::default();
+    'packets: for (stream, packet) in ictx.packets() {
+        let ist_idx = stream.index();
+        if copied_streams[ist_idx].is_none() {
+            continue;
+        }
+        if let Some(held) = held_back.as_mut() {
+            let (time_base, medium) = ist_streams[ist_idx];
+            let before_start = packet.pts().or(packet.dts()).is_some_and(|ts| (ts as f64 * f64::from(time_base)) < start_secs);
+            if before_start {
+                let keyframe = medium == ffmpeg::media::Type::Video && packet.is_key();
+                if keyframe {
+                    held.clear();
+                }
+                if keyframe || !held.is_empty() {
+                    held.push((ist_idx, packet));
+                }
+                continue;
+            }
+        }
+
+        for (ist_idx, mut packet) in held_back.take().into_iter().flatten().chain([(ist_idx, packet)]) {
+            let (time_base, medium) = ist_streams[ist_idx];
+            let Some(ost_idx) = copied_streams[ist_idx] else {
+                continue;
+            };
+            let Some(ts) = packet.pts().or(packet.dts()) else {
+                continue;
+            };
+            let packet_secs = ts as f64 * f64::from(time_base);
+            if packet_secs >= end_secs {
+                finished[ist_idx] = true;
+                if copied_streams.iter().zip(&finished).all(|(copied, done)| copied.is_none() || *done) {
+                    break 'packets;
+                }
+                continue;
+            }
+            if zero_secs.is_some_and(|zero_secs| packet_secs < zero_secs) {
+                continue;
+            }
+            if !started[ist_idx] {
+                if medium == ffmpeg::media::Type::Video && !packet.is_key() {
+                    continue;
+                }
+                started[ist_idx] = true;
+            }
+            let zero_secs = *zero_secs.get_or_insert(packet_secs.min(start_secs));
+
+            tracker.update(&packet, time_base);
+            let offset = (zero_secs / f64::from(time_base)).round() as i64;
+            packet.set_pts(packet.pts().map(|pts| pts - offset));
+            packet.set_dts(packet.dts().map(|dts| dts - offset));
+            packet.rescale_ts(time_base, ost_time_bases[ost_idx]);
+            packet.set_position(-1);
+            packet.set_stream(ost_idx);
+            write_stats.record(packet.write_interleaved(&mut octx), output_path);
+        }
+    }
+
+    octx.write_trailer()?;
+    tracker.finish();
+    write_stats.check(output_path, false)
+}
+
+// Whether this FFmpeg build has a muxer called `format_name` (e.g. "mp4", "mpegts", "matroska").
+pub fn output_format_available(format_name: &str) -> bool {
+    if ensure_ffmpeg_initialized().is_err() {
+        return false;
+    }
+    let Ok(name) = std::ffi::CString::new(format_name) else {
+        return false;
+    };
+    unsafe { !ffmpeg::ffi::av_guess_format(name.as_ptr(), std::ptr::null(), std::ptr::null()).is_null() }
+}
+
+// Whether this FFmpeg build has a filter called `filter_name` (e.g. "subtitles", "overlay").
+pub fn filter_available(filter_name: &str) -> bool {
+    ensure_ffmpeg_initialized().is_ok() && ffmpeg::filter::find(filter_name).is_some()
+}
+
+// The build option a filter depends on, for the filters this crate uses that FFmpeg builds
+// commonly leave out. The rest come with every libavfilter.
+pub fn filter_build_requirement(filter_name: &str) -> Option<&'static str> {
+    match filter_name {
+        "subtitles" => Some("libass (--enable-libass)"),
+        "boxblur" => Some("GPL components (--enable-gpl)"),
+        "hwupload" => Some("hardware acceleration support (e.g. --enable-vaapi)"),
+        _ => None,
+    }
+}
+
+// Container families whose codec rules differ, from output_format or else the output extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputContainer {
+    Mp4,
+    Mov,
+    Matroska,
+    WebM,
+    Other, // Any other muxer; no codec checks are made for it
+}
+
+impl OutputContainer {
+    pub fn supports_soft_subtitles(&self) -> bool {
+        soft_subtitle_codec(*self).is_some()
+    }
+
+    // Whether chapters written by remux_video end up in the file; other muxers drop them.
+    pub fn supports_chapters(&self) -> bool {
+        *self != OutputContainer::Other
+    }
+
+    pub fn detect(output_path: &str, format_name: Option<&str>) -> Self {
+        let name = match format_name {
+            Some(format_name) => format_name.to_ascii_lowercase(),
+            None => Path::new(output_path)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_ascii_lowercase())
+                .unwrap_or_else(|| "mp4".to_string()),
+        };
+        match name.as_str() {
+            "mp4" | "m4v" => OutputContainer::Mp4,
+            "mov" => OutputContainer::Mov,
+            "mkv" | "matroska" => OutputContainer::Matroska,
+            "webm" => OutputContainer::WebM,
+            _ => OutputContainer::Other,
+        }
+    }
+}
+
+// The video encoder and audio codec a short gets when none is configured: VP9 and Opus for WebM,
+// which allows nothing else, and H.264 and AAC for every other container.
+pub fn default_codecs_for_container(output_path: &str, format_name: Option<&str>) -> (VideoEncoder, AudioCodec) {
+    match OutputContainer::detect(output_path, format_name) {
+        OutputContainer::WebM => (VideoEncoder::Vp9, AudioCodec::Opus),
+        _ => (VideoEncoder::X264, AudioCodec::Aac),
+    }
+}
+
+// Fails when the container of `output_path` cannot hold `encoder`'s video or `audio_codec`'s
+// audio. Copy is rejected for WebM because the source audio is rarely Opus and filtered audio
+// falls back to AAC.
+pub fn check_container_codecs(
+    output_path: &str,
+    format_name: Option<&str>,
+    encoder: VideoEncoder,
+    audio_codec: AudioCodec,
+) -> Result<(), ShortsError> {
+    let container = OutputContainer::detect(output_path, format_name);
+    let video_ok = match container {
+        OutputContainer::WebM => encoder == VideoEncoder::Vp9,
+        OutputContainer::Mov => encoder.is_h264(),
+        _ => true,
+    };
+    let audio_ok = match container {
+        OutputContainer::WebM => audio_codec == AudioCodec::Opus,
+        OutputContainer::Mov => audio_codec != AudioCodec::Opus,
+        _ => true,
+    };
+    if video_ok && audio_ok {
+        return Ok(());
+    }
+    Err(ShortsError::InvalidArgument(format!(
+        "{:?} output {} cannot hold {} video with {} audio; use {}",
+        container,
+        output_path,
+        encoder.codec_name(),
+        audio_codec.codec_name().unwrap_or("copied"),
+        match container {
+            OutputContainer::WebM => "encoder vp9 with audio_codec opus",
+            _ => "an H.264 encoder with audio_codec aac or copy",
+        }
+    )))
+}
+
+// Whether the best video and audio streams of `input_path` can go into `container` as they are.
+// Only WebM is picky: a short that no step re-encoded still has the source's H.264 and AAC.
+pub fn container_accepts_streams(input_path: &str, container: OutputContainer) -> Result<bool, ShortsError> {
+    if container != OutputContainer::WebM {
+        return Ok(true);
+    }
+    ensure_ffmpeg_initialized()?;
+    let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+    let codec = |medium| ictx.streams().best(medium).map(|stream| stream.parameters().id());
+    let video_ok = codec(ffmpeg::media::Type::Video)
+        .is_none_or(|id| matches!(id, ffmpeg::codec::Id::VP8 | ffmpeg::codec::Id::VP9 | ffmpeg::codec::Id::AV1));
+    let audio_ok = codec(ffmpeg::media::Type::Audio)
+        .is_none_or(|id| matches!(id, ffmpeg::codec::Id::OPUS | ffmpeg::codec::Id::VORBIS));
+    Ok(video_ok && audio_ok)
+}
+
+// Re-encodes the video and audio of `input_path` with `encode_settings` without changing them
+// otherwise, e.g. to bring a short that no step re-encoded to the codecs of its container.
+pub fn reencode_video(
+    input_path: &str,
+    output_path: &str,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<PacketWriteStats, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    let has_audio = {
+        let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+        ictx.streams().best(ffmpeg::media::Type::Audio).is_some()
+    };
+    Ok(transcode_with_filters(
+        input_path,
+        output_path,
+        Some("null"),
+        has_audio.then_some("anull"),
+        encode_settings,
+        |_| {},
+    )?)
+}
+
+// Copies the video and audio streams of `input_path` into `output_path` without re-encoding. With
+// `format_name` the container is that muxer, whatever the extension of `output_path` (which may
+// have none); otherwise it follows the extension. The input's metadata is copied, with the
+// (tag, value) pairs of `metadata_overrides` set over it; an empty value removes the tag.
+// `output_options` are (key, value) muxer options, e.g. ("movflags", "+faststart"), passed to the
+// muxer as given (a later pair replaces an earlier one with the same key); ones it does not know
+// are logged and ignored. `chapters` (see build_chapters) replace any chapters of the input.
+pub fn remux_video(
+    input_path: &str,
+    output_path: &str,
+    format_name: Option<&str>,
+    metadata_overrides: &[(&str, &str)],
+    output_options: &[(&str, &str)],
+    chapters: &[Chapter],
+) -> Result<PacketWriteStats, ShortsError> {
+    remux_streams(input_path, output_path, format_name, metadata_overrides, output_options, chapters, None)
+}
+
+// A chapter marker of a video, as players such as VLC list them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub start_secs: f64,
+    pub end_secs: f64,
+    pub title: String,
+}
+
+// Chapters shorter than this are merged into the one before them.
+const MIN_CHAPTER_SECS: f64 = 1.0;
+
+// Turns chapter start times (e.g. from detect_scenes) into chapters covering a `duration_secs`
+// long video back to back. `titles` go with the start times at the same index; chapters without
+// one are called "Chapter N". Starts are sorted and those outside the video dropped, a first
+// chapter is added at 0 when none starts there, and a start less than MIN_CHAPTER_SECS after the
+// previous one, or before the end, is dropped. Without any starts the video is a single chapter.
+pub fn build_chapters(start_secs: &[f64], titles: &[&str], duration_secs: f64) -> Vec<Chapter> {
+    if !(duration_secs.is_finite() && duration_secs > 0.0) {
+        return Vec::new();
+    }
+    let mut starts: Vec<(f64, Option<&str>)> = start_secs
+        .iter()
+        .enumerate()
+        .filter(|(_, start)| start.is_finite() && **start >= 0.0 && **start < duration_secs)
+        .map(|(index, start)| (*start, titles.get(index).copied()))
+        .collect();
+    starts.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if starts.first().is_none_or(|(start, _)| *start >= MIN_CHAPTER_SECS) {
+        starts.insert(0, (0.0, None));
+    }
+    starts[0].0 = 0.0;
+
+    let mut kept: Vec<(f64, Option<&str>)> = Vec::new();
+    for (start, title) in starts {
+        let too_close = kept.last().is_some_and(|(previous, _)| start - previous < MIN_CHAPTER_SECS);
+        if !too_close && (kept.is_empty() || duration_secs - start >= MIN_CHAPTER_SECS) {
+            kept.push((start, title));
+        }
+    }
+    let ends: Vec<f64> = kept.iter().skip(1).map(|(start, _)| *start).chain([duration_secs]).collect();
+    kept.into_iter()
+        .zip(ends)
+        .enumerate()
+        .map(|(index, ((start_secs, title), end_secs))| Chapter {
+            start_secs,
+            end_secs,
+            title: title.map(str::to_string).unwrap_or_else(|| format!("Chapter {}", index + 1)),
+        })
+        .collect()
+}
+
+// Copies `input_path` into `output_path` like remux_video, with chapters starting at
+// `start_secs` and named `titles` (see build_chapters). MP4, MOV, Matroska and WebM keep
+// chapters; other containers drop them.
+pub fn write_chapters(
+    input_path: &str,
+    output_path: &str,
+    start_secs: &[f64],
+    titles: &[&str],
+) -> Result<PacketWriteStats, ShortsError> {
+    let duration_secs = probe_duration_secs(input_path)?;
+    let chapters = build_chapters(start_secs, titles, duration_secs);
+    remux_video(input_path, output_path, None, &[], &[], &chapters)
+}
+
+// Copies the video and audio of `video_path` into `output_path` like remux_video and adds the
+// cues of the SRT or WebVTT file `subtitle_path` as a soft subtitle track, which players can turn
+// on and off. Nothing is re-encoded. The track's codec suits the container: mov_text for MP4 and
+// MOV, WebVTT for WebM and SubRip for Matroska; other containers are rejected.
+pub fn mux_soft_subtitles(
+    video_path: &str,
+    subtitle_path: &str,
+    output_path: &str,
+    format_name: Option<&str>,
+    metadata_overrides: &[(&str, &str)],
+    output_options: &[(&str, &str)],
+    chapters: &[Chapter],
+) -> Result<PacketWriteStats, ShortsError> {
+    let extension = Path::new(subtitle_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    if extension != "srt" && extension != "vtt" {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Soft subtitles must be an .srt or .vtt file: {}",
+            subtitle_path
+        )));
+    }
+    let container = OutputContainer::detect(output_path, format_name);
+    let codec = soft_subtitle_codec(container).ok_or_else(|| {
+        ShortsError::InvalidArgument(format!(
+            "{:?} output {} cannot hold soft subtitles; use an .mp4, .mov, .mkv or .webm output",
+            container, output_path
+        ))
+    })?;
+    let cues = crate::subtitle_generation::parse_srt(Path::new(subtitle_path))?;
+    remux_streams(video_path, output_path, format_name, metadata_overrides, output_options, chapters, Some((codec, &cues)))
+}
+
+// Codec of the soft subtitle track `container` takes, or None when it has no text subtitle codec
+// we can write.
+fn soft_subtitle_codec(container: OutputContainer) -> Option<ffmpeg::codec::Id> {
+    match container {
+        OutputContainer::Mp4 | OutputContainer::Mov => Some(ffmpeg::codec::Id::MOV_TEXT),
+        OutputContainer::WebM => Some(ffmpeg::codec::Id::WEBVTT),
+        OutputContainer::Matroska => Some(ffmpeg::codec::Id::SUBRIP),
+        OutputContainer::Other => None,
+    }
+}
+
+// Default 3GPP text sample entry of a mov_text track (bottom-centered white text, font "Serif"),
+// as FFmpeg's mov_text encoder writes it; MP4 players need it to show the track.
+const MOV_TEXT_SAMPLE_ENTRY: [u8; 48] = [
+    0, 0, 0, 0, // display flags
+    1, 0xFF, // centered horizontally, at the bottom
+    0, 0, 0, 0, // background color
+    0, 0, 0, 0, 0, 0, 0, 0, // text box
+    0, 0, 0, 0, 0, 1, 0, 0x12, 0xFF, 0xFF, 0xFF, 0xFF, // style: font 1, size 18, white
+    0, 0, 0, 0x12, b'f', b't', b'a', b'b', 0, 1, 0, 1, 5, b'S', b'e', b'r', b'i', b'f', // font table
+];
+
+fn soft_subtitle_parameters(codec: ffmpeg::codec::Id) -> Result<ffmpeg::codec::Parameters, Error> {
+    let mut parameters = ffmpeg::codec::Parameters::new();
+    unsafe {
+        let raw = parameters.as_mut_ptr();
+        (*raw).codec_type = ffmpeg::media::Type::Subtitle.into();
+        (*raw).codec_id = codec.into();
+        if codec == ffmpeg::codec::Id::MOV_TEXT {
+            let size = MOV_TEXT_SAMPLE_ENTRY.len();
+            // Freed with the parameters.
+            let extradata = ffmpeg::ffi::av_mallocz(size + ffmpeg::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize) as *mut u8;
+            if extradata.is_null() {
+                bail!("Failed to allocate the mov_text sample entry");
+            }
+            std::ptr::copy_nonoverlapping(MOV_TEXT_SAMPLE_ENTRY.as_ptr(), extradata, size);
+            (*raw).extradata = extradata;
+            (*raw).extradata_size = size as i32;
+        }
+    }
+    Ok(parameters)
+}
+
+// Packet payload of one cue: mov_text samples start with the text length, WebVTT and SubRip
+// packets are the bare text.
+fn soft_subtitle_payload(codec: ffmpeg::codec::Id, text: &str) -> Vec<u8> {
+    if codec != ffmpeg::codec::Id::MOV_TEXT {
+        return text.as_bytes().to_vec();
+    }
+    let text = &text.as_bytes()[..text.len().min(u16::MAX as usize)];
+    let mut payload = (text.len() as u16).to_be_bytes().to_vec();
+    payload.extend_from_slice(text);
+    payload
+}
+
+fn remux_streams(
+    input_path: &str,
+    output_path: &str,
+    format_name: Option<&str>,
+    metadata_overrides: &[(&str, &str)],
+    output_options: &[(&str, &str)],
+    chapters: &[Chapter],
+    soft_subtitles: Option<(ffmpeg::codec::Id, &[SubtitleCue])>,
+) -> Result<PacketWriteStats, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    if let Some(format_name) = format_name {
+        if !output_format_available(format_name) {
+            return Err(ShortsError::InvalidArgument(format!(
+                "Output format '{}' is not supported by this FFmpeg build (see `ffmpeg -muxers`)",
+                format_name
+            )));
+        }
+    }
+
+    let mut ictx = ffmpeg::format::input(&Path::new(input_path))?;
+    let mut octx = match format_name {
+        Some(format_name) => ffmpeg::format::output_as(&Path::new(output_path), format_name)?,
+        None => ffmpeg::format::output(&Path::new(output_path))?,
+    };
+
+    // Maps input stream index -> output stream index for the streams that are copied.
+    let mut copied_streams: Vec<Option<usize>> = vec![None; ictx.nb_streams() as usize];
+    for ist_stream in ictx.streams() {
+        let medium = ist_stream.parameters().medium();
+        if medium != ffmpeg::media::Type::Video && medium != ffmpeg::media::Type::Audio {
+            continue;
+        }
+        let mut ost_stream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+        ost_stream.set_parameters(ist_stream.parameters());
+        // The input container's codec tag may not be valid in the output container; let the muxer choose.
+        unsafe {
+            (*ost_stream.parameters().as_mut_ptr()).codec_tag = 0;
+        }
+        copied_streams[ist_stream.index()] = Some(ost_stream.index());
+    }
+    let subtitle_stream = match soft_subtitles {
+        Some((codec, _)) => {
+            let mut ost_stream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            ost_stream.set_parameters(soft_subtitle_parameters(codec)?);
+            ost_stream.set_time_base((1, 1000));
+            Some(ost_stream.index())
+        }
+        None => None,
+    };
+
+    let metadata: ffmpeg::Dictionary = merge_metadata(ictx.metadata().iter(), metadata_overrides).into_iter().collect();
+    octx.set_metadata(metadata);
+    for (index, chapter) in chapters.iter().enumerate() {
+        let to_ms = |secs: f64| (secs * 1000.0).round() as i64;
+        octx.add_chapter(index as i64, (1, 1000), to_ms(chapter.start_secs), to_ms(chapter.end_secs), &chapter.title)?;
+    }
+    if output_options.is_empty() {
+        octx.write_header()?;
+    } else {
+        debug!("Muxer options for {}: {:?}", output_path, output_options);
+        let unused = octx.write_header_with(output_options.iter().copied().collect())?;
+        let unused: Vec<String> = unused.iter().map(|(key, _)| key.to_string()).collect();
+        if !unused.is_empty() {
+            warn!("The muxer of {} does not know the output option(s) {}; they were ignored", output_path, unused.join(", "));
+        }
+    }
+
+    let mut write_stats = PacketWriteStats::default();
+    if let (Some(ost_idx), Some((codec, cues))) = (subtitle_stream, soft_subtitles) {
+        // Interleaved writing holds the cues back until the video and audio catch up with them.
+        let ost_time_base = octx
+            .stream(ost_idx)
+            .map(|stream| stream.time_base())
+            .ok_or_else(|| Error::msg(format!("Output stream {} disappeared after writing the header", ost_idx)))?;
+        for cue in cues {
+            let start_ms = cue.start.as_millis() as i64;
+            let mut packet = ffmpeg::Packet::copy(&soft_subtitle_payload(codec, &cue.text));
+            packet.set_pts(Some(start_ms));
+            packet.set_dts(Some(start_ms));
+            packet.set_duration((cue.end.as_millis() as i64 - start_ms).max(0));
+            packet.rescale_ts((1, 1000), ost_time_base);
+            packet.set_stream(ost_idx);
+            write_stats.record(packet.write_interleaved(&mut octx), output_path);
+        }
+    }
+    for (stream, mut packet) in ictx.packets() {
+        let Some(ost_idx) = copied_streams[stream.index()] else {
+            continue;
+        };
+        let ost_time_base = octx
+            .stream(ost_idx)
+            .map(|stream| stream.time_base())
+            .ok_or_else(|| Error::msg(format!("Output stream {} disappeared after writing the header", ost_idx)))?;
+        packet.rescale_ts(stream.time_base(), ost_time_base);
+        packet.set_position(-1);
+        packet.set_stream(ost_idx);
+        write_stats.record(packet.write_interleaved(&mut octx), output_path);
+    }
+
+    octx.write_trailer()?;
+    write_stats.check(output_path, false)
+}
+
+// Whether a container tag tells where the video was recorded, e.g. "location" (as FFmpeg reads
+// the GPS coordinates of phone recordings) or "com.apple.quicktime.location.ISO6709".
+pub fn is_location_tag(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key.contains("location") || key.contains("gps")
+}
+
+// Reads the container tags of `path`, e.g. to see what an output would carry over from it.
+pub fn probe_metadata(path: &str) -> Result<Vec<(String, String)>, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    let ictx = ffmpeg::format::input(&Path::new(path))?;
+    Ok(ictx.metadata().iter().map(|(key, value)| (key.to_string(), value.to_string())).collect())
+}
+
+// Sets `overrides` over the `source` tags, keeping their order; an empty override value removes
+// the tag.
+fn merge_metadata<'a>(
+    source: impl Iterator<Item = (&'a str, &'a str)>,
+    overrides: &[(&str, &str)],
+) -> Vec<(String, String)> {
+    let mut merged: Vec<(String, String)> = source
+        .filter(|(key, _)| !overrides.iter().any(|(tag, _)| tag.eq_ignore_ascii_case(key)))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    merged.extend(
+        overrides
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(tag, value)| (tag.to_string(), value.to_string())),
+    );
+    merged
+}
+
+// What has to match between inputs for concat_videos to join them without re-encoding: the codec
+// and frame format of the best video stream, and the codec and format of the best audio stream.
+#[derive(Debug, Clone, PartialEq)]
+struct ConcatStreamLayout {
+    video_codec: ffmpeg::codec::Id,
+    width: u32,
+    height: u32,
+    pixel_format: ffmpeg::format::Pixel,
+    rotation_degrees: u32, // Copied streams keep their rotation, so inputs have to agree on it
+    audio: Option<(ffmpeg::codec::Id, u32, u16)>, // Codec, sample rate and channel count
+}
+
+fn probe_concat_layout(path: &str) -> Result<ConcatStreamLayout, Error> {
+    let ictx = ffmpeg::format::input(&Path::new(path))?;
+    let video_stream = ictx
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| Error::msg(format!("No video stream found in '{}'", path)))?;
+    let video_decoder = ffmpeg::codec::context::Context::from_parameters(video_stream.parameters())?
+        .decoder()
+        .video()?;
+    let audio = match ictx.streams().best(ffmpeg::media::Type::Audio) {
+        Some(audio_stream) => {
+            let audio_decoder = ffmpeg::codec::context::Context::from_parameters(audio_stream.parameters())?
+                .decoder()
+                .audio()?;
+            Some((audio_stream.parameters().id(), audio_decoder.rate(), audio_decoder.channels()))
+        }
+        None => None,
+    };
+    Ok(ConcatStreamLayout {
+        video_codec: video_stream.parameters().id(),
+        width: video_decoder.width(),
+        height: video_decoder.height(),
+        pixel_format: video_decoder.format(),
+        rotation_degrees: stream_rotation_degrees(&video_stream),
+        audio,
+    })
+}
+
+// Joins `input_paths` end to end into `output_path`, keeping the best video and audio stream of
+// each. Inputs that share codecs and formats are stream-copied; otherwise each one is re-encoded
+// first, which needs them to agree on the frame size and on the audio sample rate and channels.
+pub fn concat_videos(
+    input_paths: &[&str],
+    output_path: &str,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    ensure_ffmpeg_initialized()?;
+
+    let first_path = match input_paths.first() {
+        Some(path) => *path,
+        None => return Err(ShortsError::InvalidArgument("concat_videos needs at least one input video".to_string())),
+    };
+    let layouts = input_paths
+        .iter()
+        .map(|path| probe_concat_layout(path).with_context(|| format!("Failed to read input video '{}'", path)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let first = &layouts[0];
+
+    if layouts.iter().all(|layout| layout == first) {
+        return Ok(concat_stream_copy(input_paths, output_path)?);
+    }
+
+    for (path, layout) in input_paths.iter().zip(&layouts).skip(1) {
+        if (layout.width, layout.height) != (first.width, first.height) {
+            return Err(ShortsError::InvalidArgument(format!(
+                "Cannot join '{}' ({}x{}) after '{}' ({}x{}): all input videos must have the same frame size",
+                path, layout.width, layout.height, first_path, first.width, first.height
+            )));
+        }
+        let audio_format = |layout: &ConcatStreamLayout| layout.audio.map(|(_, rate, channels)| (rate, channels));
+        if audio_format(layout) != audio_format(first) {
+            return Err(ShortsError::InvalidArgument(format!(
+                "Cannot join '{}' after '{}': all input videos must have audio with the same sample rate and channel count, or none at all",
+                path, first_path
+            )));
+        }
+    }
+
+    warn!("Input videos use different codecs or formats; re-encoding them before joining");
+    Ok(concat_reencoded(input_paths, output_path, first.audio.is_some(), encode_settings)?)
+}
+
+// Re-encodes every input with the same settings, at the first input's frame rate, into parts next
+// to `output_path` and stream-copies the parts together. The parts are removed afterwards.
+fn concat_reencoded(
+    input_paths: &[&str],
+    output_path: &str,
+    has_audio: bool,
+    encode_settings: &VideoEncodeSettings,
 ) -> Result<(), Error> {
-    ensure_ffmpeg_initialized();
+    let frame_rate = {
+        let ictx = ffmpeg::format::input(&Path::new(input_paths[0]))?;
+        ictx.streams().best(ffmpeg::media::Type::Video).map(|stream| stream.avg_frame_rate())
+    };
+    let video_filter = match frame_rate {
+        Some(rate) if rate.numerator() > 0 && rate.denominator() > 0 => {
+            format!("fps={}/{}", rate.numerator(), rate.denominator())
+        }
+        _ => "null".to_string(),
+    };
+    let audio_filter = if has_audio { Some("anull") } else { None };
+
+    let output = Path::new(output_path);
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("joined");
+    let part_paths = (1..=input_paths.len())
+        .map(|number| {
+            output
+                .with_file_name(format!("{}_concat_part{:02}.mp4", stem, number))
+                .to_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| Error::msg("Failed to convert concat part path to string"))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let result = input_paths
+        .iter()
+        .zip(&part_paths)
+        .try_for_each(|(input_path, part_path)| {
+            transcode_with_filters(input_path, part_path, Some(&video_filter), audio_filter, encode_settings, |_| {})
+                .map(|_| ())
+                .with_context(|| format!("Failed to re-encode '{}' for joining", input_path))
+        })
+        .and_then(|()| {
+            let parts: Vec<&str> = part_paths.iter().map(|s| s.as_str()).collect();
+            concat_stream_copy(&parts, output_path)
+        });
+    for part_path in &part_paths {
+        if Path::new(part_path).exists() {
+            if let Err(e) = std::fs::remove_file(part_path) {
+                warn!("Failed to remove {}: {}", part_path, e);
+            }
+        }
+    }
+    result
+}
+
+// Stream-copies the best video and audio stream of each input into `output_path`, shifting the
+// timestamps of each input so it starts where the previous one ended. The inputs must share
+// codec parameters, which concat_videos checks.
+fn concat_stream_copy(input_paths: &[&str], output_path: &str) -> Result<(), Error> {
+    const COPIED_MEDIA: [ffmpeg::media::Type; 2] = [ffmpeg::media::Type::Video, ffmpeg::media::Type::Audio];
+
+    let mut octx = ffmpeg::format::output(&Path::new(output_path))?;
+    {
+        let ictx = ffmpeg::format::input(&Path::new(input_paths[0]))?;
+        for medium in COPIED_MEDIA {
+            if let Some(ist_stream) = ictx.streams().best(medium) {
+                let mut ost_stream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+                ost_stream.set_parameters(ist_stream.parameters());
+                // The input container's codec tag may not be valid in the output container; let the muxer choose.
+                unsafe {
+                    (*ost_stream.parameters().as_mut_ptr()).codec_tag = 0;
+                }
+            }
+        }
+        octx.set_metadata(ictx.metadata().to_owned());
+    }
+    octx.write_header()?;
+
+    let mut last_dts: Vec<Option<i64>> = vec![None; octx.nb_streams() as usize];
+    let mut offset_secs = 0.0;
+    for input_path in input_paths {
+        let mut ictx = ffmpeg::format::input(&Path::new(input_path))?;
+
+        // Maps input stream index -> output stream index, in the order the output streams were added.
+        let mut stream_map: Vec<Option<usize>> = vec![None; ictx.nb_streams() as usize];
+        let mut input_start_secs = f64::INFINITY;
+        let mut ost_idx = 0;
+        for medium in COPIED_MEDIA {
+            if let Some(ist_stream) = ictx.streams().best(medium) {
+                stream_map[ist_stream.index()] = Some(ost_idx);
+                ost_idx += 1;
+                if ist_stream.start_time() != ffmpeg::ffi::AV_NOPTS_VALUE {
+                    let start_secs = ist_stream.start_time() as f64 * f64::from(ist_stream.time_base());
+                    input_start_secs = input_start_secs.min(start_secs);
+                }
+            }
+        }
+        if !input_start_secs.is_finite() {
+            input_start_secs = 0.0;
+        }
+
+        let mut input_end_secs = offset_secs;
+        for (stream, mut packet) in ictx.packets() {
+            let ost_idx = match stream_map[stream.index()] {
+                Some(ost_idx) => ost_idx,
+                None => continue,
+            };
+            let ist_time_base = stream.time_base();
+            let ost_time_base = octx
+                .stream(ost_idx)
+                .map(|stream| stream.time_base())
+                .ok_or_else(|| Error::msg(format!("Output stream {} disappeared after writing the header", ost_idx)))?;
+
+            let shift = ((offset_secs - input_start_secs) / f64::from(ist_time_base)).round() as i64;
+            packet.set_pts(packet.pts().map(|pts| pts + shift));
+            packet.set_dts(packet.dts().map(|dts| dts + shift));
+            if let Some(pts) = packet.pts() {
+                input_end_secs = input_end_secs.max((pts + packet.duration()) as f64 * f64::from(ist_time_base));
+            }
+            packet.rescale_ts(ist_time_base, ost_time_base);
+
+            // With B-frames an input's first DTS can fall just before the previous input's last
+            // one; nudge it forward, as the ffmpeg command line tool does, so the muxer accepts it.
+            if let (Some(dts), Some(previous)) = (packet.dts(), last_dts[ost_idx]) {
+                if dts <= previous {
+                    packet.set_dts(Some(previous + 1));
+                    if packet.pts().is_some_and(|pts| pts <= previous) {
+                        packet.set_pts(Some(previous + 1));
+                    }
+                }
+            }
+            last_dts[ost_idx] = packet.dts();
+
+            packet.set_position(-1);
+            packet.set_stream(ost_idx);
+            packet.write_interleaved(&mut octx)?;
+        }
+        offset_secs = input_end_secs;
+    }
+
+    octx.write_trailer()?;
+    Ok(())
+}
+
+// Width used for cropped short outputs; the height is derived from the target aspect ratio
+// (e.g., 9:16 -> 1080x1920, 1:1 -> 1080x1080, 4:5 -> 1080x1350).
+const SHORT_OUTPUT_WIDTH: u32 = 1080;
+
+// Helper function to parse aspect ratio strings like "9:16" into (width, height) parts.
+pub(crate) fn parse_aspect_ratio(aspect: &str) -> Result<(u32, u32), Error> {
+    let (w, h) = aspect
+        .split_once(':')
+        .ok_or_else(|| Error::msg(format!("Invalid aspect ratio '{}'. Use the form W:H, e.g. '9:16'.", aspect)))?;
+    let w: u32 = w.trim().parse()
+        .map_err(|_| Error::msg(format!("Invalid aspect ratio width in '{}'", aspect)))?;
+    let h: u32 = h.trim().parse()
+        .map_err(|_| Error::msg(format!("Invalid aspect ratio height in '{}'", aspect)))?;
+    if w == 0 || h == 0 {
+        bail!("Aspect ratio parts must be greater than zero: {}", aspect);
+    }
+    Ok((w, h))
+}
+
+// libx264 (with yuv420p) rejects odd frame dimensions, so every computed size is rounded down to even.
+fn round_down_to_even(value: u32) -> u32 {
+    (value & !1).max(2)
+}
+
+// Computes a centered crop rectangle (width, height, x, y) with the target aspect ratio.
+// A source wider than the target loses its sides; a source already narrower than the target
+// keeps its full width and loses top/bottom instead.
+fn compute_center_crop(src_w: u32, src_h: u32, aspect_w: u32, aspect_h: u32) -> (u32, u32, u32, u32) {
+    // Cross-multiply to compare src_w/src_h against aspect_w/aspect_h without float rounding.
+    let (crop_w, crop_h) = if src_w as u64 * aspect_h as u64 > src_h as u64 * aspect_w as u64 {
+        ((src_h as u64 * aspect_w as u64 / aspect_h as u64) as u32, src_h)
+    } else {
+        (src_w, (src_w as u64 * aspect_h as u64 / aspect_w as u64) as u32)
+    };
+    let crop_w = round_down_to_even(crop_w.min(src_w));
+    let crop_h = round_down_to_even(crop_h.min(src_h));
+    (crop_w, crop_h, (src_w - crop_w) / 2, (src_h - crop_h) / 2)
+}
+
+// Reads the frame size of the best video stream as it is displayed, i.e. with width and height
+// swapped when the stream is rotated by 90 or 270 degrees. Re-encoding bakes that rotation in
+// (see upright_filter_spec), so crops, overlays and caption margins are computed for this size.
+fn probe_video_dimensions(ictx: &ffmpeg::format::context::Input) -> Result<(u32, u32), Error> {
+    let stream = ictx
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| Error::msg("No video stream found in input"))?;
+    let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+    Ok(match stream_rotation_degrees(&stream) {
+        90 | 270 => (decoder.height(), decoder.width()),
+        _ => (decoder.width(), decoder.height()),
+    })
+}
+
+// The clockwise rotation (0, 90, 180 or 270) a player applies to `stream` to show it upright, as
+// phones record portrait video: from the display matrix side data, or else the older "rotate" tag.
+fn stream_rotation_degrees(stream: &ffmpeg::format::stream::Stream) -> u32 {
+    let display_matrix = unsafe {
+        let parameters = stream.parameters();
+        let raw = parameters.as_ptr();
+        let side_data = ffmpeg::ffi::av_packet_side_data_get(
+            (*raw).coded_side_data,
+            (*raw).nb_coded_side_data,
+            ffmpeg::ffi::AVPacketSideDataType::AV_PKT_DATA_DISPLAYMATRIX,
+        );
+        // A display matrix is 9 32-bit fixed-point values.
+        if side_data.is_null() || (*side_data).size < 9 * 4 {
+            None
+        } else {
+            // av_display_rotation_get gives the counterclockwise angle.
+            Some(-ffmpeg::ffi::av_display_rotation_get((*side_data).data as *const i32))
+        }
+    };
+    let degrees = display_matrix
+        .filter(|degrees| degrees.is_finite())
+        .or_else(|| stream.metadata().get("rotate").and_then(|rotate| rotate.trim().parse::<f64>().ok()));
+    degrees.map(normalize_rotation).unwrap_or(0)
+}
+
+// Rounds a rotation in degrees, clockwise and possibly negative, to the nearest of 0, 90, 180 and 270.
+fn normalize_rotation(degrees: f64) -> u32 {
+    ((degrees / 90.0).round() as i64).rem_euclid(4) as u32 * 90
+}
+
+// Prepends to `filter_spec` the filters turning frames rotated by `rotation_degrees` upright, so
+// that the rest of the chain (and the output, which carries no rotation) sees them as displayed.
+fn upright_filter_spec(rotation_degrees: u32, filter_spec: &str) -> String {
+    match rotation_degrees {
+        90 => prepend_video_filters("transpose=clock", filter_spec),
+        180 => prepend_video_filters("hflip,vflip", filter_spec),
+        270 => prepend_video_filters("transpose=cclock", filter_spec),
+        _ => filter_spec.to_string(),
+    }
+}
+
+// Runs the frames through `filters` before `filter_spec`. Specs that take their input from the
+// [in] label (e.g. the blur background or watermark graphs) get the filters' output in its place.
+fn prepend_video_filters(filters: &str, filter_spec: &str) -> String {
+    if filter_spec.contains("[in]") {
+        format!("[in]{}[prepended];{}", filters, filter_spec.replacen("[in]", "[prepended]", 1))
+    } else {
+        format!("{},{}", filters, filter_spec)
+    }
+}
+
+// Relative difference between a stream's r_frame_rate and avg_frame_rate above which it is taken
+// for variable frame rate, e.g. a screen recording that only stores the frames that changed.
+const VFR_RATE_TOLERANCE: f64 = 0.01;
+
+// Highest r_frame_rate a variable frame rate stream is converted to; larger values are usually a
+// time base rather than a real frame rate, and avg_frame_rate is used instead.
+const MAX_CFR_FRAME_RATE: f64 = 120.0;
+
+// The constant frame rate to convert a variable frame rate stream to, None when it looks constant.
+// r_frame_rate is the lowest rate all of the stream's timestamps fit (its nominal rate) and
+// avg_frame_rate what the frames actually average; they differ when frames were dropped or
+// stretched.
+fn variable_frame_rate_target(r_frame_rate: ffmpeg::Rational, avg_frame_rate: ffmpeg::Rational) -> Option<ffmpeg::Rational> {
+    let valid = |rate: ffmpeg::Rational| rate.numerator() > 0 && rate.denominator() > 0;
+    if !valid(r_frame_rate) || !valid(avg_frame_rate) {
+        return None;
+    }
+    let (nominal, average) = (f64::from(r_frame_rate), f64::from(avg_frame_rate));
+    if (nominal - average).abs() <= average * VFR_RATE_TOLERANCE {
+        return None;
+    }
+    Some(if nominal <= MAX_CFR_FRAME_RATE { r_frame_rate } else { avg_frame_rate })
+}
+
+// Pixel format handed to the encoder. yuv420p is what players expect from H.264, whatever the source used.
+const ENCODER_PIXEL_FORMAT: ffmpeg::format::Pixel = ffmpeg::format::Pixel::YUV420P;
+
+// VAAPI encoders take frames in GPU memory, uploaded in nv12 by the hwupload filter.
+const VAAPI_UPLOAD_PIXEL_FORMAT: &str = "nv12";
+
+// How re-encoded video is compressed. `bit_rate` takes precedence over `crf`; with neither, each
+// encoder uses its default quality. The audio fields apply to the audio re-encoded alongside it.
+#[derive(Debug, Clone, Default)]
+pub struct VideoEncodeSettings {
+    pub encoder: VideoEncoder,
+    pub crf: Option<u8>, // 0..=51, lower is better quality
+    pub bit_rate: Option<u64>, // Bits per second
+    pub x264_preset: X264Preset, // libx264 only, like x264_tune
+    pub x264_tune: Option<X264Tune>,
+    pub audio_codec: AudioCodec,
+    pub audio_bit_rate: Option<u64>, // Bits per second; DEFAULT_AUDIO_BIT_RATE when None
+    pub two_pass_stats: Option<PathBuf>, // libx264 with a bit_rate: burn_subtitles encodes in two passes, logging to this file
+    pub pass: Option<EncodePass>, // Set by burn_subtitles for each pass of a two-pass encode
+    pub keep_variable_frame_rate: bool, // Re-encode VFR sources as they are instead of at a constant frame rate
+}
+
+// One pass of a two-pass libx264 encode. The first only analyses the video into the stats file;
+// the second uses it to spend the bitrate where it is needed, so the size comes out close to
+// bit_rate * duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodePass {
+    First,
+    Second,
+}
+
+// Highest CRF/QP value accepted by the H.264 encoders.
+pub const MAX_CRF: u8 = 51;
+
+// Parses a bitrate such as "4M", "2500k" or "800000" into bits per second.
+pub fn parse_bit_rate(bit_rate: &str) -> Result<u64, ShortsError> {
+    let trimmed = bit_rate.trim();
+    let (number, multiplier) = match trimmed.chars().last() {
+        Some('k') | Some('K') => (&trimmed[..trimmed.len() - 1], 1_000.0),
+        Some('m') | Some('M') => (&trimmed[..trimmed.len() - 1], 1_000_000.0),
+        _ => (trimmed, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok((value * multiplier).round() as u64),
+        _ => Err(ShortsError::InvalidArgument(format!(
+            "Invalid video bitrate '{}'. Use bits per second, optionally with a k or M suffix (e.g., '4M').",
+            bit_rate
+        ))),
+    }
+}
+
+// Returns whether this FFmpeg build includes `encoder`. It doesn't check that the matching
+// hardware is present; opening a hardware encoder without it still fails (and falls back to libx264).
+pub fn video_encoder_available(encoder: VideoEncoder) -> Result<bool, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    Ok(ffmpeg::encoder::find_by_name(encoder.codec_name()).is_some())
+}
+
+// Lists the video encoders included in this FFmpeg build.
+pub fn available_video_encoders() -> Result<Vec<VideoEncoder>, ShortsError> {
+    let mut available = Vec::new();
+    for encoder in [VideoEncoder::X264, VideoEncoder::Nvenc, VideoEncoder::Vaapi, VideoEncoder::VideoToolbox, VideoEncoder::Vp9] {
+        if video_encoder_available(encoder)? {
+            available.push(encoder);
+        }
+    }
+    Ok(available)
+}
+
+// Checks the requested encoder up front: returns it when FFmpeg has it, otherwise warns and
+// falls back to libx264. Fails only when no H.264 encoder is available at all, or for VP9, which
+// has no stand-in.
+pub fn resolve_video_encoder(requested: VideoEncoder) -> Result<VideoEncoder, ShortsError> {
+    if video_encoder_available(requested)? {
+        return Ok(requested);
+    }
+    let available = available_video_encoders()?;
+    if !requested.is_h264() {
+        return Err(anyhow!(
+            "Video encoder {} is not available in this FFmpeg build (available: {:?})",
+            requested.codec_name(),
+            available
+        )
+        .into());
+    }
+    if requested != VideoEncoder::X264 && available.contains(&VideoEncoder::X264) {
+        warn!(
+            "Video encoder {} is not available in this FFmpeg build (available: {:?}); falling back to libx264",
+            requested.codec_name(),
+            available
+        );
+        return Ok(VideoEncoder::X264);
+    }
+    if ffmpeg::encoder::find(ffmpeg::codec::Id::H264).is_some() {
+        // Some other H.264 encoder, picked by VideoTranscoder when libx264 is missing.
+        warn!("Video encoder {} is not available in this FFmpeg build; using FFmpeg's default H.264 encoder", requested.codec_name());
+        return Ok(VideoEncoder::X264);
+    }
+    Err(anyhow!(
+        "Video encoder {} is not available and this FFmpeg build has no H.264 encoder (available: {:?})",
+        requested.codec_name(),
+        available
+    )
+    .into())
+}
+
+// Encoder options for `settings`. Without a bitrate or CRF, libx264 and VAAPI already default to
+// constant quality (CRF 23 / QP 20); NVENC is switched to the same kind of mode, since it would
+// otherwise target 2 Mb/s, and VideoToolbox, which has no quality mode here, gets 8 Mb/s instead
+// of its very low default.
+fn encoder_options(settings: &VideoEncodeSettings) -> ffmpeg::Dictionary<'static> {
+    let mut options = ffmpeg::Dictionary::new();
+    if settings.encoder == VideoEncoder::X264 {
+        options.set("preset", settings.x264_preset.name());
+        if let Some(tune) = settings.x264_tune {
+            options.set("tune", tune.name());
+        }
+        if let (Some(pass), Some(stats_path)) = (settings.pass, &settings.two_pass_stats) {
+            options.set("flags", if pass == EncodePass::First { "+pass1" } else { "+pass2" });
+            options.set("stats", &stats_path.to_string_lossy());
+        }
+    }
+    if settings.encoder == VideoEncoder::Nvenc {
+        options.set("preset", "p4");
+        options.set("rc", "vbr");
+    }
+    if let Some(bit_rate) = settings.bit_rate {
+        options.set("b", &bit_rate.to_string());
+        return options;
+    }
+    match settings.encoder {
+        VideoEncoder::X264 => {
+            if let Some(crf) = settings.crf {
+                options.set("crf", &crf.to_string());
+            }
+        }
+        VideoEncoder::Nvenc => {
+            options.set("cq", &settings.crf.unwrap_or(23).to_string());
+            options.set("b", "0");
+        }
+        VideoEncoder::Vaapi => {
+            if let Some(crf) = settings.crf {
+                options.set("qp", &crf.to_string());
+            }
+        }
+        VideoEncoder::VideoToolbox => {
+            if settings.crf.is_some() {
+                warn!("h264_videotoolbox has no CRF mode; ignoring crf and encoding at 8 Mb/s (set a video bitrate instead)");
+            }
+            options.set("b", "8M");
+        }
+        VideoEncoder::Vp9 => {
+            // Constant quality needs the bitrate cap lifted; row-mt spreads the encode over the cores.
+            options.set("crf", &settings.crf.unwrap_or(32).to_string());
+            options.set("b", "0");
+            options.set("row-mt", "1");
+        }
+    }
+    options
+}
+
+// An FFmpeg hardware device context reference, released on drop.
+struct HwDevice(*mut ffmpeg::ffi::AVBufferRef);
 
+impl HwDevice {
+    // Opens the default VAAPI device (usually /dev/dri/renderD128).
+    fn vaapi() -> Result<Self, Error> {
+        let mut device = std::ptr::null_mut();
+        let ret = unsafe {
+            ffmpeg::ffi::av_hwdevice_ctx_create(
+                &mut device,
+                ffmpeg::ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if ret < 0 {
+            bail!("Failed to open a VAAPI device: {}", ffmpeg::Error::from(ret));
+        }
+        Ok(HwDevice(device))
+    }
+}
+
+impl Drop for HwDevice {
+    fn drop(&mut self) {
+        unsafe { ffmpeg::ffi::av_buffer_unref(&mut self.0) };
+    }
+}
+
+// Decodes one video stream, runs its frames through a filter graph and encodes the result with H.264.
+// The output stream's parameters come from the opened encoder, never from the input stream, so they
+// always describe the data that is actually written.
+struct VideoTranscoder {
+    ist_index: usize,
+    ost_index: usize,
+    decoder: ffmpeg::decoder::Video,
+    filter: ffmpeg::filter::Graph,
+    encoder: ffmpeg::encoder::video::Encoder,
+    encoder_time_base: ffmpeg::Rational,
+    ost_time_base: ffmpeg::Rational,
+}
+
+impl VideoTranscoder {
+    fn new(
+        ist: &ffmpeg::format::stream::Stream,
+        octx: &mut ffmpeg::format::context::Output,
+        filter_spec: &str,
+        settings: &VideoEncodeSettings,
+    ) -> Result<Self, Error> {
+        let global_header = octx.format().flags().contains(ffmpeg::format::Flags::GLOBAL_HEADER);
+        // The encoded stream carries no rotation, so it is applied to the frames instead.
+        let mut filter_spec = upright_filter_spec(stream_rotation_degrees(ist), filter_spec);
+        // Variable frame rate video plays back with uneven motion (and captions) once re-encoded
+        // as it is, so the fps filter evens it out, duplicating or dropping frames as needed.
+        if let Some(rate) = variable_frame_rate_target(ist.rate(), ist.avg_frame_rate()) {
+            if settings.keep_variable_frame_rate {
+                info!("Video has a variable frame rate ({:.2} fps on average); keeping it as configured", f64::from(ist.avg_frame_rate()));
+            } else {
+                info!(
+                    "Video has a variable frame rate ({:.2} fps on average); converting it to a constant {:.2} fps",
+                    f64::from(ist.avg_frame_rate()),
+                    f64::from(rate)
+                );
+                filter_spec = prepend_video_filters(&format!("fps={}/{}", rate.numerator(), rate.denominator()), &filter_spec);
+            }
+        }
+        let open = |settings: &VideoEncodeSettings| {
+            let decoder = ffmpeg::codec::context::Context::from_parameters(ist.parameters())?
+                .decoder()
+                .video()?;
+            let (filter, codec, encoder, time_base) =
+                open_video_encoder(&decoder, ist.time_base(), &filter_spec, settings, global_header)?;
+            Ok::<_, Error>((decoder, filter, codec, encoder, time_base))
+        };
+        // Hardware encoders can be compiled in without a usable device; fall back rather than fail.
+        let (decoder, filter, codec, encoder, out_time_base) = match open(settings) {
+            Err(e) if settings.encoder.is_h264() && settings.encoder != VideoEncoder::X264 => {
+                warn!("Could not open video encoder {} ({}); falling back to libx264", settings.encoder.codec_name(), e);
+                open(&VideoEncodeSettings { encoder: VideoEncoder::X264, ..settings.clone() })?
+            }
+            result => result?,
+        };
+
+        let mut ost = octx.add_stream(codec)?;
+        let ost_index = ost.index();
+        ost.set_parameters(&encoder);
+        ost.set_time_base(out_time_base);
+
+        Ok(VideoTranscoder {
+            ist_index: ist.index(),
+            ost_index,
+            decoder,
+            filter,
+            encoder,
+            encoder_time_base: out_time_base,
+            // Replaced with the muxer's choice once the header has been written.
+            ost_time_base: out_time_base,
+        })
+    }
+
+    fn send_packet(
+        &mut self,
+        packet: &ffmpeg::Packet,
+        octx: &mut ffmpeg::format::context::Output,
+    ) -> Result<(), Error> {
+        self.decoder.send_packet(packet)?;
+        self.receive_decoded_frames(octx)
+    }
+
+    // Flushes the decoder, the filter graph and the encoder, in that order.
+    fn finish(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        self.decoder.send_eof()?;
+        self.receive_decoded_frames(octx)?;
+        self.filter_source()?.source().flush()?;
+        self.receive_filtered_frames(octx)?;
+        self.encoder.send_eof()?;
+        self.receive_encoded_packets(octx)
+    }
+
+    fn filter_source(&mut self) -> Result<ffmpeg::filter::Context, Error> {
+        self.filter
+            .get("in")
+            .ok_or_else(|| Error::msg("Video filter graph has no input"))
+    }
+
+    fn receive_decoded_frames(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        let mut decoded = ffmpeg::frame::Video::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            self.filter_source()?.source().add(&decoded)?;
+            self.receive_filtered_frames(octx)?;
+        }
+        Ok(())
+    }
+
+    fn receive_filtered_frames(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        let mut sink = self
+            .filter
+            .get("out")
+            .ok_or_else(|| Error::msg("Video filter graph has no output"))?;
+        let mut filtered = ffmpeg::frame::Video::empty();
+        while sink.sink().frame(&mut filtered).is_ok() {
+            // Let the encoder pick frame types instead of inheriting the decoder's.
+            filtered.set_kind(ffmpeg::picture::Type::None);
+            self.encoder.send_frame(&filtered)?;
+            self.receive_encoded_packets(octx)?;
+        }
+        Ok(())
+    }
+
+    fn receive_encoded_packets(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        let mut encoded = ffmpeg::Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(self.ost_index);
+            encoded.rescale_ts(self.encoder_time_base, self.ost_time_base);
+            encoded.write_interleaved(octx)?;
+        }
+        Ok(())
+    }
+}
+
+// Builds the filter graph for `encoder` and opens the encoder with the frame size, aspect ratio
+// and time base the graph produces (e.g. after cropping and scaling).
+fn open_video_encoder(
+    decoder: &ffmpeg::decoder::Video,
+    time_base: ffmpeg::Rational,
+    filter_spec: &str,
+    settings: &VideoEncodeSettings,
+    global_header: bool,
+) -> Result<(ffmpeg::filter::Graph, ffmpeg::Codec, ffmpeg::encoder::video::Encoder, ffmpeg::Rational), Error> {
+    let encoder_choice = settings.encoder;
+    let codec = match ffmpeg::encoder::find_by_name(encoder_choice.codec_name()) {
+        Some(codec) => codec,
+        None if encoder_choice == VideoEncoder::X264 => ffmpeg::encoder::find(ffmpeg::codec::Id::H264)
+            .ok_or_else(|| Error::msg("No H.264 encoder is available in this FFmpeg build"))?,
+        None => bail!("Video encoder {} is not available in this FFmpeg build", encoder_choice.codec_name()),
+    };
+
+    let hw_device = if encoder_choice == VideoEncoder::Vaapi { Some(HwDevice::vaapi()?) } else { None };
+    let mut filter = build_video_filter_graph(decoder, time_base, filter_spec, hw_device.as_ref(), ENCODER_PIXEL_FORMAT)?;
+
+    let mut sink = filter
+        .get("out")
+        .ok_or_else(|| Error::msg("Video filter graph has no output"))?;
+    let sink_ptr = unsafe { sink.as_ptr() };
+    let (out_width, out_height, out_aspect, hw_frames) = unsafe {
+        (
+            ffmpeg::ffi::av_buffersink_get_w(sink_ptr),
+            ffmpeg::ffi::av_buffersink_get_h(sink_ptr),
+            ffmpeg::ffi::av_buffersink_get_sample_aspect_ratio(sink_ptr),
+            ffmpeg::ffi::av_buffersink_get_hw_frames_ctx(sink_ptr),
+        )
+    };
+    let out_time_base = sink.sink().time_base();
+
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    encoder.set_width(out_width as u32);
+    encoder.set_height(out_height as u32);
+    encoder.set_aspect_ratio(ffmpeg::Rational::from(out_aspect));
+    encoder.set_frame_rate(decoder.frame_rate());
+    encoder.set_time_base(out_time_base);
+    if hw_device.is_some() {
+        if hw_frames.is_null() {
+            bail!("The VAAPI filter graph did not produce hardware frames");
+        }
+        encoder.set_format(ffmpeg::format::Pixel::VAAPI);
+        unsafe {
+            (*encoder.as_mut_ptr()).hw_frames_ctx = ffmpeg::ffi::av_buffer_ref(hw_frames);
+        }
+    } else {
+        encoder.set_format(ENCODER_PIXEL_FORMAT);
+    }
+    if global_header {
+        encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+    }
+    let encoder = encoder.open_as_with(codec, encoder_options(settings))?;
+
+    Ok((filter, codec, encoder, out_time_base))
+}
+
+// Builds "in -> <filter_spec> -> format=<pixel_format> -> out" for frames coming out of `decoder`.
+// With a VAAPI device the frames are converted to nv12 and uploaded to the GPU instead.
+fn build_video_filter_graph(
+    decoder: &ffmpeg::decoder::Video,
+    time_base: ffmpeg::Rational,
+    filter_spec: &str,
+    hw_device: Option<&HwDevice>,
+    pixel_format: ffmpeg::format::Pixel,
+) -> Result<ffmpeg::filter::Graph, Error> {
+    let mut graph = ffmpeg::filter::Graph::new();
+
+    let aspect = decoder.aspect_ratio();
+    let (aspect_num, aspect_den) = if aspect.numerator() > 0 && aspect.denominator() > 0 {
+        (aspect.numerator(), aspect.denominator())
+    } else {
+        (1, 1)
+    };
+    let buffer_args = format!(
+        "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+        decoder.width(),
+        decoder.height(),
+        ffmpeg::ffi::AVPixelFormat::from(decoder.format()) as i32,
+        time_base.numerator(),
+        time_base.denominator(),
+        aspect_num,
+        aspect_den
+    );
+
+    let buffer = ffmpeg::filter::find("buffer").ok_or_else(|| Error::msg("FFmpeg 'buffer' filter not found"))?;
+    let buffersink =
+        ffmpeg::filter::find("buffersink").ok_or_else(|| Error::msg("FFmpeg 'buffersink' filter not found"))?;
+    graph.add(&buffer, "in", &buffer_args)?;
+    graph.add(&buffersink, "out", "")?;
+
+    if let Some(device) = hw_device {
+        let hwupload =
+            ffmpeg::filter::find("hwupload").ok_or_else(|| Error::msg("FFmpeg 'hwupload' filter not found"))?;
+        let mut upload = graph.add(&hwupload, "upload", "")?;
+        // The filter takes its own reference to the device, so `device` may be dropped afterwards.
+        unsafe {
+            (*upload.as_mut_ptr()).hw_device_ctx = ffmpeg::ffi::av_buffer_ref(device.0);
+        }
+        graph
+            .output("in", 0)?
+            .input("upload", 0)?
+            .parse(&format!("{},format={}", filter_spec, VAAPI_UPLOAD_PIXEL_FORMAT))?;
+        graph.output("upload", 0)?.input("out", 0)?.parse("null")?;
+    } else {
+        let pixel_format_name = pixel_format
+            .descriptor()
+            .map(|d| d.name())
+            .ok_or_else(|| Error::msg(format!("Unknown pixel format {:?}", pixel_format)))?;
+        graph
+            .output("in", 0)?
+            .input("out", 0)?
+            .parse(&format!("{},format={}", filter_spec, pixel_format_name))?;
+    }
+    graph.validate()?;
+
+    Ok(graph)
+}
+
+// Bitrate of re-encoded audio unless configured otherwise. AAC at 128 kb/s is what short-form
+// platforms deliver anyway.
+pub const DEFAULT_AUDIO_BIT_RATE: u64 = 128_000;
+
+// Sample rate used when the audio encoder does not support the source's, e.g. Opus with 44.1 kHz.
+const FALLBACK_AUDIO_SAMPLE_RATE: i32 = 48_000;
+
+// Estimates the video bitrate that makes a `duration_secs` long short fit in `target_bytes`,
+// leaving room for audio at `audio_bit_rate` and about 2% of container overhead.
+pub fn bit_rate_for_target_size(target_bytes: u64, duration_secs: f64, audio_bit_rate: u64) -> Result<u64, ShortsError> {
+    if duration_secs <= 0.0 {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Cannot estimate a bitrate for a clip of {} seconds",
+            duration_secs
+        )));
+    }
+    let total_bit_rate = target_bytes as f64 * 8.0 * 0.98 / duration_secs;
+    let video_bit_rate = total_bit_rate - audio_bit_rate as f64;
+    if video_bit_rate < 100_000.0 {
+        return Err(ShortsError::InvalidArgument(format!(
+            "A {:.1} MB target is too small for a {}s short (it leaves {:.0} kb/s for video)",
+            target_bytes as f64 / 1_000_000.0,
+            duration_secs,
+            video_bit_rate.max(0.0) / 1000.0
+        )));
+    }
+    Ok(video_bit_rate as u64)
+}
+
+// Decodes one audio stream, runs it through a filter graph and encodes the result with the
+// configured audio codec (AAC when that is Copy, since filtered audio has to be encoded).
+// Like VideoTranscoder, the output stream's parameters come from the opened encoder.
+struct AudioTranscoder {
+    ist_index: usize,
+    ost_index: usize,
+    decoder: ffmpeg::decoder::Audio,
+    filter: ffmpeg::filter::Graph,
+    encoder: ffmpeg::encoder::audio::Encoder,
+    encoder_time_base: ffmpeg::Rational,
+    ost_time_base: ffmpeg::Rational,
+}
+
+impl AudioTranscoder {
+    fn new(
+        ist: &ffmpeg::format::stream::Stream,
+        octx: &mut ffmpeg::format::context::Output,
+        filter_spec: &str,
+        settings: &VideoEncodeSettings,
+    ) -> Result<Self, Error> {
+        let decoder = ffmpeg::codec::context::Context::from_parameters(ist.parameters())?
+            .decoder()
+            .audio()?;
+
+        let codec = match settings.audio_codec {
+            AudioCodec::Opus => ffmpeg::encoder::find_by_name("libopus")
+                .or_else(|| ffmpeg::encoder::find(ffmpeg::codec::Id::OPUS))
+                .ok_or_else(|| Error::msg("No Opus encoder is available in this FFmpeg build"))?,
+            AudioCodec::Aac | AudioCodec::Copy => ffmpeg::encoder::find_by_name("aac")
+                .or_else(|| ffmpeg::encoder::find(ffmpeg::codec::Id::AAC))
+                .ok_or_else(|| Error::msg("No AAC encoder is available in this FFmpeg build"))?,
+        };
+        let sample_format = codec
+            .audio()?
+            .formats()
+            .and_then(|mut formats| formats.next())
+            .ok_or_else(|| Error::msg(format!("The {} encoder reports no supported sample formats", codec.name())))?;
+        // Keep the source rate when the encoder takes it; otherwise let the filter graph resample.
+        let supported_rates: Vec<i32> = codec.audio()?.rates().map(|rates| rates.collect()).unwrap_or_default();
+        let sample_rate = if supported_rates.is_empty() || supported_rates.contains(&(decoder.rate() as i32)) {
+            decoder.rate() as i32
+        } else if supported_rates.contains(&FALLBACK_AUDIO_SAMPLE_RATE) {
+            FALLBACK_AUDIO_SAMPLE_RATE
+        } else {
+            supported_rates[0]
+        };
+        // Some inputs (e.g. WAV without a channel mask) leave the layout unset; fall back to the
+        // default layout for the channel count so mono and stereo sources are both handled.
+        let channel_layout = if decoder.channel_layout().bits() != 0 {
+            decoder.channel_layout()
+        } else {
+            ffmpeg::ChannelLayout::default(decoder.channels() as i32)
+        };
+
+        let mut filter = build_audio_filter_graph(
+            &decoder,
+            ist.time_base(),
+            channel_layout,
+            sample_format,
+            sample_rate,
+            filter_spec,
+        )?;
+        let out_time_base = filter
+            .get("out")
+            .ok_or_else(|| Error::msg("Audio filter graph has no output"))?
+            .sink()
+            .time_base();
+
+        let global_header = octx.format().flags().contains(ffmpeg::format::Flags::GLOBAL_HEADER);
+
+        let mut ost = octx.add_stream(codec)?;
+        let ost_index = ost.index();
+
+        let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .audio()?;
+        encoder.set_rate(sample_rate);
+        encoder.set_channel_layout(channel_layout);
+        encoder.set_format(sample_format);
+        encoder.set_bit_rate(settings.audio_bit_rate.unwrap_or(DEFAULT_AUDIO_BIT_RATE) as usize);
+        encoder.set_time_base(out_time_base);
+        if global_header {
+            encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = encoder.open_as(codec)?;
+
+        // AAC and Opus only accept fixed-size frames (1024 and 960 samples), so let the sink cut them to size.
+        if encoder.frame_size() > 0 {
+            if let Some(mut sink) = filter.get("out") {
+                sink.sink().set_frame_size(encoder.frame_size());
+            }
+        }
+
+        ost.set_parameters(&encoder);
+        ost.set_time_base(out_time_base);
+
+        Ok(AudioTranscoder {
+            ist_index: ist.index(),
+            ost_index,
+            decoder,
+            filter,
+            encoder,
+            encoder_time_base: out_time_base,
+            // Replaced with the muxer's choice once the header has been written.
+            ost_time_base: out_time_base,
+        })
+    }
+
+    fn send_packet(
+        &mut self,
+        packet: &ffmpeg::Packet,
+        octx: &mut ffmpeg::format::context::Output,
+    ) -> Result<(), Error> {
+        self.decoder.send_packet(packet)?;
+        self.receive_decoded_frames(octx)
+    }
+
+    // Flushes the decoder, the filter graph and the encoder, in that order.
+    fn finish(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        self.decoder.send_eof()?;
+        self.receive_decoded_frames(octx)?;
+        self.filter_source()?.source().flush()?;
+        self.receive_filtered_frames(octx)?;
+        self.encoder.send_eof()?;
+        self.receive_encoded_packets(octx)
+    }
+
+    fn filter_source(&mut self) -> Result<ffmpeg::filter::Context, Error> {
+        self.filter
+            .get("in")
+            .ok_or_else(|| Error::msg("Audio filter graph has no input"))
+    }
+
+    fn receive_decoded_frames(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        let mut decoded = ffmpeg::frame::Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            self.filter_source()?.source().add(&decoded)?;
+            self.receive_filtered_frames(octx)?;
+        }
+        Ok(())
+    }
+
+    fn receive_filtered_frames(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        let mut sink = self
+            .filter
+            .get("out")
+            .ok_or_else(|| Error::msg("Audio filter graph has no output"))?;
+        let mut filtered = ffmpeg::frame::Audio::empty();
+        while sink.sink().frame(&mut filtered).is_ok() {
+            self.encoder.send_frame(&filtered)?;
+            self.receive_encoded_packets(octx)?;
+        }
+        Ok(())
+    }
+
+    fn receive_encoded_packets(&mut self, octx: &mut ffmpeg::format::context::Output) -> Result<(), Error> {
+        let mut encoded = ffmpeg::Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(self.ost_index);
+            encoded.rescale_ts(self.encoder_time_base, self.ost_time_base);
+            encoded.write_interleaved(octx)?;
+        }
+        Ok(())
+    }
+}
+
+// Builds "in -> <filter_spec> -> out" for frames coming out of `decoder`. The sink is restricted to
+// what the encoder accepts, so FFmpeg inserts any sample format/rate conversion that is needed
+// (loudnorm, for example, upsamples to 192 kHz internally).
+fn build_audio_filter_graph(
+    decoder: &ffmpeg::decoder::Audio,
+    time_base: ffmpeg::Rational,
+    channel_layout: ffmpeg::ChannelLayout,
+    sample_format: ffmpeg::format::Sample,
+    sample_rate: i32,
+    filter_spec: &str,
+) -> Result<ffmpeg::filter::Graph, Error> {
+    let mut graph = ffmpeg::filter::Graph::new();
+
+    let abuffer_args = format!(
+        "time_base={}/{}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+        time_base.numerator(),
+        time_base.denominator(),
+        decoder.rate(),
+        decoder.format().name(),
+        channel_layout.bits()
+    );
+
+    let abuffer = ffmpeg::filter::find("abuffer").ok_or_else(|| Error::msg("FFmpeg 'abuffer' filter not found"))?;
+    let abuffersink =
+        ffmpeg::filter::find("abuffersink").ok_or_else(|| Error::msg("FFmpeg 'abuffersink' filter not found"))?;
+    graph.add(&abuffer, "in", &abuffer_args)?;
+    graph.add(&abuffersink, "out", "")?;
+
+    {
+        let mut sink = graph
+            .get("out")
+            .ok_or_else(|| Error::msg("Audio filter graph has no output"))?;
+        sink.set_sample_format(sample_format);
+        sink.set_channel_layout(channel_layout);
+        sink.set_sample_rate(sample_rate as u32);
+    }
+
+    graph.output("in", 0)?.input("out", 0)?.parse(filter_spec)?;
+    graph.validate()?;
+
+    Ok(graph)
+}
+
+// Re-encodes the best video stream and/or the best audio stream of the input through the given
+// filter specs (FFmpeg filter syntax, as for -vf/-af). Streams without a filter, and any other
+// audio/video streams, are stream-copied; data and embedded subtitle streams are dropped.
+// Shared by the passes that change frame contents (cropping, burning subtitles, loudness, etc.).
+fn transcode_with_filters<F: FnMut(f64)>(
+    input_path: &str,
+    output_path: &str,
+    video_filter: Option<&str>,
+    audio_filter: Option<&str>,
+    encode_settings: &VideoEncodeSettings,
+    progress: F,
+) -> Result<PacketWriteStats, Error> {
     let mut ictx = ffmpeg::format::input(&Path::new(input_path))?;
-    
-    let mut opts = ffmpeg::Dictionary::new();
-    opts.set("ss", &start_secs.to_string()); // set returns (), no ?
-    opts.set("t", &duration_secs.to_string()); // set returns (), no ?
-    opts.set("c", "copy"); // Use stream copy // set returns (), no ?
+    // The first pass of a two-pass encode only writes its stats file; the packets are discarded.
+    let mut octx = if encode_settings.pass == Some(EncodePass::First) {
+        ffmpeg::format::output_as(&Path::new(output_path), "null")?
+    } else {
+        ffmpeg::format::output(&Path::new(output_path))?
+    };
+    let mut tracker = ProgressTracker::new(progress, 0.0, input_duration_secs(&ictx).unwrap_or(0.0));
 
-    let mut octx = ffmpeg::format::output_with(&Path::new(output_path), opts)?;
+    let mut video_transcoder = match video_filter {
+        Some(filter_spec) => {
+            let video_stream = ictx
+                .streams()
+                .best(ffmpeg::media::Type::Video)
+                .ok_or_else(|| Error::msg(format!("No video stream found in '{}'", input_path)))?;
+            Some(VideoTranscoder::new(&video_stream, &mut octx, filter_spec, encode_settings)?)
+        }
+        None => None,
+    };
+    let mut audio_transcoder = match audio_filter {
+        Some(filter_spec) => {
+            let audio_stream = ictx
+                .streams()
+                .best(ffmpeg::media::Type::Audio)
+                .ok_or_else(|| ShortsError::NoAudioStream(input_path.to_string()))?;
+            Some(AudioTranscoder::new(&audio_stream, &mut octx, filter_spec, encode_settings)?)
+        }
+        None => None,
+    };
+    let transcoded_video = video_transcoder.as_ref().map(|t| t.ist_index);
+    let transcoded_audio = audio_transcoder.as_ref().map(|t| t.ist_index);
 
+    // Maps input stream index -> output stream index for the streams that are copied as-is.
+    let mut copied_streams: Vec<Option<usize>> = vec![None; ictx.nb_streams() as usize];
     for ist_stream in ictx.streams() {
-        let ist_params = ist_stream.parameters();
-        if ist_params.medium() == ffmpeg::media::Type::Video || ist_params.medium() == ffmpeg::media::Type::Audio {
-            let mut ost_stream = octx.add_stream(None)?; 
-            ost_stream.set_parameters(ist_params.clone());
-            // Codec tag is part of parameters, should be copied by set_parameters if relevant.
-            // Explicit tag setting removed as it was causing errors and set_parameters should handle it.
+        let ist_idx = ist_stream.index();
+        let medium = ist_stream.parameters().medium();
+        if (medium != ffmpeg::media::Type::Audio && medium != ffmpeg::media::Type::Video)
+            || Some(ist_idx) == transcoded_video
+            || Some(ist_idx) == transcoded_audio
+        {
+            continue;
+        }
+        let mut ost_stream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+        ost_stream.set_parameters(ist_stream.parameters());
+        // The input container's codec tag may not be valid in the output container; let the muxer choose.
+        unsafe {
+            (*ost_stream.parameters().as_mut_ptr()).codec_tag = 0;
         }
+        copied_streams[ist_idx] = Some(ost_stream.index());
     }
-    
+
     octx.set_metadata(ictx.metadata().to_owned());
-    octx.write_header()?; // Options should be applied by the context based on the dictionary.
+    octx.write_header()?;
+
+    let output_time_base = |octx: &ffmpeg::format::context::Output, ost_idx: usize| {
+        octx.stream(ost_idx)
+            .map(|stream| stream.time_base())
+            .ok_or_else(|| Error::msg(format!("Output stream {} disappeared after writing the header", ost_idx)))
+    };
+    if let Some(transcoder) = video_transcoder.as_mut() {
+        transcoder.ost_time_base = output_time_base(&octx, transcoder.ost_index)?;
+    }
+    if let Some(transcoder) = audio_transcoder.as_mut() {
+        transcoder.ost_time_base = output_time_base(&octx, transcoder.ost_index)?;
+    }
 
+    let mut write_stats = PacketWriteStats::default();
     for (stream, mut packet) in ictx.packets() {
         let ist_idx = stream.index();
-        if octx.stream(ist_idx).is_some() { // Check if the stream was actually added to output
-            packet.set_stream(ist_idx);
-            match packet.write_interleaved(&mut octx) {
-                Ok(_) => (),
-                Err(e) => {
-                    // Log error but continue if it's a minor issue, or break/return
-                    eprintln!("Failed to write packet: {}", e);
-                    // Depending on the error, you might want to break or return Err(e.into())
-                }
+        if Some(ist_idx) == transcoded_video {
+            tracker.update(&packet, stream.time_base());
+            if let Some(transcoder) = video_transcoder.as_mut() {
+                transcoder.send_packet(&packet, &mut octx)?;
+            }
+        } else if Some(ist_idx) == transcoded_audio {
+            tracker.update(&packet, stream.time_base());
+            if let Some(transcoder) = audio_transcoder.as_mut() {
+                transcoder.send_packet(&packet, &mut octx)?;
             }
+        } else if let Some(ost_idx) = copied_streams[ist_idx] {
+            tracker.update(&packet, stream.time_base());
+            packet.rescale_ts(stream.time_base(), output_time_base(&octx, ost_idx)?);
+            packet.set_position(-1);
+            packet.set_stream(ost_idx);
+            write_stats.record(packet.write_interleaved(&mut octx), output_path);
         }
     }
 
+    if let Some(transcoder) = video_transcoder.as_mut() {
+        transcoder.finish(&mut octx)?;
+    }
+    if let Some(transcoder) = audio_transcoder.as_mut() {
+        transcoder.finish(&mut octx)?;
+    }
     octx.write_trailer()?;
+    tracker.finish();
+    Ok(write_stats.check(output_path, false)?)
+}
+
+// Re-encodes the video through `filter_spec` with H.264, copying audio.
+fn reencode_with_video_filter<F: FnMut(f64)>(
+    input_path: &str,
+    output_path: &str,
+    filter_spec: &str,
+    encode_settings: &VideoEncodeSettings,
+    progress: F,
+) -> Result<PacketWriteStats, Error> {
+    transcode_with_filters(input_path, output_path, Some(filter_spec), None, encode_settings, progress)
+}
+
+// Re-encodes the audio through `filter_spec` with the configured audio codec, copying video.
+fn reencode_with_audio_filter<F: FnMut(f64)>(
+    input_path: &str,
+    output_path: &str,
+    filter_spec: &str,
+    encode_settings: &VideoEncodeSettings,
+    progress: F,
+) -> Result<PacketWriteStats, Error> {
+    transcode_with_filters(input_path, output_path, None, Some(filter_spec), encode_settings, progress)
+}
+
+// Frame size of a short with the given aspect ratio: SHORT_OUTPUT_WIDTH wide, e.g. 1080x1920 for 9:16.
+fn short_frame_size(aspect_w: u32, aspect_h: u32) -> (u32, u32) {
+    let out_w = SHORT_OUTPUT_WIDTH;
+    (out_w, round_down_to_even((out_w as u64 * aspect_h as u64 / aspect_w as u64) as u32))
+}
+
+// Crops the video around its center to `target_aspect` (e.g., "9:16", "1:1", "4:5") and scales
+// the result to a SHORT_OUTPUT_WIDTH-wide frame, e.g. 1080x1920 for 9:16.
+pub fn crop_to_vertical(
+    input_path: &str,
+    output_path: &str,
+    target_aspect: &str,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    ensure_ffmpeg_initialized()?;
+
+    let (aspect_w, aspect_h) = parse_aspect_ratio(target_aspect)?;
+    let (src_w, src_h) = {
+        let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+        probe_video_dimensions(&ictx)?
+    };
+
+    let (crop_w, crop_h, crop_x, crop_y) = compute_center_crop(src_w, src_h, aspect_w, aspect_h);
+    let (out_w, out_h) = short_frame_size(aspect_w, aspect_h);
+
+    let filter_string = format!(
+        "crop={}:{}:{}:{},scale={}:{},setsar=1",
+        crop_w, crop_h, crop_x, crop_y, out_w, out_h
+    );
+
+    reencode_with_video_filter(input_path, output_path, &filter_string, encode_settings, |_| {})?;
+    Ok(())
+}
+
+// Builds the filter that scales the video to fit inside `target_w` x `target_h` and centers it on
+// bars of `pad_color` (any color convert_color_to_ffmpeg_rgb accepts).
+pub fn build_pad_filter(target_w: u32, target_h: u32, pad_color: &str) -> Result<String, ShortsError> {
+    Ok(format!(
+        "scale={w}:{h}:force_original_aspect_ratio=decrease:force_divisible_by=2,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2:color={c},setsar=1",
+        w = target_w,
+        h = target_h,
+        c = convert_color_to_ffmpeg_rgb(pad_color)?
+    ))
+}
+
+// Builds the filter that letterboxes the video like build_pad_filter, but over a blurred copy of
+// itself instead of solid bars. The copy is scaled up until it covers the whole frame and then
+// cropped to it, so no bars are left whatever the source aspect ratio.
+pub fn build_blur_background_filter(target_w: u32, target_h: u32) -> String {
+    format!(
+        "[in]split=2[background][foreground];\
+         [background]scale={w}:{h}:force_original_aspect_ratio=increase,crop={w}:{h},boxblur=20:2[blurred];\
+         [foreground]scale={w}:{h}:force_original_aspect_ratio=decrease:force_divisible_by=2[fitted];\
+         [blurred][fitted]overlay=(W-w)/2:(H-h)/2,setsar=1",
+        w = target_w,
+        h = target_h
+    )
+}
+
+// yuv420p (and so x264) needs even frame dimensions.
+fn check_target_frame_size(target_w: u32, target_h: u32) -> Result<(), ShortsError> {
+    if target_w == 0 || target_h == 0 || !target_w.is_multiple_of(2) || !target_h.is_multiple_of(2) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Target frame size must be even and not zero, got {}x{}",
+            target_w, target_h
+        )));
+    }
+    Ok(())
+}
+
+// Scales the video into a `target_w` x `target_h` frame with the letterbox look: the whole picture
+// is kept, centered on bars of `pad_color` (e.g. "black", "#202020"). Copies audio.
+pub fn pad_to_aspect(
+    input_path: &str,
+    output_path: &str,
+    target_w: u32,
+    target_h: u32,
+    pad_color: &str,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    check_target_frame_size(target_w, target_h)?;
+    let filter_string = build_pad_filter(target_w, target_h, pad_color)?;
+    ensure_ffmpeg_initialized()?;
+    reencode_with_video_filter(input_path, output_path, &filter_string, encode_settings, |_| {})?;
+    Ok(())
+}
+
+// Scales the video into a `target_w` x `target_h` frame with the blurred-background look: the
+// whole picture is kept, letterboxed over a blurred, enlarged copy of itself. Copies audio.
+pub fn fit_with_blur_background(
+    input_path: &str,
+    output_path: &str,
+    target_w: u32,
+    target_h: u32,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    check_target_frame_size(target_w, target_h)?;
+    ensure_ffmpeg_initialized()?;
+    let filter_string = build_blur_background_filter(target_w, target_h);
+    reencode_with_video_filter(input_path, output_path, &filter_string, encode_settings, |_| {})?;
+    Ok(())
+}
+
+// Brings the video to `target_aspect` in a SHORT_OUTPUT_WIDTH-wide frame the way `mode` says:
+// center-cropped (see crop_to_vertical), padded with bars of `pad_color` (see pad_to_aspect), or
+// over a blurred background. `pad_color` is only used by AspectFitMode::Pad.
+pub fn fit_to_aspect(
+    input_path: &str,
+    output_path: &str,
+    target_aspect: &str,
+    mode: AspectFitMode,
+    pad_color: &str,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    let (aspect_w, aspect_h) = parse_aspect_ratio(target_aspect)?;
+    let (out_w, out_h) = short_frame_size(aspect_w, aspect_h);
+    match mode {
+        AspectFitMode::Crop => crop_to_vertical(input_path, output_path, target_aspect, encode_settings),
+        AspectFitMode::Pad => pad_to_aspect(input_path, output_path, out_w, out_h, pad_color, encode_settings),
+        AspectFitMode::BlurBackground => fit_with_blur_background(input_path, output_path, out_w, out_h, encode_settings),
+    }
+}
+
+// Builds the scale filter for the requested output size. When only one dimension is given the
+// other follows the aspect ratio, rounded to an even number as yuv420p requires. Returns None when
+// neither is set.
+pub fn build_scale_filter(width: Option<u32>, height: Option<u32>) -> Option<String> {
+    let (w, h) = match (width, height) {
+        (None, None) => return None,
+        (Some(w), Some(h)) => (w as i64, h as i64),
+        (Some(w), None) => (w as i64, -2),
+        (None, Some(h)) => (-2, h as i64),
+    };
+    Some(format!("scale={}:{},setsar=1", w, h))
+}
+
+// Resizes the video to `width` x `height` (see build_scale_filter), copying audio.
+pub fn scale_video(
+    input_path: &str,
+    output_path: &str,
+    width: Option<u32>,
+    height: Option<u32>,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    let filter_string = build_scale_filter(width, height).ok_or_else(|| {
+        ShortsError::InvalidArgument("scale_video needs an output width, height or both".to_string())
+    })?;
+
+    ensure_ffmpeg_initialized()?;
+    reencode_with_video_filter(input_path, output_path, &filter_string, encode_settings, |_| {})?;
+    Ok(())
+}
+
+// Integrated loudness target used when normalizing audio, in LUFS. -14 matches what most
+// social platforms normalize to.
+pub const DEFAULT_TARGET_LUFS: f64 = -14.0;
+
+// Range accepted by FFmpeg's loudnorm filter for the integrated loudness target.
+const LOUDNORM_TARGET_RANGE: std::ops::RangeInclusive<f64> = -70.0..=-5.0;
+
+// Normalizes the audio to `target_lufs` integrated loudness (EBU R128) with FFmpeg's loudnorm
+// filter, re-encoding it with the configured audio codec and copying the video. Fails if the input
+// has no audio stream.
+pub fn normalize_audio_loudness(
+    input_path: &str,
+    output_path: &str,
+    target_lufs: f64,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    if !LOUDNORM_TARGET_RANGE.contains(&target_lufs) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Target loudness {} LUFS is out of range; use a value between {} and {}",
+            target_lufs,
+            LOUDNORM_TARGET_RANGE.start(),
+            LOUDNORM_TARGET_RANGE.end()
+        )));
+    }
+
+    ensure_ffmpeg_initialized()?;
+
+    {
+        let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+        if ictx.streams().best(ffmpeg::media::Type::Audio).is_none() {
+            return Err(ShortsError::NoAudioStream(input_path.to_string()));
+        }
+    }
+
+    let filter_string = build_loudnorm_filter(target_lufs);
+    reencode_with_audio_filter(input_path, output_path, &filter_string, encode_settings, |_| {})?;
+    Ok(())
+}
+
+// Builds the loudnorm audio filter used by normalize_audio_loudness.
+pub fn build_loudnorm_filter(target_lufs: f64) -> String {
+    // TP/LRA are loudnorm's defaults for true peak and loudness range, spelled out for clarity.
+    format!("loudnorm=I={}:TP=-1.5:LRA=11", target_lufs)
+}
+
+// Builds the audio filter that mixes a music bed under the input audio ("in"). The music loops
+// forever (amovie loop=0) and amix stops with the input, so short tracks repeat and long ones are
+// cut to the video's length. With `duck`, the music is compressed whenever the voice is present.
+pub fn build_music_mix_filter(music_path: &str, music_volume: f32, duck: bool) -> String {
+    let music_chain = format!(
+        "amovie=filename='{}':loop=0,volume={}",
+        escape_path_for_ffmpeg_filter(music_path),
+        music_volume
+    );
+    if duck {
+        format!(
+            "{}[music];[in]asplit=2[voice][sidechain];\
+             [music][sidechain]sidechaincompress=threshold=0.02:ratio=8:attack=20:release=400[ducked];\
+             [voice][ducked]amix=inputs=2:duration=first:dropout_transition=0:normalize=0",
+            music_chain
+        )
+    } else {
+        format!(
+            "{}[music];[in][music]amix=inputs=2:duration=first:dropout_transition=0:normalize=0",
+            music_chain
+        )
+    }
+}
+
+// Mixes `music_path` under the video's audio at `music_volume` (1.0 = original level), re-encoding
+// the audio and copying the video. With `duck`, the music drops while someone is speaking.
+pub fn mix_background_music(
+    video_path: &str,
+    music_path: &str,
+    output_path: &str,
+    music_volume: f32,
+    duck: bool,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    if !(music_volume.is_finite() && music_volume >= 0.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Background music volume must be a non-negative number, got {}",
+            music_volume
+        )));
+    }
+    if !Path::new(music_path).is_file() {
+        return Err(ShortsError::InvalidArgument(format!("Background music file does not exist: {}", music_path)));
+    }
+
+    ensure_ffmpeg_initialized()?;
+
+    {
+        let ictx = ffmpeg::format::input(&Path::new(video_path))?;
+        if ictx.streams().best(ffmpeg::media::Type::Audio).is_none() {
+            return Err(ShortsError::NoAudioStream(video_path.to_string()));
+        }
+    }
+
+    let filter_string = build_music_mix_filter(music_path, music_volume, duck);
+    reencode_with_audio_filter(video_path, output_path, &filter_string, encode_settings, |_| {})?;
+    Ok(())
+}
+
+// Defaults for remove_silence: anything quieter than -35 dBFS for at least 0.6s counts as dead air.
+pub const DEFAULT_SILENCE_THRESHOLD_DB: f32 = -35.0;
+pub const DEFAULT_MIN_SILENCE_SECS: f64 = 0.6;
+
+// Audio kept on each side of a removed silence, so cuts don't clip the ends of words.
+const SILENCE_PADDING_SECS: f64 = 0.1;
+
+// Runs the best audio stream through FFmpeg's silencedetect filter and returns the silent spans
+// as (start, end) seconds. A silence still running at the end of the input ends at its duration.
+pub fn detect_silences(input_path: &str, threshold_db: f32, min_silence_secs: f64) -> Result<Vec<(f64, f64)>, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+
+    let mut ictx = ffmpeg::format::input(&Path::new(input_path))?;
+    let input_duration = input_duration_secs(&ictx);
+    let (stream_index, time_base, mut decoder) = {
+        let stream = ictx
+            .streams()
+            .best(ffmpeg::media::Type::Audio)
+            .ok_or_else(|| ShortsError::NoAudioStream(input_path.to_string()))?;
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .audio()?;
+        (stream.index(), stream.time_base(), decoder)
+    };
+    let channel_layout = if decoder.channel_layout().bits() != 0 {
+        decoder.channel_layout()
+    } else {
+        ffmpeg::ChannelLayout::default(decoder.channels() as i32)
+    };
+    let mut filter = build_audio_filter_graph(
+        &decoder,
+        time_base,
+        channel_layout,
+        decoder.format(),
+        decoder.rate() as i32,
+        &format!("silencedetect=noise={}dB:duration={}", threshold_db, min_silence_secs),
+    )?;
+
+    let mut silences = Vec::new();
+    let mut silence_start = None;
+    // silencedetect reports through the metadata of the frames it passes on.
+    let mut drain = |filter: &mut ffmpeg::filter::Graph| -> Result<(), Error> {
+        let mut sink = filter.get("out").ok_or_else(|| Error::msg("Audio filter graph has no output"))?;
+        let mut filtered = ffmpeg::frame::Audio::empty();
+        while sink.sink().frame(&mut filtered).is_ok() {
+            let metadata = filtered.metadata();
+            if let Some(start) = metadata.get("lavfi.silence_start").and_then(|v| v.parse::<f64>().ok()) {
+                silence_start = Some(start.max(0.0));
+            }
+            if let Some(end) = metadata.get("lavfi.silence_end").and_then(|v| v.parse::<f64>().ok()) {
+                if let Some(start) = silence_start.take() {
+                    silences.push((start, end));
+                }
+            }
+        }
+        Ok(())
+    };
+    let mut decoded = ffmpeg::frame::Audio::empty();
+    let mut feed = |decoder: &mut ffmpeg::decoder::Audio, filter: &mut ffmpeg::filter::Graph| -> Result<(), Error> {
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            filter.get("in").ok_or_else(|| Error::msg("Audio filter graph has no input"))?.source().add(&decoded)?;
+            drain(filter)?;
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet)?;
+            feed(&mut decoder, &mut filter)?;
+        }
+    }
+    decoder.send_eof()?;
+    feed(&mut decoder, &mut filter)?;
+    filter.get("in").ok_or_else(|| Error::msg("Audio filter graph has no input"))?.source().flush()?;
+    drain(&mut filter)?;
+
+    if let (Some(start), Some(end)) = (silence_start, input_duration) {
+        silences.push((start, end));
+    }
+    Ok(silences)
+}
+
+// Returns the (start, end) spans of a `total_duration` long clip that remain once `silences` are
+// cut out, keeping SILENCE_PADDING_SECS of each silence next to the audio around it.
+pub(crate) fn spans_without_silences(silences: &[(f64, f64)], total_duration: f64) -> Vec<(f64, f64)> {
+    let mut spans = Vec::new();
+    let mut span_start = 0.0;
+    for &(silence_start, silence_end) in silences {
+        // Leading and trailing silences don't need padding on their outer side.
+        let cut_start = if silence_start <= 0.0 { 0.0 } else { silence_start + SILENCE_PADDING_SECS };
+        let cut_end = if silence_end >= total_duration { total_duration } else { silence_end - SILENCE_PADDING_SECS };
+        if cut_end <= cut_start {
+            continue;
+        }
+        if cut_start > span_start {
+            spans.push((span_start, cut_start));
+        }
+        span_start = cut_end;
+    }
+    if span_start < total_duration {
+        spans.push((span_start, total_duration));
+    }
+    spans
+}
+
+// Builds the video and audio filters that keep only `spans` of the input and join them back
+// together. Both streams are cut at the same timestamps (trim/atrim) and concatenated, so they
+// stay in sync.
+pub fn build_silence_cut_filters(spans: &[(f64, f64)]) -> (String, String) {
+    let build = |prefix: &str, split: &str, trim: &str, setpts: &str, concat_streams: &str| {
+        if let [(start, end)] = spans {
+            return format!("{}=start={}:end={},{}=PTS-STARTPTS", trim, start, end, setpts);
+        }
+        let split_outputs: String = (0..spans.len()).map(|i| format!("[{}{}]", prefix, i)).collect();
+        let mut filter = format!("[in]{}={}{}", split, spans.len(), split_outputs);
+        for (i, (start, end)) in spans.iter().enumerate() {
+            filter.push_str(&format!(
+                ";[{}{}]{}=start={}:end={},{}=PTS-STARTPTS[{}cut{}]",
+                prefix, i, trim, start, end, setpts, prefix, i
+            ));
+        }
+        filter.push(';');
+        for i in 0..spans.len() {
+            filter.push_str(&format!("[{}cut{}]", prefix, i));
+        }
+        filter.push_str(&format!("concat=n={}:{}", spans.len(), concat_streams));
+        filter
+    };
+    (
+        build("v", "split", "trim", "setpts", "v=1:a=0"),
+        build("a", "asplit", "atrim", "asetpts", "v=0:a=1"),
+    )
+}
+
+// Removes pauses longer than `min_silence_secs` in which the audio stays below `threshold_db`
+// (dBFS), re-encoding both streams. Silences are found with silencedetect and the remaining spans
+// of video and audio are cut and joined together, keeping them in sync. Without any silence to
+// remove the input is copied unchanged. Fails if the input has no audio stream.
+pub fn remove_silence(
+    input_path: &str,
+    output_path: &str,
+    threshold_db: f32,
+    min_silence_secs: f64,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    if !(threshold_db.is_finite() && threshold_db < 0.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Silence threshold must be below 0 dB, got {}",
+            threshold_db
+        )));
+    }
+    if !(min_silence_secs.is_finite() && min_silence_secs > 0.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Minimum silence duration must be greater than zero, got {}s",
+            min_silence_secs
+        )));
+    }
+
+    let total_duration = probe_duration_secs(input_path)?;
+    let silences = detect_silences(input_path, threshold_db, min_silence_secs)?;
+    let spans = spans_without_silences(&silences, total_duration);
+    let kept: f64 = spans.iter().map(|(start, end)| end - start).sum();
+    if spans.is_empty() || kept >= total_duration {
+        std::fs::copy(input_path, output_path)?;
+        return Ok(());
+    }
+
+    let (video_filter, audio_filter) = build_silence_cut_filters(&spans);
+    transcode_with_filters(
+        input_path,
+        output_path,
+        Some(&video_filter),
+        Some(&audio_filter),
+        encode_settings,
+        |_| {},
+    )?;
+    Ok(())
+}
+
+// Scene change score (0..1, the share of the picture that changed) above which detect_scenes
+// reports a cut. 0.4 catches hard cuts without firing on fast motion.
+pub const DEFAULT_SCENE_THRESHOLD: f32 = 0.4;
+
+// Runs the best video stream through FFmpeg's scene score (select='gt(scene,threshold)') and
+// returns the times in seconds at which a new scene starts, in order. A single-scene video gives
+// an empty list, so callers can fall back to fixed-length splitting.
+pub fn detect_scenes(input_path: &str, threshold: f32) -> Result<Vec<f64>, ShortsError> {
+    if !(threshold > 0.0 && threshold < 1.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Scene threshold must be between 0 and 1 (got {})",
+            threshold
+        )));
+    }
+
+    ensure_ffmpeg_initialized()?;
+
+    let mut ictx = ffmpeg::format::input(&Path::new(input_path))?;
+    let (stream_index, time_base, mut decoder) = {
+        let stream = ictx
+            .streams()
+            .best(ffmpeg::media::Type::Video)
+            .ok_or_else(|| Error::msg(format!("No video stream found in '{}'", input_path)))?;
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+        (stream.index(), stream.time_base(), decoder)
+    };
+    let mut filter = build_video_filter_graph(
+        &decoder,
+        time_base,
+        &format!("select='gt(scene,{})'", threshold),
+        None,
+        decoder.format(),
+    )?;
+    let sink_time_base = filter
+        .get("out")
+        .ok_or_else(|| Error::msg("Video filter graph has no output"))?
+        .sink()
+        .time_base();
+
+    // Only the frames that start a new scene make it through the select filter.
+    let mut scene_starts = Vec::new();
+    let mut drain = |filter: &mut ffmpeg::filter::Graph| -> Result<(), Error> {
+        let mut sink = filter.get("out").ok_or_else(|| Error::msg("Video filter graph has no output"))?;
+        let mut selected = ffmpeg::frame::Video::empty();
+        while sink.sink().frame(&mut selected).is_ok() {
+            if let Some(pts) = selected.pts() {
+                scene_starts.push(pts as f64 * f64::from(sink_time_base));
+            }
+        }
+        Ok(())
+    };
+    let mut decoded = ffmpeg::frame::Video::empty();
+    let mut feed = |decoder: &mut ffmpeg::decoder::Video, filter: &mut ffmpeg::filter::Graph| -> Result<(), Error> {
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            filter.get("in").ok_or_else(|| Error::msg("Video filter graph has no input"))?.source().add(&decoded)?;
+            drain(filter)?;
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet)?;
+            feed(&mut decoder, &mut filter)?;
+        }
+    }
+    decoder.send_eof()?;
+    feed(&mut decoder, &mut filter)?;
+    filter.get("in").ok_or_else(|| Error::msg("Video filter graph has no input"))?.source().flush()?;
+    drain(&mut filter)?;
+
+    Ok(scene_starts)
+}
+
+// Returns the duration of the media file in seconds.
+pub fn probe_duration_secs(path: &str) -> Result<f64, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    let ictx = ffmpeg::format::input(&Path::new(path))?;
+    input_duration_secs(&ictx)
+        .ok_or_else(|| Error::msg(format!("Could not determine the duration of '{}'", path)).into())
+}
+
+// Builds the video (fade) and audio (afade) filters for a fade in over the first `in_secs` and a
+// fade out over the last `out_secs` of a `total_duration` long clip. Zero-length fades are left
+// out, so both filters are None when neither fade is requested.
+pub fn build_fade_filters(in_secs: f64, out_secs: f64, total_duration: f64) -> (Option<String>, Option<String>) {
+    let mut video_fades = Vec::new();
+    let mut audio_fades = Vec::new();
+    if in_secs > 0.0 {
+        video_fades.push(format!("fade=t=in:st=0:d={}", in_secs));
+        audio_fades.push(format!("afade=t=in:st=0:d={}", in_secs));
+    }
+    if out_secs > 0.0 {
+        let start = (total_duration - out_secs).max(0.0);
+        video_fades.push(format!("fade=t=out:st={}:d={}", start, out_secs));
+        audio_fades.push(format!("afade=t=out:st={}:d={}", start, out_secs));
+    }
+    if video_fades.is_empty() {
+        return (None, None);
+    }
+    (Some(video_fades.join(",")), Some(audio_fades.join(",")))
+}
+
+// Fades the video and its audio in over the first `in_secs` and out over the last `out_secs` of
+// the `total_duration` long input. Without any fade the input is copied unchanged, and a video
+// without audio only gets the video fades.
+pub fn apply_fades(
+    input_path: &str,
+    output_path: &str,
+    in_secs: f64,
+    out_secs: f64,
+    total_duration: f64,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    if !(in_secs.is_finite() && in_secs >= 0.0 && out_secs.is_finite() && out_secs >= 0.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Fade durations must not be negative, got {}s in and {}s out",
+            in_secs, out_secs
+        )));
+    }
+    let (video_filter, audio_filter) = match build_fade_filters(in_secs, out_secs, total_duration) {
+        (Some(video_filter), audio_filter) => (video_filter, audio_filter),
+        _ => {
+            std::fs::copy(input_path, output_path)?;
+            return Ok(());
+        }
+    };
+
+    ensure_ffmpeg_initialized()?;
+
+    let has_audio = {
+        let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+        ictx.streams().best(ffmpeg::media::Type::Audio).is_some()
+    };
+    let audio_filter = if has_audio { audio_filter } else { None };
+    transcode_with_filters(
+        input_path,
+        output_path,
+        Some(&video_filter),
+        audio_filter.as_deref(),
+        encode_settings,
+        |_| {},
+    )?;
+    Ok(())
+}
+
+// Builds the video and audio filters that play the input `factor` times as fast (below 1.0 for
+// slow motion). atempo only takes factors between 0.5 and 2.0, so larger changes chain several
+// atempo stages whose product is `factor`.
+pub fn build_speed_filters(factor: f64) -> (String, String) {
+    let mut atempo_stages = Vec::new();
+    let mut remaining = factor;
+    while remaining > 2.0 {
+        atempo_stages.push("atempo=2".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        atempo_stages.push("atempo=0.5".to_string());
+        remaining /= 0.5;
+    }
+    atempo_stages.push(format!("atempo={}", remaining));
+    (format!("setpts=PTS/{}", factor), atempo_stages.join(","))
+}
+
+// Speeds the video and its audio up by `factor` (or slows them down, below 1.0), re-encoding both
+// streams; the audio keeps its pitch. A factor of 1.0 copies the input unchanged, and a video
+// without audio only has its video retimed.
+pub fn change_speed(
+    input_path: &str,
+    output_path: &str,
+    factor: f64,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Speed factor must be greater than zero, got {}",
+            factor
+        )));
+    }
+    if factor == 1.0 {
+        std::fs::copy(input_path, output_path)?;
+        return Ok(());
+    }
+
+    ensure_ffmpeg_initialized()?;
+
+    let has_audio = {
+        let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+        ictx.streams().best(ffmpeg::media::Type::Audio).is_some()
+    };
+    let (video_filter, audio_filter) = build_speed_filters(factor);
+    transcode_with_filters(
+        input_path,
+        output_path,
+        Some(&video_filter),
+        has_audio.then_some(audio_filter.as_str()),
+        encode_settings,
+        |_| {},
+    )?;
+    Ok(())
+}
+
+// Writes the frame shown at `at_secs` into `video_path` to `output_image_path` as a JPEG (.jpg,
+// .jpeg) or PNG (.png) image, at the video's frame size. The frame is the first one at or after
+// `at_secs`; a time past the end of the video gives its last frame.
+pub fn extract_thumbnail(video_path: &str, output_image_path: &str, at_secs: f64) -> Result<(), ShortsError> {
+    if !(at_secs.is_finite() && at_secs >= 0.0) {
+        return Err(ShortsError::InvalidArgument(format!(
+            "Thumbnail time must not be negative (got {}s)",
+            at_secs
+        )));
+    }
+    let extension = Path::new(output_image_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let (codec_id, pixel_format) = match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => (ffmpeg::codec::Id::MJPEG, ffmpeg::format::Pixel::YUVJ420P),
+        Some("png") => (ffmpeg::codec::Id::PNG, ffmpeg::format::Pixel::RGB24),
+        _ => {
+            return Err(ShortsError::InvalidArgument(format!(
+                "Unsupported thumbnail format for '{}'; use a .jpg, .jpeg or .png path",
+                output_image_path
+            )))
+        }
+    };
+
+    ensure_ffmpeg_initialized()?;
+
+    let mut ictx = ffmpeg::format::input(&Path::new(video_path))?;
+    let target_secs = match input_duration_secs(&ictx) {
+        Some(duration) if at_secs > duration => {
+            warn!(
+                "Thumbnail time {}s is past the end of '{}' ({:.2}s long); using its last frame",
+                at_secs, video_path, duration
+            );
+            duration
+        }
+        _ => at_secs,
+    };
+
+    let (stream_index, time_base, rotation_degrees, mut decoder) = {
+        let stream = ictx
+            .streams()
+            .best(ffmpeg::media::Type::Video)
+            .ok_or_else(|| Error::msg(format!("No video stream found in '{}'", video_path)))?;
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+        (stream.index(), stream.time_base(), stream_rotation_degrees(&stream), decoder)
+    };
+
+    // Seek to the keyframe at or before the target, then decode forward to the exact frame.
+    let target_ts = (target_secs * AV_TIME_BASE) as i64;
+    ictx.seek(target_ts, ..=target_ts)?;
+    let frame = decode_frame_at(&mut ictx, &mut decoder, stream_index, time_base, target_secs)?
+        .ok_or_else(|| Error::msg(format!("No video frame could be decoded from '{}'", video_path)))?;
+
+    let mut filter = build_video_filter_graph(&decoder, time_base, &upright_filter_spec(rotation_degrees, "null"), None, pixel_format)?;
+    {
+        let mut source = filter
+            .get("in")
+            .ok_or_else(|| Error::msg("Video filter graph has no input"))?;
+        source.source().add(&frame)?;
+        source.source().flush()?;
+    }
+    let mut converted = ffmpeg::frame::Video::empty();
+    filter
+        .get("out")
+        .ok_or_else(|| Error::msg("Video filter graph has no output"))?
+        .sink()
+        .frame(&mut converted)?;
+
+    let codec = ffmpeg::encoder::find(codec_id)
+        .ok_or_else(|| Error::msg(format!("No {:?} encoder is available in this FFmpeg build", codec_id)))?;
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    encoder.set_width(converted.width());
+    encoder.set_height(converted.height());
+    encoder.set_format(pixel_format);
+    encoder.set_time_base(time_base);
+    let mut options = ffmpeg::Dictionary::new();
+    if codec_id == ffmpeg::codec::Id::MJPEG {
+        // The same high quality as `-q:v 2` on the ffmpeg command line.
+        options.set("qmin", "2");
+        options.set("qmax", "2");
+    }
+    let mut encoder = encoder.open_as_with(codec, options)?;
+    encoder.send_frame(&converted)?;
+    encoder.send_eof()?;
+
+    // An image encoder turns the single frame into a single packet holding the whole file.
+    let mut packet = ffmpeg::Packet::empty();
+    encoder.receive_packet(&mut packet)?;
+    let data = packet
+        .data()
+        .ok_or_else(|| Error::msg("The image encoder produced no data"))?;
+    std::fs::write(output_image_path, data)?;
     Ok(())
 }
 
-// Helper function to escape paths for FFmpeg filter strings, especially for Windows.
-// FFmpeg expects colons to be escaped, e.g., 'C\:/path/to/file.srt'
+// Decodes `stream_index` from the current position and returns the first frame at or after
+// `target_secs`, or the last frame of the stream when there is none.
+fn decode_frame_at(
+    ictx: &mut ffmpeg::format::context::Input,
+    decoder: &mut ffmpeg::decoder::Video,
+    stream_index: usize,
+    time_base: ffmpeg::Rational,
+    target_secs: f64,
+) -> Result<Option<ffmpeg::frame::Video>, Error> {
+    let mut last_frame = None;
+    let mut receive_frames = |decoder: &mut ffmpeg::decoder::Video| {
+        let mut decoded = ffmpeg::frame::Video::empty();
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let reached = decoded
+                .timestamp()
+                .is_some_and(|ts| ts as f64 * f64::from(time_base) >= target_secs);
+            last_frame = Some(std::mem::replace(&mut decoded, ffmpeg::frame::Video::empty()));
+            if reached {
+                return true;
+            }
+        }
+        false
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        if receive_frames(decoder) {
+            return Ok(last_frame);
+        }
+    }
+    decoder.send_eof()?;
+    receive_frames(decoder);
+    Ok(last_frame)
+}
+
+// Escapes a path for use inside a single-quoted filter option value, e.g. filename='...'.
+// There are two levels of unescaping: the filtergraph parser strips the quotes, then the option
+// parser treats ':' and quotes as special. Backslash separators become forward slashes (which FFmpeg
+// accepts on Windows), so C:\Users\me\sub.srt is written as C\:/Users/me/sub.srt. A quote in
+// the name cannot appear inside the graph-level quotes, so it closes them, is escaped for both
+// levels and reopens them: my'clip becomes my\'\''clip.
 fn escape_path_for_ffmpeg_filter(path: &str) -> String {
-    path.replace(":", "\\:")
-}
-
-// Helper function to convert color strings to FFmpeg's &HBBGGRR format (or &HAABBGGRR)
-// For simplicity, this version will handle common names and hex codes without alpha.
-// FFmpeg's PrimaryColour for ASS/SSA is &HAABBGGRR. For `subtitles` filter, it might be similar.
-// Let's assume BGR format for now, &HBBGGRR. Alpha will be FF (opaque).
-fn convert_color_to_ffmpeg_bgr(color_str: &str) -> Result<String, Error> {
-    let color_str = color_str.trim_start_matches('#');
-    match color_str.to_lowercase().as_str() {
-        "white" => Ok("&HFFFFFF".to_string()), // BGR: FF FF FF
-        "black" => Ok("&H000000".to_string()), // BGR: 00 00 00
-        "red"   => Ok("&H0000FF".to_string()), // BGR: 00 00 FF
-        "green" => Ok("&H00FF00".to_string()), // BGR: 00 FF 00
-        "blue"  => Ok("&HFF0000".to_string()), // BGR: FF 00 00
-        hex if hex.len() == 6 => {
-            // Assuming RRGGBB input, convert to BBGGRR
-            let r = &hex[0..2];
-            let g = &hex[2..4];
-            let b = &hex[4..6];
-            // Check if valid hex
-            u8::from_str_radix(r, 16)?;
-            u8::from_str_radix(g, 16)?;
-            u8::from_str_radix(b, 16)?;
-            Ok(format!("&H{}{}{}", b, g, r).to_uppercase())
-        }
-        _ => bail!("Unsupported color string: {}. Use common names or #RRGGBB hex.", color_str),
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '\\' => escaped.push('/'),
+            ':' | '[' | ']' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\'' => escaped.push_str("\\'\\''"),
+            _ => escaped.push(c),
+        }
     }
+    escaped
+}
+
+// Helper function to convert color strings to the &HAABBGGRR format used by ASS styles (and force_style).
+// Accepts CSS/X11 names, #RRGGBB, #RRGGBBAA and the #RGB/#RGBA shorthand. The alpha byte is passed
+// through as-is, and ASS counts alpha as transparency: 00 is opaque and FF is invisible. Names, #RGB
+// and 6-digit hex are opaque (&H00BBGGRR).
+pub(crate) fn convert_color_to_ffmpeg_bgr(color_str: &str) -> Result<String, ShortsError> {
+    let hex = normalize_color_hex(color_str)?;
+    // RRGGBB[AA] converted to AABBGGRR
+    let (r, g, b) = (&hex[0..2], &hex[2..4], &hex[4..6]);
+    let a = if hex.len() == 8 { &hex[6..8] } else { "00" };
+    Ok(format!("&H{}{}{}{}", a, b, g, r))
+}
+
+// Same as convert_color_to_ffmpeg_bgr, with the color's opacity scaled by `opacity` (0.0 to 1.0),
+// e.g. 0.5 for a half-transparent caption box.
+pub(crate) fn convert_color_to_ffmpeg_bgr_with_opacity(color_str: &str, opacity: f64) -> Result<String, ShortsError> {
+    let hex = normalize_color_hex(color_str)?;
+    let color_alpha = if hex.len() == 8 { u8::from_str_radix(&hex[6..8], 16).unwrap_or(0) } else { 0 };
+    // ASS alpha is transparency, so the opacity scales 255 - alpha.
+    let alpha = 255.0 - (255.0 - color_alpha as f64) * opacity.clamp(0.0, 1.0);
+    Ok(format!("&H{:02X}{}{}{}", alpha.round() as u8, &hex[4..6], &hex[2..4], &hex[0..2]))
+}
+
+// Converts a color string accepted by convert_color_to_ffmpeg_bgr to the 0xRRGGBB form taken by
+// FFmpeg filters such as pad. Any alpha byte is dropped: the filters' output is opaque anyway.
+pub(crate) fn convert_color_to_ffmpeg_rgb(color_str: &str) -> Result<String, ShortsError> {
+    Ok(format!("0x{}", &normalize_color_hex(color_str)?[0..6]))
+}
+
+// Resolves names and #RGB[A] shorthand and checks the digits, returning uppercase RRGGBB or RRGGBBAA.
+fn normalize_color_hex(color_str: &str) -> Result<String, ShortsError> {
+    let lower = color_str.trim_start_matches('#').to_lowercase();
+    let hex = match named_color_hex(&lower) {
+        Some(hex) => hex.to_string(),
+        // #RGB and #RGBA shorthand: each nibble is doubled, so #F80 is #FF8800
+        None if lower.len() == 3 || lower.len() == 4 => lower.chars().flat_map(|c| [c, c]).collect(),
+        None => lower,
+    };
+    if (hex.len() == 6 || hex.len() == 8) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(hex.to_uppercase())
+    } else {
+        Err(ShortsError::InvalidColor(color_str.to_string()))
+    }
+}
+
+// RRGGBB for the CSS/X11 color names accepted in colour options. "green" stays pure #00FF00 (the
+// X11 value, as it always was here) rather than the darker CSS #008000.
+fn named_color_hex(name: &str) -> Option<&'static str> {
+    let hex = match name {
+        "white" => "ffffff",
+        "black" => "000000",
+        "red" => "ff0000",
+        "green" | "lime" => "00ff00",
+        "blue" => "0000ff",
+        "yellow" => "ffff00",
+        "cyan" | "aqua" => "00ffff",
+        "magenta" | "fuchsia" => "ff00ff",
+        "orange" => "ffa500",
+        "gray" | "grey" => "808080",
+        "darkgray" | "darkgrey" => "a9a9a9",
+        "lightgray" | "lightgrey" => "d3d3d3",
+        "silver" => "c0c0c0",
+        "maroon" => "800000",
+        "olive" => "808000",
+        "navy" => "000080",
+        "purple" => "800080",
+        "teal" => "008080",
+        "pink" => "ffc0cb",
+        "hotpink" => "ff69b4",
+        "brown" => "a52a2a",
+        "gold" => "ffd700",
+        "violet" => "ee82ee",
+        "indigo" => "4b0082",
+        "coral" => "ff7f50",
+        "salmon" => "fa8072",
+        "crimson" => "dc143c",
+        "tomato" => "ff6347",
+        "turquoise" => "40e0d0",
+        "skyblue" => "87ceeb",
+        "royalblue" => "4169e1",
+        "darkblue" => "00008b",
+        "darkred" => "8b0000",
+        "darkgreen" => "006400",
+        "forestgreen" => "228b22",
+        "limegreen" => "32cd32",
+        "khaki" => "f0e68c",
+        "beige" => "f5f5dc",
+        "ivory" => "fffff0",
+        "tan" => "d2b48c",
+        "chocolate" => "d2691e",
+        "lavender" => "e6e6fa",
+        "plum" => "dda0dd",
+        "orchid" => "da70d6",
+        "slategray" | "slategrey" => "708090",
+        _ => return None,
+    };
+    Some(hex)
 }
 
 // Helper function to map alignment strings to FFmpeg's numeric Alignment values (1-9 for numpad layout)
 // Vertical: "bottom", "center", "top"
 // Horizontal: "left", "center", "right"
-fn map_alignment_to_ffmpeg_value(vertical: &str, horizontal: &str) -> Result<u8, Error> {
+pub(crate) fn map_alignment_to_ffmpeg_value(vertical: &str, horizontal: &str) -> Result<u8, ShortsError> {
     match (vertical.to_lowercase().as_str(), horizontal.to_lowercase().as_str()) {
         ("bottom", "left") => Ok(1),
         ("bottom", "center") => Ok(2),
@@ -115,27 +2845,227 @@ fn map_alignment_to_ffmpeg_value(vertical: &str, horizontal: &str) -> Result<u8,
         ("top", "left") => Ok(7),
         ("top", "center") => Ok(8),
         ("top", "right") => Ok(9),
-        _ => bail!("Invalid alignment combination: vertical='{}', horizontal='{}'. Use 'top/center/bottom' and 'left/center/right'.", vertical, horizontal),
+        _ => Err(ShortsError::InvalidAlignment {
+            vertical: vertical.to_string(),
+            horizontal: horizontal.to_string(),
+        }),
+    }
+}
+
+
+// Placement and sizing of the image composited by overlay_watermark.
+pub struct WatermarkOptions<'a> {
+    pub image_path: &'a str,
+    pub vertical_alignment: &'a str, // "top", "center", "bottom"
+    pub horizontal_alignment: &'a str, // "left", "center", "right"
+    pub margin_px: u32,
+    // Scale the image to this percentage of the video width. When None the image keeps its
+    // size, unless it doesn't fit inside the frame's margins, in which case it is shrunk to fit.
+    pub scale_percent: Option<f64>,
+}
+
+// Overlay x/y expressions for a numpad-style alignment value (see map_alignment_to_ffmpeg_value),
+// keeping `margin` pixels between the image and the frame edges it is aligned to.
+fn overlay_position_exprs(alignment: u8, margin: u32) -> (String, String) {
+    let x = match (alignment - 1) % 3 {
+        0 => margin.to_string(),
+        1 => "(main_w-overlay_w)/2".to_string(),
+        _ => format!("main_w-overlay_w-{}", margin),
+    };
+    let y = match (alignment - 1) / 3 {
+        0 => format!("main_h-overlay_h-{}", margin),
+        1 => "(main_h-overlay_h)/2".to_string(),
+        _ => margin.to_string(),
+    };
+    (x, y)
+}
+
+// Returns the size to scale the watermark image to, or None to keep its original size.
+fn compute_watermark_size(
+    frame_w: u32,
+    frame_h: u32,
+    image_w: u32,
+    image_h: u32,
+    margin: u32,
+    scale_percent: Option<f64>,
+) -> Option<(u32, u32)> {
+    let image_aspect = image_h as f64 / image_w as f64;
+    let target_w = match scale_percent {
+        Some(percent) => frame_w as f64 * percent / 100.0,
+        None => {
+            let max_w = frame_w.saturating_sub(2 * margin).max(1) as f64;
+            let max_h = frame_h.saturating_sub(2 * margin).max(1) as f64;
+            if image_w as f64 <= max_w && image_h as f64 <= max_h {
+                return None;
+            }
+            max_w.min(max_h / image_aspect)
+        }
+    };
+    let width = (target_w.round() as u32).max(1);
+    let height = ((width as f64 * image_aspect).round() as u32).max(1);
+    Some((width, height))
+}
+
+// Composites an image (e.g. a PNG logo with transparency) over every frame of the video,
+// re-encoding the video and copying the audio.
+pub fn overlay_watermark(
+    input_path: &str,
+    output_path: &str,
+    opts: &WatermarkOptions,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<(), ShortsError> {
+    ensure_ffmpeg_initialized()?;
+
+    if let Some(percent) = opts.scale_percent {
+        if !(percent > 0.0 && percent <= 100.0) {
+            return Err(ShortsError::InvalidArgument(format!(
+                "Watermark scale must be a percentage between 0 and 100, got {}",
+                percent
+            )));
+        }
+    }
+    let alignment = map_alignment_to_ffmpeg_value(opts.vertical_alignment, opts.horizontal_alignment)?;
+
+    let (frame_w, frame_h) = {
+        let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+        probe_video_dimensions(&ictx)?
+    };
+    let (image_w, image_h) = {
+        let image_ctx = ffmpeg::format::input(&Path::new(opts.image_path))
+            .map_err(|e| Error::msg(format!("Failed to open watermark image '{}': {}", opts.image_path, e)))?;
+        probe_video_dimensions(&image_ctx)?
+    };
+
+    let mut watermark_chain = format!("movie=filename='{}'", escape_path_for_ffmpeg_filter(opts.image_path));
+    if let Some((width, height)) =
+        compute_watermark_size(frame_w, frame_h, image_w, image_h, opts.margin_px, opts.scale_percent)
+    {
+        watermark_chain.push_str(&format!(",scale={}:{}", width, height));
+    }
+    let (x, y) = overlay_position_exprs(alignment, opts.margin_px);
+    let filter_string = format!("{}[watermark];[in][watermark]overlay=x={}:y={}", watermark_chain, x, y);
+
+    reencode_with_video_filter(input_path, output_path, &filter_string, encode_settings, |_| {})?;
+    Ok(())
+}
+
+// fontconfig name that resolves to the system's default sans-serif font (e.g. DejaVu Sans), used
+// when the font file is missing and missing_font_policy is fallback.
+pub const FALLBACK_FONT_NAME: &str = "Sans";
+
+// Font, outline, shadow and emphasis applied to burned SRT/VTT subtitles through force_style.
+pub struct SubtitleStyle<'a> {
+    pub font_name: Option<&'a str>, // Font family looked up through fontconfig, e.g. "DejaVu Sans", used instead of the font file
+    pub outline_color: &'a str, // Same formats as the font color
+    pub outline_width: f64, // Pixels, 0 disables the outline
+    pub shadow_depth: f64, // Pixels, 0 disables the shadow
+    pub box_color: Option<&'a str>, // Draws a box of this color behind the text instead of the outline
+    pub box_opacity: f64, // 0.0 (invisible) to 1.0, on top of any alpha in box_color
+    pub bold: bool,
+    pub italic: bool,
+    pub margin_v: u32, // Video pixels between the subtitles and the top or bottom edge they are aligned to
+    pub margin_h: u32, // Video pixels kept clear at the left and right edges
+}
+
+impl Default for SubtitleStyle<'_> {
+    // A 2px black outline keeps white captions readable over bright footage.
+    fn default() -> Self {
+        SubtitleStyle {
+            font_name: None,
+            outline_color: "black",
+            outline_width: 2.0,
+            shadow_depth: 0.0,
+            box_color: None,
+            box_opacity: 1.0,
+            bold: false,
+            italic: false,
+            margin_v: 0,
+            margin_h: 0,
+        }
+    }
+}
+
+// PlayResX/PlayResY of the script FFmpeg builds when it renders SRT/VTT subtitles. force_style
+// sizes are in these script units, which libass scales up to the frame.
+const SUBTITLE_SCRIPT_RESOLUTION: (u32, u32) = (384, 288);
+
+// Font size (in script units) of text `percent` percent of the frame height tall, e.g. 5.0 for
+// captions a twentieth of the height. libass scales script units to the frame the subtitles are
+// burned into, so the result holds for any output resolution, including after scaling.
+pub fn font_size_for_height_percent(percent: f64) -> u32 {
+    ((percent / 100.0 * SUBTITLE_SCRIPT_RESOLUTION.1 as f64).round() as u32).max(1)
+}
+
+// Converts `style`'s margins from video pixels to script units for a `frame_size` (width, height)
+// frame, as (horizontal, vertical).
+fn subtitle_margins_in_script_units(style: &SubtitleStyle, frame_size: (u32, u32)) -> (u32, u32) {
+    let scale = |pixels: u32, script: u32, frame: u32| {
+        (pixels as f64 * script as f64 / frame.max(1) as f64).round() as u32
+    };
+    (
+        scale(style.margin_h, SUBTITLE_SCRIPT_RESOLUTION.0, frame_size.0),
+        scale(style.margin_v, SUBTITLE_SCRIPT_RESOLUTION.1, frame_size.1),
+    )
+}
+
+// Builds the force_style value for SRT/VTT subtitles. Colours are &HAABBGGRR (Alpha, Blue, Green, Red),
+// as returned by convert_color_to_ffmpeg_bgr. ASS booleans are -1 for on and 0 for off. `margins`
+// are (horizontal, vertical) in script units; None keeps the renderer's defaults.
+fn build_subtitle_force_style(
+    escaped_font_path: &str,
+    font_size: u32,
+    font_color: &str,
+    alignment: u8,
+    style: &SubtitleStyle,
+    margins: Option<(u32, u32)>,
+) -> Result<String, Error> {
+    let ass_bool = |on: bool| if on { -1 } else { 0 };
+    let font = match style.font_name {
+        Some(font_name) => format!("Fontname={}", escape_path_for_ffmpeg_filter(font_name)),
+        None => format!("Fontfile='{}'", escaped_font_path),
+    };
+    // BorderStyle=3 replaces the outline with an opaque box, which libass (like VSFilter) fills
+    // with OutlineColour, pads by Outline around the text and shadows in BackColour.
+    let (border_style, outline_color) = match style.box_color {
+        Some(box_color) => (3, convert_color_to_ffmpeg_bgr_with_opacity(box_color, style.box_opacity)?),
+        None => (1, convert_color_to_ffmpeg_bgr(style.outline_color)?),
+    };
+    let mut force_style = format!(
+        "{},FontSize={},PrimaryColour={},OutlineColour={},BorderStyle={},Outline={},Shadow={},Bold={},Italic={},Alignment={}",
+        font,
+        font_size,
+        convert_color_to_ffmpeg_bgr(font_color)?,
+        outline_color,
+        border_style,
+        style.outline_width,
+        style.shadow_depth,
+        ass_bool(style.bold),
+        ass_bool(style.italic),
+        alignment
+    );
+    if border_style == 3 {
+        force_style.push_str(&format!(",BackColour={}", outline_color));
+    }
+    if let Some((margin_h, margin_v)) = margins {
+        force_style.push_str(&format!(",MarginL={},MarginR={},MarginV={}", margin_h, margin_h, margin_v));
     }
+    Ok(force_style)
 }
 
-
-pub fn burn_subtitles(
-    input_video_path: &str,
+// Builds the `subtitles` video filter used by burn_subtitles. `frame_size` is the (width, height)
+// of the video the subtitles are burned into, needed to express the style's pixel margins; when it
+// is None the margins are left out.
+#[allow(clippy::too_many_arguments)]
+pub fn build_subtitles_filter(
     subtitle_file_path: &str,
-    output_video_path: &str,
     font_path: &str,
     font_size: u32,
     font_color: &str,
-    vertical_alignment: &str, // e.g., "bottom", "center", "top"
-    horizontal_alignment: &str, // e.g., "center", "left", "right"
-) -> Result<(), Error> {
-    ensure_ffmpeg_initialized();
-
-    let mut ictx = ffmpeg::format::input(&Path::new(input_video_path))?;
-    
-    let mut opts = ffmpeg::Dictionary::new();
-
+    vertical_alignment: &str,
+    horizontal_alignment: &str,
+    style: &SubtitleStyle,
+    frame_size: Option<(u32, u32)>,
+) -> Result<String, ShortsError> {
     // --- Subtitle filter configuration ---
     let escaped_subtitle_path = escape_path_for_ffmpeg_filter(subtitle_file_path);
     let escaped_font_path = escape_path_for_ffmpeg_filter(font_path);
@@ -156,69 +3086,234 @@ pub fn burn_subtitles(
     //     .and_then(|s| s.to_str())
     //     .unwrap_or("Arial"); // Fallback font name
 
-    let ffmpeg_color = convert_color_to_ffmpeg_bgr(font_color)?;
-    let ffmpeg_alignment = map_alignment_to_ffmpeg_value(vertical_alignment, horizontal_alignment)?;
+    // ASS files carry their own styling, so force_style is only built for SRT/VTT input.
+    let is_ass_subtitle = Path::new(subtitle_file_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("ass") || e.eq_ignore_ascii_case("ssa"))
+        .unwrap_or(false);
+
+    Ok(if is_ass_subtitle {
+        format!("subtitles=filename='{}'", escaped_subtitle_path)
+    } else {
+        let ffmpeg_alignment = map_alignment_to_ffmpeg_value(vertical_alignment, horizontal_alignment)?;
+        let margins = frame_size.map(|frame_size| subtitle_margins_in_script_units(style, frame_size));
+        let force_style = build_subtitle_force_style(&escaped_font_path, font_size, font_color, ffmpeg_alignment, style, margins)?;
+
+        format!(
+            "subtitles=filename='{}':force_style='{}'",
+            escaped_subtitle_path,
+            force_style
+        )
+    })
+}
+
+// One subtitle file burned by burn_subtitle_tracks, with its own font and placement, e.g. the
+// original captions at the bottom and a translation at the top.
+pub struct SubtitleTrack<'a> {
+    pub subtitle_file_path: &'a str,
+    pub font_path: &'a str,
+    pub font_size: u32,
+    pub font_color: &'a str,
+    pub vertical_alignment: &'a str,
+    pub horizontal_alignment: &'a str,
+    pub style: &'a SubtitleStyle<'a>,
+}
+
+// Builds one `subtitles` filter per track, chained in order so later tracks are drawn over the
+// earlier ones. Two SRT/VTT tracks at the same alignment would be drawn over each other, so that is
+// rejected; ASS tracks position themselves and are not checked.
+pub fn build_subtitle_tracks_filter(tracks: &[SubtitleTrack], frame_size: Option<(u32, u32)>) -> Result<String, ShortsError> {
+    if tracks.is_empty() {
+        return Err(ShortsError::InvalidArgument("No subtitle tracks to burn".to_string()));
+    }
+    let mut claimed_alignments: Vec<(u8, &str)> = Vec::new();
+    let mut filters = Vec::with_capacity(tracks.len());
+    for track in tracks {
+        let filter = build_subtitles_filter(
+            track.subtitle_file_path,
+            track.font_path,
+            track.font_size,
+            track.font_color,
+            track.vertical_alignment,
+            track.horizontal_alignment,
+            track.style,
+            frame_size,
+        )?;
+        if filter.contains(":force_style=") {
+            let alignment = map_alignment_to_ffmpeg_value(track.vertical_alignment, track.horizontal_alignment)?;
+            if let Some((_, other_path)) = claimed_alignments.iter().find(|(claimed, _)| *claimed == alignment) {
+                return Err(ShortsError::InvalidArgument(format!(
+                    "Subtitle tracks {} and {} are both aligned {}/{}; give each track its own position",
+                    other_path, track.subtitle_file_path, track.vertical_alignment, track.horizontal_alignment
+                )));
+            }
+            claimed_alignments.push((alignment, track.subtitle_file_path));
+        }
+        filters.push(filter);
+    }
+    Ok(filters.join(","))
+}
+
+// Draws the subtitles into the video frames: the video is decoded, run through the libass
+// `subtitles` filter and re-encoded. The audio is re-encoded with encode_settings' audio codec, or
+// copied with AudioCodec::Copy.
+#[allow(clippy::too_many_arguments)]
+pub fn burn_subtitles(
+    input_video_path: &str,
+    subtitle_file_path: &str,
+    output_video_path: &str,
+    font_path: &str,
+    font_size: u32,
+    font_color: &str,
+    vertical_alignment: &str, // e.g., "bottom", "center", "top"
+    horizontal_alignment: &str, // e.g., "center", "left", "right"
+    style: &SubtitleStyle,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<PacketWriteStats, ShortsError> {
+    burn_subtitles_with_progress(
+        input_video_path,
+        subtitle_file_path,
+        output_video_path,
+        font_path,
+        font_size,
+        font_color,
+        vertical_alignment,
+        horizontal_alignment,
+        style,
+        encode_settings,
+        |_| {},
+    )
+}
 
-    // Construct force_style string for SRT
-    // PrimaryColour format is &HAABBGGRR (Alpha, Blue, Green, Red)
-    // We'll use opaque (FF for alpha). So &HFFBBGGRR
-    let primary_colour_bgr = ffmpeg_color.trim_start_matches("&H");
-    let force_style = format!(
-        "Fontfile='{}',FontSize={},PrimaryColour=&HFF{},Alignment={}",
-        escaped_font_path, // Using Fontfile with escaped path
+// Same as burn_subtitles, calling `progress` with the fraction of the input written so far.
+#[allow(clippy::too_many_arguments)]
+pub fn burn_subtitles_with_progress<F: FnMut(f64)>(
+    input_video_path: &str,
+    subtitle_file_path: &str,
+    output_video_path: &str,
+    font_path: &str,
+    font_size: u32,
+    font_color: &str,
+    vertical_alignment: &str,
+    horizontal_alignment: &str,
+    style: &SubtitleStyle,
+    encode_settings: &VideoEncodeSettings,
+    progress: F,
+) -> Result<PacketWriteStats, ShortsError> {
+    let track = SubtitleTrack {
+        subtitle_file_path,
+        font_path,
         font_size,
-        primary_colour_bgr, // Assuming ffmpeg_color is &HBBGGRR, so FF + BBGGRR
-        ffmpeg_alignment
-    );
-    
-    let filter_string = format!(
-        "subtitles=filename='{}':force_style='{}'",
-        escaped_subtitle_path,
-        force_style
-    );
+        font_color,
+        vertical_alignment,
+        horizontal_alignment,
+        style,
+    };
+    burn_subtitle_tracks_with_progress(input_video_path, &[track], output_video_path, encode_settings, progress)
+}
 
-    opts.set("vf", &filter_string);
-    opts.set("c:v", "libx264"); // Re-encode video
-    opts.set("c:a", "copy");    // Copy audio
+// Burns several subtitle tracks in one re-encode, e.g. bilingual captions; see
+// build_subtitle_tracks_filter for how they are combined.
+pub fn burn_subtitle_tracks(
+    input_video_path: &str,
+    tracks: &[SubtitleTrack],
+    output_video_path: &str,
+    encode_settings: &VideoEncodeSettings,
+) -> Result<PacketWriteStats, ShortsError> {
+    burn_subtitle_tracks_with_progress(input_video_path, tracks, output_video_path, encode_settings, |_| {})
+}
 
-    let mut octx = ffmpeg::format::output_with(&Path::new(output_video_path), opts)?;
+// Same as burn_subtitle_tracks, calling `progress` with the fraction of the input written so far.
+// With encode_settings.two_pass_stats the video is encoded twice (see EncodePass), each pass
+// taking half of the progress; the stats files are removed afterwards.
+pub fn burn_subtitle_tracks_with_progress<F: FnMut(f64)>(
+    input_video_path: &str,
+    tracks: &[SubtitleTrack],
+    output_video_path: &str,
+    encode_settings: &VideoEncodeSettings,
+    mut progress: F,
+) -> Result<PacketWriteStats, ShortsError> {
+    // libass would silently render with its default font instead.
+    if let Some(track) = tracks.iter().find(|track| track.style.font_name.is_none() && !Path::new(track.font_path).is_file()) {
+        return Err(ShortsError::InvalidArgument(format!("Font file does not exist: {}", track.font_path)));
+    }
+    ensure_ffmpeg_initialized()?;
 
-    // Stream mapping and parameter copying
-    for ist_stream in ictx.streams() {
-        let ist_params = ist_stream.parameters();
-        let mut ost_stream = octx.add_stream(None)?; 
-        
-        // For both video (being re-encoded with filter) and audio (being copied),
-        // copying original parameters is a good starting point. 
-        // FFmpeg will adjust video parameters as needed based on libx264 and filter requirements.
-        ost_stream.set_parameters(ist_params.clone());
-        // Explicit tag setting removed as it was causing errors and set_parameters should handle it.
+    let (frame_size, has_audio) = {
+        let ictx = ffmpeg::format::input(&Path::new(input_video_path))?;
+        (probe_video_dimensions(&ictx)?, ictx.streams().best(ffmpeg::media::Type::Audio).is_some())
+    };
+    let filter_string = build_subtitle_tracks_filter(tracks, Some(frame_size))?;
+
+    // The audio is re-encoded with the configured codec, since copying it is not always valid in
+    // the output container; AudioCodec::Copy keeps it as it is.
+    let audio_filter = (has_audio && encode_settings.audio_codec != AudioCodec::Copy).then_some("anull");
+    let Some(stats_path) = &encode_settings.two_pass_stats else {
+        return Ok(transcode_with_filters(
+            input_video_path,
+            output_video_path,
+            Some(&filter_string),
+            audio_filter,
+            encode_settings,
+            progress,
+        )?);
+    };
+    if encode_settings.encoder != VideoEncoder::X264 || encode_settings.bit_rate.is_none() {
+        return Err(ShortsError::InvalidArgument(
+            "Two-pass encoding needs the x264 encoder and a video bitrate".to_string(),
+        ));
     }
-    
-    octx.set_metadata(ictx.metadata().to_owned());
-    octx.write_header()?;
 
-    // Transcoding/Filtering loop (simplified for -vf and -c:a copy)
-    // When -vf is used with re-encoding, and -c:a copy, ffmpeg handles the complexities.
-    // We just need to feed all packets.
-    for (stream, mut packet) in ictx.packets() {
-        let ist_idx = stream.index();
-        if octx.stream(ist_idx).is_some() { // If this stream is part of our output
-            packet.set_stream(ist_idx); // Map to the same stream index in output
-            match packet.write_interleaved(&mut octx) {
-                Ok(_) => (),
-                Err(e) => eprintln!("Failed to write packet: {}", e), // Log and continue or break
+    let pass_settings = |pass| VideoEncodeSettings { pass: Some(pass), ..encode_settings.clone() };
+    // The audio is only encoded in the second pass, which is the one that gets written.
+    let result = transcode_with_filters(
+        input_video_path,
+        output_video_path,
+        Some(&filter_string),
+        None,
+        &pass_settings(EncodePass::First),
+        |fraction| progress(fraction * 0.5),
+    )
+    .and_then(|_| {
+        transcode_with_filters(
+            input_video_path,
+            output_video_path,
+            Some(&filter_string),
+            audio_filter,
+            &pass_settings(EncodePass::Second),
+            |fraction| progress(0.5 + fraction * 0.5),
+        )
+    });
+    remove_two_pass_stats(stats_path);
+    Ok(result?)
+}
+
+// Removes the files libx264 writes for a two-pass encode: the stats file, its macroblock-tree
+// data and the ".temp" files they are written to before being renamed.
+fn remove_two_pass_stats(stats_path: &Path) {
+    for suffix in ["", ".temp", ".mbtree", ".mbtree.temp"] {
+        let mut path = stats_path.as_os_str().to_owned();
+        path.push(suffix);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Could not remove two-pass stats file {}: {}", Path::new(&path).display(), e)
             }
+            _ => {}
         }
     }
-
-    octx.write_trailer()?;
-    Ok(())
 }
 
 
-pub fn extract_audio(input_path: &str, audio_output_path: &str) -> Result<(), Error> {
-    ensure_ffmpeg_initialized();
+// Copies the best audio stream of the input to `audio_output_path` for transcription. This
+// intermediate is never delivered, so it ignores the audio settings of the shorts.
+// With `audio_stream_index` that stream of the input is extracted instead of the best audio stream.
+pub fn extract_audio(
+    input_path: &str,
+    audio_output_path: &str,
+    audio_stream_index: Option<usize>,
+) -> Result<PacketWriteStats, ShortsError> {
+    ensure_ffmpeg_initialized()?;
 
     let mut ictx = ffmpeg::format::input(&Path::new(input_path))?;
     
@@ -231,13 +3326,9 @@ pub fn extract_audio(input_path: &str, audio_output_path: &str) -> Result<(), Er
 
     let mut octx = ffmpeg::format::output_with(&Path::new(audio_output_path), opts)?;
 
-    let best_audio_stream_index = ictx
-        .streams()
-        .best(ffmpeg::media::Type::Audio)
-        .ok_or_else(|| Error::msg("No audio stream found in input"))?
-        .index();
+    let audio_stream_index = select_audio_stream(&ictx, input_path, audio_stream_index)?;
 
-    let ist_audio = ictx.stream(best_audio_stream_index)
+    let ist_audio = ictx.stream(audio_stream_index)
         .ok_or_else(|| Error::msg("Could not retrieve input audio stream"))?;
     let ist_audio_params = ist_audio.parameters();
     
@@ -249,18 +3340,983 @@ pub fn extract_audio(input_path: &str, audio_output_path: &str) -> Result<(), Er
     octx.set_metadata(ictx.metadata().to_owned());
     octx.write_header()?; // Options should be applied by the context.
 
+    let mut write_stats = PacketWriteStats::default();
     for (stream, mut packet) in ictx.packets() {
-        if stream.index() == best_audio_stream_index {
+        if stream.index() == audio_stream_index {
             packet.set_stream(0); // Output stream index for the single audio stream will be 0
-            match packet.write_interleaved(&mut octx) {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("Failed to write audio packet: {}", e);
+            write_stats.record(packet.write_interleaved(&mut octx), audio_output_path);
+        }
+    }
+
+    octx.write_trailer()?;
+    write_stats.check(audio_output_path, false)
+}
+
+// audio_peaks first collects the peak of each block of this many samples, so the decoded audio is
+// never held in memory as a whole.
+const PEAK_BLOCK_SAMPLES: usize = 256;
+
+// The peak amplitude of the best audio stream of `input_path` in each of `buckets` equal parts of
+// it, e.g. to draw a waveform for picking trim points: from 0.0 (silence) to 1.0 (full scale),
+// taking the loudest channel of each sample. Fails with NoAudioStream when the input has no audio
+// and with InvalidArgument when `buckets` is 0.
+pub fn audio_peaks(input_path: &str, buckets: usize) -> Result<Vec<f32>, ShortsError> {
+    if buckets == 0 {
+        return Err(ShortsError::InvalidArgument("audio_peaks needs at least 1 bucket".to_string()));
+    }
+    ensure_ffmpeg_initialized()?;
+
+    let mut ictx = ffmpeg::format::input(&Path::new(input_path))?;
+    let (stream_index, time_base, mut decoder) = {
+        let stream = ictx
+            .streams()
+            .best(ffmpeg::media::Type::Audio)
+            .ok_or_else(|| ShortsError::NoAudioStream(input_path.to_string()))?;
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .audio()?;
+        (stream.index(), stream.time_base(), decoder)
+    };
+    let channel_layout = if decoder.channel_layout().bits() != 0 {
+        decoder.channel_layout()
+    } else {
+        ffmpeg::ChannelLayout::default(decoder.channels() as i32)
+    };
+    // Converts whatever the decoder produces to interleaved f32 samples.
+    let mut filter = build_audio_filter_graph(
+        &decoder,
+        time_base,
+        channel_layout,
+        ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed),
+        decoder.rate() as i32,
+        "anull",
+    )?;
+
+    let mut block_peaks = Vec::new();
+    let (mut block_peak, mut block_samples) = (0.0f32, 0);
+    let mut drain = |filter: &mut ffmpeg::filter::Graph| -> Result<(), Error> {
+        let mut sink = filter.get("out").ok_or_else(|| Error::msg("Audio filter graph has no output"))?;
+        let mut filtered = ffmpeg::frame::Audio::empty();
+        while sink.sink().frame(&mut filtered).is_ok() {
+            let channels = (filtered.channels() as usize).max(1);
+            let values = filtered.samples() * channels;
+            let data = filtered.data(0);
+            let data = &data[..(values * 4).min(data.len())];
+            for sample in data.chunks_exact(channels * 4) {
+                let peak = sample
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).abs())
+                    .fold(0.0f32, f32::max);
+                block_peak = block_peak.max(peak);
+                block_samples += 1;
+                if block_samples == PEAK_BLOCK_SAMPLES {
+                    block_peaks.push(block_peak);
+                    (block_peak, block_samples) = (0.0, 0);
                 }
             }
         }
+        Ok(())
+    };
+    let mut decoded = ffmpeg::frame::Audio::empty();
+    let mut feed = |decoder: &mut ffmpeg::decoder::Audio, filter: &mut ffmpeg::filter::Graph| -> Result<(), Error> {
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            filter.get("in").ok_or_else(|| Error::msg("Audio filter graph has no input"))?.source().add(&decoded)?;
+            drain(filter)?;
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet)?;
+            feed(&mut decoder, &mut filter)?;
+        }
     }
+    decoder.send_eof()?;
+    feed(&mut decoder, &mut filter)?;
+    filter.get("in").ok_or_else(|| Error::msg("Audio filter graph has no input"))?.source().flush()?;
+    drain(&mut filter)?;
 
-    octx.write_trailer()?;
-    Ok(())
+    if block_samples > 0 {
+        block_peaks.push(block_peak);
+    }
+    Ok(bucket_peaks(&block_peaks, buckets))
+}
+
+// Merges `block_peaks` into `buckets` peaks, each the loudest of its share of the blocks. With
+// fewer blocks than buckets, neighbouring buckets repeat a block; without any, all are 0.
+fn bucket_peaks(block_peaks: &[f32], buckets: usize) -> Vec<f32> {
+    if block_peaks.is_empty() {
+        return vec![0.0; buckets];
+    }
+    let block_count = block_peaks.len();
+    (0..buckets)
+        .map(|bucket| {
+            let start = bucket * block_count / buckets;
+            let end = ((bucket + 1) * block_count / buckets).max(start + 1);
+            block_peaks[start..end].iter().copied().fold(0.0f32, f32::max).min(1.0)
+        })
+        .collect()
+}
+
+// One audio stream of an input file, as listed by probe_audio_streams.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioStreamInfo {
+    pub index: usize, // Stream index in the file, as taken by audio_stream_index
+    pub codec: String, // e.g. "aac"
+    pub language: Option<String>, // The stream's language tag, e.g. "eng"
+    pub title: Option<String>, // The stream's title tag, e.g. "Microphone"
+}
+
+impl std::fmt::Display for AudioStreamInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}, language {}", self.index, self.codec, self.language.as_deref().unwrap_or("unknown"))?;
+        if let Some(title) = &self.title {
+            write!(f, ", \"{}\"", title)?;
+        }
+        Ok(())
+    }
+}
+
+// Basic facts about a video file, as read by probe_video.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VideoInfo {
+    pub duration_secs: Option<f64>, // None when FFmpeg could not determine it
+    pub width: u32,
+    pub height: u32,
+    pub rotation_degrees: u32, // Clockwise rotation players apply (0, 90, 180 or 270); width and height are as displayed
+    pub fps: Option<f64>, // Average frame rate of the video stream, None when unknown
+    pub variable_frame_rate: bool, // The frame rate varies (r_frame_rate and avg_frame_rate differ), e.g. a screen recording
+    pub video_codec: String, // e.g. "h264"
+    pub has_audio: bool,
+    pub audio_codec: Option<String>, // Of the best audio stream, e.g. "aac"
+}
+
+// Reads the duration, frame size, frame rate and codecs of `input_path`, opening it once. The
+// streams described are FFmpeg's best video and audio ones, which the processing steps use.
+// Fails with NotAVideo when there is no video stream.
+pub fn probe_video(input_path: &str) -> Result<VideoInfo, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+    let video_stream = ictx.streams().best(ffmpeg::media::Type::Video).ok_or_else(|| ShortsError::NotAVideo {
+        path: input_path.to_string(),
+        reason: "it has no video stream".to_string(),
+    })?;
+    let (width, height) = probe_video_dimensions(&ictx)?;
+    let fps = [video_stream.avg_frame_rate(), video_stream.rate()]
+        .into_iter()
+        .find(|rate| rate.numerator() > 0 && rate.denominator() > 0)
+        .map(f64::from);
+    let audio_codec = ictx
+        .streams()
+        .best(ffmpeg::media::Type::Audio)
+        .map(|stream| stream.parameters().id().name().to_string());
+    Ok(VideoInfo {
+        duration_secs: input_duration_secs(&ictx),
+        width,
+        height,
+        rotation_degrees: stream_rotation_degrees(&video_stream),
+        fps,
+        variable_frame_rate: variable_frame_rate_target(video_stream.rate(), video_stream.avg_frame_rate()).is_some(),
+        video_codec: video_stream.parameters().id().name().to_string(),
+        has_audio: audio_codec.is_some(),
+        audio_codec,
+    })
+}
+
+// Lists the audio streams of `input_path`, e.g. to find the audio_stream_index of a separate
+// microphone track.
+pub fn probe_audio_streams(input_path: &str) -> Result<Vec<AudioStreamInfo>, ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    let ictx = ffmpeg::format::input(&Path::new(input_path))?;
+    Ok(audio_streams(&ictx))
+}
+
+fn audio_streams(ictx: &ffmpeg::format::context::Input) -> Vec<AudioStreamInfo> {
+    ictx.streams()
+        .filter(|stream| stream.parameters().medium() == ffmpeg::media::Type::Audio)
+        .map(|stream| {
+            let metadata = stream.metadata();
+            AudioStreamInfo {
+                index: stream.index(),
+                codec: stream.parameters().id().name().to_string(),
+                language: metadata.get("language").map(str::to_string),
+                title: metadata.get("title").map(str::to_string),
+            }
+        })
+        .collect()
+}
+
+// The audio stream to use: stream `index` when given, which has to be an audio stream, otherwise
+// the best one.
+fn select_audio_stream(
+    ictx: &ffmpeg::format::context::Input,
+    input_path: &str,
+    index: Option<usize>,
+) -> Result<usize, ShortsError> {
+    let Some(index) = index else {
+        return ictx
+            .streams()
+            .best(ffmpeg::media::Type::Audio)
+            .map(|stream| stream.index())
+            .ok_or_else(|| ShortsError::NoAudioStream(input_path.to_string()));
+    };
+    let streams = audio_streams(ictx);
+    if streams.is_empty() {
+        return Err(ShortsError::NoAudioStream(input_path.to_string()));
+    }
+    if streams.iter().any(|stream| stream.index == index) {
+        Ok(index)
+    } else {
+        Err(ShortsError::InvalidArgument(describe_wrong_audio_stream(input_path, index, &streams)))
+    }
+}
+
+// Fails with NotAVideo unless FFmpeg can open `input_path` and finds a video stream in it, to
+// catch inputs such as the .srt or an audio file before any processing starts. Still images are
+// rejected too, although FFmpeg reads them as one-frame videos.
+pub fn check_video_file(input_path: &str) -> Result<(), ShortsError> {
+    ensure_ffmpeg_initialized()?;
+    let not_a_video = |reason: String| ShortsError::NotAVideo { path: input_path.to_string(), reason };
+    let ictx = ffmpeg::format::input(&Path::new(input_path))
+        .map_err(|e| not_a_video(format!("FFmpeg cannot read it ({})", e)))?;
+    let format_name = ictx.format().name().to_string();
+    if format_name.starts_with("image2") || format_name.ends_with("_pipe") {
+        return Err(not_a_video(format!("it is a still image ({})", format_name)));
+    }
+    let streams: Vec<(ffmpeg::media::Type, String)> = ictx
+        .streams()
+        .map(|stream| (stream.parameters().medium(), stream.parameters().id().name().to_string()))
+        .collect();
+    if streams.iter().any(|(medium, _)| *medium == ffmpeg::media::Type::Video) {
+        return Ok(());
+    }
+    Err(not_a_video(format!("it has no video stream ({} file with {})", format_name, describe_streams(&streams))))
+}
+
+// Whether `input_path` is a video file, see check_video_file.
+pub fn is_video_file(input_path: &str) -> bool {
+    check_video_file(input_path).is_ok()
+}
+
+// Lists streams as e.g. "streams: audio (mp3), data (bin_data)".
+fn describe_streams(streams: &[(ffmpeg::media::Type, String)]) -> String {
+    if streams.is_empty() {
+        return "no streams".to_string();
+    }
+    let described: Vec<String> = streams
+        .iter()
+        .map(|(medium, codec)| format!("{:?} ({})", medium, codec).to_lowercase())
+        .collect();
+    format!("streams: {}", described.join(", "))
+}
+
+fn describe_wrong_audio_stream(input_path: &str, index: usize, streams: &[AudioStreamInfo]) -> String {
+    format!(
+        "Stream {} of '{}' is not an audio stream. Available audio streams: {}",
+        index,
+        input_path,
+        streams.iter().map(|stream| stream.to_string()).collect::<Vec<_>>().join("; ")
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDir;
+
+    #[test]
+    fn packet_write_failures_are_tolerated_up_to_the_ratio_unless_strict() {
+        let few = PacketWriteStats { total: 1000, failed: 10 };
+        assert_eq!(few.check("out.mp4", false).unwrap(), few);
+        assert!(matches!(
+            few.check("out.mp4", true),
+            Err(ShortsError::PacketWriteFailed { failed: 10, total: 1000, .. })
+        ));
+        assert!(PacketWriteStats { total: 1000, failed: 11 }.check("out.mp4", false).is_err());
+        assert!(PacketWriteStats::default().check("out.mp4", true).is_ok());
+    }
+
+    #[test]
+    fn wrong_audio_stream_index_lists_the_available_streams() {
+        let streams = [
+            AudioStreamInfo { index: 1, codec: "aac".to_string(), language: Some("eng".to_string()), title: Some("Desktop".to_string()) },
+            AudioStreamInfo { index: 2, codec: "opus".to_string(), language: None, title: None },
+        ];
+        assert_eq!(
+            describe_wrong_audio_stream("in.mkv", 0, &streams),
+            "Stream 0 of 'in.mkv' is not an audio stream. Available audio streams: 1: aac, language eng, \"Desktop\"; 2: opus, language unknown"
+        );
+    }
+
+    #[test]
+    fn metadata_overrides_replace_or_remove_the_source_tags() {
+        let source = [("title", "Raw recording"), ("encoder", "Lavf60"), ("comment", "take 3")];
+        let merged = merge_metadata(source.into_iter(), &[("title", "My short"), ("comment", ""), ("artist", "Me")]);
+        let merged: Vec<(&str, &str)> = merged.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(merged, [("encoder", "Lavf60"), ("title", "My short"), ("artist", "Me")]);
+    }
+
+    #[test]
+    fn stream_summary_names_each_stream_and_its_codec() {
+        assert_eq!(describe_streams(&[]), "no streams");
+        assert_eq!(
+            describe_streams(&[(ffmpeg::media::Type::Audio, "mp3".to_string()), (ffmpeg::media::Type::Subtitle, "subrip".to_string())]),
+            "streams: audio (mp3), subtitle (subrip)"
+        );
+        let err = ShortsError::NotAVideo { path: "talk.srt".to_string(), reason: "it has no video stream (srt file with streams: subtitle (subrip))".to_string() };
+        assert_eq!(err.to_string(), "'talk.srt' is not a video: it has no video stream (srt file with streams: subtitle (subrip))");
+    }
+
+    #[test]
+    fn soft_subtitles_use_the_text_codec_of_the_container() {
+        assert_eq!(soft_subtitle_codec(OutputContainer::detect("out.mp4", None)), Some(ffmpeg::codec::Id::MOV_TEXT));
+        assert_eq!(soft_subtitle_codec(OutputContainer::detect("out.webm", None)), Some(ffmpeg::codec::Id::WEBVTT));
+        assert_eq!(soft_subtitle_codec(OutputContainer::detect("out.mkv", None)), Some(ffmpeg::codec::Id::SUBRIP));
+        assert!(!OutputContainer::detect("out.ts", None).supports_soft_subtitles());
+
+        assert_eq!(soft_subtitle_payload(ffmpeg::codec::Id::MOV_TEXT, "Hi\nthere"), b"\x00\x08Hi\nthere");
+        assert_eq!(soft_subtitle_payload(ffmpeg::codec::Id::WEBVTT, "Hi"), b"Hi");
+
+        let err = mux_soft_subtitles("in.mp4", "captions.ass", "out.mp4", None, &[], &[], &[]).unwrap_err();
+        assert!(matches!(err, ShortsError::InvalidArgument(_)));
+        let err = mux_soft_subtitles("in.mp4", "captions.srt", "out.ts", None, &[], &[], &[]).unwrap_err();
+        assert!(err.to_string().contains("cannot hold soft subtitles"));
+    }
+
+    #[test]
+    fn container_picks_default_codecs_and_rejects_incompatible_ones() {
+        assert_eq!(default_codecs_for_container("out.webm", None), (VideoEncoder::Vp9, AudioCodec::Opus));
+        assert_eq!(default_codecs_for_container("out.MKV", None), (VideoEncoder::X264, AudioCodec::Aac));
+        assert_eq!(default_codecs_for_container("out.mp4", Some("webm")), (VideoEncoder::Vp9, AudioCodec::Opus));
+        assert_eq!(OutputContainer::detect("out", None), OutputContainer::Mp4);
+        assert_eq!(OutputContainer::detect("out.ts", None), OutputContainer::Other);
+
+        assert!(check_container_codecs("out.webm", None, VideoEncoder::Vp9, AudioCodec::Opus).is_ok());
+        assert!(check_container_codecs("out.webm", None, VideoEncoder::X264, AudioCodec::Opus).is_err());
+        assert!(check_container_codecs("out.webm", None, VideoEncoder::Vp9, AudioCodec::Copy).is_err());
+        assert!(check_container_codecs("out.mov", None, VideoEncoder::Nvenc, AudioCodec::Opus).is_err());
+        assert!(check_container_codecs("out.mkv", None, VideoEncoder::Vp9, AudioCodec::Copy).is_ok());
+    }
+
+    #[test]
+    fn speed_filters_chain_atempo_outside_its_range() {
+        assert_eq!(build_speed_filters(1.25), ("setpts=PTS/1.25".to_string(), "atempo=1.25".to_string()));
+        assert_eq!(build_speed_filters(5.0).1, "atempo=2,atempo=2,atempo=1.25");
+        assert_eq!(build_speed_filters(0.2).1, "atempo=0.5,atempo=0.5,atempo=0.8");
+    }
+
+    #[test]
+    fn center_crop_landscape_to_vertical() {
+        // 1920x1080 -> 9:16 keeps full height and crops the sides
+        assert_eq!(compute_center_crop(1920, 1080, 9, 16), (606, 1080, 657, 0));
+    }
+
+    #[test]
+    fn center_crop_source_already_narrower_than_target() {
+        // 720x1600 is narrower than 9:16, so the crop trims top/bottom instead
+        assert_eq!(compute_center_crop(720, 1600, 9, 16), (720, 1280, 0, 160));
+    }
+
+    #[test]
+    fn center_crop_dimensions_are_even() {
+        let (w, h, _, _) = compute_center_crop(1281, 721, 4, 5);
+        assert_eq!(w % 2, 0);
+        assert_eq!(h % 2, 0);
+    }
+
+    #[test]
+    fn pad_filter_centers_the_video_on_colored_bars() {
+        assert_eq!(
+            build_pad_filter(1080, 1920, "black").unwrap(),
+            "scale=1080:1920:force_original_aspect_ratio=decrease:force_divisible_by=2,pad=1080:1920:(ow-iw)/2:(oh-ih)/2:color=0x000000,setsar=1"
+        );
+        assert!(build_pad_filter(1080, 1920, "#1E90FF80").unwrap().contains(":color=0x1E90FF,"));
+        assert!(build_pad_filter(1080, 1920, "mauve").is_err());
+        assert!(check_target_frame_size(1080, 1920).is_ok());
+        assert!(check_target_frame_size(1081, 1920).is_err());
+    }
+
+    #[test]
+    fn blur_background_covers_the_frame_behind_the_fitted_video() {
+        let filter = build_blur_background_filter(1080, 1920);
+        // Scaling up to cover and cropping to the frame leaves no bars at any source aspect ratio.
+        assert!(filter.contains("[background]scale=1080:1920:force_original_aspect_ratio=increase,crop=1080:1920,boxblur"));
+        assert!(filter.contains("[foreground]scale=1080:1920:force_original_aspect_ratio=decrease"));
+        assert!(filter.ends_with("overlay=(W-w)/2:(H-h)/2,setsar=1"));
+        assert!(!filter.contains("  "));
+        assert_eq!(short_frame_size(9, 16), (1080, 1920));
+    }
+
+    #[test]
+    fn parse_aspect_ratio_rejects_bad_input() {
+        assert_eq!(parse_aspect_ratio("9:16").unwrap(), (9, 16));
+        assert!(parse_aspect_ratio("916").is_err());
+        assert!(parse_aspect_ratio("0:16").is_err());
+    }
+
+    #[test]
+    fn height_percent_font_size_is_in_script_units() {
+        // 288 script lines span the frame height.
+        assert_eq!(font_size_for_height_percent(5.0), 14);
+        assert_eq!(font_size_for_height_percent(25.0), 72);
+        assert_eq!(font_size_for_height_percent(0.01), 1);
+    }
+
+    #[test]
+    fn subtitle_force_style_includes_outline_and_emphasis() {
+        let style = SubtitleStyle { shadow_depth: 1.5, bold: true, ..SubtitleStyle::default() };
+        let force_style = build_subtitle_force_style("font.ttf", 24, "#FFFF00", 2, &style, None).unwrap();
+        assert_eq!(
+            force_style,
+            "Fontfile='font.ttf',FontSize=24,PrimaryColour=&H0000FFFF,OutlineColour=&H00000000,BorderStyle=1,Outline=2,Shadow=1.5,Bold=-1,Italic=0,Alignment=2"
+        );
+    }
+
+    #[test]
+    fn filter_paths_escape_windows_separators_quotes_and_brackets() {
+        assert_eq!(escape_path_for_ffmpeg_filter("C:\\Users\\me\\my'clip\\sub.srt"), "C\\:/Users/me/my\\'\\''clip/sub.srt");
+        assert_eq!(escape_path_for_ffmpeg_filter("D:/clips/[final] take.srt"), "D\\:/clips/\\[final\\] take.srt");
+        assert_eq!(escape_path_for_ffmpeg_filter("/home/me/subs, v2.srt"), "/home/me/subs, v2.srt");
+        assert_eq!(escape_path_for_ffmpeg_filter("relative\\sub.srt"), "relative/sub.srt");
+    }
+
+    #[test]
+    fn subtitles_filter_escapes_paths_and_converts_colors() {
+        let filter = build_subtitles_filter(
+            "C:/shorts/subs.srt",
+            "C:/fonts/Inter.ttf",
+            32,
+            "#FF8800",
+            "top",
+            "left",
+            &SubtitleStyle::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            filter,
+            "subtitles=filename='C\\:/shorts/subs.srt':force_style='Fontfile='C\\:/fonts/Inter.ttf',FontSize=32,\
+             PrimaryColour=&H000088FF,OutlineColour=&H00000000,BorderStyle=1,Outline=2,Shadow=0,Bold=0,Italic=0,Alignment=7'"
+        );
+
+        // ASS subtitles keep their own styling, so only the file is passed.
+        let filter = build_subtitles_filter("C:/shorts/subs.ass", "font.ttf", 32, "white", "top", "left", &SubtitleStyle::default(), None).unwrap();
+        assert_eq!(filter, "subtitles=filename='C\\:/shorts/subs.ass'");
+        assert!(build_subtitles_filter("subs.srt", "font.ttf", 32, "mauve", "top", "left", &SubtitleStyle::default(), None).is_err());
+    }
+
+    #[test]
+    fn subtitle_tracks_chain_and_need_their_own_alignment() {
+        let style = SubtitleStyle { font_name: Some(FALLBACK_FONT_NAME), ..SubtitleStyle::default() };
+        let track = |path, vertical| SubtitleTrack {
+            subtitle_file_path: path,
+            font_path: "",
+            font_size: 24,
+            font_color: "white",
+            vertical_alignment: vertical,
+            horizontal_alignment: "center",
+            style: &style,
+        };
+        let filter = build_subtitle_tracks_filter(&[track("en.srt", "bottom"), track("es.srt", "top")], None).unwrap();
+        let filters: Vec<&str> = filter.split(",subtitles=").collect();
+        assert_eq!(filters.len(), 2, "{}", filter);
+        assert!(filters[0].starts_with("subtitles=filename='en.srt'") && filters[0].ends_with("Alignment=2'"), "{}", filter);
+        assert!(filters[1].starts_with("filename='es.srt'") && filters[1].ends_with("Alignment=8'"), "{}", filter);
+
+        let err = build_subtitle_tracks_filter(&[track("en.srt", "bottom"), track("es.srt", "bottom")], None).unwrap_err();
+        assert!(err.to_string().contains("both aligned bottom/center"), "{}", err);
+        // ASS tracks carry their own positions.
+        assert!(build_subtitle_tracks_filter(&[track("en.srt", "bottom"), track("es.ass", "bottom")], None).is_ok());
+        assert!(build_subtitle_tracks_filter(&[], None).is_err());
+    }
+
+    #[test]
+    fn font_name_replaces_the_font_file() {
+        let style = SubtitleStyle { font_name: Some("DejaVu Sans"), ..SubtitleStyle::default() };
+        let force_style = build_subtitle_force_style("font.ttf", 24, "white", 2, &style, None).unwrap();
+        assert!(force_style.starts_with("Fontname=DejaVu Sans,FontSize=24,"), "{}", force_style);
+        assert!(!force_style.contains("Fontfile"));
+    }
+
+    #[test]
+    fn subtitle_margins_scale_from_video_pixels_to_script_units() {
+        // 200px above the bottom of a 1080x1920 short, 54px in from each side.
+        let style = SubtitleStyle { margin_v: 200, margin_h: 54, ..SubtitleStyle::default() };
+        assert_eq!(subtitle_margins_in_script_units(&style, (1080, 1920)), (19, 30));
+
+        let filter = build_subtitles_filter("subs.srt", "font.ttf", 24, "white", "bottom", "center", &style, Some((1080, 1920))).unwrap();
+        assert!(filter.ends_with(",Alignment=2,MarginL=19,MarginR=19,MarginV=30'"), "{}", filter);
+        let filter = build_subtitles_filter("subs.srt", "font.ttf", 24, "white", "bottom", "center", &style, None).unwrap();
+        assert!(!filter.contains("Margin"));
+    }
+
+    #[test]
+    fn bit_rate_parsing_and_target_size_estimate() {
+        assert_eq!(parse_bit_rate("4M").unwrap(), 4_000_000);
+        assert_eq!(parse_bit_rate("2.5m").unwrap(), 2_500_000);
+        assert_eq!(parse_bit_rate("800k").unwrap(), 800_000);
+        assert_eq!(parse_bit_rate("64000").unwrap(), 64_000);
+        assert!(parse_bit_rate("fast").is_err());
+        assert!(parse_bit_rate("0").is_err());
+
+        // 50 MB over 60s: 6.53 Mb/s in total, minus 192 kb/s of audio.
+        assert_eq!(bit_rate_for_target_size(50_000_000, 60.0, 192_000).unwrap(), 6_341_333);
+        assert!(bit_rate_for_target_size(1_000_000, 60.0, DEFAULT_AUDIO_BIT_RATE).is_err());
+    }
+
+    #[test]
+    fn two_pass_stats_files_are_removed_afterwards() {
+        let dir = TestDir::new("two_pass");
+        let stats_path = dir.join("short_x264_2pass.log");
+        for name in ["short_x264_2pass.log", "short_x264_2pass.log.mbtree", "other.log"] {
+            std::fs::write(dir.join(name), b"stats").unwrap();
+        }
+        remove_two_pass_stats(&stats_path);
+        let mut left: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        left.sort();
+        assert_eq!(left, ["other.log"]);
+    }
+
+    #[test]
+    fn scale_filter_keeps_aspect_for_a_single_dimension() {
+        assert_eq!(build_scale_filter(None, None), None);
+        assert_eq!(build_scale_filter(Some(720), Some(1280)).unwrap(), "scale=720:1280,setsar=1");
+        assert_eq!(build_scale_filter(Some(720), None).unwrap(), "scale=720:-2,setsar=1");
+        assert_eq!(build_scale_filter(None, Some(1280)).unwrap(), "scale=-2:1280,setsar=1");
+    }
+
+    #[test]
+    fn silence_cuts_keep_padding_and_drop_edge_silences() {
+        let spans = spans_without_silences(&[(0.0, 1.5), (4.0, 6.0), (9.0, 10.0)], 10.0);
+        let expected = [(1.4, 4.1), (5.9, 9.1)];
+        assert_eq!(spans.len(), expected.len());
+        for ((start, end), (expected_start, expected_end)) in spans.iter().zip(expected) {
+            assert!((start - expected_start).abs() < 1e-9 && (end - expected_end).abs() < 1e-9, "{:?}", spans);
+        }
+        assert_eq!(spans_without_silences(&[], 10.0), vec![(0.0, 10.0)]);
+        assert!(spans_without_silences(&[(0.0, 10.0)], 10.0).is_empty());
+
+        let (video, audio) = build_silence_cut_filters(&[(0.0, 2.0)]);
+        assert_eq!(video, "trim=start=0:end=2,setpts=PTS-STARTPTS");
+        assert_eq!(audio, "atrim=start=0:end=2,asetpts=PTS-STARTPTS");
+        let (video, _) = build_silence_cut_filters(&[(0.0, 2.0), (3.0, 4.5)]);
+        assert_eq!(
+            video,
+            "[in]split=2[v0][v1];[v0]trim=start=0:end=2,setpts=PTS-STARTPTS[vcut0];\
+             [v1]trim=start=3:end=4.5,setpts=PTS-STARTPTS[vcut1];[vcut0][vcut1]concat=n=2:v=1:a=0"
+        );
+    }
+
+    #[test]
+    fn fade_filters_skip_zero_length_fades() {
+        assert_eq!(build_fade_filters(0.0, 0.0, 30.0), (None, None));
+        assert_eq!(
+            build_fade_filters(0.5, 0.0, 30.0),
+            (Some("fade=t=in:st=0:d=0.5".to_string()), Some("afade=t=in:st=0:d=0.5".to_string()))
+        );
+        let (video, audio) = build_fade_filters(1.0, 2.0, 30.0);
+        assert_eq!(video.unwrap(), "fade=t=in:st=0:d=1,fade=t=out:st=28:d=2");
+        assert_eq!(audio.unwrap(), "afade=t=in:st=0:d=1,afade=t=out:st=28:d=2");
+    }
+
+    #[test]
+    fn convert_color_handles_alpha() {
+        assert_eq!(convert_color_to_ffmpeg_bgr("#FF000080").unwrap(), "&H800000FF");
+        assert_eq!(convert_color_to_ffmpeg_bgr("#112233").unwrap(), "&H00332211");
+        assert_eq!(convert_color_to_ffmpeg_bgr("white").unwrap(), "&H00FFFFFF");
+        assert!(convert_color_to_ffmpeg_bgr("#FF0000G0").is_err());
+    }
+
+    #[test]
+    fn caption_box_replaces_the_outline_with_a_translucent_box() {
+        let style = SubtitleStyle { box_color: Some("#20202080"), box_opacity: 0.5, ..SubtitleStyle::default() };
+        let force_style = build_subtitle_force_style("font.ttf", 24, "white", 2, &style, None).unwrap();
+        // 0x80 transparency leaves 127/255 opaque; half of that is 0xC0 transparency.
+        assert!(force_style.contains(",OutlineColour=&HC0202020,BorderStyle=3,Outline=2,"), "{}", force_style);
+        assert!(force_style.ends_with(",BackColour=&HC0202020"), "{}", force_style);
+        assert_eq!(convert_color_to_ffmpeg_bgr_with_opacity("black", 1.0).unwrap(), "&H00000000");
+        assert_eq!(convert_color_to_ffmpeg_bgr_with_opacity("red", 0.0).unwrap(), "&HFF0000FF");
+    }
+
+    #[test]
+    fn convert_color_accepts_named_colors_and_short_hex() {
+        for (input, expected) in [
+            ("yellow", "&H0000FFFF"),
+            ("Cyan", "&H00FFFF00"),
+            ("magenta", "&H00FF00FF"),
+            ("orange", "&H0000A5FF"),
+            ("grey", "&H00808080"),
+            ("green", "&H0000FF00"),
+            ("#FFF", "&H00FFFFFF"),
+            ("#f80", "&H000088FF"),
+            ("F80", "&H000088FF"),
+            ("#F008", "&H880000FF"),
+        ] {
+            assert_eq!(convert_color_to_ffmpeg_bgr(input).unwrap(), expected, "{}", input);
+        }
+        for input in ["#GGG", "#FFFFF", "mauve", ""] {
+            assert!(convert_color_to_ffmpeg_bgr(input).is_err(), "{}", input);
+        }
+    }
+
+    // Generates a 2 second 320x240 MPEG-4 Part 2 test clip called `file_name` in `dir` with the
+    // ffmpeg CLI, with a sine tone of `audio_channels` channels (none when 0). The tests using it
+    // are ignored by default, so where the CLI is missing they are reported as ignored rather than
+    // passed; run them with `cargo test -- --include-ignored`.
+    fn generate_sample_clip(dir: &Path, file_name: &str, audio_channels: u32) -> PathBuf {
+        let path = dir.join(file_name);
+        let mut command = std::process::Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "lavfi", "-i", "testsrc=size=320x240:rate=25:duration=2"]);
+        if audio_channels > 0 {
+            command
+                .args(["-f", "lavfi", "-i", "sine=frequency=440:duration=2"])
+                .args(["-ac", &audio_channels.to_string(), "-c:a", "aac"]);
+        }
+        let generated = command
+            .args(["-c:v", "mpeg4", "-shortest"])
+            .arg(&path)
+            .status()
+            .expect("the ffmpeg CLI is needed to generate the sample clip");
+        assert!(generated.success(), "ffmpeg failed to generate {:?}", path);
+        path
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn trim_seek_modes_start_on_the_keyframe_around_the_start() {
+        let dir = TestDir::new("trim_seek");
+        let clip = generate_sample_clip(&dir, "clip.mp4", 2);
+
+        // Not timed here: on a 2s sample both modes finish instantly. Fast skips reading the packets
+        // before the start, so what it saves grows with the start offset into the input.
+        let mut durations = Vec::new();
+        for seek_mode in [SeekMode::Accurate, SeekMode::Fast] {
+            let output = dir.join(format!("trimmed_{:?}.mp4", seek_mode));
+            let stats = trim_video_with_progress(clip.to_str().unwrap(), output.to_str().unwrap(), 0.5, 1.0, None, seek_mode, |_| {})
+                .unwrap();
+            assert!(stats.total > 0);
+            let duration = probe_duration_secs(output.to_str().unwrap()).unwrap();
+            assert!(duration > 0.0 && duration < 1.5, "{:?}: {}s", seek_mode, duration);
+            durations.push(duration);
+        }
+        // Fast starts at the keyframe before 0.5s, Accurate at or after it.
+        assert!(durations[1] >= durations[0] - 0.05, "{:?}", durations);
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn probe_video_describes_the_sample_clip() {
+        let dir = TestDir::new("probe_video");
+        let with_audio = generate_sample_clip(&dir, "clip.mp4", 2);
+        let silent = generate_sample_clip(&dir, "silent.mp4", 0);
+
+        let info = probe_video(with_audio.to_str().unwrap()).unwrap();
+        assert_eq!((info.width, info.height), (320, 240));
+        assert_eq!(info.fps, Some(25.0));
+        assert_eq!(info.video_codec, "mpeg4");
+        assert_eq!((info.has_audio, info.audio_codec.as_deref()), (true, Some("aac")));
+        assert!((info.duration_secs.unwrap() - 2.0).abs() < 0.1, "{:?}", info.duration_secs);
+        let silent_info = probe_video(silent.to_str().unwrap()).unwrap();
+        assert_eq!((silent_info.has_audio, silent_info.audio_codec), (false, None));
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn fractional_trim_duration_is_kept() {
+        let dir = TestDir::new("trim_fractional");
+        let clip = generate_sample_clip(&dir, "clip.mp4", 2);
+
+        let output = dir.join("trimmed.mp4");
+        trim_video(clip.to_str().unwrap(), output.to_str().unwrap(), 0.0, 1.5).unwrap();
+        let duration = probe_duration_secs(output.to_str().unwrap()).unwrap();
+        assert!((duration - 1.5).abs() < 0.15, "{}s", duration);
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn burn_subtitles_outputs_h264_video() {
+        let dir = TestDir::new("burn_subtitles");
+
+        // The sample is MPEG-4 Part 2, so an H.264 output proves the video was re-encoded.
+        let clip = generate_sample_clip(&dir, "clip.mp4", 2);
+
+        let srt = dir.join("clip.srt");
+        std::fs::write(&srt, "1\n00:00:00,000 --> 00:00:01,500\nHello there\n").unwrap();
+        let output = dir.join("burned.mp4");
+
+        burn_subtitles(
+            clip.to_str().unwrap(),
+            srt.to_str().unwrap(),
+            output.to_str().unwrap(),
+            "DejaVuSans.ttf",
+            24,
+            "white",
+            "bottom",
+            "center",
+            &SubtitleStyle { font_name: Some(FALLBACK_FONT_NAME), ..SubtitleStyle::default() },
+            &VideoEncodeSettings::default(),
+        )
+        .unwrap();
+
+        let ictx = ffmpeg::format::input(&output).unwrap();
+        let video_stream = ictx.streams().best(ffmpeg::media::Type::Video).unwrap();
+        assert_eq!(video_stream.parameters().id(), ffmpeg::codec::Id::H264);
+        assert_eq!(probe_video_dimensions(&ictx).unwrap(), (320, 240));
+    }
+
+    // Decodes the frame `at_secs` into `path` and returns the luma plane, row by row.
+    fn decode_luma_at(path: &Path, at_secs: f64) -> (Vec<u8>, usize, usize) {
+        let mut ictx = ffmpeg::format::input(&path).unwrap();
+        let stream = ictx.streams().best(ffmpeg::media::Type::Video).unwrap();
+        let (stream_index, time_base) = (stream.index(), stream.time_base());
+        let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+            .unwrap()
+            .decoder()
+            .video()
+            .unwrap();
+        let frame = decode_frame_at(&mut ictx, &mut decoder, stream_index, time_base, at_secs)
+            .unwrap()
+            .unwrap();
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let luma = (0..height)
+            .flat_map(|row| frame.data(0)[row * frame.stride(0)..][..width].to_vec())
+            .collect();
+        (luma, width, height)
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn burn_subtitles_draws_the_captions_into_the_frames() {
+        let dir = TestDir::new("burn_captions");
+        let clip = generate_sample_clip(&dir, "clip.mp4", 2);
+
+        // Both outputs go through the same re-encode; only one of them has a caption on screen.
+        let burn = |cue: &str, output: &Path| {
+            let srt = output.with_extension("srt");
+            std::fs::write(&srt, format!("1\n{}\nHELLO THERE\n", cue)).unwrap();
+            burn_subtitles(
+                clip.to_str().unwrap(),
+                srt.to_str().unwrap(),
+                output.to_str().unwrap(),
+                "DejaVuSans.ttf",
+                32,
+                "white",
+                "bottom",
+                "center",
+                // Whatever fonts are installed; the font file above does not exist.
+                &SubtitleStyle { font_name: Some(FALLBACK_FONT_NAME), ..SubtitleStyle::default() },
+                &VideoEncodeSettings::default(),
+            )
+            .unwrap()
+        };
+        let captioned = dir.join("captioned.mp4");
+        let uncaptioned = dir.join("uncaptioned.mp4");
+        burn("00:00:00,000 --> 00:00:02,000", &captioned);
+        burn("00:01:00,000 --> 00:01:01,000", &uncaptioned);
+
+        let (with_caption, width, height) = decode_luma_at(&captioned, 1.0);
+        let (without_caption, ..) = decode_luma_at(&uncaptioned, 1.0);
+        let mean_difference = |rows: std::ops::Range<usize>| {
+            let pixels = rows.len() * width;
+            rows.flat_map(|row| row * width..(row + 1) * width)
+                .map(|i| with_caption[i].abs_diff(without_caption[i]) as f64)
+                .sum::<f64>()
+                / pixels as f64
+        };
+        // The caption sits at the bottom; the top of the frame only differs by encoder noise.
+        let bottom = mean_difference(height * 3 / 4..height);
+        let top = mean_difference(0..height / 4);
+        assert!(bottom > top + 2.0, "caption area differs by {:.2}, top by {:.2}", bottom, top);
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn remux_video_uses_the_named_format_whatever_the_extension() {
+        let dir = TestDir::new("remux");
+        let clip = generate_sample_clip(&dir, "clip.mp4", 2);
+
+        let output = dir.join("stream");
+        let stats = remux_video(clip.to_str().unwrap(), output.to_str().unwrap(), Some("mpegts"), &[], &[], &[]).unwrap();
+        assert!(stats.total > 0);
+        assert_eq!(stats.failed, 0);
+        let ictx = ffmpeg::format::input(&output).unwrap();
+        assert_eq!(ictx.format().name(), "mpegts");
+
+        let err = remux_video(clip.to_str().unwrap(), output.to_str().unwrap(), Some("no_such_muxer"), &[], &[], &[]).unwrap_err();
+        assert!(matches!(err, ShortsError::InvalidArgument(_)));
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn normalize_audio_loudness_handles_mono_and_stereo() {
+        let dir = TestDir::new("loudnorm");
+
+        for channels in [1u16, 2] {
+            let clip = generate_sample_clip(&dir, &format!("clip_{}ch.mp4", channels), channels as u32);
+            let output = dir.join(format!("normalized_{}ch.mp4", channels));
+            normalize_audio_loudness(clip.to_str().unwrap(), output.to_str().unwrap(), DEFAULT_TARGET_LUFS, &VideoEncodeSettings::default())
+                .unwrap();
+
+            let ictx = ffmpeg::format::input(&output).unwrap();
+            let audio_stream = ictx.streams().best(ffmpeg::media::Type::Audio).unwrap();
+            let decoder = ffmpeg::codec::context::Context::from_parameters(audio_stream.parameters())
+                .unwrap()
+                .decoder()
+                .audio()
+                .unwrap();
+            assert_eq!(decoder.channels(), channels);
+            assert!(ictx.streams().best(ffmpeg::media::Type::Video).is_some());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn normalize_audio_loudness_fails_without_audio() {
+        let dir = TestDir::new("loudnorm_silent");
+
+        let clip = generate_sample_clip(&dir, "silent.mp4", 0);
+        let output = dir.join("normalized.mp4");
+        let err = normalize_audio_loudness(clip.to_str().unwrap(), output.to_str().unwrap(), DEFAULT_TARGET_LUFS, &VideoEncodeSettings::default())
+            .unwrap_err();
+        assert!(matches!(err, ShortsError::NoAudioStream(_)));
+    }
+
+    #[test]
+    fn overlay_position_follows_alignment() {
+        // top-right
+        assert_eq!(
+            overlay_position_exprs(9, 20),
+            ("main_w-overlay_w-20".to_string(), "20".to_string())
+        );
+        // bottom-left
+        assert_eq!(
+            overlay_position_exprs(1, 10),
+            ("10".to_string(), "main_h-overlay_h-10".to_string())
+        );
+        // center
+        assert_eq!(
+            overlay_position_exprs(5, 10),
+            ("(main_w-overlay_w)/2".to_string(), "(main_h-overlay_h)/2".to_string())
+        );
+    }
+
+    #[test]
+    fn watermark_size_scales_or_fits_to_frame() {
+        // Small logo without a scale percentage keeps its size
+        assert_eq!(compute_watermark_size(1080, 1920, 200, 100, 20, None), None);
+        // Percentage of the video width, keeping the logo's aspect ratio
+        assert_eq!(compute_watermark_size(1080, 1920, 200, 100, 20, Some(10.0)), Some((108, 54)));
+        // Logo wider than the frame is shrunk to fit inside the margins
+        assert_eq!(compute_watermark_size(1080, 1920, 2000, 500, 20, None), Some((1040, 260)));
+    }
+
+    #[test]
+    fn music_mix_filter_loops_music_and_follows_video_length() {
+        let plain = build_music_mix_filter("music.mp3", 0.2, false);
+        assert!(plain.starts_with("amovie=filename='music.mp3':loop=0,volume=0.2[music];"));
+        assert!(plain.contains("[in][music]amix=inputs=2:duration=first"));
+        assert!(!plain.contains("sidechaincompress"));
+
+        let ducked = build_music_mix_filter("music.mp3", 0.2, true);
+        assert!(ducked.contains("[music][sidechain]sidechaincompress"));
+        assert!(ducked.contains("[voice][ducked]amix=inputs=2:duration=first"));
+    }
+
+    #[test]
+    fn normalize_audio_loudness_rejects_out_of_range_target() {
+        assert!(normalize_audio_loudness("unused.mp4", "unused_out.mp4", 3.0, &VideoEncodeSettings::default()).is_err());
+    }
+
+    #[test]
+    fn block_peaks_are_merged_into_the_requested_buckets() {
+        assert_eq!(bucket_peaks(&[0.1, 0.5, 0.2, 0.3, 0.9, 0.4], 3), [0.5, 0.3, 0.9]);
+        assert_eq!(bucket_peaks(&[0.1, 0.5, 0.2, 0.3, 0.9], 2), [0.5, 0.9]);
+        // Fewer blocks than buckets; samples over full scale are capped.
+        assert_eq!(bucket_peaks(&[0.2, 1.3], 4), [0.2, 0.2, 1.0, 1.0]);
+        assert_eq!(bucket_peaks(&[], 2), [0.0, 0.0]);
+        assert!(matches!(audio_peaks("unused.mp4", 0), Err(ShortsError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn chapters_start_at_zero_in_order_and_cover_the_video() {
+        let spans = |chapters: Vec<Chapter>| -> Vec<(f64, f64, String)> {
+            chapters.into_iter().map(|c| (c.start_secs, c.end_secs, c.title)).collect()
+        };
+        assert_eq!(spans(build_chapters(&[], &[], 30.0)), [(0.0, 30.0, "Chapter 1".to_string())]);
+        // Unsorted, out of range, too close to the previous start or to the end.
+        assert_eq!(
+            spans(build_chapters(&[20.0, 5.0, 5.4, -1.0, 29.5, 45.0], &["Outro", "Intro"], 30.0)),
+            [
+                (0.0, 5.0, "Chapter 1".to_string()),
+                (5.0, 20.0, "Intro".to_string()),
+                (20.0, 30.0, "Outro".to_string()),
+            ]
+        );
+        // A scene change right at the start becomes the first chapter, keeping its title.
+        assert_eq!(spans(build_chapters(&[0.3, 10.0], &["Opening"], 12.0))[0], (0.0, 10.0, "Opening".to_string()));
+        assert!(build_chapters(&[1.0], &[], 0.0).is_empty());
+    }
+
+    #[test]
+    fn rotations_are_normalized_to_quarter_turns_and_undone_by_filters() {
+        assert_eq!(normalize_rotation(-90.0), 270);
+        assert_eq!(normalize_rotation(90.0), 90);
+        assert_eq!(normalize_rotation(-180.0), 180);
+        assert_eq!(normalize_rotation(359.0), 0);
+        assert_eq!(upright_filter_spec(90, "null"), "transpose=clock,null");
+        assert_eq!(upright_filter_spec(180, "null"), "hflip,vflip,null");
+        assert_eq!(upright_filter_spec(270, "null"), "transpose=cclock,null");
+        assert_eq!(upright_filter_spec(0, "null"), "null");
+    }
+
+    #[test]
+    fn variable_frame_rates_are_detected_and_evened_out_before_the_filters() {
+        let rate = ffmpeg::Rational::new;
+        assert_eq!(variable_frame_rate_target(rate(60, 1), rate(2350, 100)), Some(rate(60, 1)));
+        assert_eq!(variable_frame_rate_target(rate(90000, 1), rate(2997, 100)), Some(rate(2997, 100)));
+        assert_eq!(variable_frame_rate_target(rate(30000, 1001), rate(30000, 1001)), None);
+        assert_eq!(variable_frame_rate_target(rate(30, 1), rate(2990, 100)), None);
+        assert_eq!(variable_frame_rate_target(rate(30, 1), rate(0, 0)), None);
+
+        assert_eq!(prepend_video_filters("fps=60/1", "scale=1080:1920"), "fps=60/1,scale=1080:1920");
+        assert_eq!(
+            prepend_video_filters("fps=60/1", "movie=logo.png[watermark];[in][watermark]overlay=x=10:y=10"),
+            "[in]fps=60/1[prepended];movie=logo.png[watermark];[prepended][watermark]overlay=x=10:y=10"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg CLI"]
+    fn rotated_sources_are_probed_and_reencoded_upright() {
+        let dir = TestDir::new("rotation");
+        let clip = generate_sample_clip(&dir, "clip.mp4", 0);
+        let rotated = dir.join("rotated.mp4");
+        let upright = dir.join("upright.mp4");
+        // Tags the 320x240 clip the way a phone tags portrait video: a display matrix rotating
+        // it 90 degrees counterclockwise, i.e. 270 clockwise.
+        let tagged = std::process::Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-display_rotation", "90", "-i"])
+            .arg(&clip)
+            .args(["-c", "copy"])
+            .arg(&rotated)
+            .status()
+            .unwrap();
+        assert!(tagged.success());
+
+        let info = probe_video(rotated.to_str().unwrap()).unwrap();
+        assert_eq!((info.rotation_degrees, info.width, info.height), (270, 240, 320));
+
+        reencode_video(rotated.to_str().unwrap(), upright.to_str().unwrap(), &VideoEncodeSettings::default()).unwrap();
+        let info = probe_video(upright.to_str().unwrap()).unwrap();
+        let (_, width, height) = decode_luma_at(&upright, 0.5);
+
+        assert_eq!((info.rotation_degrees, info.width, info.height), (0, 240, 320));
+        assert_eq!((width, height), (240, 320));
+    }
 }
@@ -0,0 +1,207 @@
+use crate::error::ShortsError;
+use anyhow::Error;
+use log::info;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// whisper.cpp models resolve_whisper_model can download by name, with the SHA-1 of their ggml file
+// as published by whisper.cpp (models/README.md).
+const GGML_MODELS: [(&str, &str); 12] = [
+    ("tiny", "bd577a113a864445d4c299885e0cb97d4ba92b5f"),
+    ("tiny.en", "c78c86eb1a8faa21b369bcd33207cc90d64ae9df"),
+    ("base", "465707469ff3a37a2b9b8d8f89f2f99de7299dac"),
+    ("base.en", "137c40403d78fd54d454da0f9bd998f78703390c"),
+    ("small", "55356645c2b361a969dfd0ef2c5a50d530afd8d5"),
+    ("small.en", "db8a495a91d927739e50b3fc1cc4c6b8f6c2d022"),
+    ("medium", "fd9727b6e1217c2f614f9b698455c4ffd82463b4"),
+    ("medium.en", "8c30f0e44ce9560643ebd10bbe50cd20eafd3723"),
+    ("large-v1", "b1caaf735c4cc1429223d5a74f0f4d0b9b59a299"),
+    ("large-v2", "0f4c8e34f21cf1a914c59d8b3ce882345ad349d6"),
+    ("large-v3", "ad82bf6a9043ceed055076d0fd39f5f186ff8062"),
+    ("large-v3-turbo", "4af2b29d7ec73d781377bfd1758ca957a807e941"),
+];
+
+// Where the ggml files are downloaded from, as <GGML_MODEL_URL>/ggml-<name>.bin.
+const GGML_MODEL_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+
+// Executable used for the downloads, looked up on PATH.
+const DOWNLOAD_BINARY: &str = "curl";
+
+// The directory models are cached in when no whisper_model_cache_dir is configured:
+// $XDG_CACHE_HOME/shorts_generator/models, or the platform's usual cache directory under the home
+// directory. None when neither is known.
+pub fn default_model_cache_dir() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let cache_root = var("XDG_CACHE_HOME")
+        .or_else(|| var("LOCALAPPDATA"))
+        .or_else(|| {
+            var("HOME").map(|home| {
+                if cfg!(target_os = "macos") {
+                    home.join("Library/Caches")
+                } else {
+                    home.join(".cache")
+                }
+            })
+        })?;
+    Some(cache_root.join("shorts_generator").join("models"))
+}
+
+// The known model `name` refers to, accepting the file name form too, e.g. "base.en" or
+// "ggml-base.en.bin". Returns the name and the SHA-1 of its file.
+fn known_model(name: &str) -> Option<(&'static str, &'static str)> {
+    let name = name.strip_prefix("ggml-").unwrap_or(name);
+    let name = name.strip_suffix(".bin").unwrap_or(name);
+    GGML_MODELS.iter().copied().find(|(known, _)| *known == name)
+}
+
+// What a whisper_model_path refers to: an existing file, or a known model kept at `cache_path`.
+enum ModelLocation {
+    File(PathBuf),
+    Cached { name: &'static str, sha1: &'static str, cache_path: PathBuf },
+}
+
+fn locate_model(model: &str, cache_dir: Option<&Path>) -> Result<ModelLocation, ShortsError> {
+    if Path::new(model).is_file() {
+        return Ok(ModelLocation::File(PathBuf::from(model)));
+    }
+    let (name, sha1) = known_model(model).ok_or_else(|| {
+        let names: Vec<&str> = GGML_MODELS.iter().map(|(name, _)| *name).collect();
+        ShortsError::InvalidArgument(format!(
+            "Whisper model '{}' is neither a file nor a known model name ({})",
+            model,
+            names.join(", ")
+        ))
+    })?;
+    let cache_dir = cache_dir.map(Path::to_path_buf).or_else(default_model_cache_dir).ok_or_else(|| {
+        ShortsError::InvalidArgument(format!(
+            "No cache directory for whisper model '{}': set whisper_model_cache_dir or HOME",
+            model
+        ))
+    })?;
+    Ok(ModelLocation::Cached { name, sha1, cache_path: cache_dir.join(format!("ggml-{}.bin", name)) })
+}
+
+// Checks that resolve_whisper_model can find `model` without downloading anything when `offline`,
+// so a missing model is reported before any video is processed.
+pub fn check_whisper_model(model: &str, cache_dir: Option<&Path>, offline: bool) -> Result<(), ShortsError> {
+    match locate_model(model, cache_dir)? {
+        ModelLocation::Cached { name, cache_path, .. } if offline && !cache_path.is_file() => Err(offline_error(name, &cache_path)),
+        _ => Ok(()),
+    }
+}
+
+fn offline_error(name: &str, cache_path: &Path) -> ShortsError {
+    ShortsError::InvalidArgument(format!(
+        "Whisper model '{}' is not cached at {} and whisper_offline forbids downloading it",
+        name,
+        cache_path.display()
+    ))
+}
+
+// Returns the path of the whisper.cpp model `model` refers to: the file itself when it exists,
+// otherwise a known model name such as "base.en" (or "ggml-base.en.bin"), kept as
+// ggml-<name>.bin in `cache_dir` (default: default_model_cache_dir()). A model missing from the
+// cache is downloaded on first use, with `curl`, and only kept once its SHA-1 checksum matches;
+// with `offline` that fails with InvalidArgument instead. Cached files are not checked again.
+pub fn resolve_whisper_model(model: &str, cache_dir: Option<&Path>, offline: bool) -> Result<PathBuf, ShortsError> {
+    let (name, sha1, cache_path) = match locate_model(model, cache_dir)? {
+        ModelLocation::File(path) => return Ok(path),
+        ModelLocation::Cached { cache_path, .. } if cache_path.is_file() => return Ok(cache_path),
+        ModelLocation::Cached { name, sha1, cache_path } => (name, sha1, cache_path),
+    };
+    if offline {
+        return Err(offline_error(name, &cache_path));
+    }
+    if let Some(parent) = cache_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Downloaded next to its final name, so an interrupted download is never taken for the model.
+    let partial_path = cache_path.with_extension("bin.part");
+    let url = format!("{}/ggml-{}.bin", GGML_MODEL_URL, name);
+    info!("Downloading whisper model '{}' from {} to {}", name, url, cache_path.display());
+    let result = download(&url, &partial_path).and_then(|()| {
+        let actual = file_sha1(&partial_path)?;
+        if actual != sha1 {
+            return Err(Error::msg(format!(
+                "Checksum mismatch for whisper model '{}' downloaded from {}: expected SHA-1 {}, got {}",
+                name, url, sha1, actual
+            ))
+            .into());
+        }
+        std::fs::rename(&partial_path, &cache_path)?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial_path);
+        return Err(e);
+    }
+    info!("Whisper model '{}' downloaded to {}", name, cache_path.display());
+    Ok(cache_path)
+}
+
+fn download(url: &str, output_path: &Path) -> Result<(), ShortsError> {
+    let output = Command::new(DOWNLOAD_BINARY)
+        .args(["--fail", "--location", "--silent", "--show-error", "--retry", "2", "--output"])
+        .arg(output_path)
+        .arg(url)
+        .output()
+        .map_err(|e| Error::msg(format!("Failed to run '{}' to download {}: {}", DOWNLOAD_BINARY, url, e)))?;
+    if !output.status.success() {
+        return Err(Error::msg(format!(
+            "Failed to download {} ({}): {}",
+            url,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+// The SHA-1 of the file at `path` as lowercase hex.
+fn file_sha1(path: &Path) -> Result<String, ShortsError> {
+    let mut file = File::open(path)?;
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.digest().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_resolve_to_files_or_the_cache_without_downloading_offline() {
+        let dir = std::env::temp_dir().join(format!("shorts_whisper_models_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let own_model = dir.join("my-model.bin");
+        std::fs::write(&own_model, b"abc").unwrap();
+        std::fs::write(dir.join("ggml-tiny.en.bin"), b"cached").unwrap();
+
+        let own = resolve_whisper_model(own_model.to_str().unwrap(), Some(&dir), true);
+        let cached = resolve_whisper_model("ggml-tiny.en.bin", Some(&dir), true);
+        let offline = resolve_whisper_model("base.en", Some(&dir), true);
+        let offline_check = check_whisper_model("base.en", Some(&dir), true);
+        let online_check = check_whisper_model("base.en", Some(&dir), false);
+        let unknown = check_whisper_model("huge", Some(&dir), false);
+        let sha1 = file_sha1(&own_model).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(own.unwrap(), own_model);
+        assert_eq!(cached.unwrap(), dir.join("ggml-tiny.en.bin"));
+        assert!(matches!(offline, Err(ShortsError::InvalidArgument(_))));
+        assert!(matches!(offline_check, Err(ShortsError::InvalidArgument(_))));
+        assert!(online_check.is_ok());
+        assert!(matches!(unknown, Err(ShortsError::InvalidArgument(_))));
+        assert_eq!(sha1, "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+}
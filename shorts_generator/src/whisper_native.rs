@@ -0,0 +1,146 @@
+use anyhow::{Result, Error, bail};
+use std::fmt::Write as _;
+use std::path::Path;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+// whisper.cpp expects 16 kHz mono f32 samples.
+const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+// Transcribes a WAV file (as produced by extract_audio) with whisper.cpp and writes the result as SRT.
+// `language` is a whisper language code; None auto-detects it. With `translate_to_english` the
+// transcript is translated to English.
+pub(crate) fn transcribe_to_srt(
+    audio_path: &Path,
+    model_path: &Path,
+    srt_path: &Path,
+    language: Option<&str>,
+    translate_to_english: bool,
+) -> Result<(), Error> {
+    let samples = load_wav_as_whisper_input(audio_path)?;
+
+    let model_path_str = model_path
+        .to_str()
+        .ok_or_else(|| Error::msg("Whisper model path is not valid UTF-8"))?;
+    let ctx = WhisperContext::new_with_params(model_path_str, WhisperContextParameters::default())
+        .map_err(|e| Error::msg(format!("Failed to load whisper model '{}': {}", model_path.display(), e)))?;
+    let mut state = ctx
+        .create_state()
+        .map_err(|e| Error::msg(format!("Failed to create whisper state: {}", e)))?;
+
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_print_special(false);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_timestamps(false);
+    // whisper.cpp defaults to English rather than detecting the language.
+    params.set_language(Some(language.unwrap_or("auto")));
+    params.set_translate(translate_to_english);
+
+    state
+        .full(params, &samples)
+        .map_err(|e| Error::msg(format!("Whisper transcription failed: {}", e)))?;
+
+    let segment_count = state
+        .full_n_segments()
+        .map_err(|e| Error::msg(format!("Failed to read whisper segments: {}", e)))?;
+
+    let mut srt = String::new();
+    for i in 0..segment_count {
+        let read_err = |e| Error::msg(format!("Failed to read whisper segment {}: {}", i, e));
+        // Segment timestamps are reported in centiseconds.
+        let start_cs = state.full_get_segment_t0(i).map_err(read_err)?;
+        let end_cs = state.full_get_segment_t1(i).map_err(read_err)?;
+        let text = state.full_get_segment_text(i).map_err(read_err)?;
+
+        writeln!(
+            srt,
+            "{}\n{} --> {}\n{}\n",
+            i + 1,
+            format_srt_timestamp(start_cs.max(0) as u64 * 10),
+            format_srt_timestamp(end_cs.max(0) as u64 * 10),
+            text.trim()
+        )?;
+    }
+
+    std::fs::write(srt_path, srt)?;
+    Ok(())
+}
+
+// Formats milliseconds as an SRT timestamp (HH:MM:SS,mmm).
+fn format_srt_timestamp(total_ms: u64) -> String {
+    let hours = total_ms / 3_600_000;
+    let minutes = (total_ms % 3_600_000) / 60_000;
+    let seconds = (total_ms % 60_000) / 1_000;
+    let millis = total_ms % 1_000;
+    format!("{:02}:{:02}:{:02},{:03}", hours, minutes, seconds, millis)
+}
+
+// Reads a PCM WAV file and converts it to the 16 kHz mono f32 samples whisper.cpp expects.
+fn load_wav_as_whisper_input(audio_path: &Path) -> Result<Vec<f32>, Error> {
+    let reader = hound::WavReader::open(audio_path)?;
+    let spec = reader.spec();
+
+    let samples: Vec<f32> = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16) => reader
+            .into_samples::<i16>()
+            .map(|s| s.map(|v| v as f32 / 32768.0))
+            .collect::<Result<_, _>>()?,
+        (hound::SampleFormat::Float, 32) => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        (format, bits) => bail!(
+            "Unsupported WAV sample format {:?} ({} bits) in '{}'",
+            format,
+            bits,
+            audio_path.display()
+        ),
+    };
+
+    let mono = downmix_to_mono(&samples, spec.channels);
+    Ok(resample_linear(&mono, spec.sample_rate, WHISPER_SAMPLE_RATE))
+}
+
+// Averages interleaved channels into a single mono channel.
+fn downmix_to_mono(samples: &[f32], channels: u16) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+    samples
+        .chunks(channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+// Simple linear-interpolation resampler; good enough for speech recognition input.
+fn resample_linear(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from_rate as f64 / to_rate as f64;
+    let out_len = (samples.len() as f64 / ratio).floor() as usize;
+    (0..out_len)
+        .map(|i| {
+            let pos = i as f64 * ratio;
+            let idx = pos.floor() as usize;
+            let frac = (pos - idx as f64) as f32;
+            let a = samples[idx];
+            let b = *samples.get(idx + 1).unwrap_or(&a);
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_timestamp_formatting() {
+        assert_eq!(format_srt_timestamp(0), "00:00:00,000");
+        assert_eq!(format_srt_timestamp(3_723_456), "01:02:03,456");
+    }
+
+    #[test]
+    fn resample_halves_length_from_32k_to_16k() {
+        let samples: Vec<f32> = (0..32).map(|i| i as f32).collect();
+        assert_eq!(resample_linear(&samples, 32_000, 16_000).len(), 16);
+    }
+}